@@ -0,0 +1,34 @@
+//! Compares `ContentHash` with the SHA1 it replaced on a large document.
+//!
+//! Run with `cargo bench --bench content_hash`.
+
+use std::time::{Duration, Instant};
+
+use rs_readme::ContentHash;
+use sha1::{Digest, Sha1};
+
+const ITERATIONS: u32 = 20;
+
+fn time(iterations: u32, mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    start.elapsed() / iterations
+}
+
+fn main() {
+    // About 20 MB of markdown, like a big generated document
+    let doc = "## A heading\n\nSome *text* with a [link](./a.md) and `code`.\n\n".repeat(320_000);
+
+    let xxh3 = time(ITERATIONS, || {
+        std::hint::black_box(ContentHash::of(doc.as_bytes()));
+    });
+    let sha1 = time(ITERATIONS, || {
+        std::hint::black_box(Sha1::digest(doc.as_bytes()));
+    });
+
+    println!("document: {} MB", doc.len() / 1_000_000);
+    println!("ContentHash (XXH3): {:?} per hash", xxh3);
+    println!("SHA1:               {:?} per hash", sha1);
+}
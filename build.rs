@@ -0,0 +1,41 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+/// The static assets worth compressing, woff and woff2 are already compressed.
+const COMPRESSIBLE: &[&str] = &[
+    "static/style.css",
+    "static/reload.js",
+    "static/octicons/octicons.css",
+    "static/octicons/octicons.eot",
+    "static/octicons/octicons.svg",
+    "static/octicons/octicons.ttf",
+];
+
+/// Gzips the compressible static assets into `OUT_DIR` so they can be embedded next to the
+/// originals and sent to browsers that accept gzip.
+fn main() {
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+
+    for asset in COMPRESSIBLE {
+        println!("cargo:rerun-if-changed={}", asset);
+
+        let contents = fs::read(asset).unwrap_or_else(|err| panic!("reading {}: {}", asset, err));
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder
+            .write_all(&contents)
+            .and_then(|_| encoder.finish())
+            .and_then(|gzipped| {
+                let name = Path::new(asset).file_name().expect("assets are files");
+                fs::write(
+                    Path::new(&out_dir).join(format!("{}.gz", name.to_string_lossy())),
+                    gzipped,
+                )
+            })
+            .unwrap_or_else(|err| panic!("compressing {}: {}", asset, err));
+    }
+}
@@ -0,0 +1,193 @@
+use log::warn;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use crate::variables::timestamp_of;
+
+/// How big an access log grows before it's rotated, 10MiB.
+pub const DEFAULT_MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
+
+/// How many rotated logs are kept, `access.log.1` being the newest.
+const ROTATED_LOGS: u32 = 3;
+
+/// One answered request, as it's written to an [`AccessLog`].
+pub(crate) struct Access<'a> {
+    /// Who asked, when that's known.
+    pub peer: Option<&'a str>,
+    pub method: &'a str,
+    pub path: &'a str,
+    pub status: u16,
+
+    /// How big the body was, when that's known before it's sent.
+    pub bytes: Option<u64>,
+    pub duration: Duration,
+}
+
+/// An append-only log of the requests the server answered, one per line, for keeping track of
+/// who reads what on a shared server, see `--access-log`.
+///
+/// Lines look like `2024-02-29T13:05:09Z 10.0.0.7 GET /README.md 200 5120 12ms`, with `-` for
+/// what isn't known. When the log would grow past its maximum size it's moved to `<log>.1`,
+/// older ones to `<log>.2` and so on, and a new one is started.
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    path: PathBuf,
+    max_size: u64,
+    file: Arc<Mutex<Option<(File, u64)>>>,
+}
+
+impl AccessLog {
+    /// Logs to the file at `path`, appending to it if it's already there. It's opened on the
+    /// first request, failing to write to it is logged as a warning.
+    pub fn new(path: impl Into<PathBuf>) -> AccessLog {
+        AccessLog {
+            path: path.into(),
+            max_size: DEFAULT_MAX_LOG_SIZE,
+            file: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Rotates the log when it would grow past `max_size` bytes instead of 10MiB, `0` means
+    /// never.
+    pub fn with_max_size(mut self, max_size: u64) -> AccessLog {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Appends `access` to the log.
+    pub(crate) fn record(&self, access: &Access<'_>) {
+        let line = format!(
+            "{} {} {} {} {} {} {}ms\n",
+            timestamp_of(SystemTime::now()),
+            access.peer.unwrap_or("-"),
+            access.method,
+            access.path,
+            access.status,
+            access
+                .bytes
+                .map_or_else(|| "-".to_string(), |bytes| bytes.to_string()),
+            access.duration.as_millis()
+        );
+        if let Err(err) = self.append(&line) {
+            warn!("Could not write to the access log {:?}: {}", self.path, err);
+        }
+    }
+
+    fn append(&self, line: &str) -> io::Result<()> {
+        let mut file = self.lock();
+        let (mut opened, mut size) = match file.take() {
+            Some(file) => file,
+            None => open(&self.path)?,
+        };
+        if self.max_size > 0 && size > 0 && size + line.len() as u64 > self.max_size {
+            drop(opened);
+            rotate(&self.path)?;
+            (opened, size) = open(&self.path)?;
+        }
+
+        opened.write_all(line.as_bytes())?;
+        *file = Some((opened, size + line.len() as u64));
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<(File, u64)>> {
+        match self.file.lock() {
+            Ok(file) => file,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+/// Opens the log at `path` for appending, with how big it already is.
+fn open(path: &Path) -> io::Result<(File, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+/// Moves the log at `path` to `<path>.1`, shifting the older ones along and dropping the oldest.
+fn rotate(path: &Path) -> io::Result<()> {
+    let rotated = |n: u32| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    };
+
+    for n in (1..ROTATED_LOGS).rev() {
+        if rotated(n).exists() {
+            fs::rename(rotated(n), rotated(n + 1))?;
+        }
+    }
+    fs::rename(path, rotated(1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn access(path: &str) -> Access<'_> {
+        Access {
+            peer: Some("10.0.0.7"),
+            method: "GET",
+            path,
+            status: 200,
+            bytes: None,
+            duration: Duration::from_millis(12),
+        }
+    }
+
+    #[test]
+    fn appends_a_line_per_request() {
+        let dir = std::env::temp_dir().join(format!("rs-readme-access-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.log");
+        fs::write(&path, "earlier\n").unwrap();
+
+        let log = AccessLog::new(&path);
+        log.record(&access("/README.md"));
+        log.record(&Access {
+            peer: None,
+            bytes: Some(5120),
+            ..access("/docs/guide.md")
+        });
+
+        let written = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = written.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "earlier");
+        assert!(lines[1].ends_with("Z 10.0.0.7 GET /README.md 200 - 12ms"));
+        assert!(lines[2].ends_with("Z - GET /docs/guide.md 200 5120 12ms"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rotates_by_size() {
+        let dir =
+            std::env::temp_dir().join(format!("rs-readme-access-rotate-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.log");
+
+        // Each line is 51 bytes, so two fit
+        let log = AccessLog::new(&path).with_max_size(120);
+        for n in 0..7 {
+            log.record(&access(&format!("/{}.md", n)));
+        }
+
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert!(read("access.log").contains("/6.md"));
+        assert!(read("access.log.1").contains("/4.md"));
+        assert!(read("access.log.1").contains("/5.md"));
+        assert!(read("access.log.2").contains("/2.md"));
+        assert!(read("access.log.3").contains("/0.md"));
+        assert!(!dir.join("access.log.4").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
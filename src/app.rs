@@ -0,0 +1,552 @@
+use glob::Pattern;
+use http_types::Url;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tide::Server;
+
+use crate::access_log::{AccessLog, DEFAULT_MAX_LOG_SIZE};
+use crate::archive_finder::{is_archive, ArchiveFinder};
+use crate::code_theme::CodeTheme;
+use crate::config::ConfigError;
+use crate::content_finder::{FileFinder, DEFAULT_MAX_SIZE};
+use crate::diagrams::PlantUml;
+use crate::events::Events;
+use crate::i18n::Lang;
+use crate::image_proxy::ImageProxy;
+use crate::limits::{DEFAULT_MAX_CONVERSIONS, DEFAULT_MAX_UPDATE_STREAMS};
+use crate::markdown_converter::Converter;
+use crate::navigation::NAV_FILE;
+use crate::offline_converter::OfflineConverter;
+use crate::page_cache::DEFAULT_CAPACITY;
+use crate::pins::Pins;
+use crate::piped::{PipedDocument, PipedFinder, PIPED_DOCUMENT};
+#[cfg(feature = "plugins")]
+use crate::plugins::Plugin;
+use crate::remote_finder::{split_document_url, RemoteFinder};
+use crate::table::DEFAULT_FILTER_ROWS;
+use crate::updates::{DEFAULT_DEBOUNCE, DEFAULT_PUSH_INTERVAL};
+use crate::web_server::{build_app_with, Converters, State, DEFAULT_CONVERT_TIMEOUT};
+use crate::webhook::Webhook;
+
+/// The GitHub API used unless [`AppBuilder::github_api`] says otherwise.
+const GITHUB_API: &str = "https://api.github.com";
+
+/// The server `rs-readme` runs, for embedding it in other programs.
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// let builder = rs_readme::App::builder().root("docs").port(4000);
+/// let addr = builder.addr();
+/// let server = builder.build().expect("invalid config");
+/// server.listen(addr).await
+/// # }
+/// ```
+pub struct App;
+
+impl App {
+    pub fn builder() -> AppBuilder {
+        AppBuilder::default()
+    }
+}
+
+/// Configures an rs-readme server, see [`App`].
+pub struct AppBuilder {
+    root: PathBuf,
+    remote: Option<Url>,
+    piped: Option<PipedDocument>,
+    offline: bool,
+    context: Option<String>,
+    github_api: String,
+    host: String,
+    port: u16,
+    config: Option<PathBuf>,
+    events: Events,
+    cache_size: usize,
+    max_file_size: u64,
+    plain_text: bool,
+    sanitize_html: bool,
+    trust_html: bool,
+    sortable_tables: bool,
+    filter_tables_over: usize,
+    allow_remote: bool,
+    edit: bool,
+    debug_errors: bool,
+    lang: Lang,
+    max_conversions: usize,
+    max_update_streams: usize,
+    debounce: Duration,
+    push_interval: Duration,
+    convert_timeout: Duration,
+    plantuml_server: Option<String>,
+    title: Option<String>,
+    code_theme: CodeTheme,
+    proxy_images: bool,
+    webhook: Option<Url>,
+    access_log: Option<PathBuf>,
+    access_log_max_size: u64,
+    defines: HashMap<String, String>,
+    treat_as_md: Vec<Pattern>,
+    #[cfg(feature = "plugins")]
+    plugins: Vec<Plugin>,
+    extensions: Vec<Extension>,
+}
+
+/// A hook run on the server after rs-readme's own routes and middleware are added.
+type Extension = Box<dyn FnOnce(&mut Server<Arc<State>>)>;
+
+impl Default for AppBuilder {
+    fn default() -> Self {
+        AppBuilder {
+            root: PathBuf::from("."),
+            remote: None,
+            piped: None,
+            offline: true,
+            context: None,
+            github_api: GITHUB_API.to_string(),
+            host: "127.0.0.1".to_string(),
+            port: 4000,
+            config: None,
+            events: Events::disabled(),
+            cache_size: DEFAULT_CAPACITY,
+            max_file_size: DEFAULT_MAX_SIZE,
+            plain_text: false,
+            sanitize_html: false,
+            trust_html: false,
+            sortable_tables: false,
+            filter_tables_over: DEFAULT_FILTER_ROWS,
+            allow_remote: false,
+            edit: false,
+            debug_errors: false,
+            lang: Lang::English,
+            max_conversions: DEFAULT_MAX_CONVERSIONS,
+            max_update_streams: DEFAULT_MAX_UPDATE_STREAMS,
+            debounce: DEFAULT_DEBOUNCE,
+            push_interval: DEFAULT_PUSH_INTERVAL,
+            convert_timeout: DEFAULT_CONVERT_TIMEOUT,
+            plantuml_server: None,
+            title: None,
+            code_theme: CodeTheme::default(),
+            proxy_images: false,
+            webhook: None,
+            access_log: None,
+            access_log_max_size: DEFAULT_MAX_LOG_SIZE,
+            defines: HashMap::new(),
+            treat_as_md: Vec::new(),
+            #[cfg(feature = "plugins")]
+            plugins: Vec::new(),
+            extensions: Vec::new(),
+        }
+    }
+}
+
+impl AppBuilder {
+    /// The folder to serve files from, defaults to `.`. A `.zip`, `.tar` or `.tar.gz` archive is
+    /// served from without extracting it, see [`ArchiveFinder`].
+    pub fn root(mut self, root: impl Into<PathBuf>) -> AppBuilder {
+        self.root = root.into();
+        self
+    }
+
+    /// Fetches documents over HTTP instead of reading them from [`AppBuilder::root`], see
+    /// [`RemoteFinder`]. A URL naming a document, like a raw file on GitHub, is shown at the root
+    /// of the site and relative links are fetched from beside it.
+    pub fn remote(mut self, url: Url) -> AppBuilder {
+        self.remote = Some(url);
+        self
+    }
+
+    /// Shows `document` at the root of the site, with the files it links to still read from
+    /// [`AppBuilder::root`], see [`PipedFinder`]. Only for folders, not archives or
+    /// [`AppBuilder::remote`].
+    pub fn piped(mut self, document: PipedDocument) -> AppBuilder {
+        self.piped = Some(document);
+        self
+    }
+
+    /// Whether to render with pulldown-cmark (the default) or the GitHub API.
+    pub fn offline(mut self, offline: bool) -> AppBuilder {
+        self.offline = offline;
+        self
+    }
+
+    /// The GitHub context to render in, should be of the form: `user/repo` or `org/repo`.
+    pub fn context(mut self, context: impl Into<String>) -> AppBuilder {
+        self.context = Some(context.into());
+        self
+    }
+
+    /// The GitHub API to render with, mostly useful for testing.
+    pub fn github_api(mut self, github_api: impl Into<String>) -> AppBuilder {
+        self.github_api = github_api.into();
+        self
+    }
+
+    /// The host to serve on, defaults to `127.0.0.1`.
+    pub fn host(mut self, host: impl Into<String>) -> AppBuilder {
+        self.host = host.into();
+        self
+    }
+
+    /// The port to serve on, defaults to `4000`.
+    pub fn port(mut self, port: u16) -> AppBuilder {
+        self.port = port;
+        self
+    }
+
+    /// A config file to load, see [`crate::Config`].
+    pub fn config(mut self, config: impl Into<PathBuf>) -> AppBuilder {
+        self.config = Some(config.into());
+        self
+    }
+
+    /// Where to report what the server is doing.
+    pub fn events(mut self, events: Events) -> AppBuilder {
+        self.events = events;
+        self
+    }
+
+    /// How many rendered pages to keep in memory, defaults to `128`. `0` turns the cache off.
+    pub fn cache_size(mut self, cache_size: usize) -> AppBuilder {
+        self.cache_size = cache_size;
+        self
+    }
+
+    /// The biggest file to render in bytes, defaults to 10 MiB. `0` means no limit.
+    pub fn max_file_size(mut self, max_file_size: u64) -> AppBuilder {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// Whether to render `.txt` and extensionless files like `LICENSE` as preformatted text,
+    /// off by default.
+    pub fn plain_text(mut self, plain_text: bool) -> AppBuilder {
+        self.plain_text = plain_text;
+        self
+    }
+
+    /// Whether to strip scripts and other active content from `.html` files before serving them,
+    /// off by default.
+    pub fn sanitize_html(mut self, sanitize_html: bool) -> AppBuilder {
+        self.sanitize_html = sanitize_html;
+        self
+    }
+
+    /// Whether to serve `.html` files without the sandbox that keeps their scripts from running,
+    /// off by default.
+    pub fn trust_html(mut self, trust_html: bool) -> AppBuilder {
+        self.trust_html = trust_html;
+        self
+    }
+
+    /// Whether tables in documents sort when a header is clicked, off by default. The ones with
+    /// more than [`AppBuilder::filter_tables_over`] rows get a filter box too.
+    pub fn sortable_tables(mut self, sortable_tables: bool) -> AppBuilder {
+        self.sortable_tables = sortable_tables;
+        self
+    }
+
+    /// How many rows a sortable table can have before it gets a filter box, defaults to `20`.
+    pub fn filter_tables_over(mut self, rows: usize) -> AppBuilder {
+        self.filter_tables_over = rows;
+        self
+    }
+
+    /// Whether to answer requests from other machines, off by default so serving on `0.0.0.0`
+    /// doesn't share the folder with the whole network by accident.
+    pub fn allow_remote(mut self, allow_remote: bool) -> AppBuilder {
+        self.allow_remote = allow_remote;
+        self
+    }
+
+    /// Whether documents can be edited from the browser at `/edit/<path>` and saved back to the
+    /// folder, off by default. Documents served from a URL or an archive can't be.
+    pub fn edit(mut self, edit: bool) -> AppBuilder {
+        self.edit = edit;
+        self
+    }
+
+    /// Whether error pages show everything known about the error, like the converter's whole
+    /// response and the request that hit it, off by default to keep them short on shared servers.
+    pub fn debug_errors(mut self, debug_errors: bool) -> AppBuilder {
+        self.debug_errors = debug_errors;
+        self
+    }
+
+    /// The language of rs-readme's own pages, like its error pages, defaults to English.
+    pub fn lang(mut self, lang: Lang) -> AppBuilder {
+        self.lang = lang;
+        self
+    }
+
+    /// How many conversions can run at once, defaults to `4`. Others wait their turn, `0` means
+    /// no limit.
+    pub fn max_conversions(mut self, max_conversions: usize) -> AppBuilder {
+        self.max_conversions = max_conversions;
+        self
+    }
+
+    /// How many live-reload connections can be open at once, defaults to `32`. Others get a
+    /// `503`, `0` means no limit.
+    pub fn max_update_streams(mut self, max_update_streams: usize) -> AppBuilder {
+        self.max_update_streams = max_update_streams;
+        self
+    }
+
+    /// How long a changed file has to stay the same before live-reloading pages get it,
+    /// defaults to 200ms.
+    pub fn debounce(mut self, debounce: Duration) -> AppBuilder {
+        self.debounce = debounce;
+        self
+    }
+
+    /// How long each live-reloading page waits between updates at least, defaults to 500ms.
+    /// Only the newest of the updates that came meanwhile is sent. `Duration::ZERO` sends all.
+    pub fn min_push_interval(mut self, push_interval: Duration) -> AppBuilder {
+        self.push_interval = push_interval;
+        self
+    }
+
+    /// How long a conversion can take before the page shows an error instead, defaults to 30
+    /// seconds. `Duration::ZERO` waits forever.
+    pub fn convert_timeout(mut self, convert_timeout: Duration) -> AppBuilder {
+        self.convert_timeout = convert_timeout;
+        self
+    }
+
+    /// A PlantUML server, like `https://www.plantuml.com/plantuml`, to draw ` ```plantuml `
+    /// code blocks with. They're shown as source without one.
+    pub fn plantuml_server(mut self, plantuml_server: impl Into<String>) -> AppBuilder {
+        self.plantuml_server = Some(plantuml_server.into());
+        self
+    }
+
+    /// Adds a WebAssembly plugin's converter and code block stage, see [`Plugin::load_dir`].
+    #[cfg(feature = "plugins")]
+    pub fn plugin(mut self, plugin: Plugin) -> AppBuilder {
+        self.plugins.push(plugin);
+        self
+    }
+
+    /// Put before every page's title in the browser tab, like `myproject – Installation`.
+    /// Pages are titled with just their document's otherwise.
+    pub fn title(mut self, title: impl Into<String>) -> AppBuilder {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// The colors of highlighted code, defaults to [`CodeTheme::GitHub`].
+    pub fn code_theme(mut self, code_theme: CodeTheme) -> AppBuilder {
+        self.code_theme = code_theme;
+        self
+    }
+
+    /// Whether to serve remote images in documents, like badges, through rs-readme, keeping
+    /// copies for when the network isn't there. Off by default.
+    pub fn proxy_images(mut self, proxy_images: bool) -> AppBuilder {
+        self.proxy_images = proxy_images;
+        self
+    }
+
+    /// POSTs the path and hash of documents to `url` as JSON when they change, see [`Webhook`].
+    pub fn webhook(mut self, url: Url) -> AppBuilder {
+        self.webhook = Some(url);
+        self
+    }
+
+    /// Appends every answered request to the file at `path`, see [`AccessLog`].
+    pub fn access_log(mut self, path: impl Into<PathBuf>) -> AppBuilder {
+        self.access_log = Some(path.into());
+        self
+    }
+
+    /// How big the access log grows before it's rotated, defaults to 10MiB. `0` means never.
+    pub fn access_log_max_size(mut self, max_size: u64) -> AppBuilder {
+        self.access_log_max_size = max_size;
+        self
+    }
+
+    /// Sets the value of `{{name}}` placeholders in documents, over any from the config file.
+    pub fn define(mut self, name: impl Into<String>, value: impl Into<String>) -> AppBuilder {
+        self.defines.insert(name.into(), value.into());
+        self
+    }
+
+    /// Renders files in the folder matching `pattern` as markdown whatever their extension,
+    /// like an extensionless `README`. Can be called more than once.
+    pub fn treat_as_md(mut self, pattern: Pattern) -> AppBuilder {
+        self.treat_as_md.push(pattern);
+        self
+    }
+
+    /// Adds endpoints or middleware to the server once it's built, see [`build_app_with`].
+    pub fn extend(
+        mut self,
+        extension: impl FnOnce(&mut Server<Arc<State>>) + 'static,
+    ) -> AppBuilder {
+        self.extensions.push(Box::new(extension));
+        self
+    }
+
+    /// The `host:port` the server should listen on.
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    /// Builds the [`State`] the server runs with, for callers that need to hold on to it (to
+    /// reload config, say) before calling [`crate::build_app`] themselves. Extensions aren't
+    /// applied.
+    pub fn build_state(self) -> Result<State, ConfigError> {
+        self.into_state().map(|(state, _)| state)
+    }
+
+    /// Builds the configured `tide::Server`, ready to `listen` on [`AppBuilder::addr`].
+    pub fn build(self) -> Result<Server<Arc<State>>, ConfigError> {
+        let (state, extensions) = self.into_state()?;
+
+        Ok(build_app_with(Arc::new(state), |server| {
+            for extension in extensions {
+                extension(server);
+            }
+        }))
+    }
+
+    fn into_state(self) -> Result<(State, Vec<Extension>), ConfigError> {
+        let github = Converter::new(self.github_api, self.context);
+        let converter = if self.offline {
+            Converters::Offline(OfflineConverter::new().with_source_lines(true))
+        } else {
+            Converters::Github(github.clone())
+        };
+
+        let state = if let Some(url) = self.remote {
+            let (base, index) = match split_document_url(&url) {
+                Some((base, name)) => (base, Some(name)),
+                None => (url, None),
+            };
+            let state = State::new(
+                converter,
+                RemoteFinder::new(base).with_max_size(self.max_file_size),
+            );
+            match index {
+                Some(index) => state.with_index(index),
+                None => state,
+            }
+        } else if is_archive(&self.root) {
+            let finder = ArchiveFinder::new(self.root)
+                .with_max_size(self.max_file_size)
+                .with_plain_text(self.plain_text);
+            State::new(converter, finder)
+        } else {
+            let nav_file = self.root.join(NAV_FILE);
+            let root = self.root.clone();
+            let finder = FileFinder::new(self.root)
+                .with_max_size(self.max_file_size)
+                .with_plain_text(self.plain_text)
+                .with_treat_as_md(self.treat_as_md.clone());
+            let state = match self.piped {
+                Some(document) => State::new(converter, PipedFinder::new(document, finder))
+                    .with_index(PIPED_DOCUMENT),
+                None => State::new(converter, finder),
+            };
+            let state = match Pins::for_folder(&root) {
+                Some(pins) => state.with_pins(pins),
+                None => state,
+            };
+            state.with_nav_file(nav_file).with_root(root)
+        };
+        let state = state
+            .with_github_converter(github)
+            .with_events(self.events)
+            .with_cache_size(self.cache_size)
+            .with_max_conversions(self.max_conversions)
+            .with_max_update_streams(self.max_update_streams)
+            .with_debounce(self.debounce)
+            .with_min_push_interval(self.push_interval)
+            .with_convert_timeout(self.convert_timeout)
+            .with_sanitized_html(self.sanitize_html)
+            .with_trusted_html(self.trust_html)
+            .with_remote_access(self.allow_remote)
+            .with_editing(self.edit)
+            .with_host(self.host.clone())
+            .with_debug_errors(self.debug_errors)
+            .with_lang(self.lang)
+            .with_code_theme(self.code_theme)
+            .with_defines(self.defines)
+            .with_treat_as_md(self.treat_as_md)
+            .with_source_lines(true);
+        let state = if self.sortable_tables {
+            state.with_sortable_tables(self.filter_tables_over)
+        } else {
+            state
+        };
+        let state = match self.title {
+            Some(title) => state.with_title(title),
+            None => state,
+        };
+        let state = match self.plantuml_server {
+            Some(server) => state.with_post_processor(PlantUml::new(server)),
+            None => state,
+        };
+        #[cfg(feature = "plugins")]
+        let state = self.plugins.into_iter().fold(state, State::with_plugin);
+        let state = if self.proxy_images {
+            state.with_image_proxy(ImageProxy::default())
+        } else {
+            state
+        };
+        let state = match self.webhook {
+            Some(url) => state.with_webhook(Webhook::new(url)),
+            None => state,
+        };
+        let state = match self.access_log {
+            Some(path) => {
+                state.with_access_log(AccessLog::new(path).with_max_size(self.access_log_max_size))
+            }
+            None => state,
+        };
+
+        let state = match self.config {
+            Some(config) => state.with_config_file(config)?,
+            None => state,
+        };
+
+        Ok((state, self.extensions))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn addr_defaults_to_localhost_4000() {
+        assert_eq!(App::builder().addr(), "127.0.0.1:4000");
+        assert_eq!(
+            App::builder().host("0.0.0.0").port(8080).addr(),
+            "0.0.0.0:8080"
+        );
+    }
+
+    #[test]
+    fn missing_config_fails_to_build() {
+        let built = App::builder().config("test_dir/nope.toml").build();
+
+        assert!(matches!(built, Err(ConfigError::CouldNotRead(_))));
+    }
+
+    #[async_std::test]
+    async fn summarizes_what_it_serves() {
+        let (state, _) = App::builder().root("test_dir").into_state().unwrap();
+
+        let summary = state.summary().await;
+
+        assert_eq!(summary.root, PathBuf::from("test_dir").canonicalize().ok());
+        assert!(summary.documents.is_some_and(|documents| documents > 0));
+        assert_eq!(summary.index, "README.md");
+        assert!(!summary.index_missing);
+        assert_eq!(summary.converter, "offline");
+    }
+}
@@ -0,0 +1,393 @@
+use flate2::read::GzDecoder;
+use log::{error, warn};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::content_finder::{
+    normalize_text, ContentError, ContentFinder, ContentHash, DocumentFormat, DEFAULT_MAX_SIZE,
+};
+use crate::links::normalize;
+
+/// Whether `path` looks like an archive [`ArchiveFinder`] can read: `.zip`, `.tar`, `.tar.gz`
+/// or `.tgz`.
+pub fn is_archive(path: &Path) -> bool {
+    ArchiveKind::of(path).is_some()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    fn of(path: &Path) -> Option<ArchiveKind> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Implements [`ContentFinder`] by reading files out of a `.zip`, `.tar` or `.tar.gz` archive,
+/// for previewing a bundle of docs without extracting it (`--folder docs.zip`).
+///
+/// When everything in the archive is in one folder, paths are relative to that folder. When
+/// there's no `README.md` at the top, one listing the markdown files inside is made up.
+///
+/// The archive is read again when its modification time or size changes.
+pub struct ArchiveFinder {
+    archive: PathBuf,
+    max_size: u64,
+    plain_text: bool,
+    contents: Mutex<Option<Contents>>,
+}
+
+/// The files in the archive as of when it had this modification time and size.
+struct Contents {
+    modified: Option<SystemTime>,
+    len: u64,
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl ArchiveFinder {
+    /// Reads files out of the archive at `archive`.
+    pub fn new(archive: impl Into<PathBuf>) -> ArchiveFinder {
+        ArchiveFinder {
+            archive: archive.into(),
+            max_size: DEFAULT_MAX_SIZE,
+            plain_text: false,
+            contents: Mutex::new(None),
+        }
+    }
+
+    /// Leaves out files bigger than `max_size` bytes, `0` allows anything.
+    pub fn with_max_size(mut self, max_size: u64) -> ArchiveFinder {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Also reads plain text files, see [`DocumentFormat::PlainText`].
+    pub fn with_plain_text(mut self, plain_text: bool) -> ArchiveFinder {
+        self.plain_text = plain_text;
+        self
+    }
+
+    /// Every markdown file in the archive, sorted.
+    pub fn markdown_files(&self) -> Vec<PathBuf> {
+        self.with_files(markdown_files_in).unwrap_or_default()
+    }
+
+    fn file(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+        let path = normalize(Path::new(resource)).ok_or_else(|| {
+            warn!("Refusing to fetch {}, it's outside the archive", resource);
+            ContentError::InvalidPath(resource.to_string())
+        })?;
+
+        let bytes = self
+            .with_files(|files| match files.get(&path) {
+                Some(bytes) => Some(bytes.clone()),
+                None if path == Path::new("README.md") => Some(listing(files).into_bytes()),
+                None => None,
+            })?
+            .ok_or_else(|| ContentError::CouldNotFetch(resource.to_string()))?;
+        if self.max_size > 0 && bytes.len() as u64 > self.max_size {
+            return Err(ContentError::TooLarge {
+                resource: resource.to_string(),
+                size: bytes.len() as u64,
+                limit: self.max_size,
+            });
+        }
+
+        let contents = String::from_utf8(bytes).map_err(|_| ContentError::NotMarkdown)?;
+        let contents = normalize_text(contents);
+        let hash = ContentHash::of(contents.as_bytes());
+
+        Ok((contents, hash))
+    }
+
+    /// Calls `f` with the archive's files, read again if it changed since they were last.
+    fn with_files<T>(
+        &self,
+        f: impl FnOnce(&BTreeMap<PathBuf, Vec<u8>>) -> T,
+    ) -> Result<T, ContentError> {
+        let could_not_fetch = |err: io::Error| {
+            error!(
+                "Could not read archive {}:\n{:#?}",
+                self.archive.to_string_lossy(),
+                err
+            );
+            ContentError::CouldNotFetch(self.archive.to_string_lossy().to_string())
+        };
+
+        let metadata = fs::metadata(&self.archive).map_err(could_not_fetch)?;
+        let modified = metadata.modified().ok();
+        let mut contents = match self.contents.lock() {
+            Ok(contents) => contents,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        match &*contents {
+            Some(contents)
+                if modified.is_some()
+                    && contents.modified == modified
+                    && contents.len == metadata.len() =>
+            {
+                Ok(f(&contents.files))
+            }
+            _ => {
+                let files = self.read_archive().map_err(could_not_fetch)?;
+                let found = f(&files);
+                *contents = Some(Contents {
+                    modified,
+                    len: metadata.len(),
+                    files,
+                });
+                Ok(found)
+            }
+        }
+    }
+
+    fn read_archive(&self) -> io::Result<BTreeMap<PathBuf, Vec<u8>>> {
+        let file = File::open(&self.archive)?;
+        // One byte over the limit is enough to tell a file's too big
+        let limit = match self.max_size {
+            0 => u64::MAX,
+            max_size => max_size + 1,
+        };
+
+        let files = match ArchiveKind::of(&self.archive) {
+            Some(ArchiveKind::Zip) => read_zip(file, limit)?,
+            Some(ArchiveKind::Tar) => read_tar(file, limit)?,
+            Some(ArchiveKind::TarGz) => read_tar(GzDecoder::new(file), limit)?,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "not a .zip, .tar or .tar.gz archive",
+                ))
+            }
+        };
+
+        Ok(strip_common_folder(files))
+    }
+}
+
+impl ContentFinder for ArchiveFinder {
+    fn content_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+        let format = DocumentFormat::of(Path::new(resource));
+        if format.is_none() || (format == Some(DocumentFormat::PlainText) && !self.plain_text) {
+            return Err(ContentError::NotMarkdown);
+        }
+
+        self.file(resource)
+    }
+
+    fn source_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+        self.file(resource)
+    }
+
+    fn exists(&self, resource: &str) -> bool {
+        match normalize(Path::new(resource)) {
+            Some(path) => self
+                .with_files(|files| files.contains_key(&path))
+                .unwrap_or(true),
+            None => false,
+        }
+    }
+}
+
+fn read_zip(file: File, limit: u64) -> io::Result<BTreeMap<PathBuf, Vec<u8>>> {
+    let mut archive = zip::ZipArchive::new(file)?;
+    let mut files = BTreeMap::new();
+
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i)?;
+        if !entry.is_file() {
+            continue;
+        }
+        let path = match entry.enclosed_name().and_then(|path| normalize(&path)) {
+            Some(path) => path,
+            None => continue,
+        };
+        let mut bytes = Vec::new();
+        entry.take(limit).read_to_end(&mut bytes)?;
+        files.insert(path, bytes);
+    }
+
+    Ok(files)
+}
+
+fn read_tar(reader: impl Read, limit: u64) -> io::Result<BTreeMap<PathBuf, Vec<u8>>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut files = BTreeMap::new();
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = match normalize(&entry.path()?) {
+            Some(path) => path,
+            None => continue,
+        };
+        let mut bytes = Vec::new();
+        entry.take(limit).read_to_end(&mut bytes)?;
+        files.insert(path, bytes);
+    }
+
+    Ok(files)
+}
+
+/// Makes paths relative to the one folder everything's in, if there is one, the way most
+/// archives are made.
+fn strip_common_folder(files: BTreeMap<PathBuf, Vec<u8>>) -> BTreeMap<PathBuf, Vec<u8>> {
+    let top = |path: &Path| match path.components().next() {
+        Some(Component::Normal(top)) if path.components().count() > 1 => Some(top.to_owned()),
+        _ => None,
+    };
+    let folder = match files.keys().next().and_then(|path| top(path)) {
+        Some(folder) if files.keys().all(|path| top(path).as_ref() == Some(&folder)) => folder,
+        _ => return files,
+    };
+
+    files
+        .into_iter()
+        .map(|(path, bytes)| {
+            let path = path.strip_prefix(&folder).unwrap_or(&path).to_path_buf();
+            (path, bytes)
+        })
+        .collect()
+}
+
+fn markdown_files_in(files: &BTreeMap<PathBuf, Vec<u8>>) -> Vec<PathBuf> {
+    files
+        .keys()
+        .filter(|path| DocumentFormat::of(path) == Some(DocumentFormat::Markdown))
+        .cloned()
+        .collect()
+}
+
+/// The made up `README.md` for archives without one, linking to every markdown file.
+fn listing(files: &BTreeMap<PathBuf, Vec<u8>>) -> String {
+    let mut listing = "# Contents\n\n".to_string();
+    for path in markdown_files_in(files) {
+        let path = path.to_string_lossy().replace('\\', "/");
+        listing.push_str(&format!("- [{}](./{})\n", path, path.replace(' ', "%20")));
+    }
+
+    listing
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn archive_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rs-readme-{}-{}", std::process::id(), name))
+    }
+
+    fn write_zip(path: &Path, files: &[(&str, &str)]) {
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        for (name, contents) in files {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn reads_markdown_out_of_zips() {
+        let path = archive_path("docs.zip");
+        write_zip(
+            &path,
+            &[
+                ("docs/README.md", "# Docs"),
+                ("docs/guide/setup.md", "# Setup"),
+                ("docs/logo.png", "not really"),
+            ],
+        );
+        let finder = ArchiveFinder::new(&path);
+
+        let readme = finder.content_for("README.md");
+        let setup = finder.content_for("./guide/setup.md");
+        let missing = finder.content_for("./guide/missing.md");
+        let outside = finder.content_for("../docs/README.md");
+        let image = finder.content_for("logo.png");
+        let files = finder.markdown_files();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(readme.unwrap().0, "# Docs");
+        assert_eq!(setup.unwrap().0, "# Setup");
+        assert!(matches!(missing, Err(ContentError::CouldNotFetch(_))));
+        assert!(matches!(outside, Err(ContentError::InvalidPath(_))));
+        assert_eq!(image, Err(ContentError::NotMarkdown));
+        assert_eq!(
+            files,
+            vec![PathBuf::from("README.md"), PathBuf::from("guide/setup.md")]
+        );
+    }
+
+    #[test]
+    fn reads_markdown_out_of_tarballs() {
+        let path = archive_path("docs.tar.gz");
+        let mut tar = tar::Builder::new(GzEncoder::new(
+            File::create(&path).unwrap(),
+            Compression::default(),
+        ));
+        for (name, contents) in &[("a.md", "# A"), ("notes/b.md", "# B")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, name, contents.as_bytes())
+                .unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+        let finder = ArchiveFinder::new(&path);
+
+        let b = finder.content_for("notes/b.md");
+        let readme = finder.content_for("README.md");
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(b.unwrap().0, "# B");
+        assert_eq!(
+            readme.unwrap().0,
+            "# Contents\n\n- [a.md](./a.md)\n- [notes/b.md](./notes/b.md)\n"
+        );
+    }
+
+    #[test]
+    fn refuses_files_over_the_size_limit() {
+        let path = archive_path("big.zip");
+        write_zip(&path, &[("big.md", "# Too big")]);
+        let finder = ArchiveFinder::new(&path).with_max_size(4);
+
+        let big = finder.content_for("big.md");
+        let _ = fs::remove_file(&path);
+
+        assert!(matches!(big, Err(ContentError::TooLarge { limit: 4, .. })));
+    }
+
+    #[test]
+    fn recognises_archives() {
+        assert!(is_archive(Path::new("docs.zip")));
+        assert!(is_archive(Path::new("bundle/docs.TAR.GZ")));
+        assert!(is_archive(Path::new("docs.tgz")));
+        assert!(!is_archive(Path::new("docs")));
+        assert!(!is_archive(Path::new("docs.md")));
+    }
+}
@@ -0,0 +1,634 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::body::{Body, HttpBody};
+use axum::extract::{ConnectInfo, Path, Query, Request, State as AxumState};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode, Uri};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use futures::{FutureExt, Stream, StreamExt};
+use log::error;
+
+use crate::access_log::Access;
+use crate::error::Error;
+use crate::handlers::{self, ChangeRequest, RenderedResponse, ResponseBody};
+use crate::i18n::Lang;
+use crate::image_proxy::IMAGE_ROUTE;
+use crate::panics;
+use crate::static_files;
+use crate::web_server::State;
+
+/// The query parameters rs-readme's routes look at.
+#[derive(Deserialize)]
+struct Params {
+    converter: Option<String>,
+    #[serde(rename = "ref")]
+    reference: Option<String>,
+    force: Option<String>,
+    url: Option<String>,
+    line: Option<String>,
+    hash: Option<String>,
+}
+
+/// The same routes as [`crate::build_app`] as an axum `Router`, for mounting rs-readme in an
+/// axum app.
+///
+/// Rendered pages link to `/static/...` and `/__rs-readme/...`, so the router should be
+/// `merge`d into the app rather than `nest`ed under a prefix.
+///
+/// Requests from other machines are refused unless [`State::with_remote_access`] allows them,
+/// which only works when the app is served with
+/// `into_make_service_with_connect_info::<SocketAddr>()` so the peer is known.
+pub fn axum_router(state: Arc<State>) -> Router {
+    panics::record_backtraces();
+    let router = Router::new()
+        .route("/", get(render_readme))
+        .route("/static/octicons/:file", get(octicons))
+        .route("/static/code-theme/:file", get(code_theme))
+        .route("/static/style.css", get(style))
+        .route("/static/reload.js", get(reload_script))
+        .route("/__rs-readme/reload-config", post(reload_config))
+        .route("/__rs-readme/scroll/*path", post(scroll))
+        .route("/__rs-readme/prefs", get(prefs).put(change_prefs))
+        .route("/__rs-readme/pin/*path", post(pin))
+        .route("/__rs-readme/unpin/*path", post(unpin))
+        .route("/__rs-readme/metrics", get(metrics))
+        .route("/__rs-readme/recent", get(recent))
+        .route("/__rs-readme/raw/*path", get(raw))
+        .route("/__rs-readme/poll/*path", get(poll))
+        .route("/__rs-readme/code/*path", get(code))
+        .route("/__rs-readme/frontmatter/*path", get(frontmatter))
+        .route("/api/outline/*path", get(outline))
+        .route("/api/tree", get(tree))
+        .route("/api/stats/*path", get(stats))
+        .route(IMAGE_ROUTE, get(image));
+    // Only there with `--edit`, so documents in an `edit/` folder can be read otherwise
+    let router = if state.edits() {
+        router
+            .route("/edit/", get(edit))
+            .route("/edit/*path", get(edit))
+            .route("/__rs-readme/save/*path", post(save))
+            .route("/__rs-readme/preview/*path", post(preview))
+    } else {
+        router
+    };
+    router
+        .route("/__rs-readme/", get(render_page_update))
+        .route("/__rs-readme/*path", get(render_page_update))
+        .route("/*path", get(render_markdown_path))
+        .layer(middleware::from_fn_with_state(state.clone(), loopback_only))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            error_responses,
+        ))
+        .layer(middleware::from_fn_with_state(state.clone(), catch_panics))
+        .layer(middleware::from_fn_with_state(state.clone(), log_access))
+        .with_state(state)
+}
+
+/// Writes every request to the [`State::with_access_log`] log once it's answered.
+async fn log_access(AxumState(state): AxumState<Arc<State>>, req: Request, next: Next) -> Response {
+    let Some(access_log) = state.access_log() else {
+        return next.run(req).await;
+    };
+    let started = Instant::now();
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| peer.to_string());
+    let method = req.method().to_string();
+    let path = req.uri().path().to_string();
+    let res = next.run(req).await;
+
+    access_log.record(&Access {
+        peer: peer.as_deref(),
+        method: &method,
+        path: &path,
+        status: res.status().as_u16(),
+        bytes: res.body().size_hint().exact(),
+        duration: started.elapsed(),
+    });
+    res
+}
+
+/// Catches handlers that panic, logging where with a request ID and answering with
+/// [`handlers::panic_response`], so one bad page doesn't take the server down.
+async fn catch_panics(
+    AxumState(state): AxumState<Arc<State>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let path = req.uri().path().to_string();
+    match AssertUnwindSafe(next.run(req)).catch_unwind().await {
+        Ok(res) => res,
+        Err(payload) => {
+            let request_id = panics::request_id();
+            error!(
+                "Request {} for {} panicked: {}\n{}",
+                request_id,
+                path,
+                panics::panic_message(&*payload),
+                panics::take_backtrace().map_or(String::new(), |trace| trace.to_string())
+            );
+            handlers::panic_response(&request_id, state.lang()).into_response()
+        }
+    }
+}
+
+/// Refuses requests from other machines unless remote access is allowed, see
+/// [`State::allows_peer`].
+async fn loopback_only(
+    AxumState(state): AxumState<Arc<State>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| peer.ip());
+    if !state.allows_peer(peer) {
+        return handlers::remote_forbidden(state.lang()).into_response();
+    }
+
+    next.run(req).await
+}
+
+/// Sends errors as JSON to the clients and routes that want that, see
+/// [`handlers::wants_json`], and expands error pages with [`State::with_debug_errors`].
+async fn error_responses(
+    AxumState(state): AxumState<Arc<State>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let path = req.uri().path().to_string();
+    let json = handlers::wants_json(accept(req.headers()), &path);
+    let method = req.method().to_string();
+    let url = req.uri().to_string();
+    let headers: Vec<(String, String)> = req
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect();
+    let res = next.run(req).await;
+
+    match res.extensions().get::<Error>() {
+        Some(err) if json => handlers::json_error_response(err, &path).into_response(),
+        Some(err) if state.debug_errors() => {
+            handlers::debug_error_response(err, &method, &url, &headers).into_response()
+        }
+        Some(err) if state.lang() != Lang::English && !err.converter_failed() => {
+            handlers::localized_error_response(err, &path, state.lang()).into_response()
+        }
+        _ => res,
+    }
+}
+
+async fn render_readme(
+    AxumState(state): AxumState<Arc<State>>,
+    Query(params): Query<Params>,
+    headers: HeaderMap,
+) -> RenderedResponse {
+    handlers::readme(
+        &state,
+        params.converter.as_deref(),
+        params.reference.as_deref(),
+        params.force.as_deref(),
+        if_none_match(&headers),
+        accept(&headers),
+    )
+    .await
+}
+
+async fn render_markdown_path(
+    AxumState(state): AxumState<Arc<State>>,
+    Query(params): Query<Params>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> RenderedResponse {
+    handlers::markdown_path(
+        &state,
+        uri.path(),
+        params.converter.as_deref(),
+        params.reference.as_deref(),
+        params.force.as_deref(),
+        if_none_match(&headers),
+        accept(&headers),
+    )
+    .await
+}
+
+/// Sends an event with the converted file contents and the hash of the contents every time the
+/// file changes, or a `503` when too many are already open.
+async fn render_page_update(
+    AxumState(state): AxumState<Arc<State>>,
+    Query(params): Query<Params>,
+    uri: Uri,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, RenderedResponse> {
+    let path = &uri.path()["/__rs-readme".len()..];
+    let updates = handlers::page_updates(
+        &state,
+        path,
+        params.converter.as_deref(),
+        params.reference.as_deref(),
+    )
+    .await
+    .map_err(|err| handlers::error_response(&err, uri.path()))?;
+
+    Ok(Sse::new(updates.map(|(event, message)| {
+        Ok(Event::default().event(event).data(message))
+    })))
+}
+
+async fn octicons(Path(file): Path<String>, headers: HeaderMap) -> RenderedResponse {
+    static_files::octicons(&file, accepts_gzip(&headers))
+}
+
+async fn code_theme(Path(file): Path<String>) -> RenderedResponse {
+    static_files::code_theme(&file)
+}
+
+async fn style(headers: HeaderMap) -> RenderedResponse {
+    static_files::style(accepts_gzip(&headers))
+}
+
+async fn reload_script(headers: HeaderMap) -> RenderedResponse {
+    static_files::reload_script(accepts_gzip(&headers))
+}
+
+fn accepts_gzip(headers: &HeaderMap) -> bool {
+    static_files::accepts_gzip(
+        headers
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|encodings| encodings.to_str().ok()),
+    )
+}
+
+async fn code(
+    AxumState(state): AxumState<Arc<State>>,
+    Query(params): Query<Params>,
+    uri: Uri,
+) -> RenderedResponse {
+    handlers::code(
+        &state,
+        &uri.path()["/__rs-readme/code".len()..],
+        params.reference.as_deref(),
+    )
+    .await
+}
+
+async fn raw(AxumState(state): AxumState<Arc<State>>, uri: Uri) -> RenderedResponse {
+    handlers::raw(&state, &uri.path()["/__rs-readme/raw".len()..]).await
+}
+
+async fn frontmatter(AxumState(state): AxumState<Arc<State>>, uri: Uri) -> RenderedResponse {
+    handlers::frontmatter(&state, &uri.path()["/__rs-readme/frontmatter".len()..]).await
+}
+
+async fn recent(AxumState(state): AxumState<Arc<State>>) -> RenderedResponse {
+    handlers::recent(&state).await
+}
+
+async fn tree(AxumState(state): AxumState<Arc<State>>) -> RenderedResponse {
+    handlers::tree(&state).await
+}
+
+async fn stats(
+    AxumState(state): AxumState<Arc<State>>,
+    Query(params): Query<Params>,
+    uri: Uri,
+) -> RenderedResponse {
+    handlers::stats(
+        &state,
+        &uri.path()["/api/stats".len()..],
+        params.reference.as_deref(),
+    )
+    .await
+}
+
+async fn outline(
+    AxumState(state): AxumState<Arc<State>>,
+    Query(params): Query<Params>,
+    uri: Uri,
+) -> RenderedResponse {
+    handlers::outline(
+        &state,
+        &uri.path()["/api/outline".len()..],
+        params.reference.as_deref(),
+    )
+    .await
+}
+
+async fn image(
+    AxumState(state): AxumState<Arc<State>>,
+    Query(params): Query<Params>,
+) -> RenderedResponse {
+    handlers::image(&state, params.url.as_deref()).await
+}
+
+async fn metrics(AxumState(state): AxumState<Arc<State>>) -> RenderedResponse {
+    handlers::metrics(&state)
+}
+
+async fn reload_config(AxumState(state): AxumState<Arc<State>>) -> RenderedResponse {
+    handlers::reload_config(&state)
+}
+
+async fn prefs(AxumState(state): AxumState<Arc<State>>) -> RenderedResponse {
+    handlers::prefs(&state)
+}
+
+async fn change_prefs(
+    AxumState(state): AxumState<Arc<State>>,
+    changes: String,
+) -> RenderedResponse {
+    handlers::change_prefs(&state, &changes)
+}
+
+async fn pin(
+    AxumState(state): AxumState<Arc<State>>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> RenderedResponse {
+    let path = &uri.path()["/__rs-readme/pin".len()..];
+    handlers::pin(&state, path, &change_request(&headers, &uri), true).await
+}
+
+async fn unpin(
+    AxumState(state): AxumState<Arc<State>>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> RenderedResponse {
+    let path = &uri.path()["/__rs-readme/unpin".len()..];
+    handlers::pin(&state, path, &change_request(&headers, &uri), false).await
+}
+
+async fn edit(AxumState(state): AxumState<Arc<State>>, uri: Uri) -> RenderedResponse {
+    handlers::edit(&state, &uri.path()["/edit".len()..]).await
+}
+
+async fn save(
+    AxumState(state): AxumState<Arc<State>>,
+    Query(params): Query<Params>,
+    headers: HeaderMap,
+    uri: Uri,
+    body: String,
+) -> RenderedResponse {
+    let path = &uri.path()["/__rs-readme/save".len()..];
+    handlers::save(
+        &state,
+        path,
+        &change_request(&headers, &uri),
+        params.hash.as_deref(),
+        &body,
+    )
+    .await
+}
+
+async fn preview(
+    AxumState(state): AxumState<Arc<State>>,
+    headers: HeaderMap,
+    uri: Uri,
+    body: String,
+) -> RenderedResponse {
+    handlers::preview(
+        &state,
+        &uri.path()["/__rs-readme/preview".len()..],
+        &change_request(&headers, &uri),
+        &body,
+    )
+    .await
+}
+
+/// The headers [`handlers::save`], [`handlers::preview`] and [`handlers::pin`] check to tell
+/// that one of rs-readme's own pages sent a request.
+fn change_request<'a>(headers: &'a HeaderMap, uri: &'a Uri) -> ChangeRequest<'a> {
+    let header = |name: header::HeaderName| headers.get(name).and_then(|value| value.to_str().ok());
+    ChangeRequest {
+        host: header(header::HOST).or_else(|| uri.authority().map(|authority| authority.as_str())),
+        origin: header(header::ORIGIN),
+        fetch_site: headers
+            .get("Sec-Fetch-Site")
+            .and_then(|site| site.to_str().ok()),
+        content_type: header(header::CONTENT_TYPE),
+    }
+}
+
+async fn poll(
+    AxumState(state): AxumState<Arc<State>>,
+    Query(params): Query<Params>,
+    uri: Uri,
+) -> RenderedResponse {
+    handlers::poll(
+        &state,
+        &uri.path()["/__rs-readme/poll".len()..],
+        params.converter.as_deref(),
+        params.reference.as_deref(),
+        params.hash.as_deref(),
+    )
+    .await
+}
+
+async fn scroll(
+    AxumState(state): AxumState<Arc<State>>,
+    Query(params): Query<Params>,
+    uri: Uri,
+) -> RenderedResponse {
+    handlers::scroll(
+        &state,
+        &uri.path()["/__rs-readme/scroll".len()..],
+        params.line.as_deref(),
+    )
+    .await
+}
+
+fn accept(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|types| types.to_str().ok())
+}
+
+fn if_none_match(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|tags| tags.to_str().ok())
+}
+
+impl IntoResponse for RenderedResponse {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let body = match self.body {
+            ResponseBody::Bytes(bytes) => Body::from(bytes),
+            ResponseBody::Stream(chunks) => Body::from_stream(chunks.map(Ok::<_, Infallible>)),
+        };
+        let mut res = (status, [(header::CONTENT_TYPE, self.mime)], body).into_response();
+        if let Some(etag) = self.etag.and_then(|etag| etag.parse().ok()) {
+            res.headers_mut().insert(header::ETAG, etag);
+        }
+        if let Some(vary) = self.vary.and_then(|vary| vary.parse().ok()) {
+            res.headers_mut().append(header::VARY, vary);
+        }
+        if let Some(encoding) = self
+            .content_encoding
+            .and_then(|encoding| encoding.parse().ok())
+        {
+            res.headers_mut().insert(header::CONTENT_ENCODING, encoding);
+            res.headers_mut()
+                .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+        }
+        if let Some(retry_after) = self.retry_after {
+            res.headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        if let Some(cache_control) = self
+            .cache_control
+            .and_then(|cache_control| cache_control.parse().ok())
+        {
+            res.headers_mut()
+                .insert(header::CACHE_CONTROL, cache_control);
+        }
+        if let Some(policy) = self
+            .content_security_policy
+            .and_then(|policy| policy.parse().ok())
+        {
+            res.headers_mut()
+                .insert(header::CONTENT_SECURITY_POLICY, policy);
+            res.headers_mut().insert(
+                header::X_CONTENT_TYPE_OPTIONS,
+                HeaderValue::from_static("nosniff"),
+            );
+        }
+        if let Some(request_id) = self
+            .request_id
+            .and_then(|request_id| request_id.parse().ok())
+        {
+            res.headers_mut().insert("x-request-id", request_id);
+        }
+        if let Some(err) = self.error {
+            res.extensions_mut().insert(err);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::content_finder::{ContentError, ContentFinder, ContentHash};
+    use crate::offline_converter::OfflineConverter;
+    use axum::body::to_bytes;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    struct MockFinder;
+
+    impl ContentFinder for MockFinder {
+        fn content_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+            match resource {
+                "README.md" => Ok(("# Hi".to_string(), ContentHash::of(b"# Hi"))),
+                "./panic.md" => panic!("the finder fell over"),
+                _ => Err(ContentError::CouldNotFetch(resource.to_string())),
+            }
+        }
+    }
+
+    fn router() -> Router {
+        axum_router(Arc::new(State::new(
+            OfflineConverter::default(),
+            MockFinder,
+        )))
+    }
+
+    #[async_std::test]
+    async fn renders_the_readme() {
+        let res = router()
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), 200);
+        assert!(res.headers().contains_key(header::ETAG));
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("<h1>Hi</h1>"));
+    }
+
+    #[async_std::test]
+    async fn missing_files_are_404() {
+        let res = router()
+            .oneshot(Request::get("/nope.md").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), 404);
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "text/html;charset=utf-8"
+        );
+    }
+
+    #[async_std::test]
+    async fn other_machines_are_refused() {
+        let from = |peer: &str| {
+            let mut req = Request::get("/").body(Body::empty()).unwrap();
+            req.extensions_mut()
+                .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+            req
+        };
+
+        let remote = router().oneshot(from("192.168.1.20:50000")).await.unwrap();
+        let local = router().oneshot(from("127.0.0.1:50000")).await.unwrap();
+
+        assert_eq!(remote.status(), 403);
+        assert_eq!(local.status(), 200);
+    }
+
+    #[async_std::test]
+    async fn errors_are_json_when_asked_for() {
+        let res = router()
+            .oneshot(
+                Request::get("/nope.md")
+                    .header(header::ACCEPT, "application/json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), 404);
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "not-found");
+        assert_eq!(body["resource"], "/nope.md");
+    }
+
+    #[async_std::test]
+    async fn panics_are_caught() {
+        let app = router();
+
+        let res = app
+            .clone()
+            .oneshot(Request::get("/panic.md").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let after = app
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), 500);
+        let request_id = res.headers()["x-request-id"].to_str().unwrap().to_string();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains(&format!("<code>{}</code>", request_id)));
+        assert_eq!(after.status(), 200);
+    }
+}
@@ -0,0 +1,215 @@
+use std::path::Path;
+
+use crate::links::heading_slug;
+
+/// A version's section of a changelog, see [`releases`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Release {
+    /// The anchor of the section: the version (`1.2.0`), or the heading's slug for sections
+    /// without one, like `Unreleased`.
+    pub anchor: String,
+
+    /// The heading's text.
+    pub title: String,
+
+    /// Whether this is the newest section with a version number.
+    pub latest: bool,
+}
+
+/// Whether `path` is a changelog, which gets [`releases`] done to it.
+pub fn is_changelog(path: &str) -> bool {
+    Path::new(path)
+        .file_name()
+        .is_some_and(|name| name.eq_ignore_ascii_case("CHANGELOG.md"))
+}
+
+/// Wraps each `<h2>` section of a converted changelog in a `<details>` anchored by its
+/// version, so it can be linked to and collapsed, returning the new HTML and the sections found.
+///
+/// Anything before the first `<h2>` is left as it is.
+pub fn releases(html: &str) -> (String, Vec<Release>) {
+    let headings = headings(html);
+    let first = match headings.first() {
+        Some(heading) => heading.start,
+        None => return (html.to_string(), Vec::new()),
+    };
+
+    let mut out = String::with_capacity(html.len() + headings.len() * 64);
+    out.push_str(&html[..first]);
+
+    let mut releases: Vec<Release> = Vec::with_capacity(headings.len());
+    for (at, heading) in headings.iter().enumerate() {
+        let end = headings.get(at + 1).map_or(html.len(), |next| next.start);
+        let title = text_of(&html[heading.start..heading.end]);
+        let version = version_in(&title);
+        let latest = version.is_some() && !releases.iter().any(|release| release.latest);
+        let release = Release {
+            anchor: version.unwrap_or_else(|| heading_slug(&title)),
+            title,
+            latest,
+        };
+
+        out.push_str(&format!(
+            "<details class=\"{}\" id=\"{}\" open><summary>{}</summary>{}</details>",
+            if release.latest {
+                "rs-readme-release rs-readme-latest"
+            } else {
+                "rs-readme-release"
+            },
+            escape(&release.anchor),
+            &html[heading.start..heading.end],
+            &html[heading.end..end],
+        ));
+        releases.push(release);
+    }
+
+    (out, releases)
+}
+
+/// A "latest release only" toggle and a dropdown for jumping to a version, for the page header.
+pub(crate) fn releases_html(releases: &[Release]) -> String {
+    if releases.is_empty() {
+        return String::new();
+    }
+
+    format!(
+        "{}",
+        html! {
+            span(class="rs-readme-changelog") {
+                label {
+                    input(type="checkbox", class="rs-readme-latest-toggle");
+                    : " Latest release only";
+                }
+                select(class="rs-readme-versions", onchange="if (this.value) { document.getElementById(this.value).open = true; location.hash = this.value; }") {
+                    option(value="") : "Jump to version";
+                    @ for release in releases {
+                        option(value=&release.anchor) : &release.title;
+                    }
+                }
+            }
+        }
+    )
+}
+
+/// Where a heading starts and ends in some HTML.
+struct Heading {
+    start: usize,
+    end: usize,
+}
+
+/// The `<h2>`s in `html`, with the `<div class="markdown-heading">` GitHub wraps them in.
+fn headings(html: &str) -> Vec<Heading> {
+    const WRAPPER: &str = "<div class=\"markdown-heading\"";
+
+    let mut headings = Vec::new();
+    let mut from = 0;
+    while let Some(found) = html[from..].find("<h2") {
+        let mut start = from + found;
+        let mut end = match html[start..].find("</h2>") {
+            Some(close) => start + close + "</h2>".len(),
+            None => break,
+        };
+
+        if let Some(wrapper) = html[from..start].rfind(WRAPPER).map(|at| from + at) {
+            let between = &html[wrapper..start];
+            if between.ends_with('>') && between.matches('>').count() == 1 {
+                start = wrapper;
+                end = html[end..]
+                    .find("</div>")
+                    .map_or(end, |close| end + close + 6);
+            }
+        }
+
+        headings.push(Heading { start, end });
+        from = end;
+    }
+
+    headings
+}
+
+/// The text of some HTML, without its tags.
+fn text_of(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text.replace("&amp;", "&").trim().to_string()
+}
+
+/// The version number in a heading like `[1.2.0] - 2024-01-31` or `v1.2.0`.
+fn version_in(title: &str) -> Option<String> {
+    title
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c| c == '[' || c == ']' || c == '(' || c == ')'))
+        .find(|word| {
+            word.trim_start_matches('v')
+                .starts_with(|c: char| c.is_ascii_digit())
+        })
+        .map(str::to_string)
+}
+
+fn escape(attribute: &str) -> String {
+    attribute
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn recognises_changelogs() {
+        assert!(is_changelog("./CHANGELOG.md"));
+        assert!(is_changelog("./docs/changelog.md"));
+        assert!(!is_changelog("./README.md"));
+    }
+
+    #[test]
+    fn wraps_each_version() {
+        let html = "<h1>Changelog</h1>\n<h2>[Unreleased]</h2>\n<p>a</p>\n\
+                    <h2><a href=\"x\">1.1.0</a> - 2024-02-01</h2>\n<p>b</p>\n<h2>[1.0.0]</h2>\n";
+
+        let (html, releases) = releases(html);
+
+        assert_eq!(
+            html,
+            "<h1>Changelog</h1>\n\
+             <details class=\"rs-readme-release\" id=\"unreleased\" open><summary><h2>[Unreleased]</h2></summary>\n<p>a</p>\n</details>\
+             <details class=\"rs-readme-release rs-readme-latest\" id=\"1.1.0\" open><summary><h2><a href=\"x\">1.1.0</a> - 2024-02-01</h2></summary>\n<p>b</p>\n</details>\
+             <details class=\"rs-readme-release\" id=\"1.0.0\" open><summary><h2>[1.0.0]</h2></summary>\n</details>"
+        );
+        assert_eq!(
+            releases
+                .iter()
+                .map(|r| r.anchor.as_str())
+                .collect::<Vec<_>>(),
+            vec!["unreleased", "1.1.0", "1.0.0"]
+        );
+        assert_eq!(releases[1].title, "1.1.0 - 2024-02-01");
+    }
+
+    #[test]
+    fn keeps_githubs_heading_wrappers_together() {
+        let html = "<div class=\"markdown-heading\" dir=\"auto\"><h2 class=\"heading-element\">v2.0.0</h2>\
+                    <a id=\"user-content-v200\" class=\"anchor\" href=\"#v200\"></a></div>\n<p>c</p>";
+
+        let (html, releases) = releases(html);
+
+        assert!(html.starts_with(
+            "<details class=\"rs-readme-release rs-readme-latest\" id=\"v2.0.0\" open><summary>\
+             <div class=\"markdown-heading\" dir=\"auto\">"
+        ));
+        assert!(html.ends_with("</a></div></summary>\n<p>c</p></details>"));
+        assert_eq!(releases.len(), 1);
+    }
+}
@@ -1,6 +1,13 @@
+use glob::Pattern;
+use http_types::Url;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
+use crate::code_theme::{CodeTheme, CODE_THEMES};
+use crate::i18n::Lang;
+
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "rs-readme",
@@ -12,19 +19,439 @@ pub struct Args {
     pub host: String,
 
     /// The port to serve the readme files on
-    #[structopt(short, long, default_value = "4000")]
-    pub port: usize,
+    #[structopt(short, long, default_value = "4000", parse(try_from_str = parse_port))]
+    pub port: u16,
 
-    /// The folder to use as the root when serving files
-    #[structopt(short, long, default_value = ".")]
+    /// The folder to use as the root when serving files, or a .zip, .tar or .tar.gz archive to
+    /// serve the files in
+    #[structopt(short, long, default_value = ".", parse(try_from_os_str = parse_folder))]
     pub folder: PathBuf,
 
+    /// Fetch documents over HTTP from beside this URL instead of from the folder, showing it at
+    /// the root, like a raw file on GitHub or a gist
+    #[structopt(long, conflicts_with = "stdin")]
+    pub url: Option<Url>,
+
+    /// Serve markdown piped in on stdin at the root, a form feed (\f) between documents
+    /// replaces the one shown with the next
+    #[structopt(long)]
+    pub stdin: bool,
+
     /// The GitHub context to render in, should be of the form: `user/repo` or `org/repo`
-    #[structopt(short, long)]
+    #[structopt(short, long, parse(try_from_str = parse_context))]
     pub context: Option<String>,
+
+    /// The config file to read, reloaded on SIGHUP [default: <folder>/.rs-readme.toml if it exists]
+    #[structopt(long)]
+    pub config: Option<PathBuf>,
+
+    /// How many rendered pages to keep in memory, 0 turns the cache off
+    #[structopt(long, default_value = "128")]
+    pub cache_size: usize,
+
+    /// The biggest file to render, in bytes or with a K, M or G suffix. 0 means no limit
+    #[structopt(long, default_value = "10M", parse(try_from_str = parse_size))]
+    pub max_file_size: u64,
+
+    /// Render .txt and extensionless files like LICENSE as preformatted text
+    #[structopt(long)]
+    pub plain_text: bool,
+
+    /// Strip scripts and other active content from .html files before serving them
+    #[structopt(long)]
+    pub sanitize_html: bool,
+
+    /// Let the scripts in .html files run, instead of serving them sandboxed away from
+    /// rs-readme's routes
+    #[structopt(long)]
+    pub trust_html: bool,
+
+    /// Make tables in documents sort when a header is clicked, with a filter box over big ones
+    #[structopt(long)]
+    pub sortable_tables: bool,
+
+    /// How many rows a table can have before --sortable-tables gives it a filter box
+    #[structopt(long, default_value = "20")]
+    pub filter_tables_over: usize,
+
+    /// Answer requests from other machines too. Without this only requests from this machine
+    /// are answered, even when serving on 0.0.0.0
+    #[structopt(long)]
+    pub allow_remote: bool,
+
+    /// Let documents be edited from the browser at /edit/<path>, saving them back to the folder
+    #[structopt(long)]
+    pub edit: bool,
+
+    /// Show everything known about errors on their pages, like the converter's whole response
+    /// and the request's headers, instead of a short message
+    #[structopt(long)]
+    pub debug_errors: bool,
+
+    /// The language of rs-readme's own pages, like its error pages: en or ja
+    #[structopt(long, default_value = "en")]
+    pub lang: Lang,
+
+    /// Put before every page's title in the browser tab, like "myproject – Installation"
+    #[structopt(long)]
+    pub title: Option<String>,
+
+    /// The colors of highlighted code, with a light and a dark variant that follow the page's
+    #[structopt(long, default_value = "github", possible_values = CODE_THEMES)]
+    pub code_theme: CodeTheme,
+
+    /// How many files can be converted at once, the rest wait their turn. 0 means no limit
+    #[structopt(long, default_value = "4")]
+    pub max_conversions: usize,
+
+    /// How many live-reload connections can be open at once, the rest get a 503. 0 means no limit
+    #[structopt(long, default_value = "32")]
+    pub max_update_streams: usize,
+
+    /// How long a changed file has to stay the same before live-reloading pages get it, in
+    /// milliseconds
+    #[structopt(long, default_value = "200")]
+    pub debounce_ms: u64,
+
+    /// How long each live-reloading page waits between updates at least, in milliseconds. Only
+    /// the newest of those that came meanwhile is sent
+    #[structopt(long, default_value = "500")]
+    pub min_push_interval_ms: u64,
+
+    /// How long a conversion can take before the page shows an error instead, in seconds. 0
+    /// means no limit
+    #[structopt(long, default_value = "30")]
+    pub convert_timeout: u64,
+
+    /// A PlantUML server to draw ```plantuml code blocks with, like
+    /// https://www.plantuml.com/plantuml. They're shown as source without one
+    #[structopt(long)]
+    pub plantuml_server: Option<String>,
+
+    /// POST the path and hash of a document as JSON to this URL whenever it changes, for tools
+    /// that rebuild or notify on edits. Only documents open in a browser are watched
+    #[structopt(long)]
+    pub webhook: Option<Url>,
+
+    /// Append a line for every answered request (time, peer, method, path, status, bytes and
+    /// duration) to this file, for keeping track of who reads what on a shared server
+    #[structopt(long)]
+    pub access_log: Option<PathBuf>,
+
+    /// How big the access log grows before it's moved to <access-log>.1 and a new one started, in
+    /// bytes. 0 means never
+    #[structopt(long, default_value = "10485760")]
+    pub access_log_max_size: u64,
+
+    /// Serve remote images like badges through rs-readme, keeping copies so they still show
+    /// offline
+    #[structopt(long)]
+    pub proxy_images: bool,
+
+    /// Sets a value for {{name}} placeholders in documents, as name=value. Can be given more
+    /// than once
+    #[structopt(long = "define", number_of_values = 1, parse(try_from_str = parse_define))]
+    pub defines: Vec<(String, String)>,
+
+    /// Render files matching the glob as markdown whatever their extension, like README or
+    /// docs/*.txt. Can be given more than once
+    #[structopt(long = "treat-as-md", number_of_values = 1, parse(try_from_str = parse_pattern))]
+    pub treat_as_md: Vec<Pattern>,
+
+    /// Render every markdown file into the cache at startup
+    #[structopt(long)]
+    pub prewarm: bool,
     // Whether to run in online mode, making calls to GitHub. Should only be
     // used if the offline renderer is not
     // Disabled until I can do server sent events without calling GitHub every time
     // #[structopt(short, long)]
     // pub online: bool,
+    /// Print newline-delimited JSON events to stdout instead of the usual messages, for editor
+    /// plugins supervising rs-readme
+    #[structopt(long)]
+    pub events_json: bool,
+
+    /// Send tracing spans for requests, file reads, conversions and cache lookups to this
+    /// OTLP/HTTP collector, like http://localhost:4318
+    #[cfg(feature = "otel")]
+    #[structopt(long)]
+    pub otel_endpoint: Option<Url>,
+
+    /// Load converters and code block stages from the `.wasm` plugins in this folder, a
+    /// converter is picked with `?converter=<plugin>` or the config file's `converter`
+    #[cfg(feature = "plugins")]
+    #[structopt(long)]
+    pub plugins_dir: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Things rs-readme can do besides serving files.
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Check all markdown files for broken relative links, anchors, and images
+    Lint {
+        /// The report format, either `text` or `json`
+        #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+        format: String,
+    },
+}
+
+/// Why an argument was rejected, shown by structopt after the name of the argument.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ArgError {
+    /// Port 0, which has the OS pick a port nobody would know to open.
+    PortZero,
+
+    /// A port that isn't a number up to 65535.
+    InvalidPort(String),
+
+    /// There's no folder or archive at the path to serve.
+    NoSuchFolder(PathBuf),
+
+    /// A GitHub context that isn't `user/repo`.
+    InvalidContext(String),
+
+    /// A size ending in something other than K, M or G.
+    UnknownSizeUnit(char),
+
+    /// A size that isn't a number, with why.
+    InvalidSize { size: String, reason: String },
+
+    /// A size too big to count the bytes of.
+    SizeTooBig(String),
+
+    /// A definition without a `name=`.
+    InvalidDefine(String),
+
+    /// A glob that can't be parsed, with why.
+    InvalidPattern { pattern: String, reason: String },
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgError::PortZero => write!(f, "port 0 isn't allowed, pick one from 1 to 65535"),
+            ArgError::InvalidPort(port) => {
+                write!(
+                    f,
+                    "invalid port `{}`, expected a number from 1 to 65535",
+                    port
+                )
+            }
+            ArgError::NoSuchFolder(folder) => write!(
+                f,
+                "there's no folder or archive at {}, check the path",
+                folder.display()
+            ),
+            ArgError::InvalidContext(context) => write!(
+                f,
+                "invalid context `{}`, expected user/repo or org/repo",
+                context
+            ),
+            ArgError::UnknownSizeUnit(unit) => {
+                write!(f, "unknown size unit `{}`, expected K, M or G", unit)
+            }
+            ArgError::InvalidSize { size, reason } => {
+                write!(f, "invalid size `{}`: {}", size, reason)
+            }
+            ArgError::SizeTooBig(size) => write!(f, "size `{}` is too big", size),
+            ArgError::InvalidDefine(define) => {
+                write!(f, "expected name=value, got `{}`", define)
+            }
+            ArgError::InvalidPattern { pattern, reason } => {
+                write!(f, "invalid glob `{}`: {}", pattern, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArgError {}
+
+/// Parses a port, refusing 0.
+fn parse_port(port: &str) -> Result<u16, ArgError> {
+    match port.trim().parse::<u16>() {
+        Ok(0) => Err(ArgError::PortZero),
+        Ok(port) => Ok(port),
+        Err(_) => Err(ArgError::InvalidPort(port.to_string())),
+    }
+}
+
+/// Checks there's something at the folder to serve, so a typo fails now instead of as a `404`
+/// on every page.
+fn check_folder(folder: PathBuf) -> Result<PathBuf, ArgError> {
+    if folder.exists() {
+        Ok(folder)
+    } else {
+        Err(ArgError::NoSuchFolder(folder))
+    }
+}
+
+/// [`check_folder`] for structopt, which wants paths that aren't UTF-8 too.
+fn parse_folder(folder: &OsStr) -> Result<PathBuf, OsString> {
+    check_folder(PathBuf::from(folder)).map_err(|err| err.to_string().into())
+}
+
+/// Checks a GitHub context is `user/repo`, with the characters GitHub allows in each.
+fn parse_context(context: &str) -> Result<String, ArgError> {
+    let valid = |name: &str| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+
+    match context.split_once('/') {
+        Some((owner, repo)) if valid(owner) && valid(repo) => Ok(context.to_string()),
+        _ => Err(ArgError::InvalidContext(context.to_string())),
+    }
+}
+
+/// Parses a size like `512`, `64K`, `10M` or `1G` into bytes.
+fn parse_size(size: &str) -> Result<u64, ArgError> {
+    let size = size.trim();
+    let (number, multiplier) = match size.char_indices().last() {
+        Some((at, unit)) if unit.is_ascii_alphabetic() => {
+            let multiplier = match unit.to_ascii_uppercase() {
+                'K' => 1024,
+                'M' => 1024 * 1024,
+                'G' => 1024 * 1024 * 1024,
+                _ => return Err(ArgError::UnknownSizeUnit(unit)),
+            };
+            (&size[..at], multiplier)
+        }
+        _ => (size, 1),
+    };
+
+    number
+        .parse::<u64>()
+        .map_err(|err| ArgError::InvalidSize {
+            size: size.to_string(),
+            reason: err.to_string(),
+        })?
+        .checked_mul(multiplier)
+        .ok_or_else(|| ArgError::SizeTooBig(size.to_string()))
+}
+
+/// Splits a `name=value` definition.
+fn parse_define(define: &str) -> Result<(String, String), ArgError> {
+    match define.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.to_string()))
+        }
+        _ => Err(ArgError::InvalidDefine(define.to_string())),
+    }
+}
+
+/// Parses a glob like `README` or `docs/**/*.txt`.
+fn parse_pattern(pattern: &str) -> Result<Pattern, ArgError> {
+    Pattern::new(pattern).map_err(|err| ArgError::InvalidPattern {
+        pattern: pattern.to_string(),
+        reason: err.msg.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Parses `args` after the program's name.
+    fn parse(args: &[&str]) -> Result<Args, structopt::clap::Error> {
+        Args::from_iter_safe(std::iter::once("rs-readme").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn parses_sizes_with_units() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("64K"), Ok(64 * 1024));
+        assert_eq!(parse_size("10m"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_size("1G"), Ok(1024 * 1024 * 1024));
+        assert_eq!(parse_size("10X"), Err(ArgError::UnknownSizeUnit('X')));
+        assert!(matches!(parse_size("M"), Err(ArgError::InvalidSize { .. })));
+        assert_eq!(
+            parse_size("99999999999G"),
+            Err(ArgError::SizeTooBig("99999999999G".to_string()))
+        );
+    }
+
+    #[test]
+    fn parses_definitions() {
+        assert_eq!(
+            parse_define("version=1.2=beta"),
+            Ok(("version".to_string(), "1.2=beta".to_string()))
+        );
+        assert_eq!(
+            parse_define("version"),
+            Err(ArgError::InvalidDefine("version".to_string()))
+        );
+        assert!(parse_define("=1.2").is_err());
+    }
+
+    #[test]
+    fn parses_globs() {
+        assert_eq!(
+            parse_pattern("docs/*.txt"),
+            Ok(Pattern::new("docs/*.txt").unwrap())
+        );
+        assert_eq!(
+            parse_pattern("docs/[a"),
+            Err(ArgError::InvalidPattern {
+                pattern: "docs/[a".to_string(),
+                reason: "invalid range pattern".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn refuses_port_zero() {
+        assert_eq!(parse_port("8080"), Ok(8080));
+        assert_eq!(parse_port("0"), Err(ArgError::PortZero));
+        assert_eq!(
+            parse_port("70000"),
+            Err(ArgError::InvalidPort("70000".to_string()))
+        );
+        assert!(parse(&["--port", "0"]).is_err());
+    }
+
+    #[test]
+    fn refuses_folders_that_are_not_there() {
+        assert_eq!(
+            check_folder(PathBuf::from("test_dir")),
+            Ok(PathBuf::from("test_dir"))
+        );
+        assert_eq!(
+            check_folder(PathBuf::from("test_dir/nope")),
+            Err(ArgError::NoSuchFolder(PathBuf::from("test_dir/nope")))
+        );
+
+        let err = parse(&["--folder", "test_dir/nope"]).unwrap_err();
+        assert!(err
+            .message
+            .contains("there's no folder or archive at test_dir/nope"));
+    }
+
+    #[test]
+    fn checks_contexts_are_user_slash_repo() {
+        assert_eq!(
+            parse_context("gregcline/rs-readme"),
+            Ok("gregcline/rs-readme".to_string())
+        );
+        assert_eq!(
+            parse_context("my-org/docs.site"),
+            Ok("my-org/docs.site".to_string())
+        );
+        for context in ["rs-readme", "/repo", "user/", "a/b/c", "user/re po"] {
+            assert_eq!(
+                parse_context(context),
+                Err(ArgError::InvalidContext(context.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_a_url_and_stdin_together() {
+        assert!(parse(&["--url", "https://example.com/README.md"]).is_ok());
+        assert!(parse(&["--url", "https://example.com/README.md", "--stdin"]).is_err());
+    }
 }
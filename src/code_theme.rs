@@ -0,0 +1,71 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// The names `--code-theme` takes, see [`CodeTheme`].
+pub const CODE_THEMES: &[&str] = &["github", "solarized", "ocean"];
+
+/// The colors highlighted code is shown in, a light and a dark theme of syntect's that follow
+/// the page's theme. Pages load them from [`CodeTheme::stylesheet_url`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CodeTheme {
+    /// The closest to GitHub's, with base16 Ocean when the page is dark.
+    #[default]
+    GitHub,
+    Solarized,
+    /// base16 Ocean.
+    Ocean,
+}
+
+impl CodeTheme {
+    /// The name `--code-theme` takes for the theme.
+    pub fn name(self) -> &'static str {
+        match self {
+            CodeTheme::GitHub => "github",
+            CodeTheme::Solarized => "solarized",
+            CodeTheme::Ocean => "ocean",
+        }
+    }
+
+    /// Where pages load the theme's CSS from.
+    pub fn stylesheet_url(self) -> String {
+        format!("/static/code-theme/{}.css", self.name())
+    }
+}
+
+impl fmt::Display for CodeTheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for CodeTheme {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<CodeTheme, String> {
+        match name {
+            "github" => Ok(CodeTheme::GitHub),
+            "solarized" => Ok(CodeTheme::Solarized),
+            "ocean" => Ok(CodeTheme::Ocean),
+            _ => Err(format!(
+                "Unknown code theme {}, it's one of {}",
+                name,
+                CODE_THEMES.join(", ")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn reads_theme_names() {
+        assert_eq!("ocean".parse(), Ok(CodeTheme::Ocean));
+        assert_eq!(
+            "monokai".parse::<CodeTheme>(),
+            Err("Unknown code theme monokai, it's one of github, solarized, ocean".to_string())
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+/// Settings read from the optional config file (`.rs-readme.toml` in the served folder by
+/// default). Everything in here can be changed without restarting the server by sending it a
+/// `SIGHUP` or `POST`ing to `/__rs-readme/reload-config`.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The converter to use when a request doesn't ask for one, `offline` or `github`.
+    pub converter: Option<String>,
+
+    /// The GitHub context to render in, should be of the form: `user/repo` or `org/repo`.
+    pub context: Option<String>,
+
+    /// The command `.rst` files are piped through, `rst2html` unless this says otherwise.
+    pub rst_command: Option<String>,
+
+    /// Values for `{{name}}` placeholders in documents, see [`crate::Variables`].
+    pub variables: HashMap<String, String>,
+
+    /// The names of the folders translations are kept in, like `["english", "japanese"]`.
+    /// Folders named like language codes (`en`, `ja`) are found without this.
+    pub languages: Option<Vec<String>>,
+
+    /// Subresource integrity hashes for the GitHub stylesheets pages load from its CDN, by URL,
+    /// like `sha384-...`. Browsers won't use a stylesheet that doesn't match its hash.
+    pub stylesheet_integrity: BTreeMap<String, String>,
+}
+
+/// The command `.rst` files are converted with when the config doesn't name one.
+pub const DEFAULT_RST_COMMAND: &str = "rst2html";
+
+/// The possible errors while loading the config file.
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    /// There's no config file to reload.
+    NoConfigFile,
+
+    /// The config file couldn't be read.
+    CouldNotRead(String),
+
+    /// The config file wasn't valid TOML or had the wrong types in it.
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::NoConfigFile => write!(f, "No config file was given"),
+            ConfigError::CouldNotRead(reason) => write!(f, "Could not read config\n{}", reason),
+            ConfigError::Invalid(reason) => write!(f, "Invalid config\n{}", reason),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl Config {
+    /// Reads and parses the config file at `path`.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(|err| {
+            ConfigError::CouldNotRead(format!("{}: {}", path.to_string_lossy(), err))
+        })?;
+
+        toml::from_str(&contents).map_err(|err| ConfigError::Invalid(err.to_string()))
+    }
+}
+
+/// Holds a value that can be swapped out wholesale while readers keep using the `Arc` they
+/// already loaded.
+pub struct Swap<T> {
+    current: RwLock<Arc<T>>,
+}
+
+impl<T> Swap<T> {
+    pub fn new(value: T) -> Swap<T> {
+        Swap {
+            current: RwLock::new(Arc::new(value)),
+        }
+    }
+
+    /// Returns the current value.
+    pub fn load(&self) -> Arc<T> {
+        match self.current.read() {
+            Ok(current) => current.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Replaces the current value, readers pick it up on their next `load`.
+    pub fn store(&self, value: T) {
+        match self.current.write() {
+            Ok(mut current) => *current = Arc::new(value),
+            Err(poisoned) => *poisoned.into_inner() = Arc::new(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_config() {
+        let config: Config = toml::from_str(
+            "converter = \"github\"\ncontext = \"gregcline/rs-readme\"\n\n[variables]\nversion = \"1.2.0\"\n\n\
+             [stylesheet_integrity]\n\"https://github.githubassets.com/assets/site.css\" = \"sha384-abc\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config,
+            Config {
+                converter: Some("github".to_string()),
+                context: Some("gregcline/rs-readme".to_string()),
+                rst_command: None,
+                variables: vec![("version".to_string(), "1.2.0".to_string())]
+                    .into_iter()
+                    .collect(),
+                languages: None,
+                stylesheet_integrity: vec![(
+                    "https://github.githubassets.com/assets/site.css".to_string(),
+                    "sha384-abc".to_string()
+                )]
+                .into_iter()
+                .collect(),
+            }
+        );
+    }
+
+    #[test]
+    fn missing_config_could_not_read() {
+        let err = Config::load(Path::new("test_dir/nope.toml"));
+
+        assert!(matches!(err, Err(ConfigError::CouldNotRead(_))));
+    }
+
+    #[test]
+    fn swap_keeps_old_values_alive() {
+        let swap = Swap::new(1);
+        let old = swap.load();
+
+        swap.store(2);
+
+        assert_eq!(*old, 1);
+        assert_eq!(*swap.load(), 2);
+    }
+}
@@ -1,26 +1,61 @@
+use std::collections::HashMap;
 use std::error::Error;
 use std::ffi::OsStr;
 use std::fmt;
-use std::fs::File;
-use std::io::prelude::*;
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
 
-use generic_array::{typenum::U20, GenericArray};
-use log::{error, warn};
-use sha1::{Digest, Sha1};
+#[cfg(feature = "encodings")]
+use chardetng::EncodingDetector;
+#[cfg(feature = "encodings")]
+use encoding_rs::{Encoding, UTF_8};
+#[cfg(feature = "server")]
+use glob::{MatchOptions, Pattern};
+use log::{error, info, warn};
+use xxhash_rust::xxh3::xxh3_128;
+
+use crate::links::normalize;
 
 /// The possible errors while finding some markdown content.
 ///
-/// There are a lot of possible file system errors that I just
-/// don't want/need to worry about right now all encapsulated
-/// in `CouldNotFetch`.
+/// File system errors are told apart by what they mean for the request: missing files are
+/// `CouldNotFetch`, unreadable ones `Forbidden`, folders `IsDirectory`, and anything else `Io`
+/// with its kind.
 #[derive(Debug, PartialEq)]
 pub enum ContentError {
     /// The requested content couldn't be fetched (probably a file error)
     CouldNotFetch(String),
 
+    /// The requested file exists but we aren't allowed to read it, `reason` is what the OS said
+    Forbidden { resource: String, reason: String },
+
+    /// The requested file is a folder
+    IsDirectory(String),
+
+    /// Reading the requested file failed some other way
+    Io {
+        resource: String,
+        kind: io::ErrorKind,
+    },
+
     /// The requested content wasn't markdown
     NotMarkdown,
+
+    /// The requested path climbs out of the root folder
+    InvalidPath(String),
+
+    /// The requested file is bigger than we're willing to render, sizes are in bytes
+    TooLarge {
+        resource: String,
+        size: u64,
+        limit: u64,
+    },
+
+    /// The requested file is binary, or text that can't be decoded, despite its extension
+    NotText(String),
 }
 
 impl fmt::Display for ContentError {
@@ -29,74 +64,519 @@ impl fmt::Display for ContentError {
             ContentError::CouldNotFetch(resource) => {
                 write!(f, "Could not find {}", resource.replacen("./", "", 1))
             }
+            ContentError::Forbidden { resource, reason } => write!(
+                f,
+                "Not allowed to read {}: {}",
+                resource.replacen("./", "", 1),
+                reason
+            ),
+            ContentError::IsDirectory(resource) => {
+                write!(f, "{} is a folder", resource.replacen("./", "", 1))
+            }
+            ContentError::Io { resource, kind } => {
+                write!(
+                    f,
+                    "Could not read {}: {}",
+                    resource.replacen("./", "", 1),
+                    kind
+                )
+            }
             ContentError::NotMarkdown => write!(f, "The file was not markdown"),
+            ContentError::InvalidPath(resource) => write!(f, "Invalid path {}", resource),
+            ContentError::TooLarge {
+                resource,
+                size,
+                limit,
+            } => write!(
+                f,
+                "{} is {} bytes, more than the {} byte limit",
+                resource.replacen("./", "", 1),
+                size,
+                limit
+            ),
+            ContentError::NotText(resource) => {
+                write!(f, "{} isn't a text file", resource.replacen("./", "", 1))
+            }
         }
     }
 }
 
 impl Error for ContentError {}
 
+impl ContentError {
+    /// The error for failing to read `resource` with `err`.
+    fn from_io(resource: &str, err: &io::Error) -> ContentError {
+        let resource = resource.to_string();
+        match err.kind() {
+            io::ErrorKind::NotFound => ContentError::CouldNotFetch(resource),
+            io::ErrorKind::PermissionDenied => ContentError::Forbidden {
+                resource,
+                reason: err.to_string(),
+            },
+            io::ErrorKind::IsADirectory => ContentError::IsDirectory(resource),
+            kind => ContentError::Io { resource, kind },
+        }
+    }
+}
+
+/// Identifies a version of some content so we can tell when it changes.
+///
+/// How it's computed is an implementation detail, all that matters is equal content gives equal
+/// hashes. It displays as lowercase hex, which is what gets sent to live-reload clients and used
+/// for `ETag`s.
+///
+/// It's only used to notice changes so it's a fast non-cryptographic hash (XXH3).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ContentHash(u128);
+
+impl ContentHash {
+    /// Hashes `content`.
+    pub fn of(content: &[u8]) -> ContentHash {
+        ContentHash(xxh3_128(content))
+    }
+}
+
+impl fmt::Display for ContentHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+/// The kinds of document rs-readme renders, told apart by file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentFormat {
+    /// `.md`, converted with the configured markdown converter.
+    Markdown,
+
+    /// `.rst`, converted with an external command, see `rst_command` in [`crate::Config`].
+    ReStructuredText,
+
+    /// `.csv`, shown as a table.
+    Csv,
+
+    /// `.tsv`, shown as a table.
+    Tsv,
+
+    /// `.txt` and files without an extension, like `LICENSE`, shown preformatted. Only read
+    /// when [`FileFinder::with_plain_text`] turns them on.
+    PlainText,
+}
+
+impl DocumentFormat {
+    /// The format of the document at `path`, `None` if it's not one we render.
+    pub fn of(path: &Path) -> Option<DocumentFormat> {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("md") => Some(DocumentFormat::Markdown),
+            Some("rst") => Some(DocumentFormat::ReStructuredText),
+            Some("csv") => Some(DocumentFormat::Csv),
+            Some("tsv") => Some(DocumentFormat::Tsv),
+            Some("txt") | None => Some(DocumentFormat::PlainText),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `resource`, relative to the root, matches one of `patterns`, like the ones
+/// [`FileFinder::with_treat_as_md`] takes. `*` stays within a folder, `**/` crosses them.
+#[cfg(feature = "server")]
+pub(crate) fn matches_any(patterns: &[Pattern], resource: &str) -> bool {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    let resource = resource.strip_prefix("./").unwrap_or(resource);
+
+    patterns
+        .iter()
+        .any(|pattern| pattern.matches_with(resource, options))
+}
+
 /// Something that can find some markdown content given a resource identifier.
 pub trait ContentFinder {
     /// Given a resource identifier returns the markdown string it represents.
-    fn content_for(&self, resource: &str) -> Result<(String, GenericArray<u8, U20>), ContentError>;
+    fn content_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError>;
+
+    /// The text of any file at `resource`, not only documents, for things like pulling source
+    /// code into a document. Defaults to [`ContentFinder::content_for`].
+    fn source_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+        self.content_for(resource)
+    }
+
+    /// The name of the encoding the document at `resource` was last read in, like `Shift_JIS`,
+    /// when it had to be converted to UTF-8. Defaults to `None`, everything's UTF-8.
+    fn encoding_of(&self, _resource: &str) -> Option<&'static str> {
+        None
+    }
+
+    /// Whether there's a file at `resource`, documents or not, for warning about images that
+    /// aren't there. Defaults to `true`, finders that can't tell don't warn.
+    fn exists(&self, _resource: &str) -> bool {
+        true
+    }
 }
 
+/// The biggest file [`FileFinder`] reads unless told otherwise, 10 MiB.
+pub const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
 /// Implements [`ContentFinder`] based on a file folder.
 ///
 /// It expects any `resource` to be a valid file path and will look for that
 /// path relative to `root`. If it finds a markdown file it returns its
 /// contents, otherwise it returns an error.
+///
+/// Files are only read and hashed again when their modification time or size changes, live
+/// reload asks for the same files over and over.
+///
+/// Files that aren't UTF-8 are converted from the encoding they look to be in, like Latin-1 or
+/// Shift-JIS, see [`ContentFinder::encoding_of`]. That needs the `encodings` feature, without it
+/// they aren't read.
 pub struct FileFinder {
     root: PathBuf,
+    max_size: u64,
+    plain_text: bool,
+    #[cfg(feature = "server")]
+    treat_as_md: Vec<Pattern>,
+    snapshots: Mutex<HashMap<PathBuf, Snapshot>>,
+    encodings: Mutex<HashMap<PathBuf, &'static str>>,
+}
+
+/// A file's contents as of when it had this modification time and size.
+struct Snapshot {
+    modified: SystemTime,
+    len: u64,
+    contents: String,
+    hash: ContentHash,
 }
 
 impl FileFinder {
     /// Creates a new [`FileFinder`] relative to `root`.
     pub fn new(root: PathBuf) -> FileFinder {
-        FileFinder { root }
+        FileFinder {
+            root,
+            max_size: DEFAULT_MAX_SIZE,
+            plain_text: false,
+            #[cfg(feature = "server")]
+            treat_as_md: Vec::new(),
+            snapshots: Mutex::new(HashMap::new()),
+            encodings: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Refuses files bigger than `max_size` bytes instead of reading them, `0` allows anything.
+    pub fn with_max_size(mut self, max_size: u64) -> FileFinder {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Also reads plain text files, see [`DocumentFormat::PlainText`].
+    pub fn with_plain_text(mut self, plain_text: bool) -> FileFinder {
+        self.plain_text = plain_text;
+        self
+    }
+
+    /// Reads files matching `patterns` as markdown whatever their extension, like an
+    /// extensionless `README`, see [`matches_any`].
+    #[cfg(feature = "server")]
+    pub fn with_treat_as_md(mut self, patterns: Vec<Pattern>) -> FileFinder {
+        self.treat_as_md = patterns;
+        self
+    }
+
+    /// The contents we already have for `path` if it hasn't changed since we read it.
+    fn unchanged(
+        &self,
+        path: &Path,
+        modified: Option<SystemTime>,
+        len: u64,
+    ) -> Option<(String, ContentHash)> {
+        let modified = modified?;
+
+        self.snapshots()
+            .get(path)
+            .filter(|snapshot| snapshot.modified == modified && snapshot.len == len)
+            .map(|snapshot| (snapshot.contents.clone(), snapshot.hash))
+    }
+
+    fn remember(
+        &self,
+        path: PathBuf,
+        modified: Option<SystemTime>,
+        len: u64,
+        contents: &str,
+        hash: ContentHash,
+    ) {
+        let modified = match modified {
+            Some(modified) => modified,
+            None => return,
+        };
+
+        self.snapshots().insert(
+            path,
+            Snapshot {
+                modified,
+                len,
+                contents: contents.to_string(),
+                hash,
+            },
+        );
+    }
+
+    fn encodings(&self) -> MutexGuard<'_, HashMap<PathBuf, &'static str>> {
+        match self.encodings.lock() {
+            Ok(encodings) => encodings,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn snapshots(&self) -> MutexGuard<'_, HashMap<PathBuf, Snapshot>> {
+        match self.snapshots.lock() {
+            Ok(snapshots) => snapshots,
+            Err(poisoned) => poisoned.into_inner(),
+        }
     }
 }
 
 impl ContentFinder for FileFinder {
     /// Returns the contents of the file located at the path in `resource`.
-    fn content_for(&self, resource: &str) -> Result<(String, GenericArray<u8, U20>), ContentError> {
-        let mut path = self.root.clone();
-        path.push(resource);
+    fn content_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+        let path = self.path_of(resource)?;
 
-        if path.extension().unwrap_or_else(|| OsStr::new("")) != "md" {
+        let format = DocumentFormat::of(&path);
+        #[cfg(feature = "server")]
+        let treated = matches_any(&self.treat_as_md, resource);
+        #[cfg(not(feature = "server"))]
+        let treated = false;
+        if !treated
+            && (format.is_none() || (format == Some(DocumentFormat::PlainText) && !self.plain_text))
+        {
             warn!(
-                "Tried to fetch markdown from {}, please add .md extension",
+                "Tried to fetch markdown from {}, please add .md, .rst, .csv or .tsv extension",
                 path.to_string_lossy()
             );
             return Err(ContentError::NotMarkdown);
         }
 
+        self.read_text(resource, path)
+    }
+
+    /// Returns the contents of the text file located at the path in `resource`, whatever its
+    /// extension.
+    fn source_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+        let path = self.path_of(resource)?;
+
+        self.read_text(resource, path)
+    }
+
+    fn exists(&self, resource: &str) -> bool {
+        self.path_of(resource).is_ok_and(|path| path.is_file())
+    }
+
+    fn encoding_of(&self, resource: &str) -> Option<&'static str> {
+        let path = self.path_of(resource).ok()?;
+
+        self.encodings().get(&path).copied()
+    }
+}
+
+impl FileFinder {
+    /// Where `resource` is under the root, if it doesn't climb out of it.
+    fn path_of(&self, resource: &str) -> Result<PathBuf, ContentError> {
+        if normalize(Path::new(resource)).is_none() {
+            warn!(
+                "Refusing to fetch {}, it's outside the root folder",
+                resource
+            );
+            return Err(ContentError::InvalidPath(resource.to_string()));
+        }
+
+        let mut path = self.root.clone();
+        path.push(resource);
+
+        Ok(path)
+    }
+
+    /// Reads the file at `path`, for `resource`, unless it's too big or we already have it.
+    fn read_text(
+        &self,
+        resource: &str,
+        path: PathBuf,
+    ) -> Result<(String, ContentHash), ContentError> {
+        let metadata = fs::metadata(&path).map_err(|err| {
+            error!(
+                "Could not open file {}:\n{:#?}",
+                path.to_string_lossy(),
+                err
+            );
+            ContentError::from_io(resource, &err)
+        })?;
+        if metadata.is_dir() {
+            return Err(ContentError::IsDirectory(resource.to_string()));
+        }
+        if self.max_size > 0 && metadata.len() > self.max_size {
+            warn!(
+                "Refusing to read {}, it's {} bytes",
+                path.to_string_lossy(),
+                metadata.len()
+            );
+            return Err(ContentError::TooLarge {
+                resource: resource.to_string(),
+                size: metadata.len(),
+                limit: self.max_size,
+            });
+        }
+
+        let modified = metadata.modified().ok();
+        if let Some(found) = self.unchanged(&path, modified, metadata.len()) {
+            return Ok(found);
+        }
+
         let mut file = File::open(&path).map_err(|err| {
             error!(
                 "Could not open file {}:\n{:#?}",
                 path.to_string_lossy(),
                 err
             );
-            ContentError::CouldNotFetch(resource.to_string())
+            ContentError::from_io(resource, &err)
         })?;
 
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).map_err(|err| {
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).map_err(|err| {
             error!(
                 "Could not read contents of {}:\n{:#?}",
                 path.to_string_lossy(),
                 err
             );
-            ContentError::CouldNotFetch(resource.to_string())
+            ContentError::from_io(resource, &err)
         })?;
+        let (contents, encoding) = match decode(bytes) {
+            Some(decoded) => decoded,
+            None => {
+                warn!("Not rendering {}, it isn't text", path.to_string_lossy());
+                return Err(ContentError::NotText(resource.to_string()));
+            }
+        };
+        match encoding {
+            Some(encoding) => {
+                info!(
+                    "Read {} as {}, it isn't UTF-8",
+                    path.to_string_lossy(),
+                    encoding
+                );
+                self.encodings().insert(path.clone(), encoding);
+            }
+            None => {
+                self.encodings().remove(&path);
+            }
+        }
 
-        let hash = Sha1::digest(&contents.as_bytes());
+        let contents = normalize_text(contents);
+        let hash = ContentHash::of(contents.as_bytes());
+        self.remember(path, modified, metadata.len(), &contents, hash);
 
         Ok((contents, hash))
     }
 }
 
+/// How much of a file is looked at to tell whether it's binary, the same as git.
+const SNIFF_LENGTH: usize = 8000;
+
+/// Whether `bytes` look like a binary file rather than text: there's a NUL near the start.
+pub(crate) fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(SNIFF_LENGTH)].contains(&0)
+}
+
+/// `text` without a leading byte order mark and with `\n` line endings, so documents saved on
+/// Windows hash and render the same as everywhere else. Every finder reads through this.
+pub(crate) fn normalize_text(text: String) -> String {
+    let text = match text.strip_prefix('\u{feff}') {
+        Some(rest) => rest.to_string(),
+        None => text,
+    };
+    if !text.contains('\r') {
+        return text;
+    }
+
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// A file's `bytes` as text, with the encoding they were converted from when it wasn't UTF-8.
+/// `None` when the file is binary or doesn't decode cleanly in the encoding it looks to be in.
+#[cfg(feature = "encodings")]
+fn decode(bytes: Vec<u8>) -> Option<(String, Option<&'static str>)> {
+    // UTF-16 is full of NULs, so it has to be caught by its byte order mark first
+    if let Some((encoding, bom)) =
+        Encoding::for_bom(&bytes).filter(|(encoding, _)| *encoding != UTF_8)
+    {
+        let (text, malformed) = encoding.decode_without_bom_handling(&bytes[bom..]);
+        return Some((text.into_owned(), Some(encoding.name()))).filter(|_| !malformed);
+    }
+    if is_binary(&bytes) {
+        return None;
+    }
+
+    let bytes = match String::from_utf8(bytes) {
+        Ok(text) => return Some((text, None)),
+        Err(err) => err.into_bytes(),
+    };
+    let mut detector = EncodingDetector::new();
+    detector.feed(&bytes, true);
+    let encoding = detector.guess(None, false);
+    let (text, malformed) = encoding.decode_without_bom_handling(&bytes);
+
+    Some((text.into_owned(), Some(encoding.name()))).filter(|_| !malformed)
+}
+
+/// A file's `bytes` as text, `None` when the file is binary or isn't UTF-8.
+#[cfg(not(feature = "encodings"))]
+fn decode(bytes: Vec<u8>) -> Option<(String, Option<&'static str>)> {
+    if is_binary(&bytes) {
+        return None;
+    }
+
+    String::from_utf8(bytes).ok().map(|text| (text, None))
+}
+
+/// Walks `root` and returns every markdown file under it, relative to `root`
+/// and sorted.
+///
+/// Hidden folders (like `.git`) are skipped, folders we can't read are logged
+/// and skipped.
+pub fn markdown_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut folders = vec![PathBuf::new()];
+
+    while let Some(folder) = folders.pop() {
+        let entries = match std::fs::read_dir(root.join(&folder)) {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!(
+                    "Could not read folder {}: {}",
+                    folder.to_string_lossy(),
+                    err
+                );
+                continue;
+            }
+        };
+
+        for entry in entries.filter_map(Result::ok) {
+            let name = entry.file_name();
+            if name.to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            let path = folder.join(&name);
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() => folders.push(path),
+                Ok(_) if path.extension() == Some(OsStr::new("md")) => files.push(path),
+                _ => {}
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -108,19 +588,47 @@ mod test {
         let (content_for_a, hash_for_a) = finder.content_for("test_dir/a.md").unwrap();
         let (content_for_b, hash_for_b) = finder.content_for("test_dir/b.md").unwrap();
 
-        #[cfg(not(windows))]
-        let a_expected = "# A's content\n![](./images/rust-logo.png)\n".to_string();
-        #[cfg(windows)]
-        let a_expected = "# A's content\r\n![](./images/rust-logo.png)\r\n".to_string();
-        #[cfg(not(windows))]
+        let a_expected = "# A's content\n![The Rust logo](./images/rust-logo.png)\n".to_string();
         let b_expected = "- B's content\n".to_string();
-        #[cfg(windows)]
-        let b_expected = "- B's content\r\n".to_string();
 
         assert_eq!(content_for_a, a_expected);
         assert_eq!(content_for_b, b_expected);
-        assert_eq!(hash_for_a, Sha1::digest(a_expected.as_bytes()));
-        assert_eq!(hash_for_b, Sha1::digest(b_expected.as_bytes()));
+        assert_eq!(hash_for_a, ContentHash::of(a_expected.as_bytes()));
+        assert_eq!(hash_for_b, ContentHash::of(b_expected.as_bytes()));
+    }
+
+    #[test]
+    fn content_hash_displays_as_hex() {
+        assert_eq!(
+            ContentHash::of(b"").to_string(),
+            "99aa06d3014798d86001c324468d497f"
+        );
+    }
+
+    #[test]
+    fn finds_plain_text_when_asked_to() {
+        let finder = FileFinder::new(PathBuf::from("./")).with_plain_text(true);
+
+        assert!(finder.content_for("test_dir/b.txt").is_ok());
+        assert!(finder.content_for("test_dir/NOTES").is_ok());
+        assert_eq!(
+            finder.content_for("test_dir/images/rust-logo.png"),
+            Err(ContentError::NotMarkdown)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn finds_files_treated_as_markdown() {
+        let finder = FileFinder::new(PathBuf::from("./"))
+            .with_treat_as_md(vec![Pattern::new("test_dir/*").unwrap()]);
+
+        assert!(finder.content_for("./test_dir/NOTES").is_ok());
+        assert!(finder.content_for("./test_dir/b.txt").is_ok());
+        assert_eq!(
+            finder.content_for("./test_dir/images/rust-logo.png"),
+            Err(ContentError::NotMarkdown)
+        );
     }
 
     #[test]
@@ -131,4 +639,191 @@ mod test {
 
         assert_eq!(err, Err(ContentError::NotMarkdown));
     }
+
+    #[test]
+    fn strips_byte_order_marks_and_carriage_returns() {
+        assert_eq!(
+            normalize_text("\u{feff}# Title\r\n\r\ntext\r\n".to_string()),
+            "# Title\n\ntext\n"
+        );
+        assert_eq!(normalize_text("old\rmac\r".to_string()), "old\nmac\n");
+        assert_eq!(
+            normalize_text("# \u{feff}kept\n".to_string()),
+            "# \u{feff}kept\n"
+        );
+    }
+
+    #[test]
+    fn windows_files_hash_like_unix_ones() {
+        let root = std::env::temp_dir().join(format!("rs-readme-crlf-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(
+            root.join("windows.md"),
+            "\u{feff}# Title\r\n\r\nSome text\r\n",
+        )
+        .unwrap();
+        fs::write(root.join("unix.md"), "# Title\n\nSome text\n").unwrap();
+        let finder = FileFinder::new(root.clone());
+
+        let windows = finder.content_for("./windows.md").unwrap();
+        let unix = finder.content_for("./unix.md").unwrap();
+
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(windows, unix);
+    }
+
+    #[test]
+    fn refuses_binary_files() {
+        let root = std::env::temp_dir().join(format!("rs-readme-binary-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("data.md"), b"PK\x03\x04\x00\x00\x08\x00").unwrap();
+        let finder = FileFinder::new(root.clone());
+
+        let binary = finder.content_for("./data.md");
+
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(binary, Err(ContentError::NotText("./data.md".to_string())));
+    }
+
+    #[test]
+    fn tells_apart_why_files_could_not_be_read() {
+        let root = std::env::temp_dir().join(format!("rs-readme-io-kinds-{}", std::process::id()));
+        fs::create_dir_all(root.join("docs.md")).unwrap();
+        let finder = FileFinder::new(root.clone());
+
+        let folder = finder.content_for("./docs.md");
+        let missing = finder.content_for("./missing.md");
+
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            folder,
+            Err(ContentError::IsDirectory("./docs.md".to_string()))
+        );
+        assert_eq!(
+            missing,
+            Err(ContentError::CouldNotFetch("./missing.md".to_string()))
+        );
+        let denied = io::Error::from_raw_os_error(13);
+        assert_eq!(
+            ContentError::from_io("./a.md", &denied),
+            ContentError::Forbidden {
+                resource: "./a.md".to_string(),
+                reason: denied.to_string()
+            }
+        );
+        let interrupted = io::Error::from(io::ErrorKind::Interrupted);
+        assert_eq!(
+            ContentError::from_io("./a.md", &interrupted),
+            ContentError::Io {
+                resource: "./a.md".to_string(),
+                kind: io::ErrorKind::Interrupted
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "encodings")]
+    fn converts_legacy_encodings_to_utf8() {
+        let root = std::env::temp_dir().join(format!("rs-readme-encoding-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let latin1 = "# Café crème\n\nLe garçon a mangé à la fenêtre, près du théâtre.\n";
+        let japanese = "# 日本語の文書\n\nこれは古いエディタで保存された文書です。\n";
+        let utf16 = "# Notes\n";
+        fs::write(
+            root.join("latin1.md"),
+            encoding_rs::WINDOWS_1252.encode(latin1).0,
+        )
+        .unwrap();
+        fs::write(
+            root.join("japanese.md"),
+            encoding_rs::SHIFT_JIS.encode(japanese).0,
+        )
+        .unwrap();
+        let mut utf16le = vec![0xff, 0xfe];
+        utf16le.extend(utf16.encode_utf16().flat_map(u16::to_le_bytes));
+        fs::write(root.join("utf16.md"), utf16le).unwrap();
+        fs::write(root.join("utf8.md"), latin1).unwrap();
+        let finder = FileFinder::new(root.clone());
+
+        let read = |resource: &str| finder.content_for(resource).unwrap().0;
+        assert_eq!(read("./latin1.md"), latin1);
+        assert_eq!(read("./japanese.md"), japanese);
+        assert_eq!(read("./utf16.md"), utf16);
+        assert_eq!(read("./utf8.md"), latin1);
+
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(finder.encoding_of("./latin1.md"), Some("windows-1252"));
+        assert_eq!(finder.encoding_of("./japanese.md"), Some("Shift_JIS"));
+        assert_eq!(finder.encoding_of("./utf16.md"), Some("UTF-16LE"));
+        assert_eq!(finder.encoding_of("./utf8.md"), None);
+    }
+
+    #[test]
+    fn does_not_find_content_outside_root() {
+        let finder = FileFinder::new(PathBuf::from("./test_dir"));
+
+        let err = finder.content_for("../README.md");
+
+        assert_eq!(
+            err,
+            Err(ContentError::InvalidPath("../README.md".to_string()))
+        );
+    }
+
+    #[test]
+    fn skips_reading_files_with_the_same_mtime_and_size() {
+        let root = std::env::temp_dir().join(format!("rs-readme-mtime-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let file = root.join("doc.md");
+        fs::write(&file, "aaa").unwrap();
+        let finder = FileFinder::new(root.clone());
+
+        let (first, _) = finder.content_for("doc.md").unwrap();
+        let modified = fs::metadata(&file).unwrap().modified().unwrap();
+
+        // Same size and modification time looks unchanged
+        fs::write(&file, "bbb").unwrap();
+        File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let (same, _) = finder.content_for("doc.md").unwrap();
+
+        // A different size doesn't
+        fs::write(&file, "bbbb").unwrap();
+        let (changed, hash) = finder.content_for("doc.md").unwrap();
+
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(first, "aaa");
+        assert_eq!(same, "aaa");
+        assert_eq!(changed, "bbbb");
+        assert_eq!(hash, ContentHash::of(b"bbbb"));
+    }
+
+    #[test]
+    fn refuses_files_over_the_size_limit() {
+        let finder = FileFinder::new(PathBuf::from("./")).with_max_size(4);
+
+        let err = finder.content_for("test_dir/b.md");
+
+        assert!(matches!(err, Err(ContentError::TooLarge { limit: 4, .. })));
+    }
+
+    #[test]
+    fn lists_markdown_files() {
+        let files = markdown_files(Path::new("test_dir"));
+
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from("README.md"),
+                PathBuf::from("a.md"),
+                PathBuf::from("b.md"),
+                PathBuf::from("sub_dir/a.md"),
+                PathBuf::from("sub_dir/b.md"),
+            ]
+        );
+    }
 }
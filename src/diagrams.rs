@@ -0,0 +1,229 @@
+use async_trait::async_trait;
+#[cfg(feature = "server")]
+use flate2::{write::DeflateEncoder, Compression};
+use log::debug;
+#[cfg(feature = "server")]
+use log::warn;
+#[cfg(feature = "server")]
+use std::collections::HashMap;
+#[cfg(feature = "server")]
+use std::io::Write;
+#[cfg(feature = "server")]
+use std::sync::{Mutex, MutexGuard};
+
+#[cfg(feature = "server")]
+use crate::content_finder::ContentHash;
+use crate::external_converter::ExternalConverter;
+use crate::markdown_converter::MarkdownConverter;
+use crate::post_process::PostProcessor;
+
+/// Draws ` ```dot ` code blocks as inline SVG with GraphViz's `dot`, when it's installed. Blocks
+/// are left as source when it isn't or the graph doesn't parse.
+pub struct Graphviz {
+    command: ExternalConverter,
+}
+
+impl Graphviz {
+    /// Draws with `command`, which gets the graph on stdin and should print SVG.
+    pub fn with_command(command: ExternalConverter) -> Graphviz {
+        Graphviz { command }
+    }
+}
+
+impl Default for Graphviz {
+    fn default() -> Self {
+        Graphviz::with_command(ExternalConverter::new("dot", vec!["-Tsvg".to_string()]))
+    }
+}
+
+#[async_trait]
+impl PostProcessor for Graphviz {
+    fn handles(&self, language: &str) -> bool {
+        language == "dot" || language == "graphviz"
+    }
+
+    async fn process(&self, _language: &str, source: &str) -> Option<String> {
+        match self.command.convert_markdown(source).await {
+            Ok(svg) => Some(diagram_html(&svg)),
+            Err(err) => {
+                debug!("Could not draw a dot graph: {}", err);
+                None
+            }
+        }
+    }
+}
+
+/// Draws ` ```plantuml ` code blocks with a PlantUML server, inlining the SVG it sends back.
+/// Blocks are left as source when the server can't draw them.
+///
+/// Diagrams are remembered by the hash of their source, so each one is only fetched once.
+#[cfg(feature = "server")]
+pub struct PlantUml {
+    server: String,
+    diagrams: Mutex<HashMap<ContentHash, String>>,
+}
+
+#[cfg(feature = "server")]
+impl PlantUml {
+    /// Draws with the server at `server`, like `https://www.plantuml.com/plantuml`.
+    pub fn new(server: impl Into<String>) -> PlantUml {
+        PlantUml {
+            server: server.into().trim_end_matches('/').to_string(),
+            diagrams: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn fetch(&self, source: &str) -> Result<String, String> {
+        let url = format!("{}/svg/{}", self.server, plantuml_encode(source));
+        let mut resp = surf::get(&url).await.map_err(|err| err.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("{} answered {}", url, resp.status()));
+        }
+
+        resp.body_string().await.map_err(|err| err.to_string())
+    }
+
+    fn diagrams(&self) -> MutexGuard<'_, HashMap<ContentHash, String>> {
+        match self.diagrams.lock() {
+            Ok(diagrams) => diagrams,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+#[async_trait]
+impl PostProcessor for PlantUml {
+    fn handles(&self, language: &str) -> bool {
+        language == "plantuml" || language == "puml"
+    }
+
+    async fn process(&self, _language: &str, source: &str) -> Option<String> {
+        let hash = ContentHash::of(source.as_bytes());
+        if let Some(diagram) = self.diagrams().get(&hash) {
+            return Some(diagram.clone());
+        }
+
+        match self.fetch(source).await {
+            Ok(svg) => {
+                let diagram = diagram_html(&svg);
+                self.diagrams().insert(hash, diagram.clone());
+                Some(diagram)
+            }
+            Err(err) => {
+                warn!("Could not draw a PlantUML diagram: {}", err);
+                None
+            }
+        }
+    }
+}
+
+/// PlantUML's URL encoding of diagram source: deflated, then base64 with its own alphabet.
+#[cfg(feature = "server")]
+fn plantuml_encode(source: &str) -> String {
+    const ALPHABET: &[u8; 64] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz-_";
+
+    let mut deflater = DeflateEncoder::new(Vec::new(), Compression::best());
+    // Writing to a `Vec` can't fail
+    let deflated = deflater
+        .write_all(source.as_bytes())
+        .and_then(|_| deflater.finish())
+        .unwrap_or_default();
+
+    let mut encoded = String::with_capacity(deflated.len().div_ceil(3) * 4);
+    for chunk in deflated.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let sextets = [
+            b[0] >> 2,
+            ((b[0] & 0x3) << 4) | (b[1] >> 4),
+            ((b[1] & 0xf) << 2) | (b[2] >> 6),
+            b[2] & 0x3f,
+        ];
+        encoded.extend(sextets.iter().map(|&i| ALPHABET[i as usize] as char));
+    }
+
+    encoded
+}
+
+/// Wraps the `<svg>` in `svg` for showing in the page, without the XML declaration and doctype in
+/// front of it.
+fn diagram_html(svg: &str) -> String {
+    let svg = &svg[svg.find("<svg").unwrap_or(0)..];
+    format!("<div class=\"rs-readme-diagram\">{}</div>\n", svg.trim())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn drops_everything_before_the_svg() {
+        let svg = "<?xml version=\"1.0\"?>\n<!DOCTYPE svg>\n<svg><g/></svg>\n";
+
+        assert_eq!(
+            diagram_html(svg),
+            "<div class=\"rs-readme-diagram\"><svg><g/></svg></div>\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[async_std::test]
+    async fn draws_with_the_command() {
+        let graphviz = Graphviz::with_command(ExternalConverter::new(
+            "sh",
+            vec![
+                "-c".to_string(),
+                "cat > /dev/null; echo '<svg>graph</svg>'".to_string(),
+            ],
+        ));
+
+        assert_eq!(
+            graphviz.process("dot", "digraph { a -> b }").await,
+            Some("<div class=\"rs-readme-diagram\"><svg>graph</svg></div>\n".to_string())
+        );
+    }
+
+    #[async_std::test]
+    async fn leaves_the_source_without_dot() {
+        let graphviz = Graphviz::with_command(ExternalConverter::new(
+            "rs-readme-no-such-command",
+            Vec::new(),
+        ));
+
+        assert_eq!(graphviz.process("dot", "digraph { a -> b }").await, None);
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn encodes_like_plantuml() {
+        assert_eq!(
+            plantuml_encode("Bob -> Alice : hello"),
+            "SyfFKj2rKt3CoKnELR1Io4ZDoSa70000"
+        );
+    }
+
+    #[cfg(feature = "server")]
+    #[async_std::test]
+    async fn fetches_plantuml_diagrams_once() {
+        let source = "Alice -> Bob : fetched once";
+        let m = mockito::mock("GET", format!("/svg/{}", plantuml_encode(source)).as_str())
+            .with_body("<?xml version=\"1.0\"?><svg>sequence</svg>")
+            .expect(1)
+            .create();
+        let plantuml = PlantUml::new(format!("{}/", mockito::server_url()));
+
+        let first = plantuml.process("plantuml", source).await;
+        let second = plantuml.process("plantuml", source).await;
+
+        m.assert();
+        assert_eq!(
+            first,
+            Some("<div class=\"rs-readme-diagram\"><svg>sequence</svg></div>\n".to_string())
+        );
+        assert_eq!(second, first);
+    }
+}
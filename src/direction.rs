@@ -0,0 +1,125 @@
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+use crate::frontmatter::Frontmatter;
+
+/// The `dir` and `lang` attributes a document's article is given, so Arabic and Hebrew documents
+/// read right to left. `None`s leave the attributes off, for the page's own.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct TextDirection {
+    pub(crate) dir: Option<&'static str>,
+    pub(crate) lang: Option<String>,
+}
+
+/// The [`TextDirection`] of the markdown `md`: the `dir` and `lang` in its frontmatter when it has
+/// them, or else `rtl` when most of the letters in its text, code aside, are from a
+/// right-to-left script.
+pub(crate) fn text_direction(md: &str) -> TextDirection {
+    let (start, dir, lang) = match Frontmatter::split(md) {
+        Some((frontmatter, rest)) => {
+            let field = |name: &str| {
+                frontmatter
+                    .fields
+                    .iter()
+                    .find(|(field, _)| field == name)
+                    .and_then(|(_, value)| value.as_str())
+                    .map(|value| value.trim().to_lowercase())
+            };
+            let dir = field("dir").and_then(|dir| match dir.as_str() {
+                "rtl" => Some("rtl"),
+                "ltr" => Some("ltr"),
+                "auto" => Some("auto"),
+                _ => None,
+            });
+            (md.len() - rest.len(), dir, field("lang"))
+        }
+        None => (0, None, None),
+    };
+
+    TextDirection {
+        dir: dir.or_else(|| mostly_right_to_left(&md[start..]).then_some("rtl")),
+        lang,
+    }
+}
+
+/// Whether more of the letters in the text of `md` are written right to left than left to right.
+fn mostly_right_to_left(md: &str) -> bool {
+    let (mut rtl, mut ltr) = (0usize, 0usize);
+    let mut in_code = false;
+    for event in Parser::new_ext(md, Options::all()) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code = true,
+            Event::End(Tag::CodeBlock(_)) => in_code = false,
+            Event::Text(text) if !in_code => {
+                for c in text.chars().filter(|c| c.is_alphabetic()) {
+                    if is_right_to_left(c) {
+                        rtl += 1;
+                    } else {
+                        ltr += 1;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    rtl > ltr
+}
+
+/// Whether `c` is from a right-to-left script: Hebrew, Arabic, Syriac, Thaana, N'Ko and their
+/// presentation forms, or one of the historic ones.
+fn is_right_to_left(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0590}'..='\u{08FF}'
+            | '\u{FB1D}'..='\u{FDFF}'
+            | '\u{FE70}'..='\u{FEFF}'
+            | '\u{10800}'..='\u{10FFF}'
+            | '\u{1E800}'..='\u{1EFFF}'
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn detects_right_to_left_documents() {
+        assert_eq!(
+            text_direction("# שלום עולם\n\nזהו קובץ README עבור הפרויקט.\n"),
+            TextDirection {
+                dir: Some("rtl"),
+                lang: None
+            }
+        );
+        assert_eq!(
+            text_direction("# مرحبا\n\n```rust\nfn main() { println!(\"hello world\"); }\n```\n"),
+            TextDirection {
+                dir: Some("rtl"),
+                lang: None
+            }
+        );
+        assert_eq!(
+            text_direction("# Hello\n\nA greeting: שלום\n"),
+            TextDirection::default()
+        );
+    }
+
+    #[test]
+    fn honors_the_frontmatter() {
+        assert_eq!(
+            text_direction("---\ndir: RTL\nlang: fa\n---\n# Project\n"),
+            TextDirection {
+                dir: Some("rtl"),
+                lang: Some("fa".to_string())
+            }
+        );
+        assert_eq!(
+            text_direction("---\ndir: ltr\n---\n# שלום עולם\n"),
+            TextDirection {
+                dir: Some("ltr"),
+                lang: None
+            }
+        );
+    }
+}
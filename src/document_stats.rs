@@ -0,0 +1,210 @@
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
+
+use crate::fences::FenceInfo;
+use crate::frontmatter::Frontmatter;
+
+/// How many words a minute [`DocumentStats::reading_minutes`] assumes.
+pub const WORDS_PER_MINUTE: usize = 200;
+
+/// Counts of what's in a markdown document, see [`document_stats`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DocumentStats {
+    /// Words of prose, code blocks and image descriptions left out.
+    pub words: usize,
+    pub headings: usize,
+    pub links: usize,
+    pub images: usize,
+    pub code_blocks: usize,
+
+    /// The languages code blocks are marked as, each once in the order they first appear.
+    pub code_languages: Vec<String>,
+
+    /// How long reading the prose takes at [`WORDS_PER_MINUTE`], rounded up.
+    pub reading_minutes: usize,
+
+    /// Task list items, `- [ ]` and `- [x]`, and how many of them are checked.
+    pub tasks: usize,
+    pub tasks_done: usize,
+}
+
+/// Counts the words, headings, links, images, code blocks and tasks in `md`, for docs
+/// dashboards and CI checks. Frontmatter isn't counted.
+pub fn document_stats(md: &str) -> DocumentStats {
+    let body = Frontmatter::split(md).map_or(md, |(_, rest)| rest);
+
+    let mut stats = DocumentStats {
+        words: 0,
+        headings: 0,
+        links: 0,
+        images: 0,
+        code_blocks: 0,
+        code_languages: Vec::new(),
+        reading_minutes: 0,
+        tasks: 0,
+        tasks_done: 0,
+    };
+    let mut prose = String::new();
+    // Inside a code block or an image, where text isn't prose
+    let mut skipping = 0;
+    for event in Parser::new_ext(body, Options::all()) {
+        match event {
+            Event::Start(Tag::Heading(_)) => stats.headings += 1,
+            Event::Start(Tag::Link(..)) => stats.links += 1,
+            Event::Start(Tag::Image(..)) => {
+                stats.images += 1;
+                skipping += 1;
+            }
+            Event::End(Tag::Image(..)) => skipping -= 1,
+            Event::Start(Tag::CodeBlock(kind)) => {
+                stats.code_blocks += 1;
+                skipping += 1;
+                if let CodeBlockKind::Fenced(info) = kind {
+                    // The info string can have more than the language in it, like `rust,ignore`
+                    let language = FenceInfo::parse(&info).language;
+                    let language = language.as_str();
+                    if !language.is_empty()
+                        && !stats.code_languages.iter().any(|seen| seen == language)
+                    {
+                        stats.code_languages.push(language.to_string());
+                    }
+                }
+            }
+            Event::End(Tag::CodeBlock(_)) => skipping -= 1,
+            Event::TaskListMarker(checked) => {
+                stats.tasks += 1;
+                stats.tasks_done += usize::from(checked);
+            }
+            // Text is split at formatting and links, so words are only counted once it's joined
+            Event::Text(text) | Event::Code(text) if skipping == 0 => prose.push_str(&text),
+            Event::SoftBreak
+            | Event::HardBreak
+            | Event::End(Tag::Paragraph)
+            | Event::End(Tag::Heading(_))
+            | Event::End(Tag::Item)
+            | Event::End(Tag::TableCell) => prose.push(' '),
+            _ => {}
+        }
+    }
+    stats.words = prose.split_whitespace().count();
+    stats.reading_minutes = stats.words.div_ceil(WORDS_PER_MINUTE);
+
+    stats
+}
+
+/// How many of the task list items in `md` are checked, and how many there are. `None` when it
+/// has none.
+pub(crate) fn task_progress(md: &str) -> Option<(usize, usize)> {
+    // Parsing big documents is slow, and without a marker there aren't any tasks
+    if !["[ ]", "[x]", "[X]"]
+        .iter()
+        .any(|marker| md.contains(marker))
+    {
+        return None;
+    }
+
+    let (mut done, mut total) = (0, 0);
+    for event in Parser::new_ext(md, Options::all()) {
+        if let Event::TaskListMarker(checked) = event {
+            total += 1;
+            done += usize::from(checked);
+        }
+    }
+
+    (total > 0).then_some((done, total))
+}
+
+/// A bar under the document's header showing how many of its tasks are done, like
+/// `7/12 tasks complete`. Nothing when `md` has no task list.
+pub(crate) fn task_progress_html(md: &str) -> String {
+    match task_progress(md) {
+        Some((done, total)) => format!(
+            "{}",
+            html! {
+                div(class="rs-readme-tasks") {
+                    progress(max=total.to_string(), value=done.to_string()) : format!("{}/{}", done, total);
+                    : format!(" {}/{} tasks complete", done, total);
+                }
+            }
+        ),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn counts_what_is_in_a_document() {
+        let md = "\
+---
+title: Not counted
+---
+# Install
+
+Run `cargo install rs-readme`, see [the docs](./docs.md) and [crates.io](https://crates.io).
+
+![A very long description of the logo](./logo.png)
+
+```rust,ignore
+fn main() { println!(\"not prose\"); }
+```
+
+```sh
+cargo install rs-readme
+```
+
+```rust
+fn more() {}
+```
+
+    indented code
+";
+
+        assert_eq!(
+            document_stats(md),
+            DocumentStats {
+                words: 10,
+                headings: 1,
+                links: 2,
+                images: 1,
+                code_blocks: 4,
+                code_languages: vec!["rust".to_string(), "sh".to_string()],
+                reading_minutes: 1,
+                tasks: 0,
+                tasks_done: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn counts_tasks() {
+        let md = "- [x] Write it\n- [ ] Test it\n- [X] Ship it\n- Not a task, [ ] in text\n";
+
+        let stats = document_stats(md);
+
+        assert_eq!((stats.tasks_done, stats.tasks), (2, 3));
+        assert_eq!(task_progress(md), Some((2, 3)));
+        assert_eq!(task_progress("- [link](./a.md)\n"), None);
+        assert_eq!(task_progress("`[ ]`\n"), None);
+    }
+
+    #[test]
+    fn shows_task_progress() {
+        assert_eq!(
+            task_progress_html("- [x] Write it\n- [ ] Test it\n"),
+            "<div class=\"rs-readme-tasks\"><progress max=\"2\" value=\"1\">1/2</progress> \
+             1/2 tasks complete</div>"
+        );
+        assert_eq!(task_progress_html("# No tasks\n"), "");
+    }
+
+    #[test]
+    fn reading_time_rounds_up() {
+        let md = "word ".repeat(WORDS_PER_MINUTE + 1);
+
+        assert_eq!(document_stats(&md).reading_minutes, 2);
+        assert_eq!(document_stats("").reading_minutes, 0);
+    }
+}
@@ -0,0 +1,166 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::content_finder::{normalize_text, ContentHash};
+use crate::error::Error;
+use crate::links::normalize;
+
+/// What became of a document [`save_document`] was asked to save.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Saved {
+    /// It was saved, and it's this version now.
+    As(ContentHash),
+
+    /// It was left alone, it had changed from the version the editor started from to this one.
+    Changed(ContentHash),
+}
+
+/// Saves `contents` over the document `resource` (`./docs/intro.md`) under `root`, for the
+/// editor at `/edit/<path>`, see `--edit`. The document is only saved if it's still the version
+/// `base`, as hashed from what's on disk rather than what was last read.
+///
+/// Only files that are already there can be saved, and only when they're under `root` once
+/// symlinks are followed, and only UTF-8 files, the others would change encoding. The new
+/// contents are written next to the file and moved over it, so the watcher and other readers
+/// never see half of them, and the file keeps its permissions, byte order mark and Windows line
+/// endings.
+pub(crate) fn save_document(
+    root: &Path,
+    resource: &str,
+    base: &str,
+    contents: &str,
+) -> Result<Saved, Error> {
+    let invalid = || Error::InvalidPath(resource.to_string());
+    if normalize(Path::new(resource)).is_none() {
+        return Err(invalid());
+    }
+    let root = fs::canonicalize(root).map_err(|err| io_error(resource, err))?;
+    let path = fs::canonicalize(root.join(resource)).map_err(|err| io_error(resource, err))?;
+    if !path.starts_with(&root) {
+        return Err(invalid());
+    }
+    let file_name = match path.file_name() {
+        Some(file_name) if path.is_file() => file_name.to_string_lossy(),
+        _ => return Err(invalid()),
+    };
+
+    let old = fs::read(&path).map_err(|err| io_error(resource, err))?;
+    let old = String::from_utf8(old).map_err(|_| not_utf8(resource))?;
+    let current = hash(old.clone());
+    if base != current.to_string() {
+        return Ok(Saved::Changed(current));
+    }
+
+    let saved = hash(contents.to_string());
+    let mut contents = if old.contains("\r\n") {
+        contents.replace("\r\n", "\n").replace('\n', "\r\n")
+    } else {
+        contents.to_string()
+    };
+    if old.starts_with('\u{feff}') && !contents.starts_with('\u{feff}') {
+        contents.insert(0, '\u{feff}');
+    }
+    let partial = path.with_file_name(format!(".{}.rs-readme-edit", file_name));
+    write_over(&partial, &path, contents.as_bytes()).map_err(|err| {
+        let _ = fs::remove_file(&partial);
+        io_error(resource, err)
+    })?;
+
+    Ok(Saved::As(saved))
+}
+
+/// The hash the content finders give `contents`.
+fn hash(contents: String) -> ContentHash {
+    ContentHash::of(normalize_text(contents).as_bytes())
+}
+
+/// Writes `contents` to `partial` and moves it over `path`, with `path`'s permissions.
+fn write_over(partial: &Path, path: &Path, contents: &[u8]) -> io::Result<()> {
+    let permissions = fs::metadata(path)?.permissions();
+    fs::write(partial, contents)?;
+    fs::set_permissions(partial, permissions)?;
+    fs::rename(partial, path)
+}
+
+/// The error for a document in a legacy encoding, which the editor doesn't write.
+pub(crate) fn not_utf8(resource: &str) -> Error {
+    Error::Forbidden {
+        resource: resource.to_string(),
+        reason: "it isn't UTF-8, and saving it would change its encoding".to_string(),
+    }
+}
+
+fn io_error(resource: &str, err: io::Error) -> Error {
+    match err.kind() {
+        io::ErrorKind::NotFound => Error::NotFound(resource.to_string()),
+        io::ErrorKind::PermissionDenied => Error::Forbidden {
+            resource: resource.to_string(),
+            reason: err.to_string(),
+        },
+        kind => Error::Io {
+            resource: resource.to_string(),
+            kind,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn saves_documents_under_the_root() {
+        let root = std::env::temp_dir().join(format!("rs-readme-save-{}", std::process::id()));
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("docs/a.md"), "# Old\n").unwrap();
+        fs::write(root.join("b.md"), "# Old\r\n").unwrap();
+
+        let old = ContentHash::of(b"# Old\n").to_string();
+        let saved = save_document(&root, "./docs/a.md", &old, "# New\n\nText\n").unwrap();
+        save_document(&root, "./b.md", &old, "# New\n").unwrap();
+        let stale = save_document(&root, "./b.md", &old, "# Stale\n").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(root.join("docs/a.md")).unwrap(),
+            "# New\n\nText\n"
+        );
+        assert_eq!(saved, Saved::As(ContentHash::of(b"# New\n\nText\n")));
+        assert_eq!(fs::read_to_string(root.join("b.md")).unwrap(), "# New\r\n");
+        assert_eq!(stale, Saved::Changed(ContentHash::of(b"# New\n")));
+        let new = ContentHash::of(b"# New\n").to_string();
+        save_document(&root, "./b.md", &new, "# Posted\r\n\r\nWith CRLF\r\n").unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("b.md")).unwrap(),
+            "# Posted\r\n\r\nWith CRLF\r\n"
+        );
+        assert!(!root.join("docs/.a.md.rs-readme-edit").exists());
+        fs::write(root.join("bom.md"), "\u{feff}# Old\n").unwrap();
+        save_document(&root, "./bom.md", &old, "# New\n").unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("bom.md")).unwrap(),
+            "\u{feff}# New\n"
+        );
+        fs::write(root.join("latin1.md"), b"# Caf\xe9\n").unwrap();
+        assert_eq!(
+            save_document(&root, "./latin1.md", &old, "# New\n"),
+            Err(not_utf8("./latin1.md"))
+        );
+        assert_eq!(fs::read(root.join("latin1.md")).unwrap(), b"# Caf\xe9\n");
+        assert_eq!(
+            save_document(&root, "./missing.md", &old, "# New\n"),
+            Err(Error::NotFound("./missing.md".to_string()))
+        );
+        assert_eq!(
+            save_document(&root, "./docs", &old, "# New\n"),
+            Err(Error::InvalidPath("./docs".to_string()))
+        );
+        assert_eq!(
+            save_document(&root.join("docs"), "../b.md", &old, "# Outside\n"),
+            Err(Error::InvalidPath("../b.md".to_string()))
+        );
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,236 @@
+use std::io;
+
+#[cfg(feature = "server")]
+use http_types::StatusCode;
+use thiserror::Error;
+
+use crate::content_finder::ContentError;
+use crate::markdown_converter::MarkdownError;
+
+/// Everything that can go wrong while serving or rendering a document.
+///
+/// The module errors ([`ContentError`], [`MarkdownError`]) convert into this, so callers of the
+/// library and the server's error handling only have one type to look at.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum Error {
+    /// Reading something failed for a reason other than it not existing.
+    #[error("Could not read {resource}: {kind:?}")]
+    Io {
+        resource: String,
+        kind: io::ErrorKind,
+    },
+
+    /// The requested resource exists but can't be read, `reason` is what the OS said, like
+    /// `Permission denied (os error 13)`.
+    #[error("Not allowed to read {}: {reason}", .resource.replacen("./", "", 1))]
+    Forbidden { resource: String, reason: String },
+
+    /// The requested resource doesn't exist.
+    #[error("Could not find {}", .0.replacen("./", "", 1))]
+    NotFound(String),
+
+    /// The requested resource isn't markdown.
+    #[error("The file was not markdown")]
+    NotMarkdown,
+
+    /// The converter couldn't be reached or failed.
+    #[error("Could not convert\n{0}")]
+    ConverterUnavailable(String),
+
+    /// The converter is refusing requests for now, `reset` is when it'll take them again as a
+    /// unix timestamp.
+    #[error("The converter's rate limit was reached")]
+    RateLimited { reset: Option<u64> },
+
+    /// The requested path isn't one we'll serve, like one climbing out of the root folder.
+    #[error("Invalid path {0}")]
+    InvalidPath(String),
+
+    /// The requested file is bigger than the configured limit, sizes are in bytes.
+    #[error("{} is {size} bytes, more than the {limit} byte limit", .resource.replacen("./", "", 1))]
+    TooLarge {
+        resource: String,
+        size: u64,
+        limit: u64,
+    },
+
+    /// The requested file is binary, or text that can't be decoded.
+    #[error("{} isn't a text file", .0.replacen("./", "", 1))]
+    NotText(String),
+
+    /// Too much is already going on, the client should try again in `retry_after` seconds.
+    #[error("rs-readme is busy, try again in {retry_after} seconds")]
+    Busy { retry_after: u64 },
+}
+
+#[cfg(feature = "server")]
+impl Error {
+    /// The HTTP status that best describes the error.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::Io { kind, .. } => match kind {
+                io::ErrorKind::NotFound | io::ErrorKind::IsADirectory => StatusCode::NotFound,
+                io::ErrorKind::PermissionDenied => StatusCode::Forbidden,
+                _ => StatusCode::InternalServerError,
+            },
+            Error::Forbidden { .. } => StatusCode::Forbidden,
+            Error::NotFound(_) => StatusCode::NotFound,
+            Error::NotMarkdown => StatusCode::BadRequest,
+            Error::ConverterUnavailable(_) => StatusCode::BadGateway,
+            Error::RateLimited { .. } => StatusCode::TooManyRequests,
+            Error::InvalidPath(_) => StatusCode::BadRequest,
+            Error::TooLarge { .. } => StatusCode::PayloadTooLarge,
+            Error::NotText(_) => StatusCode::UnsupportedMediaType,
+            Error::Busy { .. } => StatusCode::ServiceUnavailable,
+        }
+    }
+
+    /// Whether it's the converter that failed rather than the document, so converting it again
+    /// later might work. The handlers give these their own page.
+    pub(crate) fn converter_failed(&self) -> bool {
+        matches!(
+            self,
+            Error::ConverterUnavailable(_) | Error::RateLimited { .. }
+        )
+    }
+
+    /// A short name for the kind of error, the `code` of JSON error responses.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Io { kind, .. } => match kind {
+                io::ErrorKind::PermissionDenied => "forbidden",
+                io::ErrorKind::IsADirectory => "is-directory",
+                _ => "io",
+            },
+            Error::Forbidden { .. } => "forbidden",
+            Error::NotFound(_) => "not-found",
+            Error::NotMarkdown => "not-markdown",
+            Error::ConverterUnavailable(_) => "converter-unavailable",
+            Error::RateLimited { .. } => "rate-limited",
+            Error::InvalidPath(_) => "invalid-path",
+            Error::TooLarge { .. } => "too-large",
+            Error::NotText(_) => "not-text",
+            Error::Busy { .. } => "busy",
+        }
+    }
+}
+
+impl From<ContentError> for Error {
+    fn from(err: ContentError) -> Self {
+        match err {
+            ContentError::CouldNotFetch(resource) => Error::NotFound(resource),
+            ContentError::Forbidden { resource, reason } => Error::Forbidden { resource, reason },
+            ContentError::IsDirectory(resource) => Error::Io {
+                resource,
+                kind: io::ErrorKind::IsADirectory,
+            },
+            ContentError::Io { resource, kind } => Error::Io { resource, kind },
+            ContentError::NotMarkdown => Error::NotMarkdown,
+            ContentError::InvalidPath(resource) => Error::InvalidPath(resource),
+            ContentError::TooLarge {
+                resource,
+                size,
+                limit,
+            } => Error::TooLarge {
+                resource,
+                size,
+                limit,
+            },
+            ContentError::NotText(resource) => Error::NotText(resource),
+        }
+    }
+}
+
+impl From<MarkdownError> for Error {
+    fn from(err: MarkdownError) -> Self {
+        match err {
+            MarkdownError::ConverterUnavailable(reason) => Error::ConverterUnavailable(reason),
+            MarkdownError::RateLimited { reset } => Error::RateLimited { reset },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn module_errors_convert_with_statuses() {
+        let cases = vec![
+            (
+                Error::from(ContentError::CouldNotFetch("./a.md".to_string())),
+                StatusCode::NotFound,
+            ),
+            (
+                Error::from(ContentError::Forbidden {
+                    resource: "./a.md".to_string(),
+                    reason: "Permission denied (os error 13)".to_string(),
+                }),
+                StatusCode::Forbidden,
+            ),
+            (
+                Error::from(ContentError::IsDirectory("./docs.md".to_string())),
+                StatusCode::NotFound,
+            ),
+            (
+                Error::from(ContentError::Io {
+                    resource: "./a.md".to_string(),
+                    kind: io::ErrorKind::Interrupted,
+                }),
+                StatusCode::InternalServerError,
+            ),
+            (
+                Error::from(ContentError::NotMarkdown),
+                StatusCode::BadRequest,
+            ),
+            (
+                Error::from(ContentError::InvalidPath("../a.md".to_string())),
+                StatusCode::BadRequest,
+            ),
+            (
+                Error::from(ContentError::TooLarge {
+                    resource: "./a.md".to_string(),
+                    size: 2,
+                    limit: 1,
+                }),
+                StatusCode::PayloadTooLarge,
+            ),
+            (
+                Error::from(ContentError::NotText("./a.md".to_string())),
+                StatusCode::UnsupportedMediaType,
+            ),
+            (
+                Error::from(MarkdownError::ConverterUnavailable("down".to_string())),
+                StatusCode::BadGateway,
+            ),
+            (
+                Error::from(MarkdownError::RateLimited { reset: None }),
+                StatusCode::TooManyRequests,
+            ),
+            (
+                Error::Io {
+                    resource: "./a.md".to_string(),
+                    kind: io::ErrorKind::PermissionDenied,
+                },
+                StatusCode::Forbidden,
+            ),
+            (
+                Error::Busy { retry_after: 5 },
+                StatusCode::ServiceUnavailable,
+            ),
+        ];
+
+        for (err, status) in cases {
+            assert_eq!(err.status(), status, "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn not_found_message_drops_leading_dot_slash() {
+        assert_eq!(
+            Error::NotFound("./a.md".to_string()).to_string(),
+            "Could not find a.md"
+        );
+    }
+}
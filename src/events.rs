@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use log::error;
+
+/// Something that happened that editor tooling supervising rs-readme might care about.
+///
+/// Serialized as one JSON object per line with an `event` field naming the variant, e.g.
+/// `{"event":"file-rendered","path":"./README.md","hash":"..."}`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    /// The server is accepting connections at `url`.
+    ServerStarted { url: String, port: Option<u16> },
+
+    /// A page was rendered for a browser.
+    FileRendered { path: String, hash: String },
+
+    /// A file's contents differ from the last time a live-reload client asked for it.
+    FileChanged { path: String, hash: String },
+
+    /// The converter failed for a file.
+    ConversionError { path: String, message: String },
+}
+
+/// Writes [`Event`]s as newline-delimited JSON, or does nothing when disabled.
+pub struct Events {
+    out: Option<Mutex<Box<dyn Write + Send>>>,
+    last_hashes: Mutex<HashMap<String, String>>,
+}
+
+impl Events {
+    /// Events that go nowhere.
+    pub fn disabled() -> Events {
+        Events {
+            out: None,
+            last_hashes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Events written to stdout, for `--events-json`.
+    pub fn stdout() -> Events {
+        Events::to_writer(io::stdout())
+    }
+
+    /// Events written to anything.
+    pub fn to_writer(out: impl Write + Send + 'static) -> Events {
+        Events {
+            out: Some(Mutex::new(Box::new(out))),
+            last_hashes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.out.is_some()
+    }
+
+    /// Writes `event` as a single line and flushes it so readers see it right away.
+    pub fn emit(&self, event: Event) {
+        let out = match &self.out {
+            Some(out) => out,
+            None => return,
+        };
+
+        let line = match serde_json::to_string(&event) {
+            Ok(line) => line,
+            Err(err) => {
+                error!("Could not serialize event {:?}: {}", event, err);
+                return;
+            }
+        };
+
+        let mut out = match out.lock() {
+            Ok(out) => out,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Err(err) = writeln!(out, "{}", line).and_then(|_| out.flush()) {
+            error!("Could not write event: {}", err);
+        }
+    }
+
+    /// Remembers `hash` for `path`, emitting [`Event::FileChanged`] if it's different from the
+    /// last one seen.
+    pub fn observe(&self, path: &str, hash: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let previous = match self.last_hashes.lock() {
+            Ok(mut hashes) => hashes.insert(path.to_string(), hash.to_string()),
+            Err(poisoned) => poisoned
+                .into_inner()
+                .insert(path.to_string(), hash.to_string()),
+        };
+
+        if previous.is_some_and(|previous| previous != hash) {
+            self.emit(Event::FileChanged {
+                path: path.to_string(),
+                hash: hash.to_string(),
+            });
+        }
+    }
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Events::disabled()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    /// A writer we can still read from after handing it to [`Events`].
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn writes_one_json_object_per_line() {
+        let buf = SharedBuf::default();
+        let events = Events::to_writer(buf.clone());
+
+        events.emit(Event::ServerStarted {
+            url: "http://127.0.0.1:4000".to_string(),
+            port: Some(4000),
+        });
+        events.emit(Event::ConversionError {
+            path: "./a.md".to_string(),
+            message: "oops".to_string(),
+        });
+
+        assert_eq!(
+            buf.contents(),
+            "{\"event\":\"server-started\",\"url\":\"http://127.0.0.1:4000\",\"port\":4000}\n\
+             {\"event\":\"conversion-error\",\"path\":\"./a.md\",\"message\":\"oops\"}\n"
+        );
+    }
+
+    #[test]
+    fn only_changed_hashes_emit_file_changed() {
+        let buf = SharedBuf::default();
+        let events = Events::to_writer(buf.clone());
+
+        events.observe("./a.md", "1");
+        events.observe("./a.md", "1");
+        events.observe("./a.md", "2");
+
+        assert_eq!(
+            buf.contents(),
+            "{\"event\":\"file-changed\",\"path\":\"./a.md\",\"hash\":\"2\"}\n"
+        );
+    }
+}
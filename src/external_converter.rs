@@ -0,0 +1,148 @@
+use async_trait::async_trait;
+use futures::channel::oneshot;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::markdown_converter::{MarkdownConverter, MarkdownError};
+
+/// Converts documents by piping them through an external command, like `rst2html` for
+/// reStructuredText.
+///
+/// The command gets the document on stdin and should print HTML on stdout. If it prints a whole
+/// HTML document only what's inside `<body>` is kept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalConverter {
+    program: String,
+    args: Vec<String>,
+}
+
+impl ExternalConverter {
+    pub fn new(program: impl Into<String>, args: Vec<String>) -> ExternalConverter {
+        ExternalConverter {
+            program: program.into(),
+            args,
+        }
+    }
+
+    /// Splits a command line like `rst2html --no-doc-title` on whitespace, `None` if it's
+    /// empty.
+    pub fn from_command_line(command: &str) -> Option<ExternalConverter> {
+        let mut words = command.split_whitespace().map(str::to_string);
+        let program = words.next()?;
+
+        Some(ExternalConverter::new(program, words.collect()))
+    }
+
+    fn run(&self, input: &str) -> Result<String, MarkdownError> {
+        let unavailable =
+            |err| MarkdownError::ConverterUnavailable(format!("{}: {}", self.program, err));
+
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(unavailable)?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(input.as_bytes()).map_err(unavailable)?;
+        }
+
+        let output = child.wait_with_output().map_err(unavailable)?;
+        if !output.status.success() {
+            return Err(MarkdownError::ConverterUnavailable(format!(
+                "{} exited with {}\n{}",
+                self.program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        Ok(body_of(&String::from_utf8_lossy(&output.stdout)).to_string())
+    }
+}
+
+#[async_trait]
+impl MarkdownConverter for ExternalConverter {
+    fn name(&self) -> &'static str {
+        "external"
+    }
+
+    /// Runs the command on its own thread so a slow one doesn't hold up the executor.
+    async fn convert_markdown(&self, md: &str) -> Result<String, MarkdownError> {
+        let (tx, rx) = oneshot::channel();
+        let converter = self.clone();
+        let input = md.to_string();
+        std::thread::spawn(move || {
+            let _ = tx.send(converter.run(&input));
+        });
+
+        rx.await.unwrap_or_else(|_| {
+            Err(MarkdownError::ConverterUnavailable(format!(
+                "{} stopped unexpectedly",
+                self.program
+            )))
+        })
+    }
+}
+
+/// What's inside the `<body>` of a whole HTML document, or `html` as it is if it's a fragment.
+fn body_of(html: &str) -> &str {
+    let start = html
+        .find("<body")
+        .and_then(|body| html[body..].find('>').map(|end| body + end + 1));
+    let end = html.rfind("</body>");
+
+    match (start, end) {
+        (Some(start), Some(end)) if start <= end => html[start..end].trim(),
+        _ => html,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_only_the_body_of_whole_documents() {
+        let html = "<html><head><title>x</title></head>\n<body class=\"doc\">\n<h1>Hi</h1>\n</body></html>";
+
+        assert_eq!(body_of(html), "<h1>Hi</h1>");
+        assert_eq!(body_of("<p>fragment</p>"), "<p>fragment</p>");
+    }
+
+    #[test]
+    fn splits_command_lines() {
+        assert_eq!(
+            ExternalConverter::from_command_line("rst2html  --no-doc-title"),
+            Some(ExternalConverter::new(
+                "rst2html",
+                vec!["--no-doc-title".to_string()]
+            ))
+        );
+        assert_eq!(ExternalConverter::from_command_line("  "), None);
+    }
+
+    #[cfg(unix)]
+    #[async_std::test]
+    async fn pipes_documents_through_the_command() {
+        let converter = ExternalConverter::new("cat", Vec::new());
+
+        let html = converter.convert_markdown("<p>as is</p>").await;
+
+        assert_eq!(html, Ok("<p>as is</p>".to_string()));
+    }
+
+    #[async_std::test]
+    async fn missing_commands_are_unavailable() {
+        let converter = ExternalConverter::new("rs-readme-no-such-command", Vec::new());
+
+        let html = converter.convert_markdown("text").await;
+
+        assert!(matches!(
+            html,
+            Err(MarkdownError::ConverterUnavailable(reason)) if reason.starts_with("rs-readme-no-such-command")
+        ));
+    }
+}
@@ -0,0 +1,112 @@
+use std::ops::RangeInclusive;
+
+/// What a fenced code block's info string asks for, like ```` ```rust {3-5} title="src/main.rs" ````.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct FenceInfo {
+    /// The first word, without anything after a comma, like the `rust` of `rust,ignore`. Empty
+    /// when there isn't one.
+    pub(crate) language: String,
+
+    /// The lines to mark, counted from 1, from a `{1,3-5}`.
+    pub(crate) marked: Vec<RangeInclusive<usize>>,
+
+    /// The caption shown above the block, from a `title="..."`.
+    pub(crate) title: Option<String>,
+}
+
+impl FenceInfo {
+    /// Reads a fenced code block's info string. Words it doesn't know are skipped.
+    pub(crate) fn parse(info: &str) -> FenceInfo {
+        let mut fence = FenceInfo::default();
+        for (at, word) in info_words(info).into_iter().enumerate() {
+            if let Some(lines) = word.strip_prefix('{').and_then(|w| w.strip_suffix('}')) {
+                fence.marked.extend(lines.split(',').filter_map(line_range));
+            } else if let Some(title) = word.strip_prefix("title=") {
+                let title = title.trim_matches(|c| c == '"' || c == '\'');
+                fence.title = Some(title.to_string()).filter(|title| !title.is_empty());
+            } else if at == 0 {
+                fence.language = word.split(',').next().unwrap_or_default().to_string();
+            }
+        }
+
+        fence
+    }
+
+    /// Whether the block has marked lines or a title, so it can't be left as it is.
+    #[cfg(feature = "highlight")]
+    pub(crate) fn is_annotated(&self) -> bool {
+        !self.marked.is_empty() || self.title.is_some()
+    }
+
+    #[cfg(feature = "highlight")]
+    pub(crate) fn is_marked(&self, line: usize) -> bool {
+        self.marked.iter().any(|range| range.contains(&line))
+    }
+}
+
+/// The words of an info string, split on whitespace outside of quotes and braces.
+fn info_words(info: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut closing = None;
+    for (at, c) in info.char_indices() {
+        match (closing, c) {
+            (Some(close), c) if c == close => closing = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => closing = Some(c),
+            (None, '{') => closing = Some('}'),
+            (None, c) if c.is_whitespace() => {
+                if let Some(start) = start.take() {
+                    words.push(&info[start..at]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(at);
+    }
+    if let Some(start) = start {
+        words.push(&info[start..]);
+    }
+
+    words
+}
+
+/// A line (`3`) or lines (`3-5`) to mark, `None` if it's neither.
+fn line_range(range: &str) -> Option<RangeInclusive<usize>> {
+    let (from, to) = range.split_once('-').unwrap_or((range, range));
+    Some(from.trim().parse().ok()?..=to.trim().parse().ok()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn reads_fence_annotations() {
+        assert_eq!(
+            FenceInfo::parse("rust,ignore {1, 3-5} title=\"src/my main.rs\" linenos"),
+            FenceInfo {
+                language: "rust".to_string(),
+                marked: vec![1..=1, 3..=5],
+                title: Some("src/my main.rs".to_string()),
+            }
+        );
+        assert_eq!(
+            FenceInfo::parse("{2} title=notes.txt"),
+            FenceInfo {
+                language: String::new(),
+                marked: vec![2..=2],
+                title: Some("notes.txt".to_string()),
+            }
+        );
+        assert_eq!(
+            FenceInfo::parse("python {x}"),
+            FenceInfo {
+                language: "python".to_string(),
+                ..FenceInfo::default()
+            }
+        );
+    }
+}
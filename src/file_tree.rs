@@ -0,0 +1,237 @@
+use log::debug;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::UNIX_EPOCH;
+
+use crate::content_finder::markdown_files;
+use crate::outline::headings;
+
+/// A markdown file or a folder holding some, see [`file_tree`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum TreeNode {
+    Folder {
+        name: String,
+        /// Relative to the root, `/` separated.
+        path: String,
+        children: Vec<TreeNode>,
+    },
+    File {
+        name: String,
+        /// Relative to the root, `/` separated.
+        path: String,
+        /// The text of its first heading.
+        title: Option<String>,
+        /// In bytes.
+        size: u64,
+        /// When it was last changed, in seconds since the Unix epoch.
+        modified: Option<u64>,
+    },
+}
+
+/// The markdown files under `root` as a tree of folders, for the sidebar and for tools
+/// listing the documents. Hidden folders and whatever git ignores (`.gitignore`,
+/// `.git/info/exclude`, ...) are left out, as are folders without markdown in them.
+pub fn file_tree(root: &Path) -> Vec<TreeNode> {
+    let files = markdown_files(root);
+    let ignored = ignored(root, &files);
+
+    let mut tree = Vec::new();
+    for file in files.iter().filter(|file| !ignored.contains(*file)) {
+        insert(&mut tree, file, file_node(root, file));
+    }
+
+    tree
+}
+
+fn file_node(root: &Path, file: &Path) -> TreeNode {
+    let metadata = std::fs::metadata(root.join(file)).ok();
+    let title = std::fs::read(root.join(file)).ok().and_then(|bytes| {
+        headings(&String::from_utf8_lossy(&bytes))
+            .into_iter()
+            .next()
+            .map(|heading| heading.text)
+    });
+
+    TreeNode::File {
+        name: name_of(file),
+        path: slash_path(file),
+        title,
+        size: metadata.as_ref().map_or(0, |metadata| metadata.len()),
+        modified: metadata
+            .and_then(|metadata| metadata.modified().ok())
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_secs()),
+    }
+}
+
+/// Adds `node`, the file at `file`, to `tree` under the folders in its path, adding them when
+/// they aren't there yet.
+fn insert(tree: &mut Vec<TreeNode>, file: &Path, node: TreeNode) {
+    let mut children = tree;
+    let mut folder = PathBuf::new();
+    for component in file.parent().into_iter().flat_map(Path::iter) {
+        folder.push(component);
+        let name = component.to_string_lossy();
+        let position = children
+            .iter()
+            .position(|child| matches!(child, TreeNode::Folder { name: n, .. } if *n == name));
+        let position = match position {
+            Some(position) => position,
+            None => {
+                children.push(TreeNode::Folder {
+                    name: name.into_owned(),
+                    path: slash_path(&folder),
+                    children: Vec::new(),
+                });
+                children.len() - 1
+            }
+        };
+        children = match &mut children[position] {
+            TreeNode::Folder { children, .. } => children,
+            TreeNode::File { .. } => unreachable!("only folders are looked up"),
+        };
+    }
+
+    children.push(node);
+}
+
+/// Which of `files` git ignores in the repository at `root`, nothing outside one.
+fn ignored(root: &Path, files: &[PathBuf]) -> HashSet<PathBuf> {
+    let child = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["check-ignore", "--stdin", "-z"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => {
+            debug!("Could not run git: {}", err);
+            return HashSet::new();
+        }
+    };
+
+    // Written from another thread so a full stdout can't block both ends
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let paths: Vec<u8> = files
+        .iter()
+        .flat_map(|file| {
+            let mut path = slash_path(file).into_bytes();
+            path.push(0);
+            path
+        })
+        .collect();
+    let writer = std::thread::spawn(move || stdin.write_all(&paths));
+
+    let output = match child.wait_with_output() {
+        Ok(output) => output,
+        Err(err) => {
+            debug!("Could not run git: {}", err);
+            return HashSet::new();
+        }
+    };
+    let _ = writer.join();
+
+    // 1 is nothing ignored, anything else is an error like not being in a repository
+    if !output.status.success() {
+        return HashSet::new();
+    }
+
+    output
+        .stdout
+        .split(|byte| *byte == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(String::from_utf8_lossy(path).into_owned()))
+        .collect()
+}
+
+fn name_of(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn slash_path(path: &Path) -> String {
+    path.iter()
+        .map(|component| component.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn paths(tree: &[TreeNode]) -> Vec<String> {
+        tree.iter()
+            .flat_map(|node| match node {
+                TreeNode::Folder { path, children, .. } => {
+                    let mut all = vec![format!("{}/", path)];
+                    all.extend(paths(children));
+                    all
+                }
+                TreeNode::File { path, .. } => vec![path.clone()],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nests_files_in_their_folders() {
+        let root = std::env::temp_dir().join(format!("rs-readme-tree-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("docs/api")).unwrap();
+        std::fs::create_dir_all(root.join("images")).unwrap();
+        std::fs::write(root.join("README.md"), "Intro\n\n## Project\n").unwrap();
+        std::fs::write(root.join("docs/guide.md"), "# Guide\n").unwrap();
+        std::fs::write(root.join("docs/api/http.md"), "# HTTP API\n").unwrap();
+        std::fs::write(root.join("images/logo.png"), "").unwrap();
+
+        let tree = file_tree(&root);
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(
+            paths(&tree),
+            vec![
+                "README.md",
+                "docs/",
+                "docs/api/",
+                "docs/api/http.md",
+                "docs/guide.md"
+            ]
+        );
+        match &tree[0] {
+            TreeNode::File { title, size, .. } => {
+                assert_eq!(title.as_deref(), Some("Project"));
+                assert_eq!(*size, 18);
+            }
+            folder => panic!("expected README.md, got {:?}", folder),
+        }
+    }
+
+    #[test]
+    fn leaves_out_what_git_ignores() {
+        let root =
+            std::env::temp_dir().join(format!("rs-readme-tree-ignored-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("target/doc")).unwrap();
+        std::fs::write(root.join("README.md"), "# Project\n").unwrap();
+        std::fs::write(root.join("target/doc/notes.md"), "# Notes\n").unwrap();
+        std::fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        let init = Command::new("git")
+            .arg("-C")
+            .arg(&root)
+            .args(["init", "-q"])
+            .status()
+            .unwrap();
+        assert!(init.success());
+
+        let tree = file_tree(&root);
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(paths(&tree), vec!["README.md"]);
+    }
+}
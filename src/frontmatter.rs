@@ -0,0 +1,195 @@
+use serde_json::{Map, Value};
+
+/// The fields of a markdown document's frontmatter, the YAML between `---` lines at its very
+/// start that Jekyll and Hugo read, in the order they were written.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Frontmatter {
+    pub fields: Vec<(String, Value)>,
+}
+
+impl Frontmatter {
+    /// Splits the frontmatter off the start of `md`, returning it and the rest of the document.
+    ///
+    /// `None` when there isn't any, or it isn't a YAML mapping, in which case the document is
+    /// left for the converter like any other.
+    pub fn split(md: &str) -> Option<(Frontmatter, &str)> {
+        let (yaml, rest) = block(md)?;
+        let fields = parse(yaml)?;
+        Some((Frontmatter { fields }, rest))
+    }
+
+    /// Why the frontmatter at the start of `md` couldn't be [`split`](Frontmatter::split) off,
+    /// `None` when it could or there isn't any.
+    pub(crate) fn problem(md: &str) -> Option<String> {
+        let (yaml, _) = block(md)?;
+        if parse(yaml).is_some() {
+            return None;
+        }
+
+        Some(yaml_problem(yaml))
+    }
+
+    /// The fields as a JSON object.
+    pub fn to_json(&self) -> Value {
+        Value::Object(self.fields.iter().cloned().collect::<Map<_, _>>())
+    }
+
+    /// A table of the fields, their names as the heading and their values under them, like
+    /// GitHub shows above a document.
+    pub(crate) fn html(&self) -> String {
+        if self.fields.is_empty() {
+            return String::new();
+        }
+
+        format!(
+            "{}",
+            html! {
+                table(class="rs-readme-frontmatter") {
+                    thead {
+                        tr {
+                            @ for (name, _) in &self.fields {
+                                th : name;
+                            }
+                        }
+                    }
+                    tbody {
+                        tr {
+                            @ for (_, value) in &self.fields {
+                                td : display(value);
+                            }
+                        }
+                    }
+                }
+            }
+        )
+    }
+}
+
+/// The YAML between the `---` lines at the start of `md` and the rest of it.
+fn block(md: &str) -> Option<(&str, &str)> {
+    let rest = md.strip_prefix("---")?;
+    let rest = rest
+        .strip_prefix("\r\n")
+        .or_else(|| rest.strip_prefix('\n'))?;
+
+    let mut at = 0;
+    for line in rest.split_inclusive('\n') {
+        if matches!(line.trim_end(), "---" | "...") {
+            return Some((&rest[..at], &rest[at + line.len()..]));
+        }
+        at += line.len();
+    }
+
+    None
+}
+
+/// The fields of a YAML mapping, `None` for anything else.
+#[cfg(feature = "frontmatter")]
+fn parse(yaml: &str) -> Option<Vec<(String, Value)>> {
+    if yaml.trim().is_empty() {
+        return Some(Vec::new());
+    }
+
+    let mapping: serde_yaml::Mapping = serde_yaml::from_str(yaml).ok()?;
+    mapping
+        .into_iter()
+        .map(|(name, value)| {
+            let name = match name {
+                serde_yaml::Value::String(name) => name,
+                other => serde_json::to_value(other).ok()?.to_string(),
+            };
+            Some((name, serde_json::to_value(value).ok()?))
+        })
+        .collect()
+}
+
+/// Only empty frontmatter can be read without the `frontmatter` feature.
+#[cfg(not(feature = "frontmatter"))]
+fn parse(yaml: &str) -> Option<Vec<(String, Value)>> {
+    Some(Vec::new()).filter(|_| yaml.trim().is_empty())
+}
+
+/// Why `yaml` isn't a mapping [`parse`] can read.
+#[cfg(feature = "frontmatter")]
+fn yaml_problem(yaml: &str) -> String {
+    match serde_yaml::from_str::<serde_yaml::Mapping>(yaml) {
+        Err(err) => err.to_string(),
+        Ok(_) => "it has fields that can't be shown".to_string(),
+    }
+}
+
+#[cfg(not(feature = "frontmatter"))]
+fn yaml_problem(_yaml: &str) -> String {
+    "it's read with the frontmatter feature, which is off".to_string()
+}
+
+/// A field's value as text, lists joined with commas.
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items.iter().map(display).collect::<Vec<_>>().join(", "),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    const DOC: &str = "---
+title: Storage
+status: accepted
+reviewers:
+  - alice
+  - bob
+rfc: 12
+---
+# Storage
+";
+
+    #[test]
+    fn splits_frontmatter_from_the_document() {
+        let (frontmatter, rest) = Frontmatter::split(DOC).unwrap();
+
+        assert_eq!(rest, "# Storage\n");
+        assert_eq!(
+            frontmatter.fields,
+            vec![
+                ("title".to_string(), json!("Storage")),
+                ("status".to_string(), json!("accepted")),
+                ("reviewers".to_string(), json!(["alice", "bob"])),
+                ("rfc".to_string(), json!(12)),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_documents_without_frontmatter() {
+        assert_eq!(Frontmatter::split("# Title\n---\n"), None);
+        assert_eq!(Frontmatter::split("---\nnot closed\n"), None);
+        assert_eq!(Frontmatter::split("---\n- a list\n---\n"), None);
+    }
+
+    #[test]
+    fn says_why_frontmatter_is_left_in() {
+        assert_eq!(Frontmatter::problem(DOC), None);
+        assert_eq!(Frontmatter::problem("# Title\n"), None);
+        assert!(Frontmatter::problem("---\n- a list\n---\n")
+            .unwrap()
+            .contains("expected a YAML mapping"));
+    }
+
+    #[test]
+    fn renders_a_table() {
+        let (frontmatter, _) = Frontmatter::split(DOC).unwrap();
+
+        assert_eq!(
+            frontmatter.html(),
+            "<table class=\"rs-readme-frontmatter\"><thead><tr><th>title</th><th>status</th>\
+             <th>reviewers</th><th>rfc</th></tr></thead><tbody><tr><td>Storage</td>\
+             <td>accepted</td><td>alice, bob</td><td>12</td></tr></tbody></table>"
+        );
+    }
+}
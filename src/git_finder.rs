@@ -0,0 +1,166 @@
+use log::debug;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::content_finder::{
+    normalize_text, ContentError, ContentFinder, ContentHash, DocumentFormat,
+};
+use crate::links::normalize;
+
+/// Implements [`ContentFinder`] by reading files as they are at a git ref (a branch, tag or
+/// commit) instead of in the working tree, for `?ref=main` and `/git/<ref>/...`.
+///
+/// Blobs are read out of the repository's object database with `git cat-file`, so `git` has to
+/// be installed.
+pub struct GitContentFinder {
+    repo: PathBuf,
+    reference: String,
+}
+
+impl GitContentFinder {
+    /// Reads from the repository at `repo` as of `reference`.
+    pub fn new(repo: impl Into<PathBuf>, reference: impl Into<String>) -> GitContentFinder {
+        GitContentFinder {
+            repo: repo.into(),
+            reference: reference.into(),
+        }
+    }
+
+    fn blob(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+        if !is_reference(&self.reference) {
+            return Err(ContentError::InvalidPath(self.reference.clone()));
+        }
+        let path = normalize(Path::new(resource))
+            .ok_or_else(|| ContentError::InvalidPath(resource.to_string()))?;
+        // `./` makes git look the path up from the folder being served rather than from the
+        // top of the repository, when it's a folder inside one
+        let object = format!(
+            "{}:./{}",
+            self.reference,
+            path.to_string_lossy().replace('\\', "/")
+        );
+
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.repo)
+            .args(["cat-file", "blob", &object])
+            .output()
+            .map_err(|err| {
+                debug!("Could not run git: {}", err);
+                ContentError::CouldNotFetch(object.clone())
+            })?;
+        if !output.status.success() {
+            debug!(
+                "git cat-file {} failed: {}",
+                object,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return Err(ContentError::CouldNotFetch(object));
+        }
+
+        let contents = String::from_utf8(output.stdout).map_err(|_| ContentError::NotMarkdown)?;
+        let contents = normalize_text(contents);
+        let hash = ContentHash::of(contents.as_bytes());
+
+        Ok((contents, hash))
+    }
+}
+
+impl ContentFinder for GitContentFinder {
+    fn content_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+        match DocumentFormat::of(Path::new(resource)) {
+            None | Some(DocumentFormat::PlainText) => Err(ContentError::NotMarkdown),
+            Some(_) => self.blob(resource),
+        }
+    }
+
+    fn source_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+        self.blob(resource)
+    }
+}
+
+/// Whether `reference` looks like a branch, tag or commit, and not like an option `git` would
+/// take instead.
+fn is_reference(reference: &str) -> bool {
+    !reference.is_empty()
+        && !reference.starts_with('-')
+        && reference
+            .chars()
+            .all(|c| c.is_alphanumeric() || "/._-~^@{}".contains(c))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    fn git(repo: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args([
+                "-c",
+                "user.name=rs-readme",
+                "-c",
+                "user.email=rs-readme@example.com",
+            ])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn reads_files_as_they_were_committed() {
+        let repo = std::env::temp_dir().join(format!("rs-readme-git-{}", std::process::id()));
+        fs::create_dir_all(repo.join("docs")).unwrap();
+        git(&repo, &["init", "-q"]);
+        fs::write(repo.join("docs/a.md"), "# Committed").unwrap();
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "-q", "-m", "First"]);
+        git(&repo, &["tag", "v1"]);
+        fs::write(repo.join("docs/a.md"), "# Edited").unwrap();
+
+        let finder = GitContentFinder::new(&repo, "v1");
+        let found = finder.content_for("./docs/a.md");
+        let missing = finder.content_for("./docs/b.md");
+        let outside = finder.content_for("../a.md");
+        let option = GitContentFinder::new(&repo, "--help").content_for("./docs/a.md");
+        let _ = fs::remove_dir_all(&repo);
+
+        assert_eq!(found.unwrap().0, "# Committed");
+        assert!(matches!(missing, Err(ContentError::CouldNotFetch(_))));
+        assert!(matches!(outside, Err(ContentError::InvalidPath(_))));
+        assert!(matches!(option, Err(ContentError::InvalidPath(_))));
+    }
+
+    #[test]
+    fn reads_files_relative_to_a_folder_in_the_repository() {
+        let repo =
+            std::env::temp_dir().join(format!("rs-readme-git-nested-{}", std::process::id()));
+        fs::create_dir_all(repo.join("docs")).unwrap();
+        git(&repo, &["init", "-q"]);
+        fs::write(repo.join("README.md"), "# Top").unwrap();
+        fs::write(repo.join("docs/README.md"), "# Docs").unwrap();
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "-q", "-m", "First"]);
+
+        let finder = GitContentFinder::new(repo.join("docs"), "HEAD");
+        let found = finder.content_for("./README.md");
+        let _ = fs::remove_dir_all(&repo);
+
+        assert_eq!(found.unwrap().0, "# Docs");
+    }
+
+    #[test]
+    fn recognises_references() {
+        assert!(is_reference("main"));
+        assert!(is_reference("feature/git-refs"));
+        assert!(is_reference("HEAD~2"));
+        assert!(is_reference("3f2a9c1"));
+        assert!(!is_reference("--output=x"));
+        assert!(!is_reference("main:secret"));
+        assert!(!is_reference(""));
+    }
+}
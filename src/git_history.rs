@@ -0,0 +1,242 @@
+use log::debug;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::links::normalize;
+use crate::variables::date_of;
+
+/// How many commits the history dropdown lists.
+pub const HISTORY_LENGTH: usize = 15;
+
+/// A commit that touched a document, see [`history`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Commit {
+    pub hash: String,
+    pub short_hash: String,
+    pub author: String,
+
+    /// The commit date, `YYYY-MM-DD`.
+    pub date: String,
+
+    /// The first line of the message.
+    pub summary: String,
+}
+
+/// The latest `limit` commits touching the document at `path` in the repository at `root`,
+/// newest first. Empty outside a repository or for files git doesn't know about.
+pub fn history(root: &Path, path: &str, limit: usize) -> Vec<Commit> {
+    let path = match normalize(Path::new(path)) {
+        Some(path) => path,
+        None => return Vec::new(),
+    };
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["log", "--follow", "--format=%H%x1f%h%x1f%an%x1f%cs%x1f%s"])
+        .arg(format!("--max-count={}", limit))
+        .arg("--")
+        .arg(&path)
+        .output();
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            debug!(
+                "No history for {}: {}",
+                path.to_string_lossy(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return Vec::new();
+        }
+        Err(err) => {
+            debug!("Could not run git: {}", err);
+            return Vec::new();
+        }
+    };
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_commit)
+        .collect()
+}
+
+/// When a document was last changed, shown under its name, see [`last_edit`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LastEdit {
+    /// The latest commit touching it.
+    Commit(Commit),
+
+    /// The date its file was modified, `YYYY-MM-DD`, for documents git doesn't know about.
+    Modified(String),
+}
+
+/// When the document at `path` under `root` was last changed: the first of its `history`, or
+/// its file's modification date when that's empty.
+pub fn last_edit(root: &Path, path: &str, history: &[Commit]) -> Option<LastEdit> {
+    if let Some(commit) = history.first() {
+        return Some(LastEdit::Commit(commit.clone()));
+    }
+
+    let path = normalize(Path::new(path))?;
+    let modified = fs::metadata(root.join(path)).ok()?.modified().ok()?;
+    Some(LastEdit::Modified(date_of(modified)))
+}
+
+/// One line of `git log --format=%H%x1f%h%x1f%an%x1f%cs%x1f%s`.
+fn parse_commit(line: &str) -> Option<Commit> {
+    let mut fields = line.splitn(5, '\u{1f}');
+
+    Some(Commit {
+        hash: fields.next()?.to_string(),
+        short_hash: fields.next()?.to_string(),
+        author: fields.next()?.to_string(),
+        date: fields.next()?.to_string(),
+        summary: fields.next()?.to_string(),
+    })
+}
+
+/// "Last edited by ..." for under the document's name.
+pub(crate) fn last_edit_html(last_edit: Option<&LastEdit>) -> String {
+    match last_edit {
+        Some(LastEdit::Commit(commit)) => format!(
+            "{}",
+            html! {
+                div(class="rs-readme-last-edit") {
+                    : format!("Last edited by {} on {} (", commit.author, commit.date);
+                    code : &commit.short_hash;
+                    : ")";
+                }
+            }
+        ),
+        Some(LastEdit::Modified(date)) => format!(
+            "{}",
+            html! {
+                div(class="rs-readme-last-edit") : format!("Last modified on {}", date);
+            }
+        ),
+        None => String::new(),
+    }
+}
+
+/// A dropdown of `commits`, each going to the document at `path` as of that commit, for the
+/// page header.
+pub(crate) fn history_html(path: &str, commits: &[Commit]) -> String {
+    if commits.is_empty() {
+        return String::new();
+    }
+    let path = path.trim_start_matches("./");
+
+    format!(
+        "{}",
+        html! {
+            select(class="rs-readme-history", title="History", onchange="if (this.value) { location.href = this.value; }") {
+                option(value="") : "History";
+                @ for commit in commits {
+                    option(value=format!("/git/{}/{}", commit.hash, path)) {
+                        : format!("{} {} {}", commit.short_hash, commit.date, commit.summary);
+                    }
+                }
+            }
+        }
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn git(repo: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(["-c", "user.name=Ada", "-c", "user.email=ada@example.com"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn lists_commits_touching_the_document() {
+        let repo = std::env::temp_dir().join(format!("rs-readme-history-{}", std::process::id()));
+        fs::create_dir_all(&repo).unwrap();
+        git(&repo, &["init", "-q"]);
+        fs::write(repo.join("a.md"), "one").unwrap();
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "-q", "-m", "Write a"]);
+        fs::write(repo.join("b.md"), "other").unwrap();
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "-q", "-m", "Write b"]);
+        fs::write(repo.join("a.md"), "two").unwrap();
+        git(&repo, &["commit", "-q", "-am", "Rewrite a\n\nWith a body"]);
+
+        let commits = history(&repo, "./a.md", HISTORY_LENGTH);
+        let untracked = history(&repo, "./c.md", HISTORY_LENGTH);
+        let _ = fs::remove_dir_all(&repo);
+
+        assert_eq!(
+            commits
+                .iter()
+                .map(|commit| commit.summary.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Rewrite a", "Write a"]
+        );
+        assert_eq!(commits[0].author, "Ada");
+        assert_eq!(commits[0].date.len(), "2024-01-31".len());
+        assert!(commits[0].hash.starts_with(&commits[0].short_hash));
+        assert_eq!(untracked, Vec::new());
+    }
+
+    #[test]
+    fn falls_back_to_the_modification_date() {
+        let root = std::env::temp_dir().join(format!("rs-readme-modified-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.md"), "").unwrap();
+
+        let edit = last_edit(&root, "./a.md", &[]);
+        let missing = last_edit(&root, "./b.md", &[]);
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(
+            edit,
+            Some(LastEdit::Modified(date_of(std::time::SystemTime::now())))
+        );
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn shows_who_edited_last() {
+        let commit = Commit {
+            hash: "3f2a9c1e".to_string(),
+            short_hash: "3f2a9c1".to_string(),
+            author: "Ada".to_string(),
+            date: "2024-01-31".to_string(),
+            summary: "Fix typo".to_string(),
+        };
+
+        assert_eq!(
+            last_edit_html(Some(&LastEdit::Commit(commit))),
+            "<div class=\"rs-readme-last-edit\">Last edited by Ada on 2024-01-31 (<code>3f2a9c1</code>)</div>"
+        );
+    }
+
+    #[test]
+    fn links_to_the_document_at_each_commit() {
+        let commits = vec![Commit {
+            hash: "3f2a9c1e".to_string(),
+            short_hash: "3f2a9c1".to_string(),
+            author: "Ada".to_string(),
+            date: "2024-01-31".to_string(),
+            summary: "Fix <typo>".to_string(),
+        }];
+
+        let html = history_html("./docs/a.md", &commits);
+
+        assert!(html.contains(
+            "<option value=\"/git/3f2a9c1e/docs/a.md\">3f2a9c1 2024-01-31 Fix &lt;typo&gt;</option>"
+        ));
+        assert_eq!(history_html("./docs/a.md", &[]), "");
+    }
+}
@@ -0,0 +1,152 @@
+use log::debug;
+use std::path::Path;
+use std::process::Command;
+
+/// Which checkout is being previewed, for the page footer, see [`repo_status`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RepoStatus {
+    /// The current branch, `None` when `HEAD` is detached.
+    pub branch: Option<String>,
+
+    /// The short hash of `HEAD`, `None` before the first commit.
+    pub head: Option<String>,
+
+    /// Whether there are uncommitted changes, untracked files included.
+    pub dirty: bool,
+}
+
+/// The branch, `HEAD` and whether there are uncommitted changes in the repository at `root`,
+/// `None` outside one.
+pub fn repo_status(root: &Path) -> Option<RepoStatus> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["status", "--porcelain=v2", "--branch"])
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            Some(parse_status(&String::from_utf8_lossy(&output.stdout)))
+        }
+        Ok(output) => {
+            debug!(
+                "No git status for {}: {}",
+                root.to_string_lossy(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            None
+        }
+        Err(err) => {
+            debug!("Could not run git: {}", err);
+            None
+        }
+    }
+}
+
+/// Reads `git status --porcelain=v2 --branch`.
+fn parse_status(status: &str) -> RepoStatus {
+    let mut repo = RepoStatus {
+        branch: None,
+        head: None,
+        dirty: false,
+    };
+
+    for line in status.lines() {
+        if let Some(oid) = line.strip_prefix("# branch.oid ") {
+            repo.head = Some(oid)
+                .filter(|oid| *oid != "(initial)")
+                .map(|oid| oid.chars().take(7).collect());
+        } else if let Some(head) = line.strip_prefix("# branch.head ") {
+            repo.branch = Some(head)
+                .filter(|head| *head != "(detached)")
+                .map(str::to_string);
+        } else if !line.starts_with('#') && !line.is_empty() {
+            repo.dirty = true;
+        }
+    }
+
+    repo
+}
+
+/// The footer saying which checkout is being previewed.
+pub(crate) fn status_html(repo: Option<&RepoStatus>) -> String {
+    let repo = match repo {
+        Some(repo) => repo,
+        None => return String::new(),
+    };
+
+    format!(
+        "{}",
+        html! {
+            footer(class="rs-readme-footer") {
+                span(class="octicon octicon-git-branch");
+                : format!(" {}", repo.branch.as_deref().unwrap_or("detached"));
+                @ if let Some(head) = &repo.head {
+                    : " @ ";
+                    code : head;
+                }
+                @ if repo.dirty {
+                    span(class="rs-readme-dirty", title="Uncommitted changes") : " ● modified";
+                }
+            }
+        }
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_porcelain_status() {
+        let status = "\
+# branch.oid 3f2a9c1e0b7d4a5f6c8e9d0a1b2c3d4e5f6a7b8c
+# branch.head docs/footer
+# branch.upstream origin/docs/footer
+1 .M N... 100644 100644 100644 3f2a9c1 3f2a9c1 README.md
+";
+
+        assert_eq!(
+            parse_status(status),
+            RepoStatus {
+                branch: Some("docs/footer".to_string()),
+                head: Some("3f2a9c1".to_string()),
+                dirty: true,
+            }
+        );
+        assert_eq!(
+            parse_status("# branch.oid (initial)\n# branch.head (detached)\n"),
+            RepoStatus {
+                branch: None,
+                head: None,
+                dirty: false,
+            }
+        );
+    }
+
+    #[test]
+    fn shows_the_branch_and_head() {
+        let repo = RepoStatus {
+            branch: Some("main".to_string()),
+            head: Some("3f2a9c1".to_string()),
+            dirty: true,
+        };
+
+        assert_eq!(
+            status_html(Some(&repo)),
+            "<footer class=\"rs-readme-footer\"><span class=\"octicon octicon-git-branch\"></span> main @ \
+             <code>3f2a9c1</code><span class=\"rs-readme-dirty\" title=\"Uncommitted changes\"> ● modified</span></footer>"
+        );
+        assert_eq!(status_html(None), "");
+    }
+
+    #[test]
+    fn nothing_outside_a_repository() {
+        let root = std::env::temp_dir().join(format!("rs-readme-no-repo-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+
+        let status = repo_status(&root);
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(status, None);
+    }
+}
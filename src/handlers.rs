@@ -0,0 +1,1668 @@
+use futures::io::AsyncReadExt;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use horrorshow::helper::doctype;
+use horrorshow::Raw;
+use http_types::{mime, StatusCode};
+use log::warn;
+use mime_guess::{self, mime::Mime, MimeGuess};
+use serde_json::json;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::ConfigError;
+use crate::content_finder::DocumentFormat;
+use crate::document_stats::document_stats;
+use crate::editing::{not_utf8, Saved};
+use crate::error::Error;
+use crate::frontmatter::Frontmatter;
+use crate::highlight::code_view;
+use crate::i18n::Lang;
+use crate::image_proxy::{is_remote, IMAGE_POLICY};
+use crate::links::{normalize, raw_href};
+use crate::markdown_converter::HtmlChunks;
+use crate::outline::outline as document_outline;
+use crate::patches;
+use crate::pins::pin_path;
+use crate::recent::recent_html;
+use crate::render::{base_html, editor_html, markdown_html, RenderOptions, RenderedPage};
+use crate::updates::{now_millis, throttle};
+use crate::web_server::State;
+
+/// Where `/git/<ref>/<path>` URLs start.
+const GIT_PREFIX: &str = "/git/";
+
+/// Where `/diff/<from>..<to>/<path>` URLs start.
+const DIFF_PREFIX: &str = "/diff/";
+
+/// The routes answering with JSON, so their errors are JSON too.
+const JSON_ROUTES: &[&str] = &[
+    "/__rs-readme/metrics",
+    "/__rs-readme/frontmatter/",
+    "/__rs-readme/poll/",
+    "/__rs-readme/prefs",
+    "/__rs-readme/save/",
+    "/api/",
+];
+
+/// How much of a raw file to send at a time.
+const RAW_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The `Content-Security-Policy` `.html` files from the folder are sent with unless they're
+/// trusted: their scripts don't run, and the page gets an origin of its own.
+const HTML_POLICY: &str = "sandbox";
+
+/// What one of rs-readme's routes responds with, independent of the web framework serving it.
+///
+/// The tide server and the axum adapter both turn these into their own response types.
+#[derive(Debug)]
+pub struct RenderedResponse {
+    pub status: u16,
+    pub mime: String,
+    pub body: ResponseBody,
+
+    /// The weak `ETag` of a rendered page, built from a hash of the page.
+    pub etag: Option<String>,
+
+    /// How the body is encoded (`gzip`), if it is.
+    pub content_encoding: Option<String>,
+
+    /// How many seconds to wait before trying again, for `503 Service Unavailable`s.
+    pub retry_after: Option<u64>,
+
+    /// How long clients can keep the response, sent as `Cache-Control`.
+    pub cache_control: Option<String>,
+
+    /// The request headers besides `Accept-Encoding` the response depends on, sent as `Vary`,
+    /// like `Accept` for pages that can be sent as their source.
+    pub vary: Option<String>,
+
+    /// What the body is allowed to do when it's opened as a page, sent as
+    /// `Content-Security-Policy` along with `X-Content-Type-Options: nosniff`, for bodies that
+    /// aren't rs-readme's own pages.
+    pub content_security_policy: Option<String>,
+
+    /// What went wrong, for error responses, so they can be sent as JSON instead to clients
+    /// wanting that, see [`json_error_response`].
+    pub error: Option<Error>,
+
+    /// The ID of the request, sent as `X-Request-Id`, for error pages that ask to be quoted.
+    pub request_id: Option<String>,
+}
+
+/// The body of a [`RenderedResponse`].
+pub enum ResponseBody {
+    Bytes(Vec<u8>),
+
+    /// Sent as it's produced, for documents too big to buffer.
+    Stream(HtmlChunks),
+}
+
+impl fmt::Debug for ResponseBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResponseBody::Bytes(bytes) => f.debug_tuple("Bytes").field(&bytes.len()).finish(),
+            ResponseBody::Stream(_) => f.write_str("Stream"),
+        }
+    }
+}
+
+impl RenderedResponse {
+    pub(crate) fn new(
+        status: StatusCode,
+        mime: impl ToString,
+        body: impl Into<Vec<u8>>,
+    ) -> RenderedResponse {
+        RenderedResponse {
+            status: status.into(),
+            mime: mime.to_string(),
+            body: ResponseBody::Bytes(body.into()),
+            etag: None,
+            content_encoding: None,
+            retry_after: None,
+            cache_control: None,
+            vary: None,
+            content_security_policy: None,
+            error: None,
+            request_id: None,
+        }
+    }
+}
+
+/// Renders `README.md` for the root of the site, or `README.rst` if there's only that.
+///
+/// It assumes that there will be a `README.md` in your folder. It lets us have a special error
+/// message for it and lets the root of the website render `README.md`.
+pub(crate) async fn readme(
+    state: &State,
+    converter: Option<&str>,
+    reference: Option<&str>,
+    force: Option<&str>,
+    if_none_match: Option<&str>,
+    accept: Option<&str>,
+) -> RenderedResponse {
+    let index = state.index().await;
+    page(
+        state,
+        "/",
+        &index,
+        converter,
+        reference,
+        force,
+        if_none_match,
+        accept,
+    )
+    .await
+}
+
+/// Renders any given URL path, images and existing HTML pages (with their stylesheets, scripts
+/// and fonts) are sent as they are.
+///
+/// Since the tool should only be used locally I assume people aren't hacking themselves.
+///
+/// Paths under `/git/<ref>/` show documents as they are at that git ref, the same as `?ref=`,
+/// and paths under `/diff/<from>..<to>/` show how they changed between two refs.
+///
+/// Documents are sent unconverted to clients that `accept` their source, see [`source_type`].
+pub(crate) async fn markdown_path(
+    state: &State,
+    path: &str,
+    converter: Option<&str>,
+    reference: Option<&str>,
+    force: Option<&str>,
+    if_none_match: Option<&str>,
+    accept: Option<&str>,
+) -> RenderedResponse {
+    if let Some(rest) = path.strip_prefix(DIFF_PREFIX) {
+        return diff(state, path, rest).await;
+    }
+    let (path, reference) = match git_path(path) {
+        Some((reference, "" | "/")) => {
+            let index = state.index().await;
+            return page(
+                state,
+                path,
+                &index,
+                converter,
+                Some(reference),
+                force,
+                if_none_match,
+                accept,
+            )
+            .await;
+        }
+        Some((reference, path)) => (path, Some(reference)),
+        None => (path, reference),
+    };
+    let file = path.rsplit('/').next().unwrap_or("rs-readme");
+    let resource = format!(".{}", path);
+
+    let mime = MimeGuess::from_path(file).first_or_text_plain();
+    if passes_through(&mime) {
+        return_file(state, path, &resource, mime).await
+    } else {
+        page(
+            state,
+            path,
+            &resource,
+            converter,
+            reference,
+            force,
+            if_none_match,
+            accept,
+        )
+        .await
+    }
+}
+
+/// The document under a `/diff/<from>..<to>/<path>` URL path (`rest` is what follows
+/// `/diff/`) with the changes between the two git refs marked. Leaving out `<to>` compares
+/// `<from>` with the working tree.
+async fn diff(state: &State, path: &str, rest: &str) -> RenderedResponse {
+    let parsed = rest.split_once('/').and_then(|(range, document)| {
+        let (from, to) = range.split_once("..")?;
+        Some((from, Some(to).filter(|to| !to.is_empty()), document))
+    });
+    let (from, to, document) = match parsed {
+        Some(parsed) if !parsed.0.is_empty() => parsed,
+        _ => return error_response(&Error::InvalidPath(path.to_string()), path),
+    };
+
+    match state
+        .render_diff(from, to, &format!("./{}", document))
+        .await
+    {
+        Ok(html) => RenderedResponse::new(StatusCode::Ok, mime::HTML, html),
+        Err(err) => error_response(&err, path),
+    }
+}
+
+/// The ref and the path under it of a `/git/<ref>/<path>` URL path.
+fn git_path(path: &str) -> Option<(&str, &str)> {
+    let rest = path.strip_prefix(GIT_PREFIX)?;
+    Some(match rest.find('/') {
+        Some(slash) => (&rest[..slash], &rest[slash..]),
+        None => (rest, ""),
+    })
+}
+
+/// Whether files of type `mime` are sent as they are rather than rendered.
+fn passes_through(mime: &Mime) -> bool {
+    match (mime.type_(), mime.subtype()) {
+        (mime_guess::mime::IMAGE, _) | (mime_guess::mime::FONT, _) => true,
+        (mime_guess::mime::TEXT, subtype) => {
+            subtype == mime_guess::mime::HTML
+                || subtype == mime_guess::mime::CSS
+                || subtype == mime_guess::mime::JAVASCRIPT
+        }
+        (mime_guess::mime::APPLICATION, subtype) => {
+            subtype == mime_guess::mime::JAVASCRIPT || subtype == mime_guess::mime::JSON
+        }
+        _ => false,
+    }
+}
+
+/// Returns static files from the root folder, like images in markdown documents or the assets
+/// of HTML pages that were generated by something else.
+///
+/// HTML pages are sanitized first if the state says to, see [`State::with_sanitized_html`].
+/// They're sandboxed too unless they're trusted, see [`State::with_trusted_html`], since they
+/// share an origin with the routes that change pins, preferences and documents.
+async fn return_file(state: &State, path: &str, resource: &str, mime: Mime) -> RenderedResponse {
+    let html = mime.subtype() == mime_guess::mime::HTML;
+    let file = match root_file(state, resource).await {
+        Ok(file) => file,
+        Err(err) => return error_response(&err, path),
+    };
+    let response = match async_std::fs::read(&file).await {
+        Ok(body) if html && state.sanitizes_html() => {
+            let body = ammonia::clean(&String::from_utf8_lossy(&body));
+            RenderedResponse::new(StatusCode::Ok, mime, body)
+        }
+        Ok(body) => RenderedResponse::new(StatusCode::Ok, mime, body),
+        Err(err) => return error_response(&io_error(resource, err), path),
+    };
+
+    if html && !state.trusts_html() {
+        RenderedResponse {
+            content_security_policy: Some(HTML_POLICY.to_string()),
+            ..response
+        }
+    } else {
+        response
+    }
+}
+
+/// The file at `path` (the URL path after `/__rs-readme/raw`) under the root folder as plain
+/// text, streamed so it works for files too big to render.
+pub(crate) async fn raw(state: &State, path: &str) -> RenderedResponse {
+    let resource = format!(".{}", path);
+    let file = match root_file(state, &resource).await {
+        Ok(file) => file,
+        Err(err) => return error_response(&err, path),
+    };
+
+    match async_std::fs::File::open(&file).await {
+        Ok(file) => RenderedResponse {
+            body: ResponseBody::Stream(file_chunks(file)),
+            ..RenderedResponse::new(StatusCode::Ok, mime::PLAIN, Vec::new())
+        },
+        Err(err) => error_response(&io_error(&resource, err), path),
+    }
+}
+
+/// Reads `file` a chunk at a time.
+fn file_chunks(file: async_std::fs::File) -> HtmlChunks {
+    Box::pin(stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut chunk = vec![0; RAW_CHUNK_SIZE];
+        match file.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(read) => {
+                chunk.truncate(read);
+                Some((chunk, Some(file)))
+            }
+            Err(err) => {
+                warn!("Stopped reading raw file: {}", err);
+                None
+            }
+        }
+    }))
+}
+
+/// The source file at `path` (the URL path after `/__rs-readme/code`) syntax highlighted, with
+/// line numbers that can be linked to as `#L42`. It's read with the content finder, as of the
+/// git `reference` if there is one.
+pub(crate) async fn code(state: &State, path: &str, reference: Option<&str>) -> RenderedResponse {
+    let resource = format!(".{}", path);
+    let source = match state.code_at(reference, &resource).await {
+        Ok((source, _)) => source,
+        Err(err) => return error_response(&err.into(), path),
+    };
+
+    let title = path.rsplit('/').next().unwrap_or("rs-readme").to_string();
+    let integrity = state.stylesheet_integrity();
+    let code_theme = state.code_theme();
+    let html = async_std::task::spawn_blocking(move || {
+        base_html(
+            &title,
+            &integrity,
+            code_theme,
+            &markdown_html(&title, &code_view(&title, &source)),
+        )
+    })
+    .await;
+
+    RenderedResponse::new(StatusCode::Ok, mime::HTML, html)
+}
+
+/// Where `resource` (`./docs/logo.png`) is under [`State::files_root`] once symlinks are
+/// followed. Anything that ends up outside of it is refused, like the editor's saves are.
+async fn root_file(state: &State, resource: &str) -> Result<PathBuf, Error> {
+    let invalid = || Error::InvalidPath(resource.to_string());
+    if normalize(Path::new(resource)).is_none() {
+        return Err(invalid());
+    }
+    let root = async_std::fs::canonicalize(state.files_root())
+        .await
+        .map_err(|err| io_error(resource, err))?;
+    let file = async_std::fs::canonicalize(root.join(resource))
+        .await
+        .map_err(|err| io_error(resource, err))?;
+    if !file.starts_with(&root) {
+        return Err(invalid());
+    }
+
+    Ok(file.into())
+}
+
+fn io_error(resource: &str, err: io::Error) -> Error {
+    match err.kind() {
+        io::ErrorKind::NotFound => Error::NotFound(resource.to_string()),
+        io::ErrorKind::PermissionDenied => Error::Forbidden {
+            resource: resource.to_string(),
+            reason: err.to_string(),
+        },
+        kind => Error::Io {
+            resource: resource.to_string(),
+            kind,
+        },
+    }
+}
+
+/// Renders the markdown at `resource`, tagged with a weak `ETag` from a hash of everything on
+/// the page, see [`State::render`].
+///
+/// If the browser already has this version of the content we just say so with a
+/// `304 Not Modified`. With `force` set to `md` any text file is rendered as markdown.
+#[allow(clippy::too_many_arguments)]
+async fn page(
+    state: &State,
+    path: &str,
+    resource: &str,
+    converter: Option<&str>,
+    reference: Option<&str>,
+    force: Option<&str>,
+    if_none_match: Option<&str>,
+    accept: Option<&str>,
+) -> RenderedResponse {
+    if let Some(mime) = source_type(accept) {
+        return source(state, path, resource, reference, mime, if_none_match).await;
+    }
+
+    let (hash, body) = match state
+        .render(converter, reference, resource, force == Some("md"))
+        .await
+    {
+        Ok(page) => page,
+        Err(err) if err.converter_failed() => {
+            return match state.last_rendered(resource) {
+                Some(last) => stale_response(&err, path, converter, &last, state.lang()),
+                None => converter_error_response(&err, path, converter, state.lang()),
+            };
+        }
+        Err(err) => return error_response(&err, path),
+    };
+
+    let etag = format!("W/\"{}\"", hash);
+    let response = if matches_etag(if_none_match, &etag) {
+        RenderedResponse::new(StatusCode::NotModified, mime::HTML, Vec::new())
+    } else {
+        RenderedResponse {
+            body,
+            ..RenderedResponse::new(StatusCode::Ok, mime::HTML, Vec::new())
+        }
+    };
+
+    RenderedResponse {
+        etag: Some(etag),
+        vary: Some("Accept".to_string()),
+        ..response
+    }
+}
+
+/// Whether `If-None-Match` lists `etag`, so the client already has this version.
+fn matches_etag(if_none_match: Option<&str>, etag: &str) -> bool {
+    if_none_match.is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag))
+}
+
+/// The unconverted document at `resource`, as `mime`, tagged with a weak `ETag` from the
+/// content hash and the type, so it's never mistaken for its page.
+async fn source(
+    state: &State,
+    path: &str,
+    resource: &str,
+    reference: Option<&str>,
+    mime: &str,
+    if_none_match: Option<&str>,
+) -> RenderedResponse {
+    let (contents, hash) = match state.source_at(reference, resource).await {
+        Ok(found) => found,
+        Err(err) => return error_response(&err.into(), path),
+    };
+
+    let etag = format!(
+        "W/\"{}-{}\"",
+        hash,
+        mime.rsplit('/').next().unwrap_or_default()
+    );
+    let mime = format!("{}; charset=utf-8", mime);
+    let response = if matches_etag(if_none_match, &etag) {
+        RenderedResponse::new(StatusCode::NotModified, mime, Vec::new())
+    } else {
+        RenderedResponse::new(StatusCode::Ok, mime, contents)
+    };
+
+    RenderedResponse {
+        etag: Some(etag),
+        vary: Some("Accept".to_string()),
+        ..response
+    }
+}
+
+/// The type to send a document's source as, when the `Accept` header prefers `text/markdown`
+/// or `text/plain` to `text/html`. Browsers ask for HTML and `curl` for anything (`*/*`), so
+/// both get the page.
+pub(crate) fn source_type(accept: Option<&str>) -> Option<&'static str> {
+    preferred_type(accept, &["text/html", "text/markdown", "text/plain"])
+        .filter(|media| *media != "text/html")
+}
+
+/// The one of `offered` that the `Accept` header likes best, earlier ones winning ties and
+/// wildcards (`text/*`, `*/*`).
+fn preferred_type<'a>(accept: Option<&str>, offered: &[&'a str]) -> Option<&'a str> {
+    let mut best: Option<(&'a str, f32)> = None;
+    for range in accept?.split(',') {
+        let mut params = range.split(';');
+        let range = params.next().unwrap_or_default().trim();
+        let quality = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let matches = |media: &&str| {
+            range == "*/*"
+                || range == *media
+                || range
+                    .strip_suffix("/*")
+                    .is_some_and(|kind| media.split('/').next() == Some(kind))
+        };
+        if let Some(media) = offered.iter().copied().find(matches) {
+            if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+                best = Some((media, quality));
+            }
+        }
+    }
+
+    best.map(|(media, _)| media)
+}
+
+/// The live-reload messages for the file at `path` (the URL path after `/__rs-readme`), as the name
+/// of the event and its JSON. `update`s have the converted contents and their hash, the front end
+/// will update if the hash differs. After the first one they have a `patch` of the blocks that
+/// changed instead when that's smaller, with the anchor of the `section` they're in, see
+/// [`patches::patch`], and they list the `anchors` the new version has so pages can keep their
+/// place. `error`s have the `code` and `message` of why a version couldn't be converted. `scroll`s
+/// have the `line` of the source an editor asked the page to scroll to, see [`scroll`].
+///
+/// Fails with [`Error::Busy`] when too many of these are already open.
+pub(crate) async fn page_updates(
+    state: &Arc<State>,
+    path: &str,
+    converter: Option<&str>,
+    reference: Option<&str>,
+) -> Result<impl Stream<Item = (&'static str, String)> + Send + 'static, Error> {
+    let permit = state.open_update_stream()?;
+
+    let (path, reference) = match git_path(path) {
+        Some((reference, path)) => (path, Some(reference)),
+        None => (path, reference),
+    };
+    let path = page_path(state, path).await;
+
+    // What the page shows since the last update, for sending just the blocks that changed
+    let mut previous: Option<String> = None;
+    let updates = state.updates_with(&path, converter, reference);
+    let updates = throttle(updates, state.min_push_interval()).map(move |update| {
+        let _ = &permit;
+        match update {
+            Ok(update) => {
+                let patch = previous
+                    .as_deref()
+                    .and_then(|previous| patches::patch(previous, &update.contents));
+                let mut message = json!({
+                    "anchors": patches::anchors(&update.contents),
+                    "timestamp": update.timestamp,
+                    "hash": update.hash.to_string(),
+                });
+                match patch {
+                    Some(patch) => message["patch"] = json!(patch),
+                    None => message["contents"] = json!(&update.contents),
+                }
+                previous = Some(update.contents);
+                ("update", message.to_string())
+            }
+            Err(err) => {
+                let message = json!({ "code": err.code(), "message": err.to_string() });
+                ("error", message.to_string())
+            }
+        }
+    });
+    let scrolls = state
+        .scroll_requests(&path)
+        .map(|line| ("scroll", json!({ "line": line }).to_string()));
+
+    Ok(stream::select(updates, scrolls))
+}
+
+/// The document at `path` (the URL path after `/__rs-readme/poll`) for pages that poll for
+/// changes, in browsers without `EventSource`. Answers with its `hash` as JSON, and with its
+/// converted `contents` too when the page's `known` hash is out of date, so unchanged documents
+/// aren't converted or sent again.
+pub(crate) async fn poll(
+    state: &State,
+    path: &str,
+    converter: Option<&str>,
+    reference: Option<&str>,
+    known: Option<&str>,
+) -> RenderedResponse {
+    let url_path = format!("/__rs-readme/poll{}", path);
+    let (path, reference) = match git_path(path) {
+        Some((reference, path)) => (path, Some(reference)),
+        None => (path, reference),
+    };
+    let path = page_path(state, path).await;
+
+    let (contents, hash) = match state.read_at(reference, &path).await {
+        Ok(found) => found,
+        Err(err) => return error_response(&err.into(), &url_path),
+    };
+    let message = if known == Some(hash.to_string().as_str()) {
+        json!({ "hash": hash.to_string() })
+    } else {
+        let options = RenderOptions {
+            full_page: false,
+            ..RenderOptions::default()
+        };
+        match state
+            .render_cached(converter, &path, &contents, hash, &options)
+            .await
+        {
+            Ok(page) => json!({
+                "hash": hash.to_string(),
+                "anchors": patches::anchors(&page.content),
+                "contents": page.content,
+                "timestamp": now_millis(),
+            }),
+            Err(err) => return error_response(&err, &url_path),
+        }
+    };
+
+    RenderedResponse::new(StatusCode::Ok, mime::JSON, message.to_string())
+}
+
+/// Asks the open pages showing the document at `path` (the URL path after
+/// `/__rs-readme/scroll`) to scroll to the part of it rendered from the source `line`, for
+/// editors keeping the preview beside the cursor. Answers with how many pages were asked.
+pub(crate) async fn scroll(state: &State, path: &str, line: Option<&str>) -> RenderedResponse {
+    let line = match line.and_then(|line| line.parse::<usize>().ok()) {
+        Some(line) => line,
+        None => {
+            return RenderedResponse::new(
+                StatusCode::BadRequest,
+                mime::PLAIN,
+                "The line to scroll to is missing or not a number",
+            )
+        }
+    };
+
+    let pages = state.scroll_to(&page_path(state, path).await, line);
+    RenderedResponse::new(
+        StatusCode::Ok,
+        mime::JSON,
+        json!({ "pages": pages }).to_string(),
+    )
+}
+
+/// Pins the document at `path` (the URL path after `/__rs-readme/pin` or `/__rs-readme/unpin`),
+/// or unpins it, for the star in the page header. Answers with the pinned documents as JSON.
+///
+/// Like the editor's routes, only rs-readme's own pages can send it, see
+/// [`ChangeRequest::cross_origin`].
+pub(crate) async fn pin(
+    state: &State,
+    path: &str,
+    request: &ChangeRequest<'_>,
+    pinned: bool,
+) -> RenderedResponse {
+    if let Some(refusal) = request.cross_origin(state) {
+        return refusal;
+    }
+    if pin_path(path).is_empty() {
+        return RenderedResponse::new(
+            StatusCode::BadRequest,
+            mime::PLAIN,
+            "The document to pin is missing",
+        );
+    }
+
+    match state.set_pinned(path, pinned).await {
+        Some(Ok(pins)) => RenderedResponse::new(
+            StatusCode::Ok,
+            mime::JSON,
+            json!({ "pinned": pins }).to_string(),
+        ),
+        Some(Err(err)) => RenderedResponse::new(
+            StatusCode::InternalServerError,
+            mime::PLAIN,
+            format!("Could not save the pins\n{}", err),
+        ),
+        None => RenderedResponse::new(
+            StatusCode::NotFound,
+            mime::PLAIN,
+            "Documents can't be pinned here",
+        ),
+    }
+}
+
+/// What the editor routes answer when documents can't be edited.
+fn not_editable() -> RenderedResponse {
+    RenderedResponse::new(
+        StatusCode::NotFound,
+        mime::PLAIN,
+        "Documents can't be edited here, see --edit",
+    )
+}
+
+/// The headers of a request that changes documents or pins, for checking one of rs-readme's own
+/// pages sent it rather than some other site the reader has open.
+pub(crate) struct ChangeRequest<'a> {
+    /// Where the request was sent, the `Host` header, like `localhost:4000`.
+    pub(crate) host: Option<&'a str>,
+    pub(crate) origin: Option<&'a str>,
+    pub(crate) fetch_site: Option<&'a str>,
+    pub(crate) content_type: Option<&'a str>,
+}
+
+impl ChangeRequest<'_> {
+    /// The refusal for a request that didn't come from one of rs-readme's own pages, if it
+    /// didn't.
+    ///
+    /// It has to be sent to `localhost`, an IP address or the host the server was started
+    /// with, so a page on another domain pointed at this machine can't send it, and from a page
+    /// of this server.
+    fn cross_origin(&self, state: &State) -> Option<RenderedResponse> {
+        let host = self.host.unwrap_or_default();
+        let hostname = match host.rsplit_once(':') {
+            Some((hostname, port)) if !port.contains(']') => hostname,
+            _ => host,
+        };
+        let hostname = hostname.trim_start_matches('[').trim_end_matches(']');
+        let known_host = hostname.eq_ignore_ascii_case("localhost")
+            || hostname.parse::<std::net::IpAddr>().is_ok()
+            || state
+                .host()
+                .is_some_and(|known| hostname.eq_ignore_ascii_case(known));
+        let same_origin = match self.origin {
+            Some(origin) => {
+                let origin = origin
+                    .strip_prefix("http://")
+                    .or_else(|| origin.strip_prefix("https://"));
+                origin == Some(host)
+            }
+            None => true,
+        };
+        let same_site = self
+            .fetch_site
+            .is_none_or(|site| site.eq_ignore_ascii_case("same-origin"));
+        if known_host && same_origin && same_site {
+            return None;
+        }
+
+        Some(change_refusal(
+            StatusCode::Forbidden,
+            "cross-origin",
+            "Documents and pins can only be changed from rs-readme's own pages",
+        ))
+    }
+
+    /// Why the request can't be trusted to change documents, if it can't: it has to pass
+    /// [`ChangeRequest::cross_origin`], and its body has to be JSON, which other sites can't
+    /// send without asking first.
+    fn refusal(&self, state: &State) -> Option<RenderedResponse> {
+        if let Some(refusal) = self.cross_origin(state) {
+            return Some(refusal);
+        }
+
+        let json = self.content_type.is_some_and(|content_type| {
+            content_type
+                .parse::<Mime>()
+                .is_ok_and(|content_type| content_type.essence_str() == "application/json")
+        });
+        if !json {
+            return Some(change_refusal(
+                StatusCode::UnsupportedMediaType,
+                "unsupported-type",
+                "The editor sends its source as application/json",
+            ));
+        }
+
+        None
+    }
+}
+
+/// A JSON error for the editor's and the pins' routes, with `code` for scripts and `message`
+/// for people.
+fn change_refusal(status: StatusCode, code: &str, message: &str) -> RenderedResponse {
+    let body = json!({ "code": code, "message": message });
+    RenderedResponse::new(status, mime::JSON, body.to_string())
+}
+
+/// The `contents` of the JSON body the editor sends, `{"contents": "# Title\n..."}`.
+fn editor_contents(body: &str) -> Option<String> {
+    let body = serde_json::from_str::<serde_json::Value>(body).ok()?;
+    Some(body.get("contents")?.as_str()?.to_string())
+}
+
+/// What the editor's routes answer when the body isn't what the editor sends.
+fn invalid_editor_body() -> RenderedResponse {
+    change_refusal(
+        StatusCode::BadRequest,
+        "invalid-body",
+        "The editor sends its source as {\"contents\": \"...\"}",
+    )
+}
+
+/// The editor for the document at `path` (the URL path after `/edit`), its source beside a
+/// preview that follows the typing, see `--edit`.
+pub(crate) async fn edit(state: &State, path: &str) -> RenderedResponse {
+    if !state.edits() {
+        return not_editable();
+    }
+
+    let resource = page_path(state, path).await;
+    let (source, hash) = match state.source_at(None, &resource).await {
+        Ok(found) => found,
+        Err(err) => return error_response(&err.into(), path),
+    };
+    if state.encoding_of(&resource).is_some() {
+        return error_response(&not_utf8(&resource), path);
+    }
+    let preview = match state.preview(&resource, source.clone()).await {
+        Ok(preview) => preview,
+        Err(err) => return error_response(&err, path),
+    };
+
+    let url = resource.trim_start_matches('.');
+    let title = format!("Editing {}", url.rsplit('/').next().unwrap_or("rs-readme"));
+    let html = base_html(
+        &title,
+        &state.stylesheet_integrity(),
+        state.code_theme(),
+        &editor_html(url, &source, &hash.to_string(), &preview),
+    );
+    RenderedResponse::new(StatusCode::Ok, mime::HTML, html)
+}
+
+/// Saves the `contents` in the JSON `body` over the document at `path` (the URL path after
+/// `/__rs-readme/save`) for the editor, answering with its new hash as JSON. `base` is the
+/// version the editor started from: when the document isn't that version any more, someone
+/// else changed it, so it's left alone with a `409 Conflict`.
+///
+/// Only requests from the editor's own page are answered, see [`ChangeRequest`].
+pub(crate) async fn save(
+    state: &State,
+    path: &str,
+    request: &ChangeRequest<'_>,
+    base: Option<&str>,
+    body: &str,
+) -> RenderedResponse {
+    if !state.edits() {
+        return not_editable();
+    }
+    if let Some(refusal) = request.refusal(state) {
+        return refusal;
+    }
+    let contents = match editor_contents(body) {
+        Some(contents) => contents,
+        None => return invalid_editor_body(),
+    };
+    let base = match base {
+        Some(base) => base,
+        None => {
+            return change_refusal(
+                StatusCode::Conflict,
+                "missing-hash",
+                "Saves need the hash of the version they change, as ?hash=",
+            )
+        }
+    };
+
+    let resource = page_path(state, path).await;
+    match state.save(&resource, base, contents).await {
+        Some(Ok(Saved::As(hash))) => RenderedResponse::new(
+            StatusCode::Ok,
+            mime::JSON,
+            json!({ "hash": hash.to_string() }).to_string(),
+        ),
+        Some(Ok(Saved::Changed(hash))) => {
+            let message = format!(
+                "{} changed since it was opened",
+                path.trim_start_matches('/')
+            );
+            let body = json!({ "code": "changed", "message": message, "hash": hash.to_string() });
+            RenderedResponse::new(StatusCode::Conflict, mime::JSON, body.to_string())
+        }
+        Some(Err(err)) => json_error_response(&err, path),
+        None => not_editable(),
+    }
+}
+
+/// The `contents` in the JSON `body` converted as the document at `path` (the URL path after
+/// `/__rs-readme/preview`) would be, without the page around it, for the editor's preview.
+///
+/// Only requests from the editor's own page are answered, see [`ChangeRequest`].
+pub(crate) async fn preview(
+    state: &State,
+    path: &str,
+    request: &ChangeRequest<'_>,
+    body: &str,
+) -> RenderedResponse {
+    if !state.edits() {
+        return not_editable();
+    }
+    if let Some(refusal) = request.refusal(state) {
+        return refusal;
+    }
+    let contents = match editor_contents(body) {
+        Some(contents) => contents,
+        None => return invalid_editor_body(),
+    };
+
+    let resource = page_path(state, path).await;
+    match state.preview(&resource, contents).await {
+        Ok(html) => RenderedResponse::new(StatusCode::Ok, mime::HTML, html),
+        Err(err) => error_response(&err, path),
+    }
+}
+
+/// The document the page at the URL `path` shows, the index for the root.
+async fn page_path(state: &State, path: &str) -> String {
+    if path.is_empty() || path == "/" {
+        format!("./{}", state.index().await)
+    } else {
+        format!(".{}", path)
+    }
+}
+
+/// Counters for keeping an eye on the server, as JSON.
+pub(crate) fn metrics(state: &State) -> RenderedResponse {
+    let metrics = json!({
+        "page_cache": state.cache_stats(),
+        "watched_documents": state.update_tasks().watching(),
+    });
+
+    RenderedResponse::new(StatusCode::Ok, mime::JSON, metrics.to_string())
+}
+
+/// The frontmatter fields of the document at `path` (the URL path after
+/// `/__rs-readme/frontmatter`) as a JSON object, empty when it hasn't any.
+pub(crate) async fn frontmatter(state: &State, path: &str) -> RenderedResponse {
+    let resource = format!(".{}", path);
+    let fields = match state.read(&resource).await {
+        Ok((contents, _)) => Frontmatter::split(&contents)
+            .map(|(frontmatter, _)| frontmatter.to_json())
+            .unwrap_or_else(|| json!({})),
+        Err(err) => return error_response(&err.into(), path),
+    };
+
+    RenderedResponse::new(StatusCode::Ok, mime::JSON, fields.to_string())
+}
+
+/// A page listing the most recently modified documents with how long ago they changed, for
+/// getting back to what was being edited.
+pub(crate) async fn recent(state: &State) -> RenderedResponse {
+    let files = state.recent_files().await;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let title = "Recently modified";
+
+    RenderedResponse::new(
+        StatusCode::Ok,
+        mime::HTML,
+        base_html(
+            title,
+            &state.stylesheet_integrity(),
+            state.code_theme(),
+            &markdown_html(title, &recent_html(&files, now)),
+        ),
+    )
+}
+
+/// The markdown files in the root folder as nested JSON, with each file's title, size and
+/// modification time.
+pub(crate) async fn tree(state: &State) -> RenderedResponse {
+    RenderedResponse::new(
+        StatusCode::Ok,
+        mime::JSON,
+        json!(state.file_tree().await).to_string(),
+    )
+}
+
+/// The heading tree of the markdown document at `path` (the URL path after `/api/outline`) as
+/// JSON, with line numbers in its source, for editor plugins building their own table of
+/// contents.
+pub(crate) async fn outline(
+    state: &State,
+    path: &str,
+    reference: Option<&str>,
+) -> RenderedResponse {
+    match markdown_source(state, path, reference).await {
+        Ok(contents) => RenderedResponse::new(
+            StatusCode::Ok,
+            mime::JSON,
+            json!(document_outline(&contents)).to_string(),
+        ),
+        Err(err) => error_response(&err, path),
+    }
+}
+
+/// Word, heading, link, image and code block counts and the reading time of the markdown
+/// document at `path` (the URL path after `/api/stats`) as JSON.
+pub(crate) async fn stats(state: &State, path: &str, reference: Option<&str>) -> RenderedResponse {
+    match markdown_source(state, path, reference).await {
+        Ok(contents) => RenderedResponse::new(
+            StatusCode::Ok,
+            mime::JSON,
+            json!(document_stats(&contents)).to_string(),
+        ),
+        Err(err) => error_response(&err, path),
+    }
+}
+
+/// The unprocessed source of the markdown document at `path`, for the routes looking at how
+/// it's written rather than how it renders.
+async fn markdown_source(
+    state: &State,
+    path: &str,
+    reference: Option<&str>,
+) -> Result<String, Error> {
+    let resource = format!(".{}", path);
+    if DocumentFormat::of(Path::new(&resource)) != Some(DocumentFormat::Markdown) {
+        return Err(Error::NotMarkdown);
+    }
+
+    let (contents, _) = state.source_at(reference, &resource).await?;
+    Ok(contents)
+}
+
+/// The remote image at `url` through the image proxy, the cached copy when it can't be
+/// fetched. Only images a rendered page was pointed at are fetched, and they're sandboxed so
+/// an SVG opened on its own can't run scripts.
+pub(crate) async fn image(state: &State, url: Option<&str>) -> RenderedResponse {
+    let proxy = match state.image_proxy() {
+        Some(proxy) => proxy,
+        None => {
+            return RenderedResponse::new(
+                StatusCode::NotFound,
+                mime::PLAIN,
+                "Images aren't proxied, see --proxy-images",
+            )
+        }
+    };
+    let url = match url.filter(|url| is_remote(url)) {
+        Some(url) => url,
+        None => {
+            return RenderedResponse::new(
+                StatusCode::BadRequest,
+                mime::PLAIN,
+                "Expected an http(s) URL in `url`",
+            )
+        }
+    };
+
+    if !proxy.proxies(url) {
+        return RenderedResponse::new(
+            StatusCode::Forbidden,
+            mime::PLAIN,
+            format!("{} isn't an image in a document here", url),
+        );
+    }
+
+    match proxy.image(url).await {
+        Some(image) => RenderedResponse {
+            content_security_policy: Some(IMAGE_POLICY.to_string()),
+            ..RenderedResponse::new(StatusCode::Ok, image.mime.as_str(), image.body)
+        },
+        None => RenderedResponse::new(
+            StatusCode::BadGateway,
+            mime::PLAIN,
+            format!("Could not fetch {} and it isn't cached", url),
+        ),
+    }
+}
+
+/// What requests from other machines get unless remote access is allowed, see
+/// `--allow-remote`.
+pub(crate) fn remote_forbidden(lang: Lang) -> RenderedResponse {
+    let status = StatusCode::Forbidden;
+    RenderedResponse::new(
+        status,
+        mime::HTML,
+        error_html(
+            "rs-readme",
+            status,
+            lang.tr("rs-readme only answers requests from the machine it's running on, start it with --allow-remote to share it"),
+            lang,
+        ),
+    )
+}
+
+/// The page for a request whose handler panicked. It's standalone, without the page script, so
+/// the browser doesn't ask the same handler again, and the server keeps answering everyone else.
+pub(crate) fn panic_response(request_id: &str, lang: Lang) -> RenderedResponse {
+    let (before, after) = lang.around(
+        "The details are in its log under request {}, please include them if you report it.",
+    );
+    let body = format!(
+        "{}",
+        html! {
+            : doctype::HTML;
+            html {
+                head {
+                    meta(charset="utf-8");
+                    title : "rs-readme";
+                    link(rel="stylesheet", href="/static/style.css");
+                }
+                body {
+                    div(class="rs-readme-panic") {
+                        h1 : lang.tr("Something went wrong");
+                        p : lang.tr("rs-readme hit a bug rendering this page. It's still running, so other pages and clients aren't affected.");
+                        p {
+                            : before;
+                            code : request_id;
+                            : after;
+                        }
+                    }
+                }
+            }
+        }
+    );
+    let mut res = RenderedResponse::new(StatusCode::InternalServerError, mime::HTML, body);
+    res.request_id = Some(request_id.to_string());
+    res
+}
+
+/// The display preferences pages start with until readers pick their own, as JSON, see
+/// [`crate::Prefs`].
+pub(crate) fn prefs(state: &State) -> RenderedResponse {
+    RenderedResponse::new(StatusCode::Ok, mime::JSON, json!(state.prefs()).to_string())
+}
+
+/// Changes the display preferences pages start with to the ones in the JSON object `changes`,
+/// answering with all of them.
+pub(crate) fn change_prefs(state: &State, changes: &str) -> RenderedResponse {
+    match state.change_prefs(changes) {
+        Ok(prefs) => RenderedResponse::new(StatusCode::Ok, mime::JSON, json!(prefs).to_string()),
+        Err(err) => RenderedResponse::new(
+            StatusCode::BadRequest,
+            mime::JSON,
+            json!({ "code": "invalid-prefs", "message": err.to_string() }).to_string(),
+        ),
+    }
+}
+
+/// Re-reads the config file, the same as sending the process a `SIGHUP`.
+pub(crate) fn reload_config(state: &State) -> RenderedResponse {
+    match state.reload_config() {
+        Ok(()) => RenderedResponse::new(StatusCode::Ok, mime::PLAIN, "Reloaded config"),
+        Err(ConfigError::NoConfigFile) => RenderedResponse::new(
+            StatusCode::NotFound,
+            mime::PLAIN,
+            ConfigError::NoConfigFile.to_string(),
+        ),
+        Err(err) => RenderedResponse::new(
+            StatusCode::InternalServerError,
+            mime::PLAIN,
+            err.to_string(),
+        ),
+    }
+}
+
+/// The error page for `err` while serving the URL `path`.
+pub(crate) fn error_response(err: &Error, path: &str) -> RenderedResponse {
+    localized_error_response(err, path, Lang::English)
+}
+
+/// The error page for `err` while serving the URL `path`, in `lang`, see [`State::with_lang`].
+pub(crate) fn localized_error_response(err: &Error, path: &str, lang: Lang) -> RenderedResponse {
+    RenderedResponse {
+        error: Some(err.clone()),
+        ..error_page(err, path, lang)
+    }
+}
+
+/// The page for the converter failing on the document at the URL `path` when there's no earlier
+/// version of it to show, see [`stale_response`].
+pub(crate) fn converter_error_response(
+    err: &Error,
+    path: &str,
+    converter: Option<&str>,
+    lang: Lang,
+) -> RenderedResponse {
+    let status = err.status();
+    let body = format!(
+        "{}",
+        html! {
+            : doctype::HTML;
+            html {
+                head {
+                    meta(charset="utf-8");
+                    title : "rs-readme";
+                    link(rel="stylesheet", href="/static/style.css");
+                }
+                body {
+                    div(class="rs-readme-converter-error") {
+                        h1 : lang.tr(status.canonical_reason());
+                        pre : err.to_string();
+                        : Raw(converter_links(path, converter, lang));
+                    }
+                }
+            }
+        }
+    );
+
+    RenderedResponse {
+        error: Some(err.clone()),
+        ..RenderedResponse::new(status, mime::HTML, body)
+    }
+}
+
+/// The `page` last rendered for the document at the URL `path`, served when converting it
+/// again failed with `err`, under a banner saying it's out of date and why. Pages live-reloading
+/// it get the new version once the converter works again, which drops the banner.
+fn stale_response(
+    err: &Error,
+    path: &str,
+    converter: Option<&str>,
+    page: &RenderedPage,
+    lang: Lang,
+) -> RenderedResponse {
+    warn!("Serving the last version of {} rendered: {}", path, err);
+    let banner = format!(
+        "{}",
+        html! {
+            div(class="rs-readme-failed rs-readme-stale", role="alert") {
+                : lang.tr("This is the last version rendered, converting it again failed:");
+                pre : err.to_string();
+                : Raw(converter_links(path, converter, lang));
+            }
+        }
+    );
+    let body = match page.html.split_once("<body>") {
+        Some((head, rest)) => format!("{}<body>{}{}", head, banner, rest),
+        None => banner + &page.html,
+    };
+
+    RenderedResponse {
+        cache_control: Some("no-store".to_string()),
+        ..RenderedResponse::new(StatusCode::Ok, mime::HTML, body)
+    }
+}
+
+/// Links to convert the document at the URL `path` with the same `converter` again, or offline.
+fn converter_links(path: &str, converter: Option<&str>, lang: Lang) -> String {
+    let retry = match converter {
+        Some(converter) => format!("{}?converter={}", path, converter),
+        None => path.to_string(),
+    };
+    let offline = format!("{}?converter=offline", path);
+    format!(
+        "{}",
+        html! {
+            p {
+                a(href=&retry) : lang.tr("Retry");
+                : " · ";
+                a(href=&offline) : lang.tr("Render offline instead");
+            }
+        }
+    )
+}
+
+/// The expanded page for `err` with `--debug-errors`: everything the error says, like the
+/// converter's whole response, its kind, and the `method`, `url` and `headers` of the request
+/// that hit it. Credentials in the headers are left out.
+pub(crate) fn debug_error_response(
+    err: &Error,
+    method: &str,
+    url: &str,
+    headers: &[(String, String)],
+) -> RenderedResponse {
+    let status = err.status();
+    let body = format!(
+        "{}",
+        html! {
+            : doctype::HTML;
+            html {
+                head {
+                    meta(charset="utf-8");
+                    title : "rs-readme";
+                    link(rel="stylesheet", href="/static/style.css");
+                }
+                body {
+                    div(class="rs-readme-debug-error") {
+                        h1 : status.canonical_reason();
+                        pre : err.to_string();
+                        h2 : "Error";
+                        table {
+                            tr { th : "Status"; td : u16::from(status); }
+                            tr { th : "Code"; td { code : err.code(); } }
+                            tr { th : "Details"; td { pre : format!("{:#?}", err); } }
+                        }
+                        h2 : "Request";
+                        table {
+                            tr { th : "Method"; td : method; }
+                            tr { th : "URL"; td { code : url; } }
+                            @ for (name, value) in headers {
+                                tr {
+                                    th : name;
+                                    td {
+                                        @ if is_credential(name) {
+                                            em : "hidden";
+                                        } else {
+                                            code : value;
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    );
+
+    RenderedResponse {
+        retry_after: match err {
+            Error::Busy { retry_after } => Some(*retry_after),
+            _ => None,
+        },
+        error: Some(err.clone()),
+        ..RenderedResponse::new(status, mime::HTML, body)
+    }
+}
+
+fn is_credential(header: &str) -> bool {
+    ["authorization", "cookie", "proxy-authorization"]
+        .iter()
+        .any(|credential| header.eq_ignore_ascii_case(credential))
+}
+
+/// `err` while serving the URL `path` as JSON, `{"code": ..., "message": ..., "resource": ...}`,
+/// for clients that can't use an error page, see [`wants_json`].
+pub(crate) fn json_error_response(err: &Error, path: &str) -> RenderedResponse {
+    let body = json!({
+        "code": err.code(),
+        "message": err.to_string(),
+        "resource": path,
+    });
+
+    RenderedResponse {
+        retry_after: match err {
+            Error::Busy { retry_after } => Some(*retry_after),
+            _ => None,
+        },
+        error: Some(err.clone()),
+        ..RenderedResponse::new(err.status(), mime::JSON, body.to_string())
+    }
+}
+
+/// Whether errors at the URL `path` should be sent as JSON: for the routes that answer with
+/// JSON, and for clients whose `Accept` header prefers it to HTML.
+pub(crate) fn wants_json(accept: Option<&str>, path: &str) -> bool {
+    JSON_ROUTES.iter().any(|route| path.starts_with(route))
+        || preferred_type(accept, &["text/html", "application/json"]) == Some("application/json")
+}
+
+fn error_page(err: &Error, path: &str, lang: Lang) -> RenderedResponse {
+    match err {
+        Error::NotMarkdown => RenderedResponse::new(
+            StatusCode::BadRequest,
+            mime::HTML,
+            not_markdown_html("rs-readme", path, lang),
+        ),
+        Error::NotFound(resource) => RenderedResponse::new(
+            StatusCode::NotFound,
+            mime::HTML,
+            file_not_found("rs-readme", resource, lang),
+        ),
+        Error::Forbidden { resource, reason } => RenderedResponse::new(
+            StatusCode::Forbidden,
+            mime::HTML,
+            forbidden_html("rs-readme", resource, reason, lang),
+        ),
+        Error::Io {
+            resource,
+            kind: io::ErrorKind::IsADirectory,
+        } => RenderedResponse::new(
+            StatusCode::NotFound,
+            mime::HTML,
+            is_directory_html("rs-readme", resource, lang),
+        ),
+        Error::TooLarge { resource, .. } => RenderedResponse::new(
+            StatusCode::PayloadTooLarge,
+            mime::HTML,
+            too_large_html("rs-readme", resource, &err.to_string(), lang),
+        ),
+        Error::NotText(resource) => RenderedResponse::new(
+            StatusCode::UnsupportedMediaType,
+            mime::HTML,
+            not_text_html("rs-readme", resource, &err.to_string(), lang),
+        ),
+        Error::Busy { retry_after } => RenderedResponse {
+            retry_after: Some(*retry_after),
+            ..RenderedResponse::new(
+                err.status(),
+                mime::HTML,
+                error_html("rs-readme", err.status(), &err.to_string(), lang),
+            )
+        },
+        err => RenderedResponse::new(
+            err.status(),
+            mime::HTML,
+            error_html("rs-readme", err.status(), &err.to_string(), lang),
+        ),
+    }
+}
+
+/// The error HTML indicating the requested file is not markdown
+/// and therefore can't be rendered.
+fn not_markdown_html(title: &str, file: &str, lang: Lang) -> String {
+    let (before, after) = lang.around("{} is not a markdown file and cannot be rendered");
+
+    format!(
+        "{}",
+        html! {
+            : doctype::HTML;
+            html {
+                head {
+                    title : title;
+                }
+                body {
+                    h1 : lang.tr("Not a Markdown File");
+                    p {
+                        : before;
+                        strong : file;
+                        : after;
+                    }
+                }
+        }}
+    )
+}
+
+/// The error HTML indicating the requested file cannot be found.
+fn file_not_found(title: &str, file: &str, lang: Lang) -> String {
+    let (before, after) = lang.around("Couldn't find {}");
+    let (index_before, index_after) = lang.around(
+        "For the index page {} will look for a file named README in the root folder. Otherwise it looks for an exact file name.",
+    );
+
+    format!(
+        "{}",
+        html! {
+            : doctype::HTML;
+            html {
+                head {
+                    title : title;
+                }
+                body {
+                    h1 {
+                        : before;
+                        : file;
+                        : after;
+                    }
+                     p {
+                         : index_before;
+                         em : "rs-readme";
+                         : index_after;
+                     }
+
+                }
+            }
+        }
+    )
+}
+
+/// The error HTML for a file rs-readme isn't allowed to read, with what the OS said about it
+/// so the permissions or security labels getting in the way can be found.
+fn forbidden_html(title: &str, resource: &str, reason: &str, lang: Lang) -> String {
+    let (before, after) = lang.around("{} is there, but rs-readme isn't allowed to read it.");
+
+    format!(
+        "{}",
+        html! {
+            : doctype::HTML;
+            html {
+                head {
+                    title : title;
+                }
+                body {
+                    h1 : lang.tr("Permission Denied");
+                    p {
+                        : before;
+                        strong : resource.replacen("./", "", 1);
+                        : after;
+                    }
+                    pre : reason;
+                    p : lang.tr("Check who owns the file and its permissions, or its SELinux label.");
+                }
+            }
+        }
+    )
+}
+
+/// The error HTML for a folder asked for as if it were a file.
+fn is_directory_html(title: &str, resource: &str, lang: Lang) -> String {
+    let (before, after) = lang.around("{} is a folder, so there's nothing to render.");
+
+    format!(
+        "{}",
+        html! {
+            : doctype::HTML;
+            html {
+                head {
+                    title : title;
+                }
+                body {
+                    h1 : lang.tr("Not a File");
+                    p {
+                        : before;
+                        strong : resource.replacen("./", "", 1);
+                        : after;
+                    }
+                }
+            }
+        }
+    )
+}
+
+/// The error HTML for a file too big to render, linking to the raw file instead.
+fn too_large_html(title: &str, resource: &str, message: &str, lang: Lang) -> String {
+    let raw = raw_href(resource);
+    let (before, after) = lang.around(". The limit can be changed with {}.");
+
+    format!(
+        "{}",
+        html! {
+            : doctype::HTML;
+            html {
+                head {
+                    title : title;
+                }
+                body {
+                    h1 : lang.tr("Too Large to Render");
+                    p {
+                        : message;
+                        : before;
+                        code : "--max-file-size";
+                        : after;
+                    }
+                    p {
+                        a(href=&raw) : lang.tr("View the raw file");
+                    }
+                }
+            }
+        }
+    )
+}
+
+/// The error HTML for a binary file with a text extension, offering to download it instead.
+fn not_text_html(title: &str, resource: &str, message: &str, lang: Lang) -> String {
+    let raw = raw_href(resource);
+
+    format!(
+        "{}",
+        html! {
+            : doctype::HTML;
+            html {
+                head {
+                    title : title;
+                }
+                body {
+                    h1 : lang.tr("Not a Text File");
+                    p {
+                        : message;
+                        : lang.tr(", it looks binary or couldn't be decoded, so it can't be rendered.");
+                    }
+                    p {
+                        a(href=&raw, download) : lang.tr("Download the raw file");
+                    }
+                }
+            }
+        }
+    )
+}
+
+/// The error HTML for anything without a more specific page.
+fn error_html(title: &str, status: StatusCode, message: &str, lang: Lang) -> String {
+    format!(
+        "{}",
+        html! {
+            : doctype::HTML;
+            html {
+                head {
+                    title : title;
+                }
+                body {
+                    h1 : lang.tr(status.canonical_reason());
+                    pre : message;
+                }
+            }
+        }
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_not_markdown_html() {
+        let expected = "\
+<!DOCTYPE html>\
+<html>\
+<head><title>rs-readme</title></head>\
+<body>\
+<h1>Not a Markdown File</h1>\
+<p><strong>test_file</strong> is not a markdown file and cannot be rendered</p>\
+</body>\
+</html>\
+";
+
+        let actual = not_markdown_html("rs-readme", "test_file", Lang::English);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn too_large_links_to_the_raw_file() {
+        let err = Error::TooLarge {
+            resource: "./docs/log.md".to_string(),
+            size: 20,
+            limit: 10,
+        };
+
+        let res = error_response(&err, "/docs/log.md");
+
+        assert_eq!(res.status, 413);
+        match res.body {
+            ResponseBody::Bytes(body) => assert!(String::from_utf8(body)
+                .unwrap()
+                .contains("<a href=\"/__rs-readme/raw/docs/log.md\">View the raw file</a>")),
+            ResponseBody::Stream(_) => panic!("error pages aren't streamed"),
+        }
+    }
+
+    #[test]
+    fn binary_files_offer_a_download() {
+        let err = Error::NotText("./data.md".to_string());
+
+        let res = error_response(&err, "/data.md");
+
+        assert_eq!(res.status, 415);
+        match res.body {
+            ResponseBody::Bytes(body) => assert!(String::from_utf8(body).unwrap().contains(
+                "<a href=\"/__rs-readme/raw/data.md\" download>Download the raw file</a>"
+            )),
+            ResponseBody::Stream(_) => panic!("error pages aren't streamed"),
+        }
+    }
+
+    #[test]
+    fn errors_keep_their_status() {
+        let res = error_response(&Error::RateLimited { reset: None }, "/a.md");
+
+        assert_eq!(res.status, 429);
+        assert_eq!(res.mime, mime::HTML.to_string());
+    }
+
+    #[test]
+    fn sources_go_to_clients_preferring_them() {
+        assert_eq!(source_type(Some("text/markdown")), Some("text/markdown"));
+        assert_eq!(
+            source_type(Some("text/html;q=0.5, text/plain")),
+            Some("text/plain")
+        );
+        assert_eq!(
+            source_type(Some("text/html,application/xhtml+xml,*/*;q=0.8")),
+            None
+        );
+        assert_eq!(source_type(Some("*/*")), None);
+        assert_eq!(source_type(Some("text/markdown;q=0, text/html")), None);
+        assert_eq!(source_type(None), None);
+    }
+}
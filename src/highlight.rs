@@ -0,0 +1,421 @@
+#[cfg(feature = "server")]
+use std::ffi::OsStr;
+#[cfg(feature = "server")]
+use std::path::Path;
+use std::sync::OnceLock;
+
+#[cfg(feature = "server")]
+use horrorshow::prelude::*;
+use pulldown_cmark::{CodeBlockKind, Event, Tag};
+use syntect::highlighting::{Color, FontStyle, Theme, ThemeSet};
+use syntect::html::{ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+use crate::code_theme::CodeTheme;
+use crate::fences::FenceInfo;
+
+/// What the classes of highlighted code start with, so the themes only style that.
+const CLASS_PREFIX: &str = "rs-readme-hl-";
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed {
+    prefix: CLASS_PREFIX,
+};
+
+impl CodeTheme {
+    /// The CSS coloring highlighted code with the light theme, or the dark one on dark pages.
+    pub fn css(self) -> String {
+        let (light, dark) = match self {
+            CodeTheme::GitHub => ("InspiredGitHub", "base16-ocean.dark"),
+            CodeTheme::Solarized => ("Solarized (light)", "Solarized (dark)"),
+            CodeTheme::Ocean => ("base16-ocean.light", "base16-ocean.dark"),
+        };
+        let themes = &themes().themes;
+
+        themes.get(light).map_or_else(String::new, |theme| {
+            theme_css(theme, ":root:not([data-rs-readme-theme=\"dark\"])")
+        }) + &themes.get(dark).map_or_else(String::new, |theme| {
+            theme_css(theme, ":root[data-rs-readme-theme=\"dark\"]")
+        })
+    }
+}
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn themes() -> &'static ThemeSet {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    THEMES.get_or_init(ThemeSet::load_defaults)
+}
+
+/// The rules of `theme` for the code it highlights, each under the `root` selector. The
+/// theme's background is left out for the page's own.
+fn theme_css(theme: &Theme, root: &str) -> String {
+    let mut css = String::new();
+    if let Some(foreground) = theme.settings.foreground {
+        css.push_str(&format!(
+            "{root} .{prefix}source, {root} .{prefix}text {{ color: {}; }}\n",
+            css_color(foreground),
+            root = root,
+            prefix = CLASS_PREFIX,
+        ));
+    }
+
+    for item in &theme.scopes {
+        let selectors: Vec<String> = item
+            .scope
+            .selectors
+            .iter()
+            .map(|selector| {
+                let scopes: Vec<String> = selector
+                    .extract_scopes()
+                    .iter()
+                    .map(|scope| {
+                        scope
+                            .build_string()
+                            .split('.')
+                            .map(|atom| format!(".{}{}", CLASS_PREFIX, atom))
+                            .collect()
+                    })
+                    .collect();
+                scopes.join(" ")
+            })
+            .filter(|scopes| !scopes.is_empty())
+            .map(|scopes| format!("{} {}", root, scopes))
+            .collect();
+
+        let mut declarations = String::new();
+        if let Some(foreground) = item.style.foreground {
+            declarations.push_str(&format!(" color: {};", css_color(foreground)));
+        }
+        if let Some(font_style) = item.style.font_style {
+            if font_style.contains(FontStyle::BOLD) {
+                declarations.push_str(" font-weight: bold;");
+            }
+            if font_style.contains(FontStyle::ITALIC) {
+                declarations.push_str(" font-style: italic;");
+            }
+            if font_style.contains(FontStyle::UNDERLINE) {
+                declarations.push_str(" text-decoration: underline;");
+            }
+        }
+        if !selectors.is_empty() && !declarations.is_empty() {
+            css.push_str(&format!("{} {{{} }}\n", selectors.join(", "), declarations));
+        }
+    }
+
+    css
+}
+
+fn css_color(color: Color) -> String {
+    if color.a == 0xff {
+        format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+    } else {
+        format!(
+            "rgba({}, {}, {}, {:.2})",
+            color.r,
+            color.g,
+            color.b,
+            f32::from(color.a) / 255.0
+        )
+    }
+}
+
+/// Highlights `code` as the language a fenced code block names (`rust`, `py`, ...), `None` if
+/// it's not one we know.
+pub(crate) fn highlight_block(code: &str, language: &str) -> Option<String> {
+    let syntax = syntaxes().find_syntax_by_token(language)?;
+
+    Some(format!(
+        "<pre><code class=\"language-{}\">{}</code></pre>\n",
+        escape(language),
+        highlight(code, syntax).unwrap_or_else(|| escape(code))
+    ))
+}
+
+/// A fenced code block, highlighted when `syntax` is known, with its marked lines wrapped in
+/// `rs-readme-code-mark` spans and its title in a caption above it.
+fn annotated_block(code: &str, fence: &FenceInfo, syntax: Option<&SyntaxReference>) -> String {
+    let lines = match syntax {
+        Some(syntax) => highlight_lines(code, syntax),
+        None => LinesWithEndings::from(code).map(escape).collect(),
+    };
+    let mut body = String::with_capacity(code.len());
+    for (number, line) in lines.iter().enumerate().map(|(at, line)| (at + 1, line)) {
+        let line = line.replace(['\r', '\n'], "");
+        if fence.is_marked(number) {
+            body.push_str(&format!(
+                "<span class=\"rs-readme-code-mark\">{}</span>",
+                line
+            ));
+        } else {
+            body.push_str(&line);
+        }
+        body.push('\n');
+    }
+
+    let class = if fence.language.is_empty() {
+        String::new()
+    } else {
+        format!(" class=\"language-{}\"", escape(&fence.language))
+    };
+    let block = format!("<pre><code{}>{}</code></pre>\n", class, body);
+    match &fence.title {
+        Some(title) => format!(
+            "<figure class=\"rs-readme-code-block\"><figcaption class=\"rs-readme-code-title\">{}</figcaption>{}</figure>\n",
+            escape(title),
+            block
+        ),
+        None => block,
+    }
+}
+
+/// Swaps the fenced code blocks in `events` that are in a language we know, or that mark lines or
+/// have a title, for highlighted HTML.
+pub(crate) fn highlight_code_blocks<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+) -> impl Iterator<Item = Event<'a>> {
+    CodeBlocks { events }
+}
+
+struct CodeBlocks<I> {
+    events: I,
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for CodeBlocks<I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        let event = self.events.next()?;
+        let fence = match &event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => FenceInfo::parse(info),
+            _ => return Some(event),
+        };
+        let syntax = Some(fence.language.as_str())
+            .filter(|language| !language.is_empty())
+            .and_then(|language| syntaxes().find_syntax_by_token(language));
+        if syntax.is_none() && !fence.is_annotated() {
+            return Some(event);
+        }
+
+        let mut code = String::new();
+        for event in &mut self.events {
+            match event {
+                Event::Text(text) => code.push_str(&text),
+                Event::End(_) => break,
+                _ => {}
+            }
+        }
+
+        let html = if fence.is_annotated() {
+            annotated_block(&code, &fence, syntax)
+        } else {
+            highlight_block(&code, &fence.language)?
+        };
+        Some(Event::Html(html.into()))
+    }
+}
+
+/// The source file `name` highlighted as a table of lines, each numbered and linkable as `#L42`.
+///
+/// The language is picked from the file's extension or name, then its first line, falling back
+/// to plain text.
+#[cfg(feature = "server")]
+pub(crate) fn code_view(name: &str, source: &str) -> String {
+    let syntaxes = syntaxes();
+    let token = Path::new(name)
+        .extension()
+        .and_then(OsStr::to_str)
+        .unwrap_or(name);
+    let syntax = syntaxes
+        .find_syntax_by_token(token)
+        .or_else(|| syntaxes.find_syntax_by_first_line(source))
+        .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+
+    let lines = highlight_lines(source, syntax);
+
+    format!(
+        "{}",
+        html! {
+            table(class="rs-readme-code") {
+                tbody {
+                    @ for (number, line) in lines.iter().enumerate().map(|(at, line)| (at + 1, line)) {
+                        tr(id=format!("L{}", number)) {
+                            td(class="rs-readme-line-number") {
+                                a(href=format!("#L{}", number)) : number;
+                            }
+                            td(class="rs-readme-line") : Raw(line.replace(['\r', '\n'], ""));
+                        }
+                    }
+                }
+            }
+        }
+    )
+}
+
+/// `code` as HTML with its tokens in spans classed by their scopes, for the [`CodeTheme`]s to
+/// color. `None` if syntect couldn't parse it.
+fn highlight(code: &str, syntax: &SyntaxReference) -> Option<String> {
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, syntaxes(), CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        generator
+            .parse_html_for_line_which_includes_newline(line)
+            .ok()?;
+    }
+
+    Some(generator.finalize())
+}
+
+/// Each line of `code` as HTML, newlines included. Spans still open at the end of a line are
+/// closed there and opened again on the next, so every line stands on its own.
+fn highlight_lines(code: &str, syntax: &SyntaxReference) -> Vec<String> {
+    let html = match highlight(code, syntax) {
+        Some(html) => html,
+        None => return LinesWithEndings::from(code).map(escape).collect(),
+    };
+
+    let mut lines = Vec::new();
+    let mut open: Vec<&str> = Vec::new();
+    let mut line = String::new();
+    let mut has_text = false;
+    let mut rest = html.as_str();
+    while let Some(at) = rest.find(['<', '\n']) {
+        has_text |= at > 0;
+        line.push_str(&rest[..at]);
+        rest = &rest[at..];
+        if let Some(after) = rest.strip_prefix('\n') {
+            line.push('\n');
+            line.push_str(&"</span>".repeat(open.len()));
+            lines.push(std::mem::replace(&mut line, open.concat()));
+            has_text = false;
+            rest = after;
+            continue;
+        }
+
+        let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+        let tag = &rest[..end];
+        if tag.starts_with("</") {
+            open.pop();
+        } else {
+            open.push(tag);
+        }
+        line.push_str(tag);
+        rest = &rest[end..];
+    }
+    if has_text || !rest.is_empty() {
+        line.push_str(rest);
+        lines.push(line);
+    }
+
+    lines
+}
+
+fn escape(text: &str) -> String {
+    format!("{}", html! { : text })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pulldown_cmark::{html, Parser};
+
+    #[test]
+    fn highlights_known_languages() {
+        let html = highlight_block("let x = 1;\n", "rust").unwrap();
+
+        assert!(html.starts_with(
+            "<pre><code class=\"language-rust\"><span class=\"rs-readme-hl-source rs-readme-hl-rust\">"
+        ));
+        assert!(html.contains("let"));
+        assert_eq!(highlight_block("x", "not-a-language"), None);
+    }
+
+    #[test]
+    fn leaves_unknown_code_blocks_alone() {
+        let md = "```not-a-language\n<x>\n```\n\n```rust,ignore\nfn main() {}\n```\n";
+        let mut out = String::new();
+
+        html::push_html(&mut out, highlight_code_blocks(Parser::new(md)));
+
+        assert!(out.starts_with(
+            "<pre><code class=\"language-not-a-language\">&lt;x&gt;\n</code></pre>\n"
+        ));
+        assert!(out.contains("<pre><code class=\"language-rust\"><span class=\"rs-readme-hl-"));
+    }
+
+    #[test]
+    fn marks_lines_and_shows_titles() {
+        let md = "```rust {2} title=\"src/main.rs\"\nfn main() {\n    run();\n}\n```\n\n\
+                  ```not-a-language {1}\n<x>\n```\n";
+        let mut out = String::new();
+
+        html::push_html(&mut out, highlight_code_blocks(Parser::new(md)));
+
+        assert!(out.starts_with(
+            "<figure class=\"rs-readme-code-block\">\
+             <figcaption class=\"rs-readme-code-title\">src/main.rs</figcaption>\
+             <pre><code class=\"language-rust\"><span class=\"rs-readme-hl-source rs-readme-hl-rust\">"
+        ));
+        assert_eq!(
+            out.matches("<span class=\"rs-readme-code-mark\">").count(),
+            2
+        );
+        let marked = out
+            .split("<span class=\"rs-readme-code-mark\">")
+            .nth(1)
+            .unwrap();
+        let marked = &marked[..marked.find('\n').unwrap()];
+        assert!(marked.contains("run") && !marked.contains("main"));
+        assert!(out.ends_with(
+            "<pre><code class=\"language-not-a-language\"><span class=\"rs-readme-code-mark\">&lt;x&gt;</span>\n</code></pre>\n"
+        ));
+    }
+
+    #[test]
+    fn code_view_numbers_lines() {
+        let html = code_view("main.rs", "fn main() {\n}\n");
+
+        assert!(html.contains(
+            "<tr id=\"L1\"><td class=\"rs-readme-line-number\"><a href=\"#L1\">1</a></td>"
+        ));
+        assert!(html.contains("<tr id=\"L2\">"));
+        assert!(!html.contains("id=\"L3\""));
+    }
+
+    #[test]
+    fn code_view_lines_stand_alone() {
+        let lines = highlight_lines(
+            "/* one\ntwo */\n",
+            syntaxes().find_syntax_by_token("rs").unwrap(),
+        );
+
+        assert_eq!(lines.len(), 2);
+        for line in &lines {
+            assert_eq!(
+                line.matches("<span").count(),
+                line.matches("</span>").count()
+            );
+        }
+        assert!(lines[1].starts_with(
+            "<span class=\"rs-readme-hl-source rs-readme-hl-rust\">\
+             <span class=\"rs-readme-hl-comment rs-readme-hl-block rs-readme-hl-rust\">two "
+        ));
+    }
+
+    #[test]
+    fn code_themes_follow_the_page() {
+        let css = CodeTheme::Solarized.css();
+
+        assert!(css.contains(
+            ":root:not([data-rs-readme-theme=\"dark\"]) .rs-readme-hl-source, \
+             :root:not([data-rs-readme-theme=\"dark\"]) .rs-readme-hl-text { color: #657b83; }"
+        ));
+        assert!(css.contains(
+            ":root[data-rs-readme-theme=\"dark\"] .rs-readme-hl-comment, \
+             :root[data-rs-readme-theme=\"dark\"] .rs-readme-hl-meta.rs-readme-hl-documentation \
+             { color: #586e75; }"
+        ));
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// The languages rs-readme's own pages, like its error pages, can be shown in, see `--lang`.
+/// Documents and the messages of errors are shown as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lang {
+    #[default]
+    English,
+    Japanese,
+}
+
+impl Lang {
+    /// The language's tag, like `ja`.
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::English => "en",
+            Lang::Japanese => "ja",
+        }
+    }
+
+    /// `text`, rs-readme's English wording of something on its pages, in this language. Text
+    /// without a translation stays English.
+    pub(crate) fn tr(self, text: &'static str) -> &'static str {
+        let table = match self {
+            Lang::English => return text,
+            Lang::Japanese => JAPANESE,
+        };
+        table
+            .iter()
+            .find(|(english, _)| *english == text)
+            .map_or(text, |(_, translated)| translated)
+    }
+
+    /// The translated `text` before and after the `{}` in it, for putting markup like the name
+    /// of a file where the language wants it.
+    pub(crate) fn around(self, text: &'static str) -> (&'static str, &'static str) {
+        self.tr(text)
+            .split_once("{}")
+            .unwrap_or((self.tr(text), ""))
+    }
+}
+
+impl FromStr for Lang {
+    type Err = String;
+
+    /// Reads a language tag like `ja` or `ja-JP`.
+    fn from_str(tag: &str) -> Result<Lang, String> {
+        let primary = tag.split(['-', '_']).next().unwrap_or_default();
+        match primary.to_ascii_lowercase().as_str() {
+            "en" => Ok(Lang::English),
+            "ja" => Ok(Lang::Japanese),
+            _ => Err(format!("Unsupported language {}, try en or ja", tag)),
+        }
+    }
+}
+
+impl fmt::Display for Lang {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// Japanese for the English text of rs-readme's pages.
+const JAPANESE: &[(&str, &str)] = &[
+    ("Not a Markdown File", "Markdown ファイルではありません"),
+    (
+        "{} is not a markdown file and cannot be rendered",
+        "{} は Markdown ファイルではないため表示できません",
+    ),
+    ("Couldn't find {}", "{} が見つかりません"),
+    (
+        "For the index page {} will look for a file named README in the root folder. Otherwise it looks for an exact file name.",
+        "インデックスページでは {} がルートフォルダーの README という名前のファイルを探します。それ以外のページではファイル名が完全に一致するファイルを探します。",
+    ),
+    ("Permission Denied", "アクセスが拒否されました"),
+    (
+        "{} is there, but rs-readme isn't allowed to read it.",
+        "{} は存在しますが、rs-readme には読み取る権限がありません。",
+    ),
+    (
+        "Check who owns the file and its permissions, or its SELinux label.",
+        "ファイルの所有者とパーミッション、または SELinux ラベルを確認してください。",
+    ),
+    ("Not a File", "ファイルではありません"),
+    (
+        "{} is a folder, so there's nothing to render.",
+        "{} はフォルダーのため、表示するものがありません。",
+    ),
+    ("Too Large to Render", "大きすぎて表示できません"),
+    (
+        ". The limit can be changed with {}.",
+        "。上限は {} で変更できます。",
+    ),
+    ("View the raw file", "元のファイルを表示"),
+    ("Not a Text File", "テキストファイルではありません"),
+    (
+        ", it looks binary or couldn't be decoded, so it can't be rendered.",
+        "。バイナリファイルか、デコードできなかったため表示できません。",
+    ),
+    ("Download the raw file", "元のファイルをダウンロード"),
+    ("Something went wrong", "問題が発生しました"),
+    (
+        "rs-readme hit a bug rendering this page. It's still running, so other pages and clients aren't affected.",
+        "rs-readme でこのページの表示中に不具合が発生しました。動作は続いているため、他のページやクライアントには影響ありません。",
+    ),
+    (
+        "The details are in its log under request {}, please include them if you report it.",
+        "詳細はリクエスト {} としてログに記録されています。報告する際はこれを添えてください。",
+    ),
+    (
+        "rs-readme only answers requests from the machine it's running on, start it with --allow-remote to share it",
+        "rs-readme は実行中のマシンからのリクエストにのみ応答します。共有するには --allow-remote を付けて起動してください",
+    ),
+    ("Retry", "再試行"),
+    ("Render offline instead", "オフラインで表示する"),
+    (
+        "This is the last version rendered, converting it again failed:",
+        "最後に表示できたバージョンです。再度の変換に失敗しました:",
+    ),
+    ("Bad Request", "不正なリクエスト"),
+    ("Forbidden", "アクセスが禁止されています"),
+    ("Not Found", "見つかりません"),
+    ("Payload Too Large", "大きすぎます"),
+    ("Unsupported Media Type", "対応していない形式です"),
+    ("Too Many Requests", "リクエストが多すぎます"),
+    ("Internal Server Error", "サーバーエラー"),
+    ("Bad Gateway", "変換に失敗しました"),
+    ("Service Unavailable", "混み合っています"),
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn reads_language_tags() {
+        assert_eq!("en".parse(), Ok(Lang::English));
+        assert_eq!("ja-JP".parse(), Ok(Lang::Japanese));
+        assert_eq!("JA".parse(), Ok(Lang::Japanese));
+        assert!("fr".parse::<Lang>().is_err());
+    }
+
+    #[test]
+    fn translates_with_english_fallback() {
+        assert_eq!(Lang::English.tr("Not a File"), "Not a File");
+        assert_eq!(Lang::Japanese.tr("Not a File"), "ファイルではありません");
+        assert_eq!(Lang::Japanese.tr("Untranslated"), "Untranslated");
+        assert_eq!(
+            Lang::Japanese.around("Couldn't find {}"),
+            ("", " が見つかりません")
+        );
+    }
+
+    #[test]
+    fn every_translation_keeps_its_placeholder() {
+        for (english, translated) in JAPANESE {
+            assert_eq!(
+                english.matches("{}").count(),
+                translated.matches("{}").count(),
+                "{}",
+                english
+            );
+        }
+    }
+}
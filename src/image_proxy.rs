@@ -0,0 +1,270 @@
+use async_std::future::timeout;
+use futures::io::AsyncReadExt;
+use log::debug;
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::content_finder::ContentHash;
+
+/// Where pages fetch proxied images from, with the image's URL in the `url` query parameter.
+pub const IMAGE_ROUTE: &str = "/__rs-readme/image";
+
+/// How long to wait for an image before falling back to the cached copy.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The biggest image the proxy passes on, in bytes.
+const MAX_IMAGE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// The `Content-Security-Policy` proxied images are sent with, so one opened on its own, like an
+/// SVG with a script in it, can't do anything as a page of this server.
+pub(crate) const IMAGE_POLICY: &str = "sandbox; default-src 'none'";
+
+/// A remote image, as fetched or as cached.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub mime: String,
+    pub body: Vec<u8>,
+}
+
+/// Fetches the remote images documents show (shields.io badges and the like) for them, keeping
+/// a copy of each on disk so they still show up offline or behind a proxy, see
+/// `--proxy-images`.
+///
+/// Only the images pages were pointed at are fetched, so it can't be used to reach anything
+/// else, and only responses that are images up to 10MiB are passed on or cached.
+pub struct ImageProxy {
+    cache_dir: PathBuf,
+    proxied: Mutex<HashSet<String>>,
+}
+
+impl ImageProxy {
+    /// Caches images in `cache_dir`, which is created when the first one is stored.
+    pub fn new(cache_dir: impl Into<PathBuf>) -> ImageProxy {
+        ImageProxy {
+            cache_dir: cache_dir.into(),
+            proxied: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Points the `src` of every remote `<img>` in `html` at the [`IMAGE_ROUTE`], remembering
+    /// them as images the proxy may fetch.
+    pub(crate) fn rewrite(&self, html: &str) -> String {
+        let mut urls = Vec::new();
+        let html = proxy_images(html, &mut urls);
+        self.lock_proxied().extend(urls);
+        html
+    }
+
+    /// Whether a page was pointed at the image at `url`, see [`ImageProxy::rewrite`].
+    pub(crate) fn proxies(&self, url: &str) -> bool {
+        self.lock_proxied().contains(url)
+    }
+
+    fn lock_proxied(&self) -> MutexGuard<'_, HashSet<String>> {
+        match self.proxied.lock() {
+            Ok(proxied) => proxied,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// The image at `url`: fetched when the network allows, otherwise the copy from the last
+    /// time it was.
+    pub async fn image(&self, url: &str) -> Option<Image> {
+        match timeout(FETCH_TIMEOUT, fetch(url)).await {
+            Ok(Ok(image)) => {
+                if let Err(err) = self.store(url, &image) {
+                    debug!("Could not cache {}: {}", url, err);
+                }
+                Some(image)
+            }
+            Ok(Err(err)) => {
+                debug!("Could not fetch {}, trying the cache: {}", url, err);
+                self.cached(url)
+            }
+            Err(_) => {
+                debug!("Timed out fetching {}, trying the cache", url);
+                self.cached(url)
+            }
+        }
+    }
+
+    fn cached(&self, url: &str) -> Option<Image> {
+        let path = self.path_of(url);
+        Some(Image {
+            mime: fs::read_to_string(path.with_extension("type")).ok()?,
+            body: fs::read(path).ok()?,
+        })
+    }
+
+    fn store(&self, url: &str, image: &Image) -> std::io::Result<()> {
+        let path = self.path_of(url);
+        fs::create_dir_all(&self.cache_dir)?;
+        fs::write(&path, &image.body)?;
+        fs::write(path.with_extension("type"), &image.mime)
+    }
+
+    fn path_of(&self, url: &str) -> PathBuf {
+        self.cache_dir
+            .join(ContentHash::of(url.as_bytes()).to_string())
+    }
+}
+
+impl Default for ImageProxy {
+    /// Caches images in `rs-readme-images` in the temp folder.
+    fn default() -> Self {
+        ImageProxy::new(std::env::temp_dir().join("rs-readme-images"))
+    }
+}
+
+async fn fetch(url: &str) -> Result<Image, String> {
+    let mut resp = surf::get(url).await.map_err(|err| err.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("{} answered {}", url, resp.status()));
+    }
+
+    let mime = match resp.content_type() {
+        Some(mime) if mime.basetype() == "image" => mime.to_string(),
+        other => return Err(format!("{} isn't an image: {:?}", url, other)),
+    };
+    let too_big = || format!("{} is bigger than {} bytes", url, MAX_IMAGE_SIZE);
+    if resp.len().is_some_and(|len| len as u64 > MAX_IMAGE_SIZE) {
+        return Err(too_big());
+    }
+    let mut body = Vec::new();
+    resp.take_body()
+        .take(MAX_IMAGE_SIZE + 1)
+        .read_to_end(&mut body)
+        .await
+        .map_err(|err| err.to_string())?;
+    if body.len() as u64 > MAX_IMAGE_SIZE {
+        return Err(too_big());
+    }
+
+    Ok(Image { mime, body })
+}
+
+/// Points the `src` of every remote `<img>` in `html` at the [`IMAGE_ROUTE`], adding their URLs
+/// to `urls`.
+fn proxy_images(html: &str, urls: &mut Vec<String>) -> String {
+    const SRC: &str = " src=\"";
+
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(img) = rest.find("<img") {
+        let tag_end = match rest[img..].find('>') {
+            Some(end) => img + end,
+            None => break,
+        };
+        let src_start = match rest[img..tag_end].find(SRC) {
+            Some(at) => img + at + SRC.len(),
+            None => {
+                out.push_str(&rest[..tag_end]);
+                rest = &rest[tag_end..];
+                continue;
+            }
+        };
+        let src_end = src_start + rest[src_start..tag_end].find('"').unwrap_or(0);
+        let src = rest[src_start..src_end].replace("&amp;", "&");
+
+        out.push_str(&rest[..src_start]);
+        if is_remote(&src) {
+            out.push_str(&format!("{}?url={}", IMAGE_ROUTE, encode(&src)));
+            urls.push(src);
+        } else {
+            out.push_str(&rest[src_start..src_end]);
+        }
+        rest = &rest[src_end..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Percent-encodes `value` for a query parameter.
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Whether `url` is one the proxy will fetch.
+pub(crate) fn is_remote(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rs-readme-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn rewrites_remote_images() {
+        let html =
+            "<p><img src=\"https://img.shields.io/badge/a-b-green?style=flat&amp;x=1\" alt=\"a\"> \
+                    <img src=\"./logo.png\"><img alt=\"none\"></p>";
+
+        let proxy = ImageProxy::new(cache_dir("rewrites"));
+
+        assert_eq!(
+            proxy.rewrite(html),
+            "<p><img src=\"/__rs-readme/image?url=https%3A%2F%2Fimg.shields.io%2Fbadge%2Fa-b-green%3Fstyle%3Dflat%26x%3D1\" alt=\"a\"> \
+             <img src=\"./logo.png\"><img alt=\"none\"></p>"
+        );
+        assert!(proxy.proxies("https://img.shields.io/badge/a-b-green?style=flat&x=1"));
+        assert!(!proxy.proxies("http://169.254.169.254/latest/meta-data/"));
+    }
+
+    #[async_std::test]
+    async fn serves_the_cached_copy_when_fetching_fails() {
+        let dir = cache_dir("images");
+        let url = format!("{}/badge.svg", mockito::server_url());
+        let m = mockito::mock("GET", "/badge.svg")
+            .with_header("content-type", "image/svg+xml")
+            .with_body("<svg>badge</svg>")
+            .create();
+        let proxy = ImageProxy::new(&dir);
+
+        let fetched = proxy.image(&url).await;
+        drop(m);
+        let _gone = mockito::mock("GET", "/badge.svg").with_status(500).create();
+        let cached = proxy.image(&url).await;
+        let _ = fs::remove_dir_all(&dir);
+
+        let image = Image {
+            mime: "image/svg+xml".to_string(),
+            body: b"<svg>badge</svg>".to_vec(),
+        };
+        assert_eq!(fetched, Some(image.clone()));
+        assert_eq!(cached, Some(image));
+    }
+
+    #[async_std::test]
+    async fn only_passes_on_images() {
+        let dir = cache_dir("not-images");
+        let _m = mockito::mock("GET", "/page.html")
+            .with_header("content-type", "text/html")
+            .with_body("<p>hi</p>")
+            .create();
+        let proxy = ImageProxy::new(&dir);
+
+        let image = proxy
+            .image(&format!("{}/page.html", mockito::server_url()))
+            .await;
+
+        assert_eq!(image, None);
+        assert!(!Path::new(&dir).exists());
+    }
+}
@@ -0,0 +1,350 @@
+use std::path::{Path, PathBuf};
+
+use crate::content_finder::{ContentFinder, DocumentFormat};
+use crate::links::normalize;
+use crate::pre_process::PreProcessor;
+
+/// Pulls other markdown files into a document where a line says
+/// `<!-- include: ./shared/setup.md -->`, so a common section can live in one file.
+///
+/// Source code comes in with mdBook's `{{#include ../src/lib.rs:usage}}`, taking what's between
+/// `ANCHOR: usage` and `ANCHOR_END: usage` comments, or a line range like `:10:20`, `:10:` or
+/// `:10`. Outside a fenced code block it's put in one.
+///
+/// Paths are relative to the document doing the including and can't leave the root folder.
+/// Included files can include others, but not themselves. Include comments in fenced code
+/// blocks are left alone, directives that can't be followed are replaced with a note saying why.
+#[derive(Debug, Default)]
+pub struct Includes;
+
+impl PreProcessor for Includes {
+    fn process(&self, path: &str, source: String, finder: &dyn ContentFinder) -> String {
+        if DocumentFormat::of(Path::new(path)) != Some(DocumentFormat::Markdown)
+            || !(source.contains("<!--") || source.contains("{{#include"))
+        {
+            return source;
+        }
+
+        let mut including = normalize(Path::new(path)).into_iter().collect();
+        expand(&source, path, finder, &mut including)
+    }
+}
+
+/// `source` with its include directives replaced, `including` is the chain of files that got us
+/// here.
+fn expand(
+    source: &str,
+    path: &str,
+    finder: &dyn ContentFinder,
+    including: &mut Vec<PathBuf>,
+) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut fence: Option<&str> = None;
+
+    for line in source.split_inclusive('\n') {
+        let trimmed = line.trim();
+        let included = match fence {
+            Some(marker) if trimmed.starts_with(marker) => {
+                fence = None;
+                None
+            }
+            Some(_) => snippet_directive(trimmed).map(|(target, part)| {
+                snippet(path, target, part, finder).unwrap_or_else(|err| not_included(target, &err))
+            }),
+            None if trimmed.starts_with("```") || trimmed.starts_with("~~~") => {
+                fence = Some(&trimmed[..3]);
+                None
+            }
+            None => match (directive(trimmed), snippet_directive(trimmed)) {
+                (Some(target), _) => Some(include(path, target, finder, including)),
+                (None, Some((target, part))) => Some(match snippet(path, target, part, finder) {
+                    Ok(code) => fenced(target, &code),
+                    Err(err) => not_included(target, &err),
+                }),
+                (None, None) => None,
+            },
+        };
+
+        match included {
+            Some(included) => {
+                out.push_str(&included);
+                if !out.ends_with('\n') {
+                    out.push('\n');
+                }
+            }
+            None => out.push_str(line),
+        }
+    }
+
+    out
+}
+
+/// The path in an `<!-- include: path -->` line.
+fn directive(line: &str) -> Option<&str> {
+    line.strip_prefix("<!--")?
+        .strip_suffix("-->")?
+        .trim()
+        .strip_prefix("include:")
+        .map(str::trim)
+}
+
+/// The path and which part of it to take from a `{{#include path:part}}` line.
+fn snippet_directive(line: &str) -> Option<(&str, Option<&str>)> {
+    let spec = line.strip_prefix("{{#include")?.strip_suffix("}}")?.trim();
+
+    Some(match spec.split_once(':') {
+        Some((target, part)) => (target, Some(part)),
+        None => (spec, None),
+    })
+}
+
+/// The lines of the source file `target` that `part` picks out, included from the document at
+/// `from`, or why they couldn't be.
+fn snippet(
+    from: &str,
+    target: &str,
+    part: Option<&str>,
+    finder: &dyn ContentFinder,
+) -> Result<String, String> {
+    let resolved =
+        resolve(from, target).ok_or_else(|| "it's outside the root folder".to_string())?;
+    let (source, _) = finder
+        .source_for(&format!("./{}", resolved.to_string_lossy()))
+        .map_err(|err| err.to_string())?;
+    let lines: Vec<&str> = source.lines().collect();
+
+    let selected = match part {
+        None => lines.into_iter().filter(|line| !is_anchor(line)).collect(),
+        Some(range) if range.chars().all(|c| c.is_ascii_digit() || c == ':') => {
+            line_range(&lines, range)?
+        }
+        Some(anchor) => anchored(&lines, anchor)?,
+    };
+
+    Ok(selected.join("\n") + "\n")
+}
+
+/// The lines `range` (`10:20`, `10:`, `:20` or just `10`, counting from 1) covers.
+fn line_range<'a>(lines: &[&'a str], range: &str) -> Result<Vec<&'a str>, String> {
+    let (start, end) = range.split_once(':').unwrap_or((range, range));
+    let parse = |number: &str, default: usize| match number {
+        "" => Ok(default),
+        number => number
+            .parse::<usize>()
+            .map_err(|_| format!("{} isn't a line number", number)),
+    };
+    let start = parse(start, 1)?;
+    let end = parse(end, lines.len())?.min(lines.len());
+
+    if start == 0 || start > end {
+        return Err(format!("lines {} aren't in the file", range));
+    }
+
+    Ok(lines[start - 1..end].to_vec())
+}
+
+/// The lines between the `ANCHOR: anchor` and `ANCHOR_END: anchor` comments, or the end of the
+/// file, leaving out any other anchors.
+fn anchored<'a>(lines: &[&'a str], anchor: &str) -> Result<Vec<&'a str>, String> {
+    let start = lines
+        .iter()
+        .position(|line| anchor_name(line, "ANCHOR:") == Some(anchor))
+        .ok_or_else(|| format!("there's no anchor {}", anchor))?
+        + 1;
+    let end = lines[start..]
+        .iter()
+        .position(|line| anchor_name(line, "ANCHOR_END:") == Some(anchor))
+        .map_or(lines.len(), |end| start + end);
+
+    Ok(lines[start..end]
+        .iter()
+        .filter(|line| !is_anchor(line))
+        .copied()
+        .collect())
+}
+
+/// The name after `marker` in a line like `// ANCHOR: usage`.
+fn anchor_name<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    let after = &line[line.find(marker)? + marker.len()..];
+
+    after
+        .trim_start()
+        .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+        .next()
+}
+
+fn is_anchor(line: &str) -> bool {
+    line.contains("ANCHOR:") || line.contains("ANCHOR_END:")
+}
+
+/// `code` from the file `target` as a fenced code block in the file's language.
+fn fenced(target: &str, code: &str) -> String {
+    let language = Path::new(target)
+        .extension()
+        .map(|extension| extension.to_string_lossy())
+        .unwrap_or_default();
+    let fence = if code.contains("```") { "````" } else { "```" };
+
+    format!("{}{}\n{}{}\n", fence, language, code, fence)
+}
+
+/// The expanded contents of `target`, included from the document at `from`.
+fn include(
+    from: &str,
+    target: &str,
+    finder: &dyn ContentFinder,
+    including: &mut Vec<PathBuf>,
+) -> String {
+    let resolved = match resolve(from, target) {
+        Some(resolved) => resolved,
+        None => return not_included(target, "it's outside the root folder"),
+    };
+    if including.contains(&resolved) {
+        return not_included(target, "it would include itself");
+    }
+
+    let resource = format!("./{}", resolved.to_string_lossy());
+    match finder.content_for(&resource) {
+        Ok((contents, _)) => {
+            including.push(resolved);
+            let expanded = expand(&contents, &resource, finder, including);
+            including.pop();
+            expanded
+        }
+        Err(err) => not_included(target, &err.to_string()),
+    }
+}
+
+/// `target` as a path from the root, relative to the folder of the document at `from` unless
+/// it starts with `/`.
+pub(crate) fn resolve(from: &str, target: &str) -> Option<PathBuf> {
+    match target.strip_prefix('/') {
+        Some(from_root) => normalize(Path::new(from_root)),
+        None => {
+            let folder = Path::new(from).parent().unwrap_or_else(|| Path::new(""));
+            normalize(&folder.join(target))
+        }
+    }
+}
+
+/// How the note for a directive that couldn't be followed starts, see [`failures`].
+const NOT_INCLUDED: &str = "> rs-readme could not include `";
+
+fn not_included(target: &str, reason: &str) -> String {
+    format!("{}{}`: {}\n", NOT_INCLUDED, target, reason)
+}
+
+/// The target and reason of every directive in the expanded `source` that couldn't be followed,
+/// from the notes left in their place.
+pub(crate) fn failures(source: &str) -> Vec<(&str, &str)> {
+    source
+        .lines()
+        .filter_map(|line| {
+            line.trim_start()
+                .strip_prefix(NOT_INCLUDED)?
+                .split_once("`: ")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::content_finder::{ContentError, ContentHash};
+    use std::collections::HashMap;
+
+    struct Files(HashMap<&'static str, &'static str>);
+
+    impl ContentFinder for Files {
+        fn content_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+            let contents = self
+                .0
+                .get(resource)
+                .ok_or_else(|| ContentError::CouldNotFetch(resource.to_string()))?;
+            Ok((contents.to_string(), ContentHash::of(contents.as_bytes())))
+        }
+    }
+
+    fn files() -> Files {
+        Files(
+            vec![
+                ("./shared/setup.md", "Setup\n<!-- include: steps.md -->\n"),
+                ("./shared/steps.md", "1. Install\n"),
+                ("./loop.md", "<!-- include: ./loop.md -->\n"),
+                (
+                    "./src/lib.rs",
+                    "use x;\n// ANCHOR: usage\nlet a = 1;\n// ANCHOR: inner\nlet b = 2;\n// ANCHOR_END: inner\n// ANCHOR_END: usage\n",
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        )
+    }
+
+    #[test]
+    fn includes_files_relative_to_the_document() {
+        let source = "# Docs\n\n<!-- include: ../shared/setup.md -->\nDone\n".to_string();
+
+        let processed = Includes.process("./docs/a.md", source, &files());
+
+        assert_eq!(processed, "# Docs\n\nSetup\n1. Install\nDone\n");
+    }
+
+    #[test]
+    fn refuses_cycles_and_escapes() {
+        let looped = Includes.process(
+            "./loop.md",
+            "<!-- include: loop.md -->\n".to_string(),
+            &files(),
+        );
+        let escaped = Includes.process(
+            "./a.md",
+            "<!-- include: ../secret.md -->\n".to_string(),
+            &files(),
+        );
+
+        assert_eq!(
+            looped,
+            "> rs-readme could not include `loop.md`: it would include itself\n"
+        );
+        assert_eq!(
+            escaped,
+            "> rs-readme could not include `../secret.md`: it's outside the root folder\n"
+        );
+    }
+
+    #[test]
+    fn includes_source_by_anchor_and_lines() {
+        let source = "{{#include src/lib.rs:usage}}\n\n```rust\n{{#include src/lib.rs:2:3}}\n```\n"
+            .to_string();
+
+        let processed = Includes.process("./a.md", source, &files());
+
+        assert_eq!(
+            processed,
+            "```rs\nlet a = 1;\nlet b = 2;\n```\n\n```rust\n// ANCHOR: usage\nlet a = 1;\n```\n"
+        );
+    }
+
+    #[test]
+    fn explains_missing_snippets() {
+        let processed = Includes.process(
+            "./a.md",
+            "{{#include src/lib.rs:nope}}\n{{#include src/lib.rs:9:}}\n".to_string(),
+            &files(),
+        );
+
+        assert_eq!(
+            processed,
+            "> rs-readme could not include `src/lib.rs`: there's no anchor nope\n\
+             > rs-readme could not include `src/lib.rs`: lines 9: aren't in the file\n"
+        );
+    }
+
+    #[test]
+    fn leaves_code_blocks_alone() {
+        let source = "```md\n<!-- include: shared/steps.md -->\n```\n".to_string();
+
+        assert_eq!(Includes.process("./a.md", source.clone(), &files()), source);
+    }
+}
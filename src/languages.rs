@@ -0,0 +1,226 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::links::normalize;
+
+/// A version of a document in another language, see [`Languages`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Translation {
+    /// The name of the language's folder, like `ja`.
+    pub language: String,
+
+    /// Where to go for it: the translated document, or the language folder's `README.md` when
+    /// there's no translation. `None` when there's neither.
+    pub href: Option<String>,
+
+    /// Whether this is the document being shown.
+    pub current: bool,
+
+    /// Whether the document hasn't been translated to this language.
+    pub missing: bool,
+}
+
+/// Finds the translations of documents kept in mirrored language folders, like `docs/en/` and
+/// `docs/ja/`.
+///
+/// Language folders are sibling folders named in `names`, or when that's not given, named like a
+/// language code: `en`, `ja`, `pt-BR`.
+pub struct Languages<'a> {
+    root: &'a Path,
+    names: Option<&'a [String]>,
+}
+
+impl<'a> Languages<'a> {
+    pub fn new(root: &'a Path, names: Option<&'a [String]>) -> Languages<'a> {
+        Languages { root, names }
+    }
+
+    /// The document at `path` in each language, itself included. Empty when it isn't in a
+    /// language folder with at least one sibling.
+    pub fn translations(&self, path: &str) -> Vec<Translation> {
+        let path = match normalize(Path::new(path)) {
+            Some(path) => path,
+            None => return Vec::new(),
+        };
+        let parts: Vec<Component> = path.components().collect();
+
+        for (at, part) in parts.iter().enumerate() {
+            let language = part.as_os_str().to_string_lossy();
+            if at + 1 == parts.len() || !self.is_language(&language) {
+                continue;
+            }
+
+            let parent: PathBuf = parts[..at].iter().collect();
+            let rest: PathBuf = parts[at + 1..].iter().collect();
+            let mut languages = self.languages_in(&parent);
+            if languages.len() < 2 || !languages.contains(&language.to_string()) {
+                continue;
+            }
+            languages.sort();
+
+            return languages
+                .into_iter()
+                .map(|other| self.translation(&parent, &rest, &language, other))
+                .collect();
+        }
+
+        Vec::new()
+    }
+
+    fn translation(
+        &self,
+        parent: &Path,
+        rest: &Path,
+        current: &str,
+        language: String,
+    ) -> Translation {
+        let translated = parent.join(&language).join(rest);
+        let index = parent.join(&language).join("README.md");
+
+        let (href, missing) = if self.root.join(&translated).is_file() {
+            (Some(href(&translated)), false)
+        } else if self.root.join(&index).is_file() {
+            (Some(href(&index)), true)
+        } else {
+            (None, true)
+        };
+
+        Translation {
+            current: language == current,
+            language,
+            href,
+            missing,
+        }
+    }
+
+    /// The language folders in `parent`.
+    fn languages_in(&self, parent: &Path) -> Vec<String> {
+        let entries = match fs::read_dir(self.root.join(parent)) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| self.is_language(name))
+            .collect()
+    }
+
+    fn is_language(&self, name: &str) -> bool {
+        match self.names {
+            Some(names) => names.iter().any(|language| language == name),
+            None => looks_like_language_code(name),
+        }
+    }
+}
+
+/// Whether `name` looks like `en`, `ja` or `pt-BR`.
+fn looks_like_language_code(name: &str) -> bool {
+    let (language, region) = match name.split_once(['-', '_']) {
+        Some((language, region)) => (language, Some(region)),
+        None => (name, None),
+    };
+
+    language.len() == 2
+        && language.chars().all(|c| c.is_ascii_lowercase())
+        && region.is_none_or(|region| {
+            (2..=4).contains(&region.len()) && region.chars().all(|c| c.is_ascii_alphabetic())
+        })
+}
+
+fn href(path: &Path) -> String {
+    format!("/{}", path.to_string_lossy())
+}
+
+/// Links to each of `translations`, for the page header.
+pub(crate) fn switcher_html(translations: &[Translation]) -> String {
+    if translations.is_empty() {
+        return String::new();
+    }
+
+    format!(
+        "{}",
+        html! {
+            span(class="rs-readme-languages") {
+                @ for translation in translations {
+                    @ if translation.current {
+                        strong : &translation.language;
+                    } else if let Some(href) = &translation.href {
+                        @ if translation.missing {
+                            a(href=href, class="rs-readme-missing-translation", title="Not translated yet") : &translation.language;
+                        } else {
+                            a(href=href) : &translation.language;
+                        }
+                    } else {
+                        span(class="rs-readme-missing-translation", title="Not translated yet") : &translation.language;
+                    }
+                }
+            }
+        }
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn root() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("rs-readme-languages-{}", std::process::id()));
+        for file in &[
+            "docs/en/README.md",
+            "docs/en/guide/setup.md",
+            "docs/en/guide/advanced.md",
+            "docs/ja/README.md",
+            "docs/ja/guide/setup.md",
+            "docs/images/logo.md",
+        ] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn finds_translations_and_falls_back_to_the_index() {
+        let root = root();
+        let languages = Languages::new(&root, None);
+
+        let setup = languages.translations("./docs/en/guide/setup.md");
+        let advanced = languages.translations("./docs/en/guide/advanced.md");
+        let outside = languages.translations("./docs/images/logo.md");
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(
+            setup,
+            vec![
+                Translation {
+                    language: "en".to_string(),
+                    href: Some("/docs/en/guide/setup.md".to_string()),
+                    current: true,
+                    missing: false,
+                },
+                Translation {
+                    language: "ja".to_string(),
+                    href: Some("/docs/ja/guide/setup.md".to_string()),
+                    current: false,
+                    missing: false,
+                },
+            ]
+        );
+        assert_eq!(advanced[1].href, Some("/docs/ja/README.md".to_string()));
+        assert!(advanced[1].missing);
+        assert_eq!(outside, Vec::new());
+    }
+
+    #[test]
+    fn recognises_language_codes() {
+        assert!(looks_like_language_code("ja"));
+        assert!(looks_like_language_code("pt-BR"));
+        assert!(looks_like_language_code("zh_Hant"));
+        assert!(!looks_like_language_code("src"));
+        assert!(!looks_like_language_code("EN"));
+    }
+}
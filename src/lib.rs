@@ -3,15 +3,173 @@ extern crate horrorshow;
 #[macro_use]
 extern crate serde_derive;
 
+#[cfg(feature = "server")]
+mod access_log;
+#[cfg(feature = "server")]
+mod app;
+#[cfg(feature = "archives")]
+mod archive_finder;
+#[cfg(feature = "axum")]
+mod axum_adapter;
+mod changelog;
+#[cfg(feature = "cli")]
 mod cli;
+mod code_theme;
+#[cfg(feature = "server")]
+mod config;
 mod content_finder;
+mod diagrams;
+mod direction;
+mod document_stats;
+#[cfg(feature = "server")]
+mod editing;
+mod error;
+mod events;
+mod external_converter;
+mod fences;
+mod file_tree;
+mod frontmatter;
+mod git_finder;
+mod git_history;
+mod git_status;
+#[cfg(feature = "server")]
+mod handlers;
+#[cfg(feature = "highlight")]
+mod highlight;
+#[cfg(feature = "server")]
+mod i18n;
+#[cfg(feature = "server")]
+mod image_proxy;
+mod includes;
+mod languages;
+#[cfg(feature = "server")]
+mod limits;
+mod links;
+mod lint;
 mod markdown_converter;
+mod navigation;
 mod offline_converter;
+mod outline;
+#[cfg(feature = "server")]
+mod page_cache;
+#[cfg(feature = "server")]
+mod panics;
+#[cfg(feature = "server")]
+mod patches;
+mod pins;
+mod piped;
+#[cfg(feature = "plugins")]
+mod plugins;
+mod post_process;
+mod pre_process;
+#[cfg(feature = "server")]
+mod prefs;
+#[cfg(feature = "server")]
+mod prose_diff;
+mod recent;
+#[cfg(feature = "server")]
+mod remote_finder;
+mod render;
+#[cfg(feature = "server")]
+mod scroll;
+#[cfg(unix)]
+mod socket_activation;
+mod source_lines;
+#[cfg(feature = "server")]
 mod static_files;
+#[cfg(feature = "server")]
+mod summary;
+#[cfg(feature = "server")]
+mod table;
+#[cfg(feature = "otel")]
+mod telemetry;
+#[cfg(feature = "test-util")]
+pub mod testing;
+#[cfg(feature = "server")]
+mod updates;
+mod variables;
+mod warnings;
+#[cfg(feature = "server")]
 mod web_server;
+#[cfg(feature = "server")]
+mod webhook;
 
-pub use cli::Args;
-pub use content_finder::{ContentError, ContentFinder, FileFinder};
-pub use markdown_converter::{Converter, MarkdownConverter, MarkdownError};
+#[cfg(feature = "server")]
+pub use access_log::{AccessLog, DEFAULT_MAX_LOG_SIZE};
+#[cfg(feature = "server")]
+pub use app::{App, AppBuilder};
+#[cfg(feature = "archives")]
+pub use archive_finder::{is_archive, ArchiveFinder};
+#[cfg(feature = "axum")]
+pub use axum_adapter::axum_router;
+pub use changelog::{is_changelog, releases, Release};
+#[cfg(feature = "cli")]
+pub use cli::{Args, Command};
+pub use code_theme::{CodeTheme, CODE_THEMES};
+#[cfg(feature = "server")]
+pub use config::{Config, ConfigError};
+pub use content_finder::{
+    markdown_files, ContentError, ContentFinder, ContentHash, DocumentFormat, FileFinder,
+};
+pub use diagrams::Graphviz;
+#[cfg(feature = "server")]
+pub use diagrams::PlantUml;
+pub use document_stats::{document_stats, DocumentStats, WORDS_PER_MINUTE};
+pub use error::Error;
+pub use events::{Event, Events};
+pub use external_converter::ExternalConverter;
+pub use file_tree::{file_tree, TreeNode};
+pub use frontmatter::Frontmatter;
+pub use git_finder::GitContentFinder;
+pub use git_history::{history, last_edit, Commit, LastEdit, HISTORY_LENGTH};
+pub use git_status::{repo_status, RepoStatus};
+#[cfg(feature = "server")]
+pub use handlers::{RenderedResponse, ResponseBody};
+#[cfg(feature = "server")]
+pub use i18n::Lang;
+#[cfg(feature = "server")]
+pub use image_proxy::{Image, ImageProxy, IMAGE_ROUTE};
+pub use includes::Includes;
+pub use languages::{Languages, Translation};
+pub use lint::{json_report, lint, text_report, IssueKind, LintIssue};
+#[cfg(feature = "github-converter")]
+pub use markdown_converter::Converter;
+pub use markdown_converter::{HtmlChunks, MarkdownConverter, MarkdownError, StreamingConverter};
+pub use navigation::{NavItem, Navigation, NAV_FILE, SUMMARY_FILE};
 pub use offline_converter::OfflineConverter;
-pub use web_server::{build_app, Converters, State};
+pub use outline::{headings, outline, Heading};
+#[cfg(feature = "server")]
+pub use page_cache::CacheStats;
+pub use pins::{config_dir, Pins, PINS_FILE};
+pub use piped::{PipedDocument, PipedFinder, PIPED_DOCUMENT};
+#[cfg(feature = "plugins")]
+pub use plugins::{Plugin, PluginError};
+pub use post_process::{post_process, PostProcessor};
+pub use pre_process::{pre_process, PreProcessor};
+#[cfg(feature = "server")]
+pub use prefs::{Prefs, PrefsError, Theme};
+#[cfg(feature = "server")]
+pub use prose_diff::prose_diff;
+pub use recent::{recent_files, RecentFile, RECENT_LIMIT};
+#[cfg(feature = "server")]
+pub use remote_finder::{split_document_url, RemoteFinder};
+pub use render::{
+    reload_script_url, render_document, RenderOptions, RenderedPage, GITHUB_STYLESHEETS,
+};
+#[cfg(unix)]
+pub use socket_activation::{systemd_listener, ActivatedListener};
+pub use source_lines::SOURCE_LINE_ATTRIBUTE;
+#[cfg(feature = "server")]
+pub use summary::Summary;
+#[cfg(feature = "server")]
+pub use table::MAX_TABLE_ROWS;
+#[cfg(feature = "otel")]
+pub use telemetry::otlp_tracing;
+#[cfg(feature = "server")]
+pub use updates::DocumentUpdate;
+pub use variables::Variables;
+pub use warnings::{document_warnings, Warning, WarningKind};
+#[cfg(feature = "server")]
+pub use web_server::{build_app, build_app_with, Converters, State, DEFAULT_CONVERT_TIMEOUT};
+#[cfg(feature = "server")]
+pub use webhook::{DocumentChange, Webhook};
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use async_lock::{Semaphore, SemaphoreGuardArc};
+use futures::StreamExt;
+
+use crate::error::Error;
+use crate::markdown_converter::HtmlChunks;
+
+/// How many conversions run at once unless `--max-conversions` says otherwise.
+pub const DEFAULT_MAX_CONVERSIONS: usize = 4;
+
+/// How many live-reload connections can be open at once unless `--max-update-streams` says
+/// otherwise.
+pub const DEFAULT_MAX_UPDATE_STREAMS: usize = 32;
+
+/// How long clients turned away by a [`Limit`] are asked to wait, in seconds.
+const RETRY_AFTER: u64 = 5;
+
+/// Proof that something is allowed to run, it gives its place back to the [`Limit`] when dropped.
+pub(crate) type Permit = Option<SemaphoreGuardArc>;
+
+/// Caps how many of something happen at once, `0` meaning no cap.
+pub(crate) struct Limit(Option<Arc<Semaphore>>);
+
+impl Limit {
+    pub fn new(max: usize) -> Limit {
+        Limit((max > 0).then(|| Arc::new(Semaphore::new(max))))
+    }
+
+    /// Waits for a place, queueing behind everything already waiting.
+    pub async fn acquire(&self) -> Permit {
+        match &self.0 {
+            Some(semaphore) => Some(semaphore.acquire_arc().await),
+            None => None,
+        }
+    }
+
+    /// Takes a place if there's one free, otherwise the caller should come back later.
+    pub fn try_acquire(&self) -> Result<Permit, Error> {
+        match &self.0 {
+            Some(semaphore) => semaphore.try_acquire_arc().map(Some).ok_or(Error::Busy {
+                retry_after: RETRY_AFTER,
+            }),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Holds on to `permit` until `chunks` is done with.
+pub(crate) fn hold(chunks: HtmlChunks, permit: Permit) -> HtmlChunks {
+    Box::pin(chunks.map(move |chunk| {
+        let _ = &permit;
+        chunk
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[async_std::test]
+    async fn places_are_given_back_when_dropped() {
+        let limit = Limit::new(1);
+
+        let permit = limit.acquire().await;
+        assert_eq!(
+            limit.try_acquire().err(),
+            Some(Error::Busy { retry_after: 5 })
+        );
+
+        drop(permit);
+        assert!(limit.try_acquire().is_ok());
+    }
+
+    #[test]
+    fn zero_is_unlimited() {
+        let limit = Limit::new(0);
+        let permits: Vec<_> = (0..100).map(|_| limit.try_acquire()).collect();
+
+        assert!(permits.iter().all(Result::is_ok));
+    }
+}
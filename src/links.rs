@@ -0,0 +1,286 @@
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+use crate::outline::headings;
+
+/// What kind of markdown element a [`Reference`] came from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReferenceKind {
+    Link,
+    Image,
+}
+
+/// A link or image destination found in a markdown document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub kind: ReferenceKind,
+    pub target: String,
+    /// The 1-based line the reference starts on.
+    pub line: usize,
+}
+
+/// Where a link destination points once it's resolved against the content root.
+#[derive(Debug, PartialEq)]
+pub enum Target {
+    /// Something with a scheme (`https:`, `mailto:`, ...) we don't check.
+    External,
+
+    /// A fragment in the same document, `#like-this`.
+    Anchor(String),
+
+    /// A file in the content root, possibly with a fragment.
+    Local {
+        path: PathBuf,
+        anchor: Option<String>,
+    },
+
+    /// A relative path that climbs out of the content root.
+    OutsideRoot,
+}
+
+/// Turns heading text into the anchor GitHub would generate for it.
+///
+/// Lowercases everything, drops punctuation other than `-` and `_` and
+/// swaps spaces for `-`.
+pub fn heading_slug(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter_map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                Some(c.to_lowercase().collect::<String>())
+            } else if c == ' ' {
+                Some("-".to_string())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Returns every link and image destination in `md`, in document order.
+pub fn references(md: &str) -> Vec<Reference> {
+    let parser = Parser::new_ext(md, Options::all());
+    // Where lines were last counted to, so big documents aren't counted from the start each time
+    let mut counted = (0, 1);
+
+    parser
+        .into_offset_iter()
+        .filter_map(|(event, range)| {
+            let (kind, target) = match event {
+                Event::Start(Tag::Link(_, dest, _)) => (ReferenceKind::Link, dest),
+                Event::Start(Tag::Image(_, dest, _)) => (ReferenceKind::Image, dest),
+                _ => return None,
+            };
+
+            let (offset, line) = counted;
+            let line = match md.get(offset..range.start) {
+                Some(between) => line + between.matches('\n').count(),
+                None => line_of(md, range.start),
+            };
+            counted = (range.start, line);
+
+            Some(Reference {
+                kind,
+                target: target.to_string(),
+                line,
+            })
+        })
+        .collect()
+}
+
+/// The images in `md` without alt text, which screen readers can only call "image".
+pub(crate) fn images_without_alt(md: &str) -> Vec<Reference> {
+    let mut missing = Vec::new();
+    let mut image: Option<(String, usize, String)> = None;
+    for (event, range) in Parser::new_ext(md, Options::all()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Image(_, dest, _)) => {
+                image = Some((dest.to_string(), range.start, String::new()));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, alt)) = image.as_mut() {
+                    alt.push_str(&text);
+                }
+            }
+            Event::End(Tag::Image(..)) => match image.take() {
+                Some((target, start, alt)) if alt.trim().is_empty() => missing.push(Reference {
+                    kind: ReferenceKind::Image,
+                    target,
+                    line: line_of(md, start),
+                }),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    missing
+}
+
+/// Returns the anchors generated for every heading in `md`.
+///
+/// Repeated headings get `-1`, `-2`, ... appended the same way GitHub does it.
+pub fn anchors(md: &str) -> HashSet<String> {
+    headings(md)
+        .into_iter()
+        .map(|heading| heading.slug)
+        .collect()
+}
+
+/// Resolves `target` the same way the server will when the browser follows it.
+///
+/// `document` is the path of the linking document relative to the content
+/// root. Paths starting with `/` are relative to the root, everything else
+/// is relative to the linking document's folder.
+pub fn resolve(document: &Path, target: &str) -> Target {
+    if target.contains("://") || target.starts_with("mailto:") || target.starts_with("data:") {
+        return Target::External;
+    }
+
+    let (path, anchor) = match target.find('#') {
+        Some(idx) => (&target[..idx], Some(target[idx + 1..].to_string())),
+        None => (target, None),
+    };
+    let path = path.split('?').next().unwrap_or("");
+
+    if path.is_empty() {
+        return Target::Anchor(anchor.unwrap_or_default());
+    }
+
+    let joined = if let Some(absolute) = path.strip_prefix('/') {
+        PathBuf::from(absolute)
+    } else {
+        document
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(path)
+    };
+
+    match normalize(&joined) {
+        Some(path) => Target::Local { path, anchor },
+        None => Target::OutsideRoot,
+    }
+}
+
+/// Collapses `.` and `..` without touching the file system, returning `None`
+/// when the path climbs above where it started.
+pub fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::Normal(part) => normalized.push(part),
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(normalized)
+}
+
+/// The `/__rs-readme/raw` URL the file at `resource` (`./docs/a.md`) is served as is from.
+#[cfg(feature = "server")]
+pub(crate) fn raw_href(resource: &str) -> String {
+    format!("/__rs-readme/raw/{}", resource.trim_start_matches("./"))
+}
+
+pub(crate) fn line_of(md: &str, offset: usize) -> usize {
+    md[..offset].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn slugs_match_github() {
+        assert_eq!(heading_slug("Usage"), "usage");
+        assert_eq!(heading_slug("Todos (maybe)"), "todos-maybe");
+        assert_eq!(
+            heading_slug("snake_case & kebab-case"),
+            "snake_case--kebab-case"
+        );
+    }
+
+    #[test]
+    fn repeated_headings_get_suffixes() {
+        let found = anchors("# Options\n## Options\n### `code` Options\n");
+
+        assert!(found.contains("options"));
+        assert!(found.contains("options-1"));
+        assert!(found.contains("code-options"));
+    }
+
+    #[test]
+    fn finds_references_with_lines() {
+        let md = "# Title\n[a](./a.md)\n\n![logo](images/logo.png)\n";
+
+        assert_eq!(
+            references(md),
+            vec![
+                Reference {
+                    kind: ReferenceKind::Link,
+                    target: "./a.md".to_string(),
+                    line: 2,
+                },
+                Reference {
+                    kind: ReferenceKind::Image,
+                    target: "images/logo.png".to_string(),
+                    line: 4,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_images_without_alt_text() {
+        let md = "![logo](logo.png)\n\n![](chart.png) ![ ](spacer.gif)\n![`cargo`](cargo.png)\n";
+
+        assert_eq!(
+            images_without_alt(md),
+            vec![
+                Reference {
+                    kind: ReferenceKind::Image,
+                    target: "chart.png".to_string(),
+                    line: 3,
+                },
+                Reference {
+                    kind: ReferenceKind::Image,
+                    target: "spacer.gif".to_string(),
+                    line: 3,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_relative_to_document() {
+        let doc = Path::new("docs/guide.md");
+
+        assert_eq!(resolve(doc, "https://github.com"), Target::External);
+        assert_eq!(resolve(doc, "#usage"), Target::Anchor("usage".to_string()));
+        assert_eq!(
+            resolve(doc, "../README.md#options"),
+            Target::Local {
+                path: PathBuf::from("README.md"),
+                anchor: Some("options".to_string()),
+            }
+        );
+        assert_eq!(
+            resolve(doc, "/test_dir/a.md"),
+            Target::Local {
+                path: PathBuf::from("test_dir/a.md"),
+                anchor: None,
+            }
+        );
+        assert_eq!(resolve(doc, "../../secret.md"), Target::OutsideRoot);
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+use crate::content_finder::markdown_files;
+use crate::links::{anchors, images_without_alt, references, resolve, ReferenceKind, Target};
+
+/// The different problems `rs-readme lint` reports.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IssueKind {
+    /// A relative link points at a file that doesn't exist.
+    BrokenLink,
+
+    /// A link's `#fragment` doesn't match any heading in its target.
+    BrokenAnchor,
+
+    /// An image points at a file that doesn't exist.
+    MissingImage,
+
+    /// An image has no alt text, so screen readers can't say what it shows.
+    MissingAlt,
+
+    /// A relative link or image climbs out of the content root.
+    OutsideRoot,
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IssueKind::BrokenLink => write!(f, "broken-link"),
+            IssueKind::BrokenAnchor => write!(f, "broken-anchor"),
+            IssueKind::MissingImage => write!(f, "missing-image"),
+            IssueKind::MissingAlt => write!(f, "missing-alt"),
+            IssueKind::OutsideRoot => write!(f, "outside-root"),
+        }
+    }
+}
+
+/// A single problem found in a markdown file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LintIssue {
+    /// The file the problem is in, relative to the content root.
+    pub file: PathBuf,
+    pub line: usize,
+    pub kind: IssueKind,
+    pub target: String,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.file.to_string_lossy(),
+            self.line,
+            self.kind,
+            self.target
+        )
+    }
+}
+
+/// Checks every markdown file under `root` for broken relative links,
+/// unresolved anchors, missing images and images without alt text.
+pub fn lint(root: &Path) -> Vec<LintIssue> {
+    let files = markdown_files(root);
+    let mut anchor_cache: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+    let mut issues = Vec::new();
+
+    for file in files {
+        let contents = match fs::read_to_string(root.join(&file)) {
+            Ok(contents) => contents,
+            Err(err) => {
+                warn!("Could not read {}: {}", file.to_string_lossy(), err);
+                continue;
+            }
+        };
+
+        for reference in references(&contents) {
+            let issue = |kind| LintIssue {
+                file: file.clone(),
+                line: reference.line,
+                kind,
+                target: reference.target.clone(),
+            };
+
+            match (resolve(&file, &reference.target), reference.kind) {
+                (Target::External, _) => {}
+                (Target::OutsideRoot, _) => issues.push(issue(IssueKind::OutsideRoot)),
+                (Target::Anchor(anchor), _) => {
+                    let found = anchor_cache
+                        .entry(file.clone())
+                        .or_insert_with(|| anchors(&contents));
+                    if !anchor.is_empty() && !found.contains(&anchor) {
+                        issues.push(issue(IssueKind::BrokenAnchor));
+                    }
+                }
+                (Target::Local { path, .. }, ReferenceKind::Image) => {
+                    if !root.join(&path).exists() {
+                        issues.push(issue(IssueKind::MissingImage));
+                    }
+                }
+                (Target::Local { path, anchor }, ReferenceKind::Link) => {
+                    if !root.join(&path).exists() {
+                        issues.push(issue(IssueKind::BrokenLink));
+                        continue;
+                    }
+
+                    let anchor = match anchor {
+                        Some(anchor) if !anchor.is_empty() => anchor,
+                        _ => continue,
+                    };
+                    if path.extension() != Some(OsStr::new("md")) {
+                        continue;
+                    }
+
+                    let found = anchor_cache.entry(path.clone()).or_insert_with(|| {
+                        fs::read_to_string(root.join(&path))
+                            .map(|md| anchors(&md))
+                            .unwrap_or_default()
+                    });
+                    if !found.contains(&anchor) {
+                        issues.push(issue(IssueKind::BrokenAnchor));
+                    }
+                }
+            }
+        }
+
+        issues.extend(
+            images_without_alt(&contents)
+                .into_iter()
+                .map(|image| LintIssue {
+                    file: file.clone(),
+                    line: image.line,
+                    kind: IssueKind::MissingAlt,
+                    target: image.target,
+                }),
+        );
+    }
+
+    issues
+}
+
+/// Formats `issues` one per line as `file:line: kind: target`.
+pub fn text_report(issues: &[LintIssue]) -> String {
+    issues.iter().map(|issue| format!("{}\n", issue)).collect()
+}
+
+/// Formats `issues` as a JSON array.
+pub fn json_report(issues: &[LintIssue]) -> String {
+    serde_json::to_string_pretty(issues).unwrap_or_else(|_| "[]".to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dir_is_clean() {
+        assert_eq!(lint(Path::new("test_dir")), vec![]);
+    }
+
+    #[test]
+    fn reports_each_kind() {
+        let root = std::env::temp_dir().join("rs-readme-lint-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("README.md"), "# Intro\n## Setup\n").unwrap();
+        fs::write(
+            root.join("docs/guide.md"),
+            "# Guide\n\
+             [ok](../README.md#setup)\n\
+             [missing](./nope.md)\n\
+             [bad anchor](../README.md#teardown)\n\
+             [self](#guide) [self bad](#nothing)\n\
+             ![img](./logo.png)\n\
+             [escape](../../etc/passwd)\n\
+             [web](https://example.com)\n\
+             ![](../README.md)\n",
+        )
+        .unwrap();
+
+        let kinds: Vec<(usize, IssueKind)> = lint(&root)
+            .into_iter()
+            .map(|issue| (issue.line, issue.kind))
+            .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                (3, IssueKind::BrokenLink),
+                (4, IssueKind::BrokenAnchor),
+                (5, IssueKind::BrokenAnchor),
+                (6, IssueKind::MissingImage),
+                (7, IssueKind::OutsideRoot),
+                (9, IssueKind::MissingAlt),
+            ]
+        );
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn text_report_is_one_issue_per_line() {
+        let issues = vec![LintIssue {
+            file: PathBuf::from("docs/guide.md"),
+            line: 3,
+            kind: IssueKind::BrokenLink,
+            target: "./nope.md".to_string(),
+        }];
+
+        assert_eq!(
+            text_report(&issues),
+            "docs/guide.md:3: broken-link: ./nope.md\n"
+        );
+    }
+}
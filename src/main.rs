@@ -1,7 +1,20 @@
+use log::{error, info};
 use std::sync::Arc;
+use std::time::Duration;
 use structopt::StructOpt;
 
-use rs_readme::{build_app, Args, Converters, FileFinder, OfflineConverter, State};
+use rs_readme::{
+    build_app, is_archive, json_report, lint, text_report, App, Args, Command, Event, Events,
+    PipedDocument,
+};
+#[cfg(unix)]
+use rs_readme::{systemd_listener, ActivatedListener};
+use tide::http::Url;
+use tide::listener::{Listener, ToListener};
+use tide::Server;
+
+/// How many files `--prewarm` renders at once.
+const PREWARM_PARALLELISM: usize = 4;
 
 #[async_std::main]
 async fn main() -> std::result::Result<(), std::io::Error> {
@@ -9,22 +22,187 @@ async fn main() -> std::result::Result<(), std::io::Error> {
 
     let args = Args::from_args();
 
-    let addr = format!("{}:{}", args.host, args.port);
+    if let Some(Command::Lint { format }) = &args.command {
+        let issues = lint(&args.folder);
+        if format == "json" {
+            println!("{}", json_report(&issues));
+        } else {
+            print!("{}", text_report(&issues));
+        }
+        std::process::exit(if issues.is_empty() { 0 } else { 1 });
+    }
+
+    #[cfg(feature = "otel")]
+    let _tracing = match &args.otel_endpoint {
+        Some(endpoint) => Some(rs_readme::otlp_tracing(endpoint).map_err(|err| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, err.to_string())
+        })?),
+        None => None,
+    };
+
+    let events = if args.events_json {
+        Events::stdout()
+    } else {
+        Events::disabled()
+    };
+
+    let mut builder = App::builder()
+        .root(args.folder.clone())
+        .host(args.host.clone())
+        .port(args.port)
+        .cache_size(args.cache_size)
+        .max_file_size(args.max_file_size)
+        .plain_text(args.plain_text)
+        .sanitize_html(args.sanitize_html)
+        .trust_html(args.trust_html)
+        .sortable_tables(args.sortable_tables)
+        .filter_tables_over(args.filter_tables_over)
+        .allow_remote(args.allow_remote)
+        .edit(args.edit)
+        .debug_errors(args.debug_errors)
+        .lang(args.lang)
+        .code_theme(args.code_theme)
+        .proxy_images(args.proxy_images)
+        .max_conversions(args.max_conversions)
+        .max_update_streams(args.max_update_streams)
+        .debounce(Duration::from_millis(args.debounce_ms))
+        .min_push_interval(Duration::from_millis(args.min_push_interval_ms))
+        .convert_timeout(Duration::from_secs(args.convert_timeout))
+        .events(events);
+    for (name, value) in &args.defines {
+        builder = builder.define(name.clone(), value.clone());
+    }
+    for pattern in &args.treat_as_md {
+        builder = builder.treat_as_md(pattern.clone());
+    }
+    if let Some(title) = &args.title {
+        builder = builder.title(title.clone());
+    }
+    if let Some(server) = &args.plantuml_server {
+        builder = builder.plantuml_server(server.clone());
+    }
+    #[cfg(feature = "plugins")]
+    if let Some(dir) = &args.plugins_dir {
+        let plugins = rs_readme::Plugin::load_dir(dir).map_err(|err| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, err.to_string())
+        })?;
+        for plugin in plugins {
+            builder = builder.plugin(plugin);
+        }
+    }
+    if args.stdin {
+        let document = PipedDocument::new();
+        builder = builder.piped(document.clone());
+        std::thread::spawn(move || document.read_from(std::io::stdin().lock()));
+    }
+    if let Some(url) = &args.url {
+        builder = builder.remote(url.clone());
+    }
+    if let Some(url) = &args.webhook {
+        builder = builder.webhook(url.clone());
+    }
+    if let Some(path) = &args.access_log {
+        builder = builder
+            .access_log(path.clone())
+            .access_log_max_size(args.access_log_max_size);
+    }
+    if let Some(context) = &args.context {
+        builder = builder.context(context.clone());
+    }
+
+    let config_path = args
+        .config
+        .clone()
+        .or_else(|| Some(args.folder.join(".rs-readme.toml")).filter(|path| path.exists()));
+    if let Some(config_path) = config_path {
+        builder = builder.config(config_path);
+    }
+
+    let addr = builder.addr();
+    if args.allow_remote {
+        let access = if args.edit {
+            "read every file and change every document"
+        } else {
+            "read every file"
+        };
+        // On stderr so it's seen even with --events-json
+        eprintln!(
+            "WARNING: --allow-remote is on, anyone who can reach {} can {} in {}",
+            addr,
+            access,
+            args.folder.display()
+        );
+    }
+    let state = Arc::new(
+        builder
+            .build_state()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?,
+    );
 
-    let converter = Converters::Offline(OfflineConverter::default());
-    // let converter = if args.offline {
-    //     Converters::Offline(OfflineConverter::new())
-    // } else {
-    //     Converters::Github(Converter::new(
-    //         "https://api.github.com".to_string(),
-    //         args.context,
-    //     ))
-    // };
+    #[cfg(unix)]
+    {
+        let state = state.clone();
+        let signals = signal_hook::iterator::Signals::new([signal_hook::SIGHUP])?;
+        std::thread::spawn(move || {
+            for _ in signals.forever() {
+                match state.reload_config() {
+                    Ok(()) => info!("Reloaded config"),
+                    Err(err) => error!("Could not reload config: {}", err),
+                }
+            }
+        });
+    }
+
+    if args.prewarm && args.url.is_none() && !is_archive(&args.folder) {
+        let state = state.clone();
+        let root = args.folder.clone();
+        async_std::task::spawn(async move {
+            let rendered = state.prewarm(&root, PREWARM_PARALLELISM).await;
+            info!("Pre-rendered {} pages", rendered);
+        });
+    }
+
+    if !args.events_json {
+        print!("{}", state.summary().await);
+    }
+
+    let app = build_app(state.clone());
+
+    #[cfg(unix)]
+    match systemd_listener() {
+        Some(ActivatedListener::Tcp(listener)) => {
+            return serve(app, listener, state.events()).await;
+        }
+        Some(ActivatedListener::Unix(listener)) => {
+            return serve(app, listener, state.events()).await;
+        }
+        None => {}
+    }
+
+    serve(app, addr, state.events()).await
+}
 
-    let state = State::new(converter, FileFinder::new(args.folder));
+/// Binds `listener`, says where we're listening (on stdout, or as an event with
+/// `--events-json`), and serves requests until the server stops.
+async fn serve<S, L>(app: Server<S>, listener: L, events: &Events) -> std::io::Result<()>
+where
+    S: Clone + Send + Sync + 'static,
+    L: ToListener<S>,
+{
+    let mut listener = app.bind(listener).await?;
 
-    let app = build_app(Arc::new(state));
+    for info in listener.info() {
+        if events.is_enabled() {
+            events.emit(Event::ServerStarted {
+                url: info.connection().to_string(),
+                port: Url::parse(info.connection())
+                    .ok()
+                    .and_then(|url| url.port()),
+            });
+        } else {
+            println!("Listening on {}", info.connection());
+        }
+    }
 
-    println!("Listening on {}", addr);
-    app.listen(addr).await
+    listener.accept().await
 }
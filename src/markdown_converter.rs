@@ -2,12 +2,20 @@ use std::error::Error;
 use std::fmt;
 
 use async_trait::async_trait;
+use futures::Stream;
+#[cfg(feature = "github-converter")]
 use log::error;
+use std::pin::Pin;
 
 /// Represents an error from the markdown converter.
 #[derive(Debug, PartialEq)]
 pub enum MarkdownError {
     ConverterUnavailable(String),
+
+    /// GitHub's API rate limit ran out, `reset` is when it refills as a unix timestamp.
+    RateLimited {
+        reset: Option<u64>,
+    },
 }
 
 impl fmt::Display for MarkdownError {
@@ -16,12 +24,19 @@ impl fmt::Display for MarkdownError {
             MarkdownError::ConverterUnavailable(reason) => {
                 write!(f, "Could not convert\n{}", reason)
             }
+            MarkdownError::RateLimited { reset: Some(reset) } => {
+                write!(f, "GitHub's rate limit was reached, it resets at {}", reset)
+            }
+            MarkdownError::RateLimited { reset: None } => {
+                write!(f, "GitHub's rate limit was reached")
+            }
         }
     }
 }
 
 impl Error for MarkdownError {}
 
+#[cfg(feature = "github-converter")]
 /// The JSON body to send some text to GitHub's API to be converted from
 /// markdown to HTML.
 #[derive(Serialize, Deserialize, Debug)]
@@ -41,20 +56,53 @@ struct MarkdownRequest {
 #[async_trait]
 pub trait MarkdownConverter {
     async fn convert_markdown(&self, md: &str) -> Result<String, MarkdownError>;
+
+    /// The converter as a [`StreamingConverter`], if it can produce HTML incrementally.
+    fn streaming(&self) -> Option<&dyn StreamingConverter> {
+        None
+    }
+
+    /// What to call the converter when saying which one is used, like `offline`.
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+}
+
+/// HTML handed out in chunks as it's produced.
+pub type HtmlChunks = Pin<Box<dyn Stream<Item = Vec<u8>> + Send + Sync>>;
+
+/// A converter that can hand out HTML as it's produced, for documents too big to buffer.
+pub trait StreamingConverter: Send + Sync {
+    /// Converts `md`, yielding the HTML in chunks. Chunks aren't split on character boundaries.
+    fn convert_markdown_stream(&self, md: String) -> HtmlChunks;
 }
 
+#[cfg(feature = "github-converter")]
 /// Can convert from markdown to HTML using the GitHub API.
+#[derive(Clone)]
 pub struct Converter {
     api_path: String,
     context: Option<String>,
 }
 
+#[cfg(feature = "github-converter")]
 impl Converter {
     /// Builds a new converter using the given GitHub API.
     pub fn new(api_path: String, context: Option<String>) -> Converter {
         Converter { api_path, context }
     }
 
+    /// Builds a converter for the same API with a different context.
+    pub fn with_context(&self, context: Option<String>) -> Converter {
+        Converter::new(self.api_path.clone(), context)
+    }
+
+    /// The GitHub context it renders in, like `gregcline/rs-readme`.
+    #[cfg(feature = "server")]
+    pub(crate) fn context(&self) -> Option<&str> {
+        self.context.as_deref()
+    }
+
     /// Builds the request body for github
     fn build_body(&self, md: &str) -> MarkdownRequest {
         if let Some(context) = &self.context {
@@ -73,8 +121,13 @@ impl Converter {
     }
 }
 
+#[cfg(feature = "github-converter")]
 #[async_trait]
 impl MarkdownConverter for Converter {
+    fn name(&self) -> &'static str {
+        "github"
+    }
+
     /// Makes a request to the GitHub API and returns the resulting string.
     async fn convert_markdown(&self, md: &str) -> Result<String, MarkdownError> {
         let client = surf::Client::new();
@@ -99,6 +152,16 @@ impl MarkdownConverter for Converter {
                 MarkdownError::ConverterUnavailable("Error awaiting response".to_string())
             })?;
 
+        let rate_limited = resp
+            .header("x-ratelimit-remaining")
+            .is_some_and(|remaining| remaining.last().as_str() == "0");
+        if rate_limited {
+            let reset = resp
+                .header("x-ratelimit-reset")
+                .and_then(|reset| reset.last().as_str().parse().ok());
+            return Err(MarkdownError::RateLimited { reset });
+        }
+
         let body = resp
             .body_string()
             .await
@@ -112,7 +175,7 @@ impl MarkdownConverter for Converter {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "github-converter"))]
 mod test {
     use super::*;
     use mockito::{mock, Matcher};
@@ -173,4 +236,26 @@ mod test {
         m.assert();
         assert_eq!(html, Ok("<h1>A thing!</h1>".to_string()));
     }
+
+    #[async_std::test]
+    async fn exhausted_rate_limit_results_in_rate_limited() {
+        let m = mock("POST", "/markdown")
+            .with_status(403)
+            .with_header("x-ratelimit-remaining", "0")
+            .with_header("x-ratelimit-reset", "1600000000")
+            .with_body("API rate limit exceeded")
+            .expect(1)
+            .create();
+
+        let converter = Converter::new(mockito::server_url(), None);
+        let html = converter.convert_markdown("# A thing!").await;
+
+        m.assert();
+        assert_eq!(
+            html,
+            Err(MarkdownError::RateLimited {
+                reset: Some(1600000000)
+            })
+        );
+    }
 }
@@ -0,0 +1,359 @@
+use horrorshow::prelude::*;
+use pulldown_cmark::{Event, Parser, Tag};
+
+#[cfg(feature = "server")]
+use crate::config::ConfigError;
+
+/// The file mdBook keeps a book's table of contents in, read from the root of the site.
+pub const SUMMARY_FILE: &str = "SUMMARY.md";
+
+/// rs-readme's own navigation file, read from the root of the site. It takes precedence over a
+/// `SUMMARY.md`.
+///
+/// ```toml
+/// pages = [{ path = "README.md", title = "Introduction" }]
+///
+/// [[section]]
+/// title = "Guide"
+/// pages = [
+///     { path = "docs/install.md", title = "Installing" },
+///     { path = "docs/usage.md" },
+/// ]
+/// ```
+pub const NAV_FILE: &str = ".rs-readme-nav.toml";
+
+/// One entry in a [`Navigation`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavItem {
+    pub title: String,
+
+    /// The document it links to, relative to the root. `None` for part titles and draft
+    /// chapters.
+    pub path: Option<String>,
+
+    /// How deeply it's nested, `0` for the top level.
+    pub depth: usize,
+}
+
+/// The reading order of the documents in a folder, shown as a sidebar with links to the
+/// previous and next page.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Navigation {
+    pub items: Vec<NavItem>,
+}
+
+impl Navigation {
+    /// Reads an mdBook `SUMMARY.md`.
+    ///
+    /// Links in (nested) lists are chapters, links outside them are prefix and suffix chapters
+    /// and headings are part titles, except a first heading before any chapter, which is the
+    /// title of the summary itself.
+    pub fn from_summary(md: &str) -> Navigation {
+        let mut items = Vec::new();
+        let mut pending: Option<NavItem> = None;
+        let mut lists = 0;
+        let mut seen_heading = false;
+
+        for event in Parser::new(md) {
+            match event {
+                Event::Start(Tag::List(_)) => {
+                    items.extend(pending.take());
+                    lists += 1;
+                }
+                Event::End(Tag::List(_)) => lists -= 1,
+                Event::Start(Tag::Item) => pending = Some(NavItem::new(lists - 1)),
+                Event::End(Tag::Item) => items.extend(pending.take()),
+                Event::Start(Tag::Heading(_)) => pending = Some(NavItem::new(0)),
+                Event::End(Tag::Heading(_)) => {
+                    let title = pending.take();
+                    if seen_heading || !items.is_empty() {
+                        items.extend(title);
+                    }
+                    seen_heading = true;
+                }
+                Event::Start(Tag::Link(_, dest, _)) => {
+                    let item = pending.get_or_insert_with(|| NavItem::new(0));
+                    item.path = page_path(&dest);
+                }
+                Event::End(Tag::Link(..)) if lists == 0 => items.extend(pending.take()),
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some(item) = pending.as_mut() {
+                        item.title.push_str(&text);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        items.retain(|item| !item.title.trim().is_empty());
+        Navigation { items }
+    }
+
+    /// Reads a [`NAV_FILE`]. Pages without a title are shown by their file name.
+    #[cfg(feature = "server")]
+    pub fn from_nav_file(toml: &str) -> Result<Navigation, ConfigError> {
+        let file: NavFile =
+            toml::from_str(toml).map_err(|err| ConfigError::Invalid(err.to_string()))?;
+
+        let mut items: Vec<NavItem> = file.pages.into_iter().map(NavPage::into_item).collect();
+        for section in file.sections {
+            items.push(NavItem {
+                title: section.title,
+                path: None,
+                depth: 0,
+            });
+            items.extend(section.pages.into_iter().map(NavPage::into_item));
+        }
+
+        Ok(Navigation { items })
+    }
+
+    /// The first document, what the index page shows.
+    pub fn first_page(&self) -> Option<&str> {
+        self.pages().next().and_then(|item| item.path.as_deref())
+    }
+
+    /// The pages before and after the one at `path` in reading order.
+    pub fn neighbours(&self, path: &str) -> (Option<&NavItem>, Option<&NavItem>) {
+        let pages: Vec<&NavItem> = self.pages().collect();
+        let current = current_path(path);
+
+        match pages
+            .iter()
+            .position(|item| item.path.as_deref() == Some(current))
+        {
+            Some(at) => (
+                at.checked_sub(1).map(|prev| pages[prev]),
+                pages.get(at + 1).copied(),
+            ),
+            None => (None, None),
+        }
+    }
+
+    /// The entries that link to a document.
+    fn pages(&self) -> impl Iterator<Item = &NavItem> {
+        self.items.iter().filter(|item| item.path.is_some())
+    }
+
+    /// `page`, the page at `path`, with the sidebar beside it and links to the previous and next
+    /// page under it. `pinned` goes at the top of the sidebar.
+    pub(crate) fn wrap(&self, path: &str, pinned: &str, page: &str) -> String {
+        let current = current_path(path);
+        let (prev, next) = self.neighbours(path);
+
+        format!(
+            "{}",
+            html! {
+                div(class="rs-readme-book") {
+                    nav(class="rs-readme-sidebar", aria-label="Contents") {
+                        : Raw(pinned);
+                        ul {
+                            @ for item in &self.items {
+                                li(class=item.class(current), style=format!("--depth: {}", item.depth)) {
+                                    @ if let Some(href) = item.href() {
+                                        a(href=href) : &item.title;
+                                    } else {
+                                        : &item.title;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    div(class="rs-readme-book-page") {
+                        : Raw(page);
+                        nav(class="rs-readme-pager", aria-label="Previous and next pages") {
+                            @ if let Some(prev) = prev.and_then(|prev| prev.href().map(|href| (href, prev))) {
+                                a(href=prev.0, rel="prev") : format!("← {}", prev.1.title);
+                            }
+                            @ if let Some(next) = next.and_then(|next| next.href().map(|href| (href, next))) {
+                                a(href=next.0, rel="next") : format!("{} →", next.1.title);
+                            }
+                        }
+                    }
+                }
+            }
+        )
+    }
+}
+
+impl NavItem {
+    fn new(depth: usize) -> NavItem {
+        NavItem {
+            title: String::new(),
+            path: None,
+            depth,
+        }
+    }
+
+    fn href(&self) -> Option<String> {
+        self.path.as_ref().map(|path| format!("/{}", path))
+    }
+
+    fn class(&self, current: &str) -> &'static str {
+        match &self.path {
+            None => "rs-readme-nav-section",
+            Some(path) if path == current => "rs-readme-nav-current",
+            Some(_) => "rs-readme-nav-page",
+        }
+    }
+}
+
+/// The contents of a [`NAV_FILE`].
+#[cfg(feature = "server")]
+#[derive(Debug, Deserialize)]
+struct NavFile {
+    #[serde(default)]
+    pages: Vec<NavPage>,
+
+    #[serde(default, rename = "section")]
+    sections: Vec<NavSection>,
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, Deserialize)]
+struct NavSection {
+    title: String,
+
+    #[serde(default)]
+    pages: Vec<NavPage>,
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, Deserialize)]
+struct NavPage {
+    path: String,
+    title: Option<String>,
+}
+
+#[cfg(feature = "server")]
+impl NavPage {
+    fn into_item(self) -> NavItem {
+        let path = current_path(&self.path).to_string();
+        let title = self
+            .title
+            .unwrap_or_else(|| path.rsplit('/').next().unwrap_or_default().to_string());
+
+        NavItem {
+            title,
+            path: Some(path),
+            depth: 0,
+        }
+    }
+}
+
+/// A summary link as a path relative to the root, without `./` or a fragment. `None` for a
+/// draft chapter's empty link or a link elsewhere.
+fn page_path(dest: &str) -> Option<String> {
+    let path = dest.split('#').next().unwrap_or_default();
+    let path = path.trim_start_matches("./").trim_start_matches('/');
+
+    if path.is_empty() || path.contains("://") {
+        None
+    } else {
+        Some(path.to_string())
+    }
+}
+
+/// The page being rendered, from a resource like `./docs/intro.md`, as a summary path.
+fn current_path(path: &str) -> &str {
+    path.trim_start_matches("./").trim_start_matches('/')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const SUMMARY: &str = "\
+# Summary
+
+[Introduction](./README.md)
+
+# Guide
+
+- [Installing](guide/install.md)
+    - [From source](guide/source.md#building)
+- [Drafted]()
+
+---
+
+[Contributors](CONTRIBUTORS.md)
+";
+
+    fn item(title: &str, path: Option<&str>, depth: usize) -> NavItem {
+        NavItem {
+            title: title.to_string(),
+            path: path.map(str::to_string),
+            depth,
+        }
+    }
+
+    #[test]
+    fn reads_mdbook_summaries() {
+        let nav = Navigation::from_summary(SUMMARY);
+
+        assert_eq!(
+            nav.items,
+            vec![
+                item("Introduction", Some("README.md"), 0),
+                item("Guide", None, 0),
+                item("Installing", Some("guide/install.md"), 0),
+                item("From source", Some("guide/source.md"), 1),
+                item("Drafted", None, 0),
+                item("Contributors", Some("CONTRIBUTORS.md"), 0),
+            ]
+        );
+        assert_eq!(nav.first_page(), Some("README.md"));
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn reads_nav_files() {
+        let toml = "\
+pages = [{ path = \"./README.md\", title = \"Introduction\" }]
+
+[[section]]
+title = \"Guide\"
+pages = [{ path = \"docs/usage.md\" }]
+";
+
+        let nav = Navigation::from_nav_file(toml).unwrap();
+
+        assert_eq!(
+            nav.items,
+            vec![
+                item("Introduction", Some("README.md"), 0),
+                item("Guide", None, 0),
+                item("usage.md", Some("docs/usage.md"), 0),
+            ]
+        );
+        assert!(matches!(
+            Navigation::from_nav_file("pages = 1"),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn finds_the_previous_and_next_pages() {
+        let nav = Navigation::from_summary(SUMMARY);
+
+        let (prev, next) = nav.neighbours("./guide/source.md");
+
+        assert_eq!(prev.map(|item| item.title.as_str()), Some("Installing"));
+        assert_eq!(next.map(|item| item.title.as_str()), Some("Contributors"));
+        assert_eq!(nav.neighbours("./elsewhere.md"), (None, None));
+    }
+
+    #[test]
+    fn marks_the_current_page() {
+        let nav = Navigation::from_summary(SUMMARY);
+
+        let html = nav.wrap("./guide/install.md", "", "<p>page</p>");
+
+        assert!(html.contains(
+            "<li class=\"rs-readme-nav-current\" style=\"--depth: 0\"><a href=\"/guide/install.md\">Installing</a></li>"
+        ));
+        assert!(html.contains("<a href=\"/README.md\" rel=\"prev\">← Introduction</a>"));
+        assert!(html.contains("<a href=\"/guide/source.md\" rel=\"next\">From source →</a>"));
+    }
+}
@@ -1,9 +1,21 @@
-use crate::markdown_converter::{MarkdownConverter, MarkdownError};
+#[cfg(feature = "highlight")]
+use crate::highlight::highlight_code_blocks;
+use crate::markdown_converter::{HtmlChunks, MarkdownConverter, MarkdownError, StreamingConverter};
+use crate::source_lines::source_lines;
 use async_trait::async_trait;
-use pulldown_cmark::{html, Options, Parser};
+use futures::channel::mpsc;
+use futures::executor::block_on;
+use futures::SinkExt;
+use pulldown_cmark::{html, Event, Options, Parser};
+use std::io::{self, Write};
+use std::thread;
+
+/// How much HTML to collect before handing it on when streaming.
+const CHUNK_SIZE: usize = 64 * 1024;
 
 pub struct OfflineConverter {
     options: Options,
+    source_lines: bool,
 }
 
 impl OfflineConverter {
@@ -11,8 +23,38 @@ impl OfflineConverter {
     pub fn new() -> OfflineConverter {
         OfflineConverter {
             options: Options::all(),
+            source_lines: false,
         }
     }
+
+    /// Marks top-level blocks with the line of the markdown they start on, see
+    /// [`crate::SOURCE_LINE_ATTRIBUTE`]. Off by default.
+    pub fn with_source_lines(mut self, source_lines: bool) -> OfflineConverter {
+        self.source_lines = source_lines;
+        self
+    }
+}
+
+/// The events of `md`, marked with their source lines when asked to.
+fn events(md: &str, options: Options, lines: bool) -> Box<dyn Iterator<Item = Event<'_>> + '_> {
+    let parser = Parser::new_ext(md, options);
+    if lines {
+        Box::new(source_lines(md, parser.into_offset_iter()))
+    } else {
+        Box::new(parser)
+    }
+}
+
+/// `events` with their fenced code blocks highlighted, see [`highlight_code_blocks`].
+#[cfg(feature = "highlight")]
+fn code_blocks<'a>(events: impl Iterator<Item = Event<'a>>) -> impl Iterator<Item = Event<'a>> {
+    highlight_code_blocks(events)
+}
+
+/// `events` as they are, highlighting needs the `highlight` feature.
+#[cfg(not(feature = "highlight"))]
+fn code_blocks<'a>(events: impl Iterator<Item = Event<'a>>) -> impl Iterator<Item = Event<'a>> {
+    events
 }
 
 impl Default for OfflineConverter {
@@ -24,12 +66,104 @@ impl Default for OfflineConverter {
 #[async_trait]
 impl MarkdownConverter for OfflineConverter {
     async fn convert_markdown(&self, md: &str) -> Result<String, MarkdownError> {
-        let parser = Parser::new_ext(&md, self.options);
+        let events = events(md, self.options, self.source_lines);
 
         let mut html_output = String::new();
 
-        html::push_html(&mut html_output, parser);
+        html::push_html(&mut html_output, code_blocks(events));
 
         Ok(html_output)
     }
+
+    fn streaming(&self) -> Option<&dyn StreamingConverter> {
+        Some(self)
+    }
+
+    fn name(&self) -> &'static str {
+        "offline"
+    }
+}
+
+impl StreamingConverter for OfflineConverter {
+    /// Converts on its own thread, a chunk at a time, so the first bytes go out before the
+    /// whole document is done.
+    fn convert_markdown_stream(&self, md: String) -> HtmlChunks {
+        let options = self.options;
+        let lines = self.source_lines;
+        let (tx, rx) = mpsc::channel(4);
+
+        thread::spawn(move || {
+            let mut out = ChunkWriter {
+                chunk: Vec::with_capacity(CHUNK_SIZE),
+                tx,
+            };
+            // Only fails once the receiver's gone, nobody's left to tell
+            let events = code_blocks(events(&md, options, lines));
+            let _ = html::write_html(&mut out, events).and_then(|_| out.flush());
+        });
+
+        Box::pin(rx)
+    }
+}
+
+/// Sends everything written to it down a channel in [`CHUNK_SIZE`] pieces.
+struct ChunkWriter {
+    chunk: Vec<u8>,
+    tx: mpsc::Sender<Vec<u8>>,
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.chunk.extend_from_slice(buf);
+        if self.chunk.len() >= CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+
+        let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(CHUNK_SIZE));
+        block_on(self.tx.send(chunk)).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::StreamExt;
+
+    #[async_std::test]
+    async fn streamed_html_matches_buffered_html() {
+        let converter = OfflineConverter::default();
+        let md = "# Title\n\n| a | b |\n|---|:-:|\n| 1 | 2 |\n\nSome *text*[^1].\n\n[^1]: A note\n"
+            .repeat(2_000);
+
+        let buffered = converter.convert_markdown(&md).await.unwrap();
+        let chunks: Vec<Vec<u8>> = converter.convert_markdown_stream(md).collect().await;
+
+        assert!(chunks.len() > 1);
+        assert_eq!(String::from_utf8(chunks.concat()).unwrap(), buffered);
+    }
+
+    /// `reload.js` looks for these classes to show notes in popovers and link back from them.
+    #[async_std::test]
+    async fn footnotes_link_to_their_notes() {
+        let converter = OfflineConverter::default();
+
+        let html = converter
+            .convert_markdown("Text[^note].\n\n[^note]: A note\n")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            html,
+            "<p>Text<sup class=\"footnote-reference\"><a href=\"#note\">1</a></sup>.</p>\n\
+             <div class=\"footnote-definition\" id=\"note\">\
+             <sup class=\"footnote-definition-label\">1</sup>\n<p>A note</p>\n</div>\n"
+        );
+    }
 }
@@ -0,0 +1,180 @@
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+use crate::frontmatter::Frontmatter;
+use crate::links::{heading_slug, line_of};
+
+/// A heading in a markdown document, with the headings under it, see [`outline`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Heading {
+    /// 1 for `#`, 2 for `##`, ...
+    pub level: u32,
+    pub text: String,
+
+    /// The anchor GitHub generates for it, `-1`, `-2`, ... appended to repeats.
+    pub slug: String,
+
+    /// The 1-based line it's on.
+    pub line: usize,
+
+    /// The headings of a deeper level after it, up to the next one of its level or higher.
+    pub children: Vec<Heading>,
+}
+
+/// Every heading in `md` in document order, without children. Frontmatter is skipped, its
+/// closing `---` would otherwise turn the line before it into a heading.
+pub fn headings(md: &str) -> Vec<Heading> {
+    let start = match Frontmatter::split(md) {
+        Some((_, rest)) => md.len() - rest.len(),
+        None => 0,
+    };
+
+    let mut headings: Vec<Heading> = Vec::new();
+    let mut current: Option<Heading> = None;
+    for (event, range) in Parser::new_ext(&md[start..], Options::all()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading(level)) => {
+                current = Some(Heading {
+                    level,
+                    text: String::new(),
+                    slug: String::new(),
+                    line: line_of(md, start + range.start),
+                    children: Vec::new(),
+                })
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(heading) = current.as_mut() {
+                    heading.text.push_str(&text);
+                }
+            }
+            Event::End(Tag::Heading(_)) => {
+                if let Some(mut heading) = current.take() {
+                    let slug = heading_slug(&heading.text);
+                    let mut candidate = slug.clone();
+                    let mut count = 0;
+                    while headings.iter().any(|seen| seen.slug == candidate) {
+                        count += 1;
+                        candidate = format!("{}-{}", slug, count);
+                    }
+                    heading.slug = candidate;
+                    headings.push(heading);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    headings
+}
+
+/// The headings of `md` as a tree, each holding the deeper ones that follow it, for building a
+/// table of contents.
+pub fn outline(md: &str) -> Vec<Heading> {
+    let mut roots = Vec::new();
+    let mut open: Vec<Heading> = Vec::new();
+
+    for heading in headings(md) {
+        while open.last().is_some_and(|last| last.level >= heading.level) {
+            let done = open.pop().unwrap();
+            attach(done, &mut open, &mut roots);
+        }
+        open.push(heading);
+    }
+    while let Some(done) = open.pop() {
+        attach(done, &mut open, &mut roots);
+    }
+
+    roots
+}
+
+/// The title of the document `md`: its frontmatter's `title`, or else the text of its first `#`
+/// heading. `None` when it has neither.
+pub(crate) fn document_title(md: &str) -> Option<String> {
+    let (start, frontmatter_title) = match Frontmatter::split(md) {
+        Some((frontmatter, rest)) => (
+            md.len() - rest.len(),
+            frontmatter
+                .fields
+                .into_iter()
+                .find(|(name, _)| name == "title")
+                .and_then(|(_, value)| value.as_str().map(str::to_string)),
+        ),
+        None => (0, None),
+    };
+    if frontmatter_title.is_some() {
+        return frontmatter_title;
+    }
+
+    let mut title: Option<String> = None;
+    for event in Parser::new_ext(&md[start..], Options::all()) {
+        match event {
+            Event::Start(Tag::Heading(1)) => title = Some(String::new()),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(title) = title.as_mut() {
+                    title.push_str(&text);
+                }
+            }
+            Event::End(Tag::Heading(1)) => break,
+            _ => {}
+        }
+    }
+
+    title
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+}
+
+/// Adds `heading` to the one it's under, or to the `roots` if it's under nothing.
+fn attach(heading: Heading, open: &mut [Heading], roots: &mut Vec<Heading>) {
+    match open.last_mut() {
+        Some(parent) => parent.children.push(heading),
+        None => roots.push(heading),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn heading(level: u32, text: &str, slug: &str, line: usize) -> Heading {
+        Heading {
+            level,
+            text: text.to_string(),
+            slug: slug.to_string(),
+            line,
+            children: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn nests_headings_under_shallower_ones() {
+        let md = "# Guide\n\n## Install\n\n### From `cargo`\n\n## Usage\n\n# Guide\n";
+
+        let mut install = heading(2, "Install", "install", 3);
+        install.children = vec![heading(3, "From cargo", "from-cargo", 5)];
+        let mut guide = heading(1, "Guide", "guide", 1);
+        guide.children = vec![install, heading(2, "Usage", "usage", 7)];
+
+        assert_eq!(outline(md), vec![guide, heading(1, "Guide", "guide-1", 9)]);
+    }
+
+    #[test]
+    fn skips_frontmatter() {
+        let md = "---\ntitle: Notes\n---\nIntro\n\n## Details\n";
+
+        assert_eq!(outline(md), vec![heading(2, "Details", "details", 6)]);
+    }
+
+    #[test]
+    fn titles_come_from_frontmatter_or_the_first_heading() {
+        assert_eq!(
+            document_title("---\ntitle: Notes\n---\n# Heading\n").as_deref(),
+            Some("Notes")
+        );
+        assert_eq!(
+            document_title("Intro\n\n## Details\n\n# Using `rs-readme`\n").as_deref(),
+            Some("Using rs-readme")
+        );
+        assert_eq!(document_title("## Details\n"), None);
+    }
+}
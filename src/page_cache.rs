@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::content_finder::ContentHash;
+use crate::git_history::{Commit, LastEdit};
+use crate::git_status::RepoStatus;
+use crate::languages::Translation;
+use crate::render::{RenderOptions, RenderedPage};
+use crate::warnings::Warning;
+
+/// How many pages are cached unless `--cache-size` says otherwise.
+pub const DEFAULT_CAPACITY: usize = 128;
+
+/// What a rendered page depends on: the document, its contents, the converter picked for the
+/// request, how it was wrapped, the `SUMMARY.md` its sidebar was built from, the translations
+/// it links to, the commits in its history, or when its file was modified, the checkout's
+/// branch and dirty state in the footer, and its warnings, which can go away without it
+/// changing.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct PageKey {
+    pub path: String,
+    pub hash: ContentHash,
+    pub converter: Option<String>,
+    pub options: RenderOptions,
+    pub navigation: Option<ContentHash>,
+    pub translations: Vec<Translation>,
+    pub history: Vec<Commit>,
+    pub last_edit: Option<LastEdit>,
+    pub repo: Option<RepoStatus>,
+    pub pins: Option<Vec<String>>,
+    pub encoding: Option<&'static str>,
+    pub warnings: Vec<Warning>,
+}
+
+/// How well the page cache is doing, see `/__rs-readme/metrics`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+/// The least recently used rendered pages, so unchanged files aren't converted again.
+pub(crate) struct PageCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// Each page and the tick it was last used on.
+    pages: HashMap<PageKey, (RenderedPage, u64)>,
+    tick: u64,
+}
+
+impl PageCache {
+    /// A cache holding up to `capacity` pages, `0` turns caching off.
+    pub fn new(capacity: usize) -> PageCache {
+        PageCache {
+            capacity,
+            entries: Mutex::new(Entries::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    #[cfg_attr(
+        feature = "otel",
+        tracing::instrument(
+            name = "cache_lookup",
+            skip_all,
+            fields(path = %key.path, hit = tracing::field::Empty)
+        )
+    )]
+    pub fn get(&self, key: &PageKey) -> Option<RenderedPage> {
+        if self.capacity == 0 {
+            return None;
+        }
+
+        let mut entries = self.entries();
+        entries.tick += 1;
+        let tick = entries.tick;
+
+        match entries.pages.get_mut(key) {
+            Some((page, used)) => {
+                *used = tick;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                #[cfg(feature = "otel")]
+                tracing::Span::current().record("hit", true);
+                Some(page.clone())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                #[cfg(feature = "otel")]
+                tracing::Span::current().record("hit", false);
+                None
+            }
+        }
+    }
+
+    /// Caches `page`, evicting the least recently used page if the cache is full.
+    pub fn insert(&self, key: PageKey, page: RenderedPage) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries();
+        entries.tick += 1;
+        let tick = entries.tick;
+
+        if entries.pages.len() >= self.capacity && !entries.pages.contains_key(&key) {
+            let oldest = entries
+                .pages
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.pages.remove(&oldest);
+            }
+        }
+
+        entries.pages.insert(key, (page, tick));
+    }
+
+    /// The page for `path` wrapped according to `options` that was used last, whatever version,
+    /// converter or surroundings it was rendered with, for showing something when it can't be
+    /// rendered now. Doesn't count as a hit or a miss.
+    pub fn last_for(&self, path: &str, options: &RenderOptions) -> Option<RenderedPage> {
+        self.entries()
+            .pages
+            .iter()
+            .filter(|(key, _)| key.path == path && key.options == *options)
+            .max_by_key(|(_, (_, used))| *used)
+            .map(|(_, (page, _))| page.clone())
+    }
+
+    /// Forgets every page, for when something they all depend on (like the config) changes.
+    pub fn clear(&self) {
+        self.entries().pages.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            len: self.entries().pages.len(),
+            capacity: self.capacity,
+        }
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        match self.entries.lock() {
+            Ok(entries) => entries,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(path: &str) -> PageKey {
+        PageKey {
+            path: path.to_string(),
+            hash: ContentHash::of(path.as_bytes()),
+            converter: None,
+            options: RenderOptions::default(),
+            navigation: None,
+            translations: Vec::new(),
+            history: Vec::new(),
+            last_edit: None,
+            repo: None,
+            pins: None,
+            encoding: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn page(title: &str) -> RenderedPage {
+        RenderedPage {
+            title: title.to_string(),
+            hash: ContentHash::of(title.as_bytes()),
+            content: String::new(),
+            html: String::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = PageCache::new(2);
+        cache.insert(key("a"), page("a"));
+        cache.insert(key("b"), page("b"));
+        cache.get(&key("a"));
+        cache.insert(key("c"), page("c"));
+
+        assert_eq!(cache.get(&key("a")), Some(page("a")));
+        assert_eq!(cache.get(&key("b")), None);
+        assert_eq!(cache.get(&key("c")), Some(page("c")));
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let cache = PageCache::new(2);
+        cache.get(&key("a"));
+        cache.insert(key("a"), page("a"));
+        cache.get(&key("a"));
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                len: 1,
+                capacity: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn zero_capacity_caches_nothing() {
+        let cache = PageCache::new(0);
+        cache.insert(key("a"), page("a"));
+
+        assert_eq!(cache.get(&key("a")), None);
+        assert_eq!(cache.stats().misses, 0);
+    }
+
+    #[test]
+    fn finds_the_last_page_used_for_a_path() {
+        let cache = PageCache::new(4);
+        let old = PageKey {
+            hash: ContentHash::of(b"old"),
+            ..key("a")
+        };
+        cache.insert(old.clone(), page("old"));
+        cache.insert(key("a"), page("new"));
+        cache.insert(key("b"), page("b"));
+        let fragment = PageKey {
+            options: RenderOptions {
+                full_page: false,
+                ..RenderOptions::default()
+            },
+            ..key("a")
+        };
+        cache.insert(fragment, page("fragment"));
+
+        let full = RenderOptions::default();
+        assert_eq!(cache.last_for("a", &full), Some(page("new")));
+        cache.get(&old);
+        assert_eq!(cache.last_for("a", &full), Some(page("old")));
+        assert_eq!(cache.last_for("c", &full), None);
+    }
+}
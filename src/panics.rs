@@ -0,0 +1,71 @@
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::panic;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Once;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+thread_local! {
+    /// Where the last panic on this thread happened, see [`record_backtraces`].
+    static BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// Makes panics remember where they happened, for [`take_backtrace`] once they're caught. The
+/// panic hook that was there before still runs after.
+pub(crate) fn record_backtraces() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            BACKTRACE.with(|backtrace| *backtrace.borrow_mut() = Some(Backtrace::force_capture()));
+            previous(info);
+        }));
+    });
+}
+
+/// The backtrace of the last panic caught on this thread. Handlers are polled on the thread
+/// that catches their panics, so it's theirs.
+pub(crate) fn take_backtrace() -> Option<Backtrace> {
+    BACKTRACE.with(|backtrace| backtrace.borrow_mut().take())
+}
+
+/// What a panic said, from its `payload`.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// An ID for a request that went wrong, to find it in the log from the error page: when the
+/// server was asked in milliseconds, and a count to tell apart requests in the same one.
+pub(crate) fn request_id() -> String {
+    static COUNT: AtomicU64 = AtomicU64::new(0);
+
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64);
+    let count = COUNT.fetch_add(1, Ordering::Relaxed);
+    format!("{:x}-{:04x}", millis, count & 0xffff)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_panic_messages() {
+        let literal = panic::catch_unwind(|| panic!("oops")).unwrap_err();
+        let formatted = panic::catch_unwind(|| panic!("oops {}", 2)).unwrap_err();
+
+        assert_eq!(panic_message(&*literal), "oops");
+        assert_eq!(panic_message(&*formatted), "oops 2");
+    }
+
+    #[test]
+    fn request_ids_differ() {
+        assert_ne!(request_id(), request_id());
+    }
+}
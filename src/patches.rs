@@ -0,0 +1,410 @@
+use similar::{capture_diff_slices_deadline, Algorithm, DiffOp};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::source_lines::SOURCE_LINE_ATTRIBUTE;
+
+/// How long to spend finding the smallest patch before settling for a bigger one.
+const DIFF_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Elements that are never closed.
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose contents aren't HTML, skipped up to their closing tag.
+const RAW_TEXT_ELEMENTS: [&str; 3] = ["script", "style", "textarea"];
+
+/// Replaces `remove` top-level blocks of a page's content with the `insert`ed ones, starting at
+/// block `at` of the content as patched by the operations before it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct PatchOp {
+    pub at: usize,
+    pub remove: usize,
+    pub insert: Vec<String>,
+}
+
+/// How to turn one version of a converted document into the next one block by block, see
+/// [`patch`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct Patch {
+    pub ops: Vec<PatchOp>,
+
+    /// The line of the source each block of the new version starts on, when blocks that didn't
+    /// change moved to other lines.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lines: Option<Vec<Option<u32>>>,
+
+    /// The new `id`s of anchors in blocks that were replaced by ones with another, so pages can
+    /// follow a renamed heading their URL's fragment points at.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub renamed: BTreeMap<String, String>,
+
+    /// The anchor of the heading over the first change, for pages following the changes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+}
+
+/// The operations that turn the top-level blocks of the converted document `old` into those of
+/// `new`, for sending to pages instead of all of `new`.
+///
+/// Blocks are compared without their [`SOURCE_LINE_ATTRIBUTE`], so lines added at the top don't
+/// change every block below them, and the new lines are sent along instead. `None` when either
+/// version isn't just a list of elements, or when the blocks to insert would be more than half
+/// of `new` anyway.
+pub(crate) fn patch(old: &str, new: &str) -> Option<Patch> {
+    let old = blocks(old)?;
+    let new_blocks = blocks(new)?;
+    let old_keys: Vec<_> = old
+        .iter()
+        .map(|block| without_source_line(block).0)
+        .collect();
+    let (new_keys, new_lines): (Vec<_>, Vec<_>) = new_blocks
+        .iter()
+        .map(|block| without_source_line(block))
+        .unzip();
+
+    let deadline = Instant::now() + DIFF_TIMEOUT;
+    let diff = capture_diff_slices_deadline(Algorithm::Myers, &old_keys, &new_keys, Some(deadline));
+
+    let mut ops = Vec::new();
+    let mut at = 0;
+    let mut inserted = 0;
+    let mut moved = false;
+    let mut renamed = BTreeMap::new();
+    for op in diff {
+        let (remove, insert) = match op {
+            DiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            } => {
+                moved |= (0..len)
+                    .any(|n| without_source_line(old[old_index + n]).1 != new_lines[new_index + n]);
+                at += len;
+                continue;
+            }
+            DiffOp::Delete { old_len, .. } => (old_len, 0..0),
+            DiffOp::Insert {
+                new_index, new_len, ..
+            } => (0, new_index..new_index + new_len),
+            DiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            } => {
+                if old_len == new_len {
+                    let replaced = old[old_index..old_index + old_len].iter();
+                    for (old, new) in replaced.zip(&new_blocks[new_index..new_index + new_len]) {
+                        for (old, new) in anchors(old).into_iter().zip(anchors(new)) {
+                            if old != new {
+                                renamed.insert(old.to_string(), new.to_string());
+                            }
+                        }
+                    }
+                }
+                (old_len, new_index..new_index + new_len)
+            }
+        };
+
+        let insert: Vec<String> = new_blocks[insert].iter().map(|b| b.to_string()).collect();
+        inserted += insert.iter().map(String::len).sum::<usize>();
+        let len = insert.len();
+        ops.push(PatchOp { at, remove, insert });
+        at += len;
+    }
+
+    if inserted * 2 > new.len() {
+        return None;
+    }
+    let section = ops.first().and_then(|first| {
+        let above = if first.insert.is_empty() {
+            first.at
+        } else {
+            first.at + 1
+        };
+        section(&new_blocks[..above])
+    });
+    Some(Patch {
+        ops,
+        lines: moved.then_some(new_lines),
+        renamed,
+        section,
+    })
+}
+
+/// The anchor of the last heading in `blocks` that has one.
+fn section(blocks: &[&str]) -> Option<String> {
+    blocks
+        .iter()
+        .rev()
+        .find_map(|block| match tag_name(block).as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                anchors(block).first().map(|anchor| anchor.to_string())
+            }
+            _ => None,
+        })
+}
+
+/// The `id`s of the elements in `html`, for pages to tell whether their URL's fragment still
+/// points somewhere after an update.
+pub(crate) fn anchors(html: &str) -> Vec<&str> {
+    let mut anchors = Vec::new();
+    let mut rest = html;
+    while let Some(found) = rest.find('<') {
+        let tag = &rest[found..];
+        let end = match tag_end(tag) {
+            Some(end) if starts_tag(tag) && !tag.starts_with("</") => end,
+            _ => {
+                rest = &tag[1..];
+                continue;
+            }
+        };
+        if let Some(start) = tag[..end].find(" id=\"") {
+            let value = &tag[start + 5..end];
+            if let Some(len) = value.find('"') {
+                anchors.push(&value[..len]);
+            }
+        }
+        rest = &tag[end..];
+    }
+    anchors
+}
+
+/// The top-level elements of `html`, without the whitespace between them. `None` when there's
+/// text or a comment between them or the tags don't balance.
+fn blocks(html: &str) -> Option<Vec<&str>> {
+    let mut blocks = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut at = 0;
+
+    while at < html.len() {
+        let rest = &html[at..];
+        let tag_start = if depth == 0 {
+            let trimmed = rest.trim_start();
+            if trimmed.is_empty() {
+                break;
+            }
+            at += rest.len() - trimmed.len();
+            if !trimmed.starts_with('<') || trimmed.starts_with("</") || !starts_tag(trimmed) {
+                return None;
+            }
+            start = at;
+            at
+        } else {
+            at + rest.find('<')?
+        };
+        let tag = &html[tag_start..];
+        if tag.starts_with("<!--") {
+            at = tag_start + tag.find("-->")? + 3;
+            continue;
+        }
+        if !starts_tag(tag) {
+            at = tag_start + 1;
+            continue;
+        }
+
+        let end = tag_start + tag_end(tag)?;
+        let name = tag_name(&html[tag_start..end]);
+        at = end;
+        if html[tag_start..].starts_with("</") {
+            depth = depth.checked_sub(1)?;
+        } else if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+            let closing = format!("</{}", name);
+            let lower = html[at..].to_ascii_lowercase();
+            at += lower.find(&closing)?;
+            at += tag_end(&html[at..])?;
+        } else if !VOID_ELEMENTS.contains(&name.as_str()) && !html[..end].ends_with("/>") {
+            depth += 1;
+        }
+
+        if depth == 0 {
+            blocks.push(&html[start..at]);
+        }
+    }
+
+    (depth == 0).then_some(blocks)
+}
+
+/// Whether the `<` `html` starts with opens a tag rather than being text.
+fn starts_tag(html: &str) -> bool {
+    let after = html[1..].strip_prefix('/').unwrap_or(&html[1..]);
+    after.starts_with(|c: char| c.is_ascii_alphabetic())
+}
+
+/// How far into `tag` its closing `>` is, past it, skipping those in quoted attributes.
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    for (at, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if open == c => quote = None,
+            (None, '>') => return Some(at + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The lowercased name of the element `tag` opens or closes.
+fn tag_name(tag: &str) -> String {
+    tag.trim_start_matches('<')
+        .trim_start_matches('/')
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+/// `block` with the [`SOURCE_LINE_ATTRIBUTE`] taken out of its opening tag, and the line it
+/// gave.
+fn without_source_line(block: &str) -> (Cow<'_, str>, Option<u32>) {
+    let opening = &block[..tag_end(block).unwrap_or(block.len())];
+    let attribute = format!(" {}=\"", SOURCE_LINE_ATTRIBUTE);
+    let found = opening.find(&attribute).and_then(|start| {
+        let value = start + attribute.len();
+        let len = opening[value..].find('"')?;
+        let line = opening[value..value + len].parse().ok()?;
+        Some((start, value + len + 1, line))
+    });
+
+    match found {
+        Some((start, end, line)) => (
+            Cow::Owned(format!("{}{}", &block[..start], &block[end..])),
+            Some(line),
+        ),
+        None => (Cow::Borrowed(block), None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn splits_into_top_level_elements() {
+        let html = "<h1 id=\"a\">Title</h1>\n<p>Some <em>text</em> a &lt; b</p>\n\
+                    <hr />\n<ul>\n<li>one<br></li>\n</ul>\n<!-- ok --><pre><code>x\n</code></pre>";
+
+        let mut expected = vec![
+            "<h1 id=\"a\">Title</h1>",
+            "<p>Some <em>text</em> a &lt; b</p>",
+            "<hr />",
+            "<ul>\n<li>one<br></li>\n</ul>",
+        ];
+        assert_eq!(blocks(html), None);
+
+        let html = html.replace("<!-- ok -->", "");
+        expected.push("<pre><code>x\n</code></pre>");
+        assert_eq!(blocks(&html), Some(expected));
+    }
+
+    #[test]
+    fn skips_raw_text_and_quoted_brackets() {
+        let html =
+            "<div title=\"a > b\"><script>if (a < b) {}</script></div>\n<style>p > a {}</style>";
+
+        assert_eq!(
+            blocks(html),
+            Some(vec![
+                "<div title=\"a > b\"><script>if (a < b) {}</script></div>",
+                "<style>p > a {}</style>"
+            ])
+        );
+    }
+
+    #[test]
+    fn text_between_elements_is_not_split() {
+        assert_eq!(blocks("<p>one</p>\ntwo"), None);
+        assert_eq!(blocks("<p>one</p>\n<p>two"), None);
+    }
+
+    #[test]
+    fn patches_the_changed_blocks() {
+        let old = "<h1>Title</h1>\n<p>one</p>\n<p>two</p>\n<p>three</p>\n<p>a long paragraph that stays the same</p>\n";
+        let new = "<h1>Title</h1>\n<p>two</p>\n<p>three!</p>\n<p>four</p>\n<p>a long paragraph that stays the same</p>\n";
+
+        assert_eq!(
+            patch(old, new),
+            Some(Patch {
+                ops: vec![
+                    PatchOp {
+                        at: 1,
+                        remove: 1,
+                        insert: vec![]
+                    },
+                    PatchOp {
+                        at: 2,
+                        remove: 1,
+                        insert: vec!["<p>three!</p>".to_string(), "<p>four</p>".to_string()]
+                    },
+                ],
+                lines: None,
+                renamed: BTreeMap::new(),
+                section: None,
+            })
+        );
+    }
+
+    #[test]
+    fn sends_moved_source_lines() {
+        let old = "<h1 data-source-line=\"1\">Title</h1>\n<p data-source-line=\"3\">a long paragraph that stays the same</p>\n";
+        let new = "<h1 data-source-line=\"1\">Title</h1>\n<p data-source-line=\"3\">new</p>\n<p data-source-line=\"5\">a long paragraph that stays the same</p>\n";
+
+        assert_eq!(
+            patch(old, new),
+            Some(Patch {
+                ops: vec![PatchOp {
+                    at: 1,
+                    remove: 0,
+                    insert: vec!["<p data-source-line=\"3\">new</p>".to_string()]
+                }],
+                lines: Some(vec![Some(1), Some(3), Some(5)]),
+                renamed: BTreeMap::new(),
+                section: None,
+            })
+        );
+    }
+
+    #[test]
+    fn follows_renamed_anchors() {
+        let old = "<h2 id=\"install\">Install</h2>\n<p>a long paragraph that stays the same</p>\n";
+        let new =
+            "<h2 id=\"installing\">Installing</h2>\n<p>a long paragraph that stays the same</p>\n";
+
+        let patch = patch(old, new).unwrap();
+        assert_eq!(
+            patch.renamed,
+            BTreeMap::from([("install".to_string(), "installing".to_string())])
+        );
+    }
+
+    #[test]
+    fn names_the_section_that_changed() {
+        let old = "<h2 id=\"a\">A</h2>\n<p>a long paragraph that stays the same</p>\n\
+                   <h2><a id=\"b\" href=\"#b\"></a>B</h2>\n<p>x</p>\n<p>another long paragraph</p>\n";
+        let new = old.replace("<p>x</p>", "<p>y</p>");
+
+        assert_eq!(patch(old, &new).unwrap().section, Some("b".to_string()));
+        let new = old.replace("<p>x</p>\n", "");
+        assert_eq!(patch(old, &new).unwrap().section, Some("b".to_string()));
+    }
+
+    #[test]
+    fn finds_anchors() {
+        let html = "<h1 id=\"title\">Title</h1>\n<p data-id=\"no\">a <a id=\"x\" href=\"#y\">b</a> id=\"z\"</p>";
+
+        assert_eq!(anchors(html), vec!["title", "x"]);
+    }
+
+    #[test]
+    fn big_changes_are_not_patched() {
+        assert_eq!(patch("<p>one</p>", "<p>two</p>"), None);
+        assert_eq!(patch("one", "<p>one</p>"), None);
+    }
+}
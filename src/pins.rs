@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The file pins are kept in, in [`config_dir`].
+pub const PINS_FILE: &str = "pins.json";
+
+/// The folder rs-readme keeps its state in, `rs-readme` in `$XDG_CONFIG_HOME` (`~/.config` when
+/// that isn't set) or `%APPDATA%` on Windows.
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("rs-readme"))
+}
+
+/// The documents starred from their page header in one folder, listed under "Pinned" at the top
+/// of the index and the sidebar.
+///
+/// They're saved in a JSON file shared by every folder, keyed by the folder's absolute path, so
+/// they're still there the next time the folder is served.
+#[derive(Debug, Clone)]
+pub struct Pins {
+    file: PathBuf,
+    folder: String,
+    pinned: Arc<Mutex<Vec<String>>>,
+}
+
+impl Pins {
+    /// The pins for `folder` saved in `file`, none when it can't be read.
+    pub fn new(file: impl Into<PathBuf>, folder: &Path) -> Pins {
+        let file = file.into();
+        let folder = folder
+            .canonicalize()
+            .unwrap_or_else(|_| folder.to_path_buf())
+            .to_string_lossy()
+            .into_owned();
+        let pinned = read_pins(&file).remove(&folder).unwrap_or_default();
+
+        Pins {
+            file,
+            folder,
+            pinned: Arc::new(Mutex::new(pinned)),
+        }
+    }
+
+    /// The pins for `folder` saved in [`PINS_FILE`] in [`config_dir`], `None` when there's no
+    /// config folder to keep them in.
+    pub fn for_folder(folder: &Path) -> Option<Pins> {
+        config_dir().map(|dir| Pins::new(dir.join(PINS_FILE), folder))
+    }
+
+    /// The pinned documents, relative to the folder and `/` separated, in the order they were
+    /// pinned.
+    pub fn pinned(&self) -> Vec<String> {
+        self.lock().clone()
+    }
+
+    /// Pins the document at `path` and saves the pins, returning them.
+    pub fn pin(&self, path: &str) -> io::Result<Vec<String>> {
+        let path = pin_path(path);
+        self.update(|pinned| {
+            if !pinned.iter().any(|pin| pin == path) {
+                pinned.push(path.to_string());
+            }
+        })
+    }
+
+    /// Unpins the document at `path` and saves the pins, returning them.
+    pub fn unpin(&self, path: &str) -> io::Result<Vec<String>> {
+        let path = pin_path(path);
+        self.update(|pinned| pinned.retain(|pin| pin != path))
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Vec<String>)) -> io::Result<Vec<String>> {
+        let mut pinned = self.lock();
+        change(&mut pinned);
+
+        // Other servers may have saved their folder's pins since this one started
+        let mut saved = read_pins(&self.file);
+        if pinned.is_empty() {
+            saved.remove(&self.folder);
+        } else {
+            saved.insert(self.folder.clone(), pinned.clone());
+        }
+        write_pins(&self.file, &saved)?;
+
+        Ok(pinned.clone())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<String>> {
+        match self.pinned.lock() {
+            Ok(pinned) => pinned,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+fn read_pins(file: &Path) -> BTreeMap<String, Vec<String>> {
+    fs::read(file)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Writes the pins next to `file` and moves them over it, so a crash can't leave half a file.
+fn write_pins(file: &Path, pins: &BTreeMap<String, Vec<String>>) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_vec_pretty(pins)?;
+    let partial = file.with_extension("json.partial");
+    fs::write(&partial, json)?;
+    fs::rename(&partial, file)
+}
+
+/// A document's path as it's pinned, from a resource (`./docs/intro.md`) or URL path
+/// (`/docs/intro.md`).
+pub(crate) fn pin_path(path: &str) -> &str {
+    path.trim_start_matches("./").trim_start_matches('/')
+}
+
+/// The star in the header of the page for the document at `path`, filled when it's in `pinned`.
+/// Clicking it pins or unpins the document, see the page script.
+pub(crate) fn star_html(path: &str, pinned: &[String]) -> String {
+    let path = pin_path(path);
+    let (action, title, star) = if pinned.iter().any(|pin| pin == path) {
+        ("unpin", "Unpin", "★")
+    } else {
+        ("pin", "Pin", "☆")
+    };
+
+    format!(
+        "{}",
+        html! {
+            button(class="rs-readme-pin", name=action, value=path, title=title) : star;
+        }
+    )
+}
+
+/// Links to the `pinned` documents for the index and sidebar, nothing when there aren't any.
+pub(crate) fn pinned_html(pinned: &[String]) -> String {
+    if pinned.is_empty() {
+        return String::new();
+    }
+
+    format!(
+        "{}",
+        html! {
+            div(class="rs-readme-pinned") {
+                strong : "Pinned";
+                ul {
+                    @ for path in pinned {
+                        li {
+                            a(href=format!("/{}", path)) : path;
+                        }
+                    }
+                }
+            }
+        }
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn pins_are_saved_per_folder() {
+        let dir = std::env::temp_dir().join(format!("rs-readme-pins-{}", std::process::id()));
+        let file = dir.join(PINS_FILE);
+        let docs = Pins::new(&file, Path::new("/projects/docs"));
+        let blog = Pins::new(&file, Path::new("/projects/blog"));
+
+        docs.pin("./guide.md").unwrap();
+        docs.pin("/README.md").unwrap();
+        docs.pin("guide.md").unwrap();
+        blog.pin("/posts/first.md").unwrap();
+        assert_eq!(docs.unpin("/README.md").unwrap(), vec!["guide.md"]);
+
+        let reopened = Pins::new(&file, Path::new("/projects/docs"));
+        assert_eq!(reopened.pinned(), vec!["guide.md"]);
+        let reopened = Pins::new(&file, Path::new("/projects/blog"));
+        assert_eq!(reopened.pinned(), vec!["posts/first.md"]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stars_show_whether_a_document_is_pinned() {
+        let pinned = vec!["docs/guide.md".to_string()];
+
+        assert_eq!(
+            star_html("./docs/guide.md", &pinned),
+            "<button class=\"rs-readme-pin\" name=\"unpin\" value=\"docs/guide.md\" title=\"Unpin\">★</button>"
+        );
+        assert_eq!(
+            star_html("README.md", &pinned),
+            "<button class=\"rs-readme-pin\" name=\"pin\" value=\"README.md\" title=\"Pin\">☆</button>"
+        );
+    }
+
+    #[test]
+    fn links_the_pinned_documents() {
+        assert_eq!(pinned_html(&[]), "");
+        assert_eq!(
+            pinned_html(&["docs/guide.md".to_string()]),
+            "<div class=\"rs-readme-pinned\"><strong>Pinned</strong>\
+             <ul><li><a href=\"/docs/guide.md\">docs/guide.md</a></li></ul></div>"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use log::error;
+use std::io::BufRead;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::content_finder::{normalize_text, ContentError, ContentFinder, ContentHash};
+use crate::links::normalize;
+
+/// The path the piped document is served at, see [`PipedFinder`].
+pub const PIPED_DOCUMENT: &str = "stdin.md";
+
+/// Separates documents on stdin, each one replaces the one before it.
+const SEPARATOR: u8 = b'\x0c';
+
+/// Markdown piped in from another program, for `--stdin`. Clones share the document, so one
+/// can be fed by [`PipedDocument::read_from`] while a [`PipedFinder`] serves it.
+#[derive(Clone, Default)]
+pub struct PipedDocument {
+    document: Arc<Mutex<Option<(String, ContentHash)>>>,
+}
+
+impl PipedDocument {
+    pub fn new() -> PipedDocument {
+        PipedDocument::default()
+    }
+
+    /// Replaces the document with `contents`.
+    pub fn set(&self, contents: String) {
+        let contents = normalize_text(contents);
+        let hash = ContentHash::of(contents.as_bytes());
+        *self.document() = Some((contents, hash));
+    }
+
+    /// Reads documents from `reader` until it ends, blocking. Each form feed (`\f`) ends a
+    /// document and the next one replaces it, so a program can keep the pipe open and send new
+    /// versions.
+    pub fn read_from(&self, mut reader: impl BufRead) {
+        loop {
+            let mut bytes = Vec::new();
+            match reader.read_until(SEPARATOR, &mut bytes) {
+                Ok(0) => return,
+                Ok(_) => {
+                    let ended = bytes.last() == Some(&SEPARATOR);
+                    if ended {
+                        bytes.pop();
+                    }
+                    self.set(String::from_utf8_lossy(&bytes).into_owned());
+                    if !ended {
+                        return;
+                    }
+                }
+                Err(err) => {
+                    error!("Could not read stdin: {}", err);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn get(&self) -> Option<(String, ContentHash)> {
+        self.document().clone()
+    }
+
+    fn document(&self) -> MutexGuard<'_, Option<(String, ContentHash)>> {
+        match self.document.lock() {
+            Ok(document) => document,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+/// Implements [`ContentFinder`] by serving a [`PipedDocument`] at [`PIPED_DOCUMENT`] and
+/// everything else, like the files it links to, from another finder.
+pub struct PipedFinder {
+    document: PipedDocument,
+    files: Box<dyn ContentFinder + Send + Sync>,
+}
+
+impl PipedFinder {
+    pub fn new(
+        document: PipedDocument,
+        files: impl ContentFinder + Send + Sync + 'static,
+    ) -> PipedFinder {
+        PipedFinder {
+            document,
+            files: Box::new(files),
+        }
+    }
+
+    fn is_piped(resource: &str) -> bool {
+        normalize(Path::new(resource)).is_some_and(|path| path == Path::new(PIPED_DOCUMENT))
+    }
+}
+
+impl ContentFinder for PipedFinder {
+    fn content_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+        if PipedFinder::is_piped(resource) {
+            // Nothing's been piped in yet
+            return self
+                .document
+                .get()
+                .ok_or_else(|| ContentError::CouldNotFetch(resource.to_string()));
+        }
+
+        self.files.content_for(resource)
+    }
+
+    fn source_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+        if PipedFinder::is_piped(resource) {
+            return self.content_for(resource);
+        }
+
+        self.files.source_for(resource)
+    }
+
+    fn encoding_of(&self, resource: &str) -> Option<&'static str> {
+        if PipedFinder::is_piped(resource) {
+            return None;
+        }
+
+        self.files.encoding_of(resource)
+    }
+
+    fn exists(&self, resource: &str) -> bool {
+        PipedFinder::is_piped(resource) || self.files.exists(resource)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::content_finder::FileFinder;
+    use std::path::PathBuf;
+
+    #[test]
+    fn serves_the_latest_piped_document() {
+        let document = PipedDocument::new();
+        let finder = PipedFinder::new(document.clone(), FileFinder::new(PathBuf::from("./")));
+
+        let before = finder.content_for("./stdin.md");
+        document.read_from("# First\x0c# Second\n".as_bytes());
+        let piped = finder.content_for("./stdin.md");
+        let file = finder.content_for("test_dir/b.md");
+
+        assert!(matches!(before, Err(ContentError::CouldNotFetch(_))));
+        assert_eq!(piped.unwrap().0, "# Second\n");
+        assert!(file.is_ok());
+    }
+
+    #[test]
+    fn keeps_a_document_ended_by_a_form_feed() {
+        let document = PipedDocument::new();
+        let finder = PipedFinder::new(document.clone(), FileFinder::new(PathBuf::from("./")));
+
+        document.read_from("# Only\x0c".as_bytes());
+
+        assert_eq!(finder.content_for("stdin.md").unwrap().0, "# Only");
+    }
+}
@@ -0,0 +1,471 @@
+use async_trait::async_trait;
+use log::{info, warn};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use wasmtime::{
+    Config, Engine, Instance, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc,
+    WasmParams, WasmResults,
+};
+
+use crate::markdown_converter::{MarkdownConverter, MarkdownError};
+use crate::post_process::PostProcessor;
+
+/// How much work a plugin can do in one call before it's stopped, in wasmtime fuel (about an
+/// instruction each), so one stuck in a loop can't hang the page.
+const FUEL: u64 = 1_000_000_000;
+
+/// How big a plugin's memory can grow in one call, in bytes.
+const MAX_MEMORY: usize = 256 * 1024 * 1024;
+
+/// The names of the built-in converters, which plugins can't take.
+const BUILT_IN_CONVERTERS: [&str; 2] = ["offline", "github"];
+
+/// A WebAssembly plugin from `--plugins-dir`, for markdown extensions of your own without
+/// forking rs-readme.
+///
+/// Plugins are WebAssembly modules without imports. They export their `memory` and an
+/// `alloc(len: i32) -> i32` that reserves `len` bytes of it for rs-readme to write to, and then
+/// one or both of:
+///
+/// - `convert(ptr: i32, len: i32) -> i64`, the HTML for the markdown at `ptr`, which makes the
+///   plugin a converter that `?converter=<name>` or the config file's `converter` picks.
+/// - `languages() -> i64`, the code block languages it handles separated by commas, and
+///   `process(language_ptr: i32, language_len: i32, ptr: i32, len: i32) -> i64`, the HTML to
+///   show instead of a block of the code at `ptr`, like the ```` ```dot ```` diagrams. Returning
+///   nothing leaves the block as it is.
+///
+/// Text is UTF-8 and results are returned as their pointer in the high 32 bits and their
+/// length in the low ones. Every call gets an instance of its own, which can't use more than
+/// [`FUEL`] and [`MAX_MEMORY`].
+#[derive(Clone)]
+pub struct Plugin {
+    name: String,
+    engine: Engine,
+    module: Module,
+    converts: bool,
+    languages: Vec<String>,
+}
+
+/// Why a plugin couldn't be loaded.
+#[derive(Debug, PartialEq)]
+pub enum PluginError {
+    /// The plugins folder or a plugin in it couldn't be read.
+    Io { path: PathBuf, reason: String },
+
+    /// The plugin isn't WebAssembly, or doesn't export what plugins have to, see [`Plugin`].
+    Invalid { plugin: String, reason: String },
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginError::Io { path, reason } => {
+                write!(f, "Could not read {}: {}", path.display(), reason)
+            }
+            PluginError::Invalid { plugin, reason } => {
+                write!(f, "The {} plugin can't be used: {}", plugin, reason)
+            }
+        }
+    }
+}
+
+impl Error for PluginError {}
+
+impl Plugin {
+    /// Loads every plugin in `dir`, in the order of their names. They're `.wasm` files, or
+    /// `.wat` ones while they're being written.
+    pub fn load_dir(dir: &Path) -> Result<Vec<Plugin>, PluginError> {
+        let io_error = |err: std::io::Error| PluginError::Io {
+            path: dir.to_path_buf(),
+            reason: err.to_string(),
+        };
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            let extension = path.extension().and_then(|extension| extension.to_str());
+            if matches!(extension, Some("wasm" | "wat")) && path.is_file() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let engine = engine();
+        paths
+            .iter()
+            .map(|path| Plugin::load_with(&engine, path))
+            .collect()
+    }
+
+    /// Loads the plugin in the file at `path`, named after it: `mermaid.wasm` is `mermaid`.
+    pub fn load(path: &Path) -> Result<Plugin, PluginError> {
+        Plugin::load_with(&engine(), path)
+    }
+
+    fn load_with(engine: &Engine, path: &Path) -> Result<Plugin, PluginError> {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let invalid = |reason: String| PluginError::Invalid {
+            plugin: name.clone(),
+            reason,
+        };
+        let bytes = fs::read(path).map_err(|err| PluginError::Io {
+            path: path.to_path_buf(),
+            reason: err.to_string(),
+        })?;
+        let module = Module::new(engine, bytes).map_err(|err| invalid(format!("{:#}", err)))?;
+
+        let exports: Vec<&str> = module.exports().map(|export| export.name()).collect();
+        let converts = exports.contains(&"convert");
+        let processes = exports.contains(&"languages") || exports.contains(&"process");
+        if !converts && !processes {
+            return Err(invalid(
+                "it exports neither `convert` nor `languages` and `process`".to_string(),
+            ));
+        }
+        if converts && BUILT_IN_CONVERTERS.contains(&name.as_str()) {
+            return Err(invalid(format!("{} is a built-in converter's name", name)));
+        }
+
+        let mut plugin = Plugin {
+            name: name.clone(),
+            engine: engine.clone(),
+            module,
+            converts,
+            languages: Vec::new(),
+        };
+        // Gets every function it exports, so one with the wrong signature fails now rather
+        // than on some page later
+        let mut call = plugin.instantiate().map_err(invalid)?;
+        call.func::<i32, i32>("alloc").map_err(invalid)?;
+        if converts {
+            call.func::<(i32, i32), i64>("convert").map_err(invalid)?;
+        }
+        if processes {
+            call.func::<(i32, i32, i32, i32), i64>("process")
+                .map_err(invalid)?;
+            let languages = call.func::<(), i64>("languages").map_err(invalid)?;
+            let languages = languages
+                .call(&mut call.store, ())
+                .map_err(|err| invalid(format!("{:#}", err)))?;
+            plugin.languages = call
+                .read(languages)
+                .map_err(invalid)?
+                .split(',')
+                .map(|language| language.trim().to_string())
+                .filter(|language| !language.is_empty())
+                .collect();
+        }
+
+        info!(
+            "Loaded the {} plugin{}{}",
+            name,
+            if converts { ", a converter" } else { "" },
+            if plugin.languages.is_empty() {
+                String::new()
+            } else {
+                format!(", for {} code blocks", plugin.languages.join(", "))
+            }
+        );
+        Ok(plugin)
+    }
+
+    /// What the plugin's called, its file name without the extension.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the plugin converts markdown, exporting `convert`.
+    pub fn converts(&self) -> bool {
+        self.converts
+    }
+
+    /// The code block languages the plugin handles, from its `languages`.
+    pub fn languages(&self) -> &[String] {
+        &self.languages
+    }
+
+    /// The HTML the plugin's `convert` gives `md`.
+    fn convert(&self, md: &str) -> Result<String, String> {
+        let mut call = self.instantiate()?;
+        let (ptr, len) = call.write(md.as_bytes())?;
+        let convert = call.func::<(i32, i32), i64>("convert")?;
+        let html = convert
+            .call(&mut call.store, (ptr, len))
+            .map_err(|err| format!("{:#}", err))?;
+        call.read(html)
+    }
+
+    /// The HTML the plugin's `process` gives a `language` block of `source`.
+    fn process_block(&self, language: &str, source: &str) -> Result<String, String> {
+        let mut call = self.instantiate()?;
+        let (language_ptr, language_len) = call.write(language.as_bytes())?;
+        let (ptr, len) = call.write(source.as_bytes())?;
+        let process = call.func::<(i32, i32, i32, i32), i64>("process")?;
+        let html = process
+            .call(&mut call.store, (language_ptr, language_len, ptr, len))
+            .map_err(|err| format!("{:#}", err))?;
+        call.read(html)
+    }
+
+    /// A fresh instance of the plugin, with its fuel and memory limit.
+    fn instantiate(&self) -> Result<Call, String> {
+        let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL).map_err(|err| format!("{:#}", err))?;
+        let instance =
+            Instance::new(&mut store, &self.module, &[]).map_err(|err| format!("{:#}", err))?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| "it doesn't export its `memory`".to_string())?;
+
+        Ok(Call {
+            store,
+            instance,
+            memory,
+        })
+    }
+}
+
+/// The engine plugins are compiled with, counting the fuel they use.
+fn engine() -> Engine {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    Engine::new(&config).expect("the plugin engine's config is valid")
+}
+
+/// An instance of a plugin for one call.
+struct Call {
+    store: Store<StoreLimits>,
+    instance: Instance,
+    memory: Memory,
+}
+
+impl Call {
+    /// The plugin's export `name`, if it's a function with these params and results.
+    fn func<Params: WasmParams, Results: WasmResults>(
+        &mut self,
+        name: &str,
+    ) -> Result<TypedFunc<Params, Results>, String> {
+        self.instance
+            .get_typed_func(&mut self.store, name)
+            .map_err(|err| format!("`{}`: {:#}", name, err))
+    }
+
+    /// Copies `bytes` into memory the plugin's `alloc` reserves, answering where they are.
+    fn write(&mut self, bytes: &[u8]) -> Result<(i32, i32), String> {
+        let len = i32::try_from(bytes.len()).map_err(|_| "the input is too big".to_string())?;
+        let alloc = self.func::<i32, i32>("alloc")?;
+        let ptr = alloc
+            .call(&mut self.store, len)
+            .map_err(|err| format!("{:#}", err))?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, bytes)
+            .map_err(|_| "`alloc` reserved memory it doesn't have".to_string())?;
+
+        Ok((ptr, len))
+    }
+
+    /// The text at the pointer and length packed into `result`.
+    fn read(&self, result: i64) -> Result<String, String> {
+        let ptr = (result as u64 >> 32) as usize;
+        let len = (result as u64 & 0xffff_ffff) as usize;
+        let bytes = self
+            .memory
+            .data(&self.store)
+            .get(ptr..ptr + len)
+            .ok_or_else(|| "it answered with memory it doesn't have".to_string())?;
+
+        String::from_utf8(bytes.to_vec()).map_err(|_| "it answered with invalid UTF-8".to_string())
+    }
+}
+
+#[async_trait]
+impl MarkdownConverter for Plugin {
+    async fn convert_markdown(&self, md: &str) -> Result<String, MarkdownError> {
+        let plugin = self.clone();
+        let md = md.to_string();
+        async_std::task::spawn_blocking(move || plugin.convert(&md))
+            .await
+            .map_err(|reason| {
+                MarkdownError::ConverterUnavailable(format!(
+                    "The {} plugin failed: {}",
+                    self.name, reason
+                ))
+            })
+    }
+
+    fn name(&self) -> &'static str {
+        "plugin"
+    }
+}
+
+#[async_trait]
+impl PostProcessor for Plugin {
+    fn handles(&self, language: &str) -> bool {
+        self.languages.iter().any(|handled| handled == language)
+    }
+
+    async fn process(&self, language: &str, source: &str) -> Option<String> {
+        let plugin = self.clone();
+        let (language, source) = (language.to_string(), source.to_string());
+        let block = language.clone();
+        match async_std::task::spawn_blocking(move || plugin.process_block(&language, &source))
+            .await
+        {
+            Ok(html) if !html.is_empty() => Some(html),
+            Ok(_) => None,
+            Err(reason) => {
+                warn!(
+                    "The {} plugin failed on a {} block: {}",
+                    self.name, block, reason
+                );
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// Upper-cases what it's given, as a converter and for ```` ```shout ```` blocks.
+    const SHOUT: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 1024))
+          (data (i32.const 0) "shout, yell")
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $at i32)
+            (local.set $at (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $at))
+          (func $upper (param $ptr i32) (param $len i32) (result i64)
+            (local $i i32)
+            (local $c i32)
+            (block $done
+              (loop $each
+                (br_if $done (i32.ge_u (local.get $i) (local.get $len)))
+                (local.set $c (i32.load8_u (i32.add (local.get $ptr) (local.get $i))))
+                (if (i32.and (i32.ge_u (local.get $c) (i32.const 97))
+                             (i32.le_u (local.get $c) (i32.const 122)))
+                  (then (i32.store8 (i32.add (local.get $ptr) (local.get $i))
+                                    (i32.sub (local.get $c) (i32.const 32)))))
+                (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                (br $each)))
+            (i64.or (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                    (i64.extend_i32_u (local.get $len))))
+          (func (export "convert") (param i32 i32) (result i64)
+            (call $upper (local.get 0) (local.get 1)))
+          (func (export "languages") (result i64)
+            (i64.const 11))
+          (func (export "process") (param i32 i32 i32 i32) (result i64)
+            (call $upper (local.get 2) (local.get 3))))
+    "#;
+
+    /// Never finishes converting.
+    const SPIN: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 0))
+          (func (export "convert") (param i32 i32) (result i64)
+            (loop $forever (br $forever))
+            (i64.const 0)))
+    "#;
+
+    fn plugins_dir(name: &str, plugins: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rs-readme-plugins-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, source) in plugins {
+            fs::write(dir.join(file), source).unwrap();
+        }
+        dir
+    }
+
+    #[async_std::test]
+    async fn plugins_convert_and_process_blocks() {
+        let dir = plugins_dir(
+            "shout",
+            &[("shout.wat", SHOUT), ("notes.txt", "not a plugin")],
+        );
+
+        let plugins = Plugin::load_dir(&dir).unwrap();
+
+        assert_eq!(plugins.len(), 1);
+        let shout = &plugins[0];
+        assert_eq!(shout.name(), "shout");
+        assert!(shout.converts());
+        assert_eq!(shout.languages(), ["shout", "yell"]);
+        assert_eq!(
+            shout.convert_markdown("# Hello, *world*").await,
+            Ok("# HELLO, *WORLD*".to_string())
+        );
+        assert!(shout.handles("yell") && !shout.handles("rust"));
+        assert_eq!(
+            PostProcessor::process(shout, "shout", "hi there").await,
+            Some("HI THERE".to_string())
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[async_std::test]
+    async fn runaway_plugins_are_stopped() {
+        let dir = plugins_dir("spin", &[("spin.wat", SPIN)]);
+        let spin = Plugin::load(&dir.join("spin.wat")).unwrap();
+
+        let converted = spin.convert_markdown("# Hello").await;
+
+        assert!(matches!(
+            converted,
+            Err(MarkdownError::ConverterUnavailable(reason)) if reason.contains("fuel")
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn refuses_what_isnt_a_plugin() {
+        let dir = plugins_dir(
+            "invalid",
+            &[
+                ("empty.wat", r#"(module (memory (export "memory") 1))"#),
+                ("offline.wat", SPIN),
+                ("garbage.wasm", "not wasm"),
+            ],
+        );
+
+        assert_eq!(
+            Plugin::load(&dir.join("empty.wat")).err(),
+            Some(PluginError::Invalid {
+                plugin: "empty".to_string(),
+                reason: "it exports neither `convert` nor `languages` and `process`".to_string()
+            })
+        );
+        assert_eq!(
+            Plugin::load(&dir.join("offline.wat")).err(),
+            Some(PluginError::Invalid {
+                plugin: "offline".to_string(),
+                reason: "offline is a built-in converter's name".to_string()
+            })
+        );
+        assert!(matches!(
+            Plugin::load(&dir.join("garbage.wasm")),
+            Err(PluginError::Invalid { .. })
+        ));
+        assert!(matches!(
+            Plugin::load_dir(&dir.join("missing")),
+            Err(PluginError::Io { .. })
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
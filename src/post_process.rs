@@ -0,0 +1,153 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Rewrites fenced code blocks in converted HTML once the converter is done with them, for things
+/// like turning diagram source into diagrams.
+///
+/// It works on the HTML so it applies whichever converter was used.
+#[async_trait]
+pub trait PostProcessor: Send + Sync {
+    /// Whether this handles code blocks fenced as `language` (`dot`, say).
+    fn handles(&self, language: &str) -> bool;
+
+    /// The HTML to show instead of a block of `source`, `None` leaves the block as it is.
+    async fn process(&self, language: &str, source: &str) -> Option<String>;
+}
+
+/// The openings of code blocks we know how to find the language of: pulldown-cmark's and
+/// GitHub's.
+const OPENINGS: [&str; 2] = ["<pre><code class=\"language-", "<pre lang=\""];
+
+const CLOSING: &str = "</code></pre>";
+
+/// Runs every code block in `html` that one of `processors` handles through it.
+pub async fn post_process(html: &str, processors: &[Arc<dyn PostProcessor>]) -> String {
+    if processors.is_empty() {
+        return html.to_string();
+    }
+
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(block) = next_block(rest) {
+        out.push_str(&rest[..block.start]);
+
+        let replacement = match processors.iter().find(|p| p.handles(block.language)) {
+            Some(processor) => processor.process(block.language, &block.source()).await,
+            None => None,
+        };
+        out.push_str(
+            replacement
+                .as_deref()
+                .unwrap_or(&rest[block.start..block.end]),
+        );
+
+        rest = &rest[block.end..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// A code block found in some HTML, `start` and `end` are byte offsets of the whole block.
+struct Block<'a> {
+    start: usize,
+    end: usize,
+    language: &'a str,
+    code: &'a str,
+}
+
+impl Block<'_> {
+    /// The code as it was written, without any highlighting markup or escaping.
+    fn source(&self) -> String {
+        let mut text = String::with_capacity(self.code.len());
+        let mut in_tag = false;
+        for c in self.code.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' if in_tag => in_tag = false,
+                c if !in_tag => text.push(c),
+                _ => {}
+            }
+        }
+
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&#39;", "'")
+            .replace("&amp;", "&")
+    }
+}
+
+fn next_block(html: &str) -> Option<Block<'_>> {
+    let (start, opening) = OPENINGS
+        .iter()
+        .filter_map(|opening| html.find(opening).map(|at| (at, opening)))
+        .min_by_key(|(at, _)| *at)?;
+
+    let language_start = start + opening.len();
+    let language_end = language_start + html[language_start..].find('"')?;
+    let mut code_start = language_end + html[language_end..].find('>')? + 1;
+    // GitHub's has the `<code>` after the `<pre ...>`
+    if html[code_start..].starts_with("<code") {
+        code_start += html[code_start..].find('>')? + 1;
+    }
+    let code_end = code_start + html[code_start..].find(CLOSING)?;
+
+    Some(Block {
+        start,
+        end: code_end + CLOSING.len(),
+        language: &html[language_start..language_end],
+        code: &html[code_start..code_end],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Shout;
+
+    #[async_trait]
+    impl PostProcessor for Shout {
+        fn handles(&self, language: &str) -> bool {
+            language == "shout"
+        }
+
+        async fn process(&self, _language: &str, source: &str) -> Option<String> {
+            Some(format!("<p>{}</p>", source.to_uppercase()))
+        }
+    }
+
+    #[async_std::test]
+    async fn replaces_handled_blocks() {
+        let html =
+            "<p>a</p>\n<pre><code class=\"language-shout\">a &amp; <span>b</span>\n</code></pre>\n\
+                    <pre><code class=\"language-rust\">fn</code></pre>\n\
+                    <pre lang=\"shout\"><code>c</code></pre>";
+
+        let processed = post_process(html, &[Arc::new(Shout)]).await;
+
+        assert_eq!(
+            processed,
+            "<p>a</p>\n<p>A & B\n</p>\n\
+             <pre><code class=\"language-rust\">fn</code></pre>\n\
+             <p>C</p>"
+        );
+    }
+
+    #[async_std::test]
+    async fn finds_annotated_blocks() {
+        let html = "<figure class=\"rs-readme-code-block\"><figcaption>a</figcaption>\
+                    <pre><code class=\"language-shout\"><span class=\"rs-readme-code-mark\">a</span>\n</code></pre>\n\
+                    </figure>\n\
+                    <pre lang=\"shout\" data-meta=\"{1} title=&quot;b&quot;\"><code>b</code></pre>";
+
+        let processed = post_process(html, &[Arc::new(Shout)]).await;
+
+        assert_eq!(
+            processed,
+            "<figure class=\"rs-readme-code-block\"><figcaption>a</figcaption><p>A\n</p>\n</figure>\n\
+             <p>B</p>"
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use crate::content_finder::{ContentFinder, ContentHash};
+
+/// Rewrites a document's source before it's converted, for things like pulling in other files.
+///
+/// It runs when the document is read, so the content hash covers the rewritten source and live
+/// reload notices changes to whatever went into it.
+pub trait PreProcessor: Send + Sync {
+    /// `source`, the document at `path`, rewritten. Other documents are read with `finder`.
+    fn process(&self, path: &str, source: String, finder: &dyn ContentFinder) -> String;
+}
+
+/// Runs `source`, read from `path` with `finder`, through each of `processors` in turn, hashing
+/// it again if any of them changed it.
+pub fn pre_process(
+    path: &str,
+    (source, hash): (String, ContentHash),
+    processors: &[Arc<dyn PreProcessor>],
+    finder: &dyn ContentFinder,
+) -> (String, ContentHash) {
+    if processors.is_empty() {
+        return (source, hash);
+    }
+
+    let processed = processors.iter().fold(source.clone(), |source, processor| {
+        processor.process(path, source, finder)
+    });
+
+    if processed == source {
+        (source, hash)
+    } else {
+        let hash = ContentHash::of(processed.as_bytes());
+        (processed, hash)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::content_finder::ContentError;
+
+    struct Shout;
+
+    impl PreProcessor for Shout {
+        fn process(&self, _path: &str, source: String, _finder: &dyn ContentFinder) -> String {
+            source.to_uppercase()
+        }
+    }
+
+    struct Nothing;
+
+    impl ContentFinder for Nothing {
+        fn content_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+            Err(ContentError::CouldNotFetch(resource.to_string()))
+        }
+    }
+
+    #[test]
+    fn rehashes_what_changed() {
+        let source = "shout".to_string();
+        let hash = ContentHash::of(source.as_bytes());
+
+        let (processed, processed_hash) =
+            pre_process("a.md", (source.clone(), hash), &[Arc::new(Shout)], &Nothing);
+        let (same, same_hash) = pre_process("a.md", (source, hash), &[], &Nothing);
+
+        assert_eq!(processed, "SHOUT");
+        assert_eq!(processed_hash, ContentHash::of(b"SHOUT"));
+        assert_eq!(same, "shout");
+        assert_eq!(same_hash, hash);
+    }
+}
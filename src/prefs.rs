@@ -0,0 +1,171 @@
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// The font sizes readers can pick, in pixels.
+const FONT_SIZES: RangeInclusive<u32> = 12..=24;
+
+/// The widths the document column can be set to, in pixels.
+const LINE_WIDTHS: RangeInclusive<u32> = 600..=1600;
+
+/// The colors pages are shown in.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// Whatever the reader's system asks for.
+    Auto,
+    Light,
+    Dark,
+}
+
+/// How pages are displayed until a reader changes it from the "Display" popover, served from
+/// `/__rs-readme/prefs`. What readers pick is kept in their browser's `localStorage` on top of
+/// these.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Prefs {
+    /// The size of the document's text, in pixels.
+    pub font_size: u32,
+
+    /// The width of the document column, in pixels.
+    pub line_width: u32,
+
+    /// Whether the document's text is set in a monospace font.
+    pub monospace: bool,
+
+    pub theme: Theme,
+}
+
+impl Default for Prefs {
+    fn default() -> Self {
+        Prefs {
+            font_size: 16,
+            line_width: 980,
+            monospace: false,
+            theme: Theme::Auto,
+        }
+    }
+}
+
+/// Why preferences couldn't be changed.
+#[derive(Debug, PartialEq)]
+pub enum PrefsError {
+    /// The changes weren't a JSON object of known preferences with the right types.
+    Invalid(String),
+
+    /// A size was outside the ones readers can pick.
+    OutOfRange {
+        pref: &'static str,
+        range: RangeInclusive<u32>,
+    },
+}
+
+impl fmt::Display for PrefsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrefsError::Invalid(reason) => write!(f, "Invalid preferences\n{}", reason),
+            PrefsError::OutOfRange { pref, range } => write!(
+                f,
+                "{} has to be between {} and {}",
+                pref,
+                range.start(),
+                range.end()
+            ),
+        }
+    }
+}
+
+impl Error for PrefsError {}
+
+impl Prefs {
+    /// These preferences with the ones in the JSON object `changes` changed, the rest are kept.
+    pub(crate) fn changed(&self, changes: &str) -> Result<Prefs, PrefsError> {
+        let changes: serde_json::Map<String, Value> =
+            serde_json::from_str(changes).map_err(|err| PrefsError::Invalid(err.to_string()))?;
+        let mut prefs = match serde_json::to_value(self) {
+            Ok(Value::Object(prefs)) => prefs,
+            _ => unreachable!("preferences serialize to an object"),
+        };
+        for (pref, value) in changes {
+            if !prefs.contains_key(&pref) {
+                return Err(PrefsError::Invalid(format!(
+                    "unknown preference `{}`",
+                    pref
+                )));
+            }
+            prefs.insert(pref, value);
+        }
+
+        let prefs: Prefs = serde_json::from_value(Value::Object(prefs))
+            .map_err(|err| PrefsError::Invalid(err.to_string()))?;
+        check_range("font_size", prefs.font_size, FONT_SIZES)?;
+        check_range("line_width", prefs.line_width, LINE_WIDTHS)?;
+        Ok(prefs)
+    }
+}
+
+fn check_range(
+    pref: &'static str,
+    value: u32,
+    range: RangeInclusive<u32>,
+) -> Result<(), PrefsError> {
+    if range.contains(&value) {
+        Ok(())
+    } else {
+        Err(PrefsError::OutOfRange { pref, range })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn changes_only_the_given_prefs() {
+        let prefs = Prefs::default()
+            .changed(r#"{"font_size": 18, "theme": "dark"}"#)
+            .unwrap();
+
+        assert_eq!(
+            prefs,
+            Prefs {
+                font_size: 18,
+                theme: Theme::Dark,
+                ..Prefs::default()
+            }
+        );
+        assert_eq!(
+            prefs.changed(r#"{"monospace": true}"#).unwrap(),
+            Prefs {
+                monospace: true,
+                ..prefs.clone()
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_bad_prefs() {
+        let prefs = Prefs::default();
+
+        assert_eq!(
+            prefs.changed(r#"{"font_size": 64}"#),
+            Err(PrefsError::OutOfRange {
+                pref: "font_size",
+                range: FONT_SIZES
+            })
+        );
+        assert_eq!(
+            prefs.changed(r#"{"colour": "red"}"#),
+            Err(PrefsError::Invalid(
+                "unknown preference `colour`".to_string()
+            ))
+        );
+        assert!(matches!(
+            prefs.changed(r#"{"theme": "sepia"}"#),
+            Err(PrefsError::Invalid(_))
+        ));
+        assert!(matches!(prefs.changed("[]"), Err(PrefsError::Invalid(_))));
+    }
+}
@@ -0,0 +1,121 @@
+use similar::{ChangeTag, TextDiff};
+use std::time::Duration;
+
+/// How long to spend finding the smallest diff before settling for a bigger one.
+const DIFF_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The markdown of `new` with the words that changed since `old` marked: removed ones in
+/// `<del>` and added ones in `<ins>`, ready for converting like any other document.
+///
+/// Fenced code is left unmarked, the tags would show up as text in it, so only its new version
+/// is kept.
+pub fn prose_diff(old: &str, new: &str) -> String {
+    let diff = TextDiff::configure()
+        .timeout(DIFF_TIMEOUT)
+        .diff_words(old, new);
+    let mut writer = Writer::default();
+
+    for op in diff.ops() {
+        let (mut deleted, mut inserted) = (String::new(), String::new());
+        for change in diff.iter_changes(op) {
+            match change.tag() {
+                ChangeTag::Equal => writer.text(change.value()),
+                ChangeTag::Delete => deleted.push_str(change.value()),
+                ChangeTag::Insert => inserted.push_str(change.value()),
+            }
+        }
+
+        writer.change(&deleted, "del");
+        writer.change(&inserted, "ins");
+    }
+
+    writer.out
+}
+
+/// Builds the marked up markdown, keeping track of whether it's in fenced code.
+#[derive(Default)]
+struct Writer {
+    out: String,
+    line: String,
+    fenced: bool,
+}
+
+impl Writer {
+    /// Adds text that's in the new version.
+    fn text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.out.push(c);
+            if c == '\n' {
+                if is_fence(&self.line) {
+                    self.fenced = !self.fenced;
+                }
+                self.line.clear();
+            } else {
+                self.line.push(c);
+            }
+        }
+    }
+
+    /// Adds `changed` text, marked with `tag` line by line.
+    fn change(&mut self, changed: &str, tag: &str) {
+        let inserted = tag == "ins";
+        if changed.trim().is_empty() {
+            if inserted {
+                self.text(changed);
+            }
+            return;
+        }
+
+        for line in changed.split_inclusive('\n') {
+            let content = line.trim();
+            if self.fenced || is_fence(content) || content.is_empty() {
+                if inserted {
+                    self.text(line);
+                }
+                continue;
+            }
+
+            let start = line.find(content).unwrap_or(0);
+            let end = start + content.len();
+            self.text(&line[..start]);
+            self.out.push_str(&format!("<{}>", tag));
+            self.out.push_str(content);
+            self.out.push_str(&format!("</{}>", tag));
+            self.line.push_str(content);
+            self.text(&line[end..]);
+        }
+    }
+}
+
+fn is_fence(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("```") || line.starts_with("~~~")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn marks_changed_words() {
+        assert_eq!(
+            prose_diff(
+                "# Install\n\nRun the old installer.\n\nIt's slow.\n",
+                "# Install\n\nRun the new installer.\n\nIt's fast now.\n"
+            ),
+            "# Install\n\nRun the <del>old</del><ins>new</ins> installer.\n\nIt's <del>slow.</del><ins>fast now.</ins>\n"
+        );
+    }
+
+    #[test]
+    fn leaves_code_unmarked() {
+        assert_eq!(
+            prose_diff(
+                "Setup:\n\n```sh\nmake old\n```\n",
+                "Setup:\n\n```sh\nmake new\n```\n"
+            ),
+            "Setup:\n\n```sh\nmake new\n```\n"
+        );
+    }
+}
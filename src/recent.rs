@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use crate::file_tree::{file_tree, TreeNode};
+
+/// How many files the recently modified page lists.
+pub const RECENT_LIMIT: usize = 50;
+
+/// A markdown file and when it was last changed, see [`recent_files`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecentFile {
+    /// Relative to the root, `/` separated.
+    pub path: String,
+    /// The text of its first heading.
+    pub title: Option<String>,
+    /// In seconds since the Unix epoch.
+    pub modified: u64,
+}
+
+/// The `limit` most recently modified markdown files under `root`, newest first, leaving out the
+/// same ones as [`file_tree`].
+pub fn recent_files(root: &Path, limit: usize) -> Vec<RecentFile> {
+    let mut files = Vec::new();
+    collect(file_tree(root), &mut files);
+
+    files.sort_by(|a, b| {
+        b.modified
+            .cmp(&a.modified)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    files.truncate(limit);
+    files
+}
+
+fn collect(tree: Vec<TreeNode>, files: &mut Vec<RecentFile>) {
+    for node in tree {
+        match node {
+            TreeNode::Folder { children, .. } => collect(children, files),
+            TreeNode::File {
+                path,
+                title,
+                modified,
+                ..
+            } => files.push(RecentFile {
+                path,
+                title,
+                modified: modified.unwrap_or(0),
+            }),
+        }
+    }
+}
+
+/// The list of `files` for the recently modified page, each with how long before `now` (in
+/// seconds since the Unix epoch) it changed.
+#[cfg(feature = "server")]
+pub(crate) fn recent_html(files: &[RecentFile], now: u64) -> String {
+    format!(
+        "{}",
+        html! {
+            @ if files.is_empty() {
+                p : "No markdown files here yet.";
+            } else {
+                ul(class="rs-readme-recent") {
+                    @ for file in files {
+                        li {
+                            a(href=format!("/{}", file.path)) : file.title.as_deref().unwrap_or(&file.path);
+                            : " ";
+                            code : &file.path;
+                            span(class="rs-readme-ago") : format!(" {}", ago(now.saturating_sub(file.modified)));
+                        }
+                    }
+                }
+            }
+        }
+    )
+}
+
+/// `seconds` as roughly how long ago, like "5 minutes ago".
+#[cfg(feature = "server")]
+fn ago(seconds: u64) -> String {
+    const UNITS: &[(u64, &str)] = &[
+        (365 * 24 * 60 * 60, "year"),
+        (30 * 24 * 60 * 60, "month"),
+        (7 * 24 * 60 * 60, "week"),
+        (24 * 60 * 60, "day"),
+        (60 * 60, "hour"),
+        (60, "minute"),
+    ];
+
+    match UNITS.iter().find(|(unit, _)| seconds >= *unit) {
+        Some((unit, name)) => {
+            let count = seconds / unit;
+            format!(
+                "{} {}{} ago",
+                count,
+                name,
+                if count == 1 { "" } else { "s" }
+            )
+        }
+        None => "just now".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn says_how_long_ago() {
+        assert_eq!(ago(5), "just now");
+        assert_eq!(ago(60), "1 minute ago");
+        assert_eq!(ago(3 * 60 * 60 + 59), "3 hours ago");
+        assert_eq!(ago(400 * 24 * 60 * 60), "1 year ago");
+    }
+
+    #[test]
+    fn links_each_file_with_its_age() {
+        let files = vec![
+            RecentFile {
+                path: "docs/guide.md".to_string(),
+                title: Some("Guide".to_string()),
+                modified: 1_000,
+            },
+            RecentFile {
+                path: "NOTES.md".to_string(),
+                title: None,
+                modified: 400,
+            },
+        ];
+
+        assert_eq!(
+            recent_html(&files, 1_120),
+            "<ul class=\"rs-readme-recent\">\
+             <li><a href=\"/docs/guide.md\">Guide</a> <code>docs/guide.md</code><span class=\"rs-readme-ago\"> 2 minutes ago</span></li>\
+             <li><a href=\"/NOTES.md\">NOTES.md</a> <code>NOTES.md</code><span class=\"rs-readme-ago\"> 12 minutes ago</span></li>\
+             </ul>"
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use async_std::future::timeout;
+use http_types::Url;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::content_finder::{
+    normalize_text, ContentError, ContentFinder, ContentHash, DocumentFormat, DEFAULT_MAX_SIZE,
+};
+use crate::links::normalize;
+
+/// How long a fetched file is reused before it's fetched again, so live reload doesn't ask the
+/// server twice a second.
+const REMOTE_TTL: Duration = Duration::from_secs(10);
+
+/// How long to wait for a file before falling back to the copy fetched last.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Implements [`ContentFinder`] by fetching files over HTTP relative to a base URL, for
+/// previewing a gist or a raw file on GitHub, see `--url`.
+///
+/// Files are reused for a few seconds after they're fetched, missing ones too, and the last copy
+/// is served when fetching fails.
+pub struct RemoteFinder {
+    base: Url,
+    max_size: u64,
+    fetched: Mutex<HashMap<Url, Fetched>>,
+}
+
+/// When a URL was fetched and what it had, `None` if nothing.
+type Fetched = (Instant, Option<(String, ContentHash)>);
+
+impl RemoteFinder {
+    /// Fetches files relative to `base`, like a folder when it ends with a `/`.
+    pub fn new(base: Url) -> RemoteFinder {
+        RemoteFinder {
+            base,
+            max_size: DEFAULT_MAX_SIZE,
+            fetched: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Refuses files bigger than `max_size` bytes, `0` allows anything.
+    pub fn with_max_size(mut self, max_size: u64) -> RemoteFinder {
+        self.max_size = max_size;
+        self
+    }
+
+    fn file(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+        let path = normalize(Path::new(resource)).ok_or_else(|| {
+            warn!("Refusing to fetch {}, it's outside the base URL", resource);
+            ContentError::InvalidPath(resource.to_string())
+        })?;
+        let url = self
+            .base
+            .join(&path.to_string_lossy().replace('\\', "/"))
+            .map_err(|_| ContentError::InvalidPath(resource.to_string()))?;
+
+        let cached = self.fetched().get(&url).cloned();
+        if let Some((at, found)) = &cached {
+            if at.elapsed() < REMOTE_TTL {
+                return found
+                    .clone()
+                    .ok_or_else(|| ContentError::CouldNotFetch(resource.to_string()));
+            }
+        }
+
+        let fetched = async_std::task::block_on(timeout(FETCH_TIMEOUT, self.fetch(&url)))
+            .unwrap_or_else(|_| Err(format!("Timed out fetching {}", url)));
+        let found = match fetched {
+            Ok(contents) => {
+                let contents = normalize_text(contents);
+                let hash = ContentHash::of(contents.as_bytes());
+                Some((contents, hash))
+            }
+            Err(err) => {
+                debug!("{}", err);
+                cached.and_then(|(_, found)| found)
+            }
+        };
+        self.fetched().insert(url, (Instant::now(), found.clone()));
+
+        found.ok_or_else(|| ContentError::CouldNotFetch(resource.to_string()))
+    }
+
+    async fn fetch(&self, url: &Url) -> Result<String, String> {
+        let mut resp = surf::get(url.as_str())
+            .await
+            .map_err(|err| format!("Could not fetch {}: {}", url, err))?;
+        if !resp.status().is_success() {
+            return Err(format!("{} answered {}", url, resp.status()));
+        }
+        if let Some(len) = resp.len() {
+            if self.max_size > 0 && len as u64 > self.max_size {
+                return Err(format!("{} is {} bytes", url, len));
+            }
+        }
+
+        resp.body_string()
+            .await
+            .map_err(|err| format!("Could not read {}: {}", url, err))
+    }
+
+    fn fetched(&self) -> MutexGuard<'_, HashMap<Url, Fetched>> {
+        match self.fetched.lock() {
+            Ok(fetched) => fetched,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl ContentFinder for RemoteFinder {
+    fn content_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+        match DocumentFormat::of(Path::new(resource)) {
+            None | Some(DocumentFormat::PlainText) => Err(ContentError::NotMarkdown),
+            Some(_) => self.file(resource),
+        }
+    }
+
+    fn source_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+        self.file(resource)
+    }
+}
+
+/// Splits the URL of a document into the folder it's in and its file name, for
+/// [`RemoteFinder::new`] and the index page. `None` when it doesn't name a file.
+pub fn split_document_url(url: &Url) -> Option<(Url, String)> {
+    let name = url.path_segments()?.next_back()?.to_string();
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut base = url.clone();
+    base.path_segments_mut().ok()?.pop().push("");
+    base.set_query(None);
+    base.set_fragment(None);
+
+    Some((base, name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fetches_relative_to_the_base_url() {
+        let base = Url::parse(&format!("{}/user/repo/main/", mockito::server_url())).unwrap();
+        let _m = mockito::mock("GET", "/user/repo/main/docs/guide.md")
+            .with_body("# Guide")
+            .create();
+        let finder = RemoteFinder::new(base);
+
+        let guide = finder.content_for("./docs/guide.md");
+        let missing = finder.content_for("./docs/missing.md");
+        let outside = finder.content_for("../other/README.md");
+        let image = finder.content_for("./logo.png");
+
+        assert_eq!(guide.unwrap().0, "# Guide");
+        assert!(matches!(missing, Err(ContentError::CouldNotFetch(_))));
+        assert!(matches!(outside, Err(ContentError::InvalidPath(_))));
+        assert_eq!(image, Err(ContentError::NotMarkdown));
+    }
+
+    #[test]
+    fn reuses_fetched_files() {
+        let base = Url::parse(&format!("{}/cached/", mockito::server_url())).unwrap();
+        let m = mockito::mock("GET", "/cached/README.md")
+            .with_body("# Once")
+            .expect(1)
+            .create();
+        let finder = RemoteFinder::new(base);
+
+        let first = finder.content_for("README.md").unwrap();
+        let second = finder.content_for("README.md").unwrap();
+
+        m.assert();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn splits_document_urls() {
+        let url =
+            Url::parse("https://raw.githubusercontent.com/user/repo/main/docs/guide.md?token=x")
+                .unwrap();
+
+        let (base, name) = split_document_url(&url).unwrap();
+
+        assert_eq!(
+            base.as_str(),
+            "https://raw.githubusercontent.com/user/repo/main/docs/"
+        );
+        assert_eq!(name, "guide.md");
+        assert_eq!(
+            split_document_url(&Url::parse("https://example.com/docs/").unwrap()),
+            None
+        );
+    }
+}
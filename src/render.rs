@@ -0,0 +1,567 @@
+#[cfg(feature = "server")]
+use futures::future;
+#[cfg(feature = "server")]
+use futures::stream::{self, StreamExt};
+use horrorshow::helper::doctype;
+use horrorshow::prelude::*;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::changelog::{self, is_changelog, releases_html, Release};
+use crate::code_theme::CodeTheme;
+use crate::content_finder::{ContentFinder, ContentHash, DocumentFormat};
+use crate::direction::{text_direction, TextDirection};
+use crate::document_stats::task_progress_html;
+use crate::error::Error;
+use crate::frontmatter::Frontmatter;
+use crate::git_history::{history_html, last_edit_html, Commit, LastEdit};
+use crate::git_status::{status_html, RepoStatus};
+use crate::languages::{switcher_html, Translation};
+#[cfg(feature = "server")]
+use crate::markdown_converter::HtmlChunks;
+use crate::markdown_converter::MarkdownConverter;
+use crate::navigation::{Navigation, SUMMARY_FILE};
+use crate::outline::document_title;
+use crate::pins::{pinned_html, star_html};
+use crate::warnings::{document_warnings, warnings_html, Warning};
+
+/// How [`render_document`] should render a page.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RenderOptions {
+    /// Whether to wrap the document in the full page, `<head>` and live-reload script included,
+    /// or just the GitHub-style box around the article.
+    pub full_page: bool,
+
+    /// Whether to render the document as markdown whatever its extension, like `?force=md`
+    /// asks.
+    pub force_markdown: bool,
+
+    /// The colors of highlighted code, see `--code-theme`.
+    pub code_theme: CodeTheme,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            full_page: true,
+            force_markdown: false,
+            code_theme: CodeTheme::default(),
+        }
+    }
+}
+
+/// A rendered document, exactly as the server would send it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedPage {
+    /// The page title, the document's frontmatter `title` or first heading, or else its file
+    /// name.
+    pub title: String,
+
+    /// The hash of the markdown source, the same one sent to live-reload clients.
+    pub hash: ContentHash,
+
+    /// The converted markdown on its own.
+    pub content: String,
+
+    /// The converted markdown wrapped according to the [`RenderOptions`].
+    pub html: String,
+
+    /// What's wrong with the document that it was rendered despite, listed above it in the
+    /// content, see [`document_warnings`].
+    pub warnings: Vec<Warning>,
+}
+
+/// Finds the markdown for `path`, converts it, and wraps it the way the server does.
+///
+/// This is the whole page pipeline without the HTTP server, so other tools can produce exactly
+/// what rs-readme would show, the sidebar from a `SUMMARY.md` included.
+pub async fn render_document(
+    finder: &(dyn ContentFinder + Send + Sync),
+    converter: &(dyn MarkdownConverter + Send + Sync),
+    path: &str,
+    options: &RenderOptions,
+) -> Result<RenderedPage, Error> {
+    let (contents, hash) = finder.content_for(path)?;
+    let navigation = match finder.content_for(SUMMARY_FILE) {
+        Ok((summary, _)) if options.full_page => Some(Navigation::from_summary(&summary))
+            .filter(|navigation| navigation.first_page().is_some()),
+        _ => None,
+    };
+
+    let warnings = document_warnings(path, &contents, finder);
+    let surroundings = Surroundings {
+        navigation: navigation.as_ref(),
+        warnings: &warnings,
+        ..Surroundings::default()
+    };
+
+    render_source(converter, path, &contents, hash, options, &surroundings).await
+}
+
+/// What goes around a document on its page: the sidebar, the language switcher, the git
+/// history dropdown, who edited it last, the checkout it's in, the pinned documents and the
+/// warnings over it.
+#[derive(Debug, Default)]
+pub(crate) struct Surroundings<'a> {
+    pub navigation: Option<&'a Navigation>,
+    pub translations: &'a [Translation],
+    pub history: &'a [Commit],
+    pub last_edit: Option<&'a LastEdit>,
+    pub repo: Option<&'a RepoStatus>,
+    /// `None` when pins aren't kept, so there's no star to pin the document with.
+    pub pins: Option<&'a [String]>,
+    /// Whether this is the index page, which lists the pinned documents over the document.
+    pub index: bool,
+    /// The encoding the document was converted to UTF-8 from, see
+    /// [`ContentFinder::encoding_of`].
+    pub encoding: Option<&'a str>,
+    /// Hashes the GitHub stylesheets have to match, see [`base_html`].
+    pub integrity: Option<&'a BTreeMap<String, String>>,
+    /// Shown at the top of the content, so they're live-reloaded with it.
+    pub warnings: &'a [Warning],
+    /// Put before the document's title in the browser tab, see [`crate::State::with_title`].
+    pub site_title: Option<&'a str>,
+}
+
+/// The second half of [`render_document`], for markdown that's already been read.
+pub(crate) async fn render_source(
+    converter: &(dyn MarkdownConverter + Send + Sync),
+    path: &str,
+    contents: &str,
+    hash: ContentHash,
+    options: &RenderOptions,
+    surroundings: &Surroundings<'_>,
+) -> Result<RenderedPage, Error> {
+    let markdown = options.force_markdown
+        || DocumentFormat::of(Path::new(path)) == Some(DocumentFormat::Markdown);
+    let title = page_title(path, contents, markdown);
+
+    let (tasks, direction) = if markdown {
+        (task_progress_html(contents), text_direction(contents))
+    } else {
+        (String::new(), TextDirection::default())
+    };
+    let content = tasks
+        + &warnings_html(surroundings.warnings)
+        + &match Frontmatter::split(contents) {
+            Some((frontmatter, rest)) if markdown => {
+                // Blank lines where the frontmatter was keep the lines of the markdown where they are
+                let lines = contents[..contents.len() - rest.len()]
+                    .matches('\n')
+                    .count();
+                frontmatter.html()
+                    + &converter
+                        .convert_markdown(&format!("{}{}", "\n".repeat(lines), rest))
+                        .await?
+            }
+            _ => converter.convert_markdown(contents).await?,
+        };
+    let (content, releases) = if is_changelog(path) {
+        changelog::releases(&content)
+    } else {
+        (content, Vec::new())
+    };
+
+    let html = if options.full_page {
+        base_html_with(
+            &tab_title(surroundings.site_title, &title),
+            surroundings.integrity.unwrap_or(&BTreeMap::new()),
+            options.code_theme,
+            &status_html(surroundings.repo),
+            &surround(surroundings, path, &title, &releases, &direction, &content),
+        )
+    } else {
+        markdown_html_with(&title, "", "", &direction, &content)
+    };
+
+    Ok(RenderedPage {
+        title,
+        hash,
+        content,
+        html,
+        warnings: surroundings.warnings.to_vec(),
+    })
+}
+
+/// The full page for `path`, titled `title`, with the HTML in `chunks` streamed into it as it
+/// arrives. `tasks` is the [`task_progress_html`] and `direction` the [`text_direction`] of the
+/// document, which have to be worked out before it's handed to the converter.
+#[cfg(feature = "server")]
+pub(crate) fn stream_page(
+    path: &str,
+    title: &str,
+    tasks: &str,
+    direction: &TextDirection,
+    code_theme: CodeTheme,
+    surroundings: &Surroundings<'_>,
+    chunks: HtmlChunks,
+) -> HtmlChunks {
+    // Render the page around a marker and split it there
+    const MARKER: &str = "\u{0}rs-readme-content\u{0}";
+    let shell = base_html_with(
+        &tab_title(surroundings.site_title, title),
+        surroundings.integrity.unwrap_or(&BTreeMap::new()),
+        code_theme,
+        &status_html(surroundings.repo),
+        &surround(
+            surroundings,
+            path,
+            title,
+            &[],
+            direction,
+            &(tasks.to_string() + &warnings_html(surroundings.warnings) + MARKER),
+        ),
+    );
+    let (head, tail) = shell.split_at(shell.find(MARKER).unwrap_or(shell.len()));
+    let tail = tail.replacen(MARKER, "", 1);
+
+    Box::pin(
+        stream::once(future::ready(head.as_bytes().to_vec()))
+            .chain(chunks)
+            .chain(stream::once(future::ready(tail.into_bytes()))),
+    )
+}
+
+/// Plain text as it is, for [`crate::DocumentFormat::PlainText`] documents.
+#[cfg(feature = "server")]
+pub(crate) fn preformatted(text: &str) -> String {
+    format!("{}", html! { pre : text; })
+}
+
+/// The editor for the document at the URL `path`, its `source` beside a `preview` of it, see
+/// `--edit`. `hash` is the version of the source it started from, saving won't overwrite
+/// another.
+#[cfg(feature = "server")]
+pub(crate) fn editor_html(path: &str, source: &str, hash: &str, preview: &str) -> String {
+    format!(
+        "{}",
+        html! {
+            div(id="rs-readme-editor", class="rs-readme-editor", data-path=path, data-hash=hash) {
+                form(class="rs-readme-editor-source") {
+                    div(class="rs-readme-editor-bar") {
+                        a(href=path) : path.trim_start_matches('/');
+                        span(class="rs-readme-editor-status", role="status") {}
+                        button(type="submit") : "Save";
+                    }
+                    // Browsers drop a newline straight after `<textarea>`, so the source's own
+                    // first one needs another in front of it
+                    textarea(name="source", aria-label="Source", spellcheck="false") {
+                        : "\n";
+                        : source;
+                    }
+                }
+                div(class="rs-readme-editor-preview markdown-body", role="region", aria-label="Preview") : Raw(preview);
+            }
+        }
+    )
+}
+
+/// The box around `content` with the language switcher in its header, beside the sidebar and
+/// over previous and next links when there's a `SUMMARY.md`.
+fn surround(
+    surroundings: &Surroundings<'_>,
+    path: &str,
+    title: &str,
+    releases: &[Release],
+    direction: &TextDirection,
+    content: &str,
+) -> String {
+    let pins = surroundings.pins.unwrap_or_default();
+    let mut header = switcher_html(surroundings.translations)
+        + &history_html(path, surroundings.history)
+        + &releases_html(releases);
+    if surroundings.pins.is_some() {
+        header += &star_html(path, pins);
+    }
+    let mut byline = last_edit_html(surroundings.last_edit) + &encoding_html(surroundings.encoding);
+    if surroundings.index {
+        byline = pinned_html(pins) + &byline;
+    }
+    let page = markdown_html_with(title, &header, &byline, direction, content);
+
+    match surroundings.navigation {
+        Some(navigation) => navigation.wrap(path, &pinned_html(pins), &page),
+        None => page,
+    }
+}
+
+/// Which encoding the document was converted from, for under its name.
+fn encoding_html(encoding: Option<&str>) -> String {
+    match encoding {
+        Some(encoding) => format!(
+            "{}",
+            html! {
+                div(class="rs-readme-encoding") : format!("Converted to UTF-8 from {}", encoding);
+            }
+        ),
+        None => String::new(),
+    }
+}
+
+/// The title of the page for the document at `path`: the [`document_title`] of its `contents`
+/// when they're `markdown`, or else its file name.
+pub(crate) fn page_title(path: &str, contents: &str, markdown: bool) -> String {
+    markdown
+        .then(|| document_title(contents))
+        .flatten()
+        .unwrap_or_else(|| path.rsplit('/').next().unwrap_or("rs-readme").to_string())
+}
+
+/// The title in the browser tab, the page's `title` after the `site`'s, like
+/// `myproject – Installation`.
+fn tab_title(site: Option<&str>, title: &str) -> String {
+    match site {
+        Some(site) => format!("{} – {}", site, title),
+        None => title.to_string(),
+    }
+}
+
+/// The stylesheets GitHub styles markdown with, loaded from its CDN until they're vendored.
+pub const GITHUB_STYLESHEETS: [&str; 3] = [
+    "https://github.githubassets.com/assets/frameworks-146fab5ea30e8afac08dd11013bb4ee0.css",
+    "https://github.githubassets.com/assets/site-897ad5fdbe32a5cd67af5d1bdc68a292.css",
+    "https://github.githubassets.com/assets/github-c21b6bf71617eeeb67a56b0d48b5bb5c.css",
+];
+
+/// The script live-reloading pages, served from `/static/reload.js`.
+pub(crate) const RELOAD_SCRIPT: &str = include_str!("../static/reload.js");
+
+/// Where pages load [`RELOAD_SCRIPT`] from. The URL changes with the script, so browsers can
+/// cache it for good.
+pub fn reload_script_url() -> &'static str {
+    static URL: OnceLock<String> = OnceLock::new();
+    URL.get_or_init(|| {
+        format!(
+            "/static/reload.js?v={}",
+            ContentHash::of(RELOAD_SCRIPT.as_bytes())
+        )
+    })
+}
+
+/// The basic HTML of our page, the `<head>` and CSS and `<body>`.
+/// Also includes the script to subscribe to the Server Sent Events for the page
+/// and update the page if the file changes, see [`reload_script_url`].
+///
+/// The [`GITHUB_STYLESHEETS`] with a hash in `integrity` (by URL, like `sha384-...`) are only
+/// used if they still match it, so a compromised CDN can't restyle the preview.
+///
+/// Highlighted code is colored with `code_theme`.
+#[cfg(feature = "server")]
+pub(crate) fn base_html(
+    title: &str,
+    integrity: &BTreeMap<String, String>,
+    code_theme: CodeTheme,
+    content: &str,
+) -> String {
+    base_html_with(title, integrity, code_theme, "", content)
+}
+
+/// [`base_html`] with `footer` at the bottom of the page.
+fn base_html_with(
+    title: &str,
+    integrity: &BTreeMap<String, String>,
+    code_theme: CodeTheme,
+    footer: &str,
+    content: &str,
+) -> String {
+    format!(
+        "{}",
+        html! {
+            : doctype::HTML;
+            html {
+                head {
+                    link(rel="stylesheet", href="/static/octicons/octicons.css");
+                    @ for url in GITHUB_STYLESHEETS {
+                        link(rel="stylesheet", href=url, integrity?=integrity.get(url).map(String::as_str), crossorigin="anonymous");
+                    }
+                    link(rel="stylesheet", href="/static/style.css");
+                    link(rel="stylesheet", href=code_theme.stylesheet_url());
+                    title : title;
+                    script(src=reload_script_url()) {}
+                }
+                body {
+                    : Raw(content);
+                    : Raw(footer);
+                }
+            }
+        }
+    )
+}
+
+/// The wrapping necessary to make the rendered markdown file to look right
+#[cfg(feature = "server")]
+pub(crate) fn markdown_html(file_name: &str, md_content: &str) -> String {
+    markdown_html_with(file_name, "", "", &TextDirection::default(), md_content)
+}
+
+/// [`markdown_html`] with `header` after the file name, `byline` under it and the article
+/// written in `direction`.
+fn markdown_html_with(
+    file_name: &str,
+    header: &str,
+    byline: &str,
+    direction: &TextDirection,
+    md_content: &str,
+) -> String {
+    format!(
+        "{}",
+        html! {
+            div(class="page") {
+                a(class="rs-readme-skip", href="#rs-readme-content") : "Skip to content";
+                div(id="preview-page", class="preview-page") {
+                    div(class="main-content") {
+                        div(class="container new-discussion-timeline experiment-repo-nav") {
+                            div(class="repository-content") {
+                                div(id="readme", class="readme boxed-group clearfix announce instapaper_body md") {
+                                    header(role="banner", class="rs-readme-header") {
+                                        h3 {
+                                            span(class="octicon octicon-book");
+                                            : format!(" {}",file_name);
+                                            : Raw(header);
+                                        }
+                                        : Raw(byline);
+                                    }
+                                    article(id="rs-readme-content", role="main", class="markdown-body entry-content", itemprop="text", dir?=direction.dir, lang?=direction.lang.as_deref()) {
+                                        : Raw(md_content);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                div : Raw("&nbsp;");
+            }
+        }
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::content_finder::FileFinder;
+    use crate::offline_converter::OfflineConverter;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_base_html() {
+        let expected = format!(
+        "\
+<!DOCTYPE html>\
+<html>\
+  <head>\
+  <link rel=\"stylesheet\" href=\"/static/octicons/octicons.css\">\
+  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/frameworks-146fab5ea30e8afac08dd11013bb4ee0.css\" crossorigin=\"anonymous\">\
+  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/site-897ad5fdbe32a5cd67af5d1bdc68a292.css\" crossorigin=\"anonymous\">\
+  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/github-c21b6bf71617eeeb67a56b0d48b5bb5c.css\" crossorigin=\"anonymous\">\
+  <link rel=\"stylesheet\" href=\"/static/style.css\">\
+  <link rel=\"stylesheet\" href=\"/static/code-theme/solarized.css\">\
+    <title>test title</title>\
+    <script src=\"{}\"></script>\
+  </head>\
+  <body>\
+    Test content\
+  </body>\
+</html>",
+        reload_script_url()
+    );
+
+        let actual = base_html(
+            "test title",
+            &BTreeMap::new(),
+            CodeTheme::Solarized,
+            "Test content",
+        );
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn github_stylesheets_are_pinned_to_their_hashes() {
+        let integrity = vec![(GITHUB_STYLESHEETS[1].to_string(), "sha384-abc".to_string())]
+            .into_iter()
+            .collect();
+
+        let html = base_html("test title", &integrity, CodeTheme::default(), "");
+
+        assert!(html.contains(&format!(
+            "<link rel=\"stylesheet\" href=\"{}\" integrity=\"sha384-abc\" crossorigin=\"anonymous\">",
+            GITHUB_STYLESHEETS[1]
+        )));
+        assert!(html.contains(&format!(
+            "<link rel=\"stylesheet\" href=\"{}\" crossorigin=\"anonymous\">",
+            GITHUB_STYLESHEETS[0]
+        )));
+    }
+
+    #[test]
+    fn test_markdown_html() {
+        let expected = "\
+<div class=\"page\">\
+  <a class=\"rs-readme-skip\" href=\"#rs-readme-content\">Skip to content</a>\
+  <div id=\"preview-page\" class=\"preview-page\">\
+    <div class=\"main-content\">\
+      <div class=\"container new-discussion-timeline experiment-repo-nav\">\
+        <div class=\"repository-content\">\
+          <div id=\"readme\" class=\"readme boxed-group clearfix announce instapaper_body md\">\
+            <header role=\"banner\" class=\"rs-readme-header\">\
+              <h3>\
+                <span class=\"octicon octicon-book\"></span> \
+                file_name.md\
+              </h3>\
+            </header>\
+            <article id=\"rs-readme-content\" role=\"main\" class=\"markdown-body entry-content\" itemprop=\"text\">\
+              Test content\
+            </article>\
+          </div>\
+        </div>\
+      </div>\
+    </div>\
+  </div>\
+  <div>&nbsp;</div>\
+</div>";
+
+        let actual = markdown_html("file_name.md", "Test content");
+
+        assert_eq!(expected, actual);
+    }
+
+    #[async_std::test]
+    async fn renders_documents_like_the_server() {
+        let finder = FileFinder::new(PathBuf::from("./"));
+        let converter = OfflineConverter::default();
+
+        let page = render_document(
+            &finder,
+            &converter,
+            "test_dir/b.md",
+            &RenderOptions {
+                full_page: false,
+                ..RenderOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(page.title, "b.md");
+        assert_eq!(page.content, "<ul>\n<li>B's content</li>\n</ul>\n");
+        assert_eq!(page.html, markdown_html("b.md", &page.content));
+    }
+
+    #[async_std::test]
+    async fn render_errors_keep_their_cause() {
+        let finder = FileFinder::new(PathBuf::from("./"));
+        let converter = OfflineConverter::default();
+
+        let err = render_document(
+            &finder,
+            &converter,
+            "test_dir/b.txt",
+            &RenderOptions::default(),
+        )
+        .await;
+
+        assert_eq!(err, Err(Error::NotMarkdown));
+    }
+}
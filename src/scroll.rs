@@ -0,0 +1,66 @@
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+/// Passes lines to scroll to from editors to the open pages showing a document, so the preview
+/// keeps up with the cursor. Pages get them over their live-reload connection as `scroll`
+/// messages.
+#[derive(Default)]
+pub(crate) struct Scrolls {
+    pages: Mutex<HashMap<String, Vec<UnboundedSender<usize>>>>,
+}
+
+impl Scrolls {
+    /// The lines the pages showing the document at `path` are asked to scroll to.
+    pub(crate) fn subscribe(&self, path: &str) -> UnboundedReceiver<usize> {
+        let (tx, rx) = mpsc::unbounded();
+        self.pages().entry(path.to_string()).or_default().push(tx);
+        rx
+    }
+
+    /// Asks the pages showing the document at `path` to scroll to `line`, returning how many
+    /// there are. Pages that have been closed are forgotten.
+    pub(crate) fn scroll(&self, path: &str, line: usize) -> usize {
+        let mut pages = self.pages();
+        let open = match pages.get_mut(path) {
+            Some(open) => open,
+            None => return 0,
+        };
+        open.retain(|page| page.unbounded_send(line).is_ok());
+
+        let told = open.len();
+        if told == 0 {
+            pages.remove(path);
+        }
+        told
+    }
+
+    fn pages(&self) -> MutexGuard<'_, HashMap<String, Vec<UnboundedSender<usize>>>> {
+        match self.pages.lock() {
+            Ok(pages) => pages,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::StreamExt;
+
+    #[async_std::test]
+    async fn scrolls_the_pages_showing_a_document() {
+        let scrolls = Scrolls::default();
+        let mut readme = scrolls.subscribe("./README.md");
+        let closed = scrolls.subscribe("./README.md");
+        let mut guide = scrolls.subscribe("./guide.md");
+        drop(closed);
+
+        assert_eq!(scrolls.scroll("./README.md", 12), 1);
+        assert_eq!(scrolls.scroll("./missing.md", 3), 0);
+        assert_eq!(scrolls.scroll("./guide.md", 4), 1);
+
+        assert_eq!(readme.next().await, Some(12));
+        assert_eq!(guide.next().await, Some(4));
+    }
+}
@@ -0,0 +1,81 @@
+use std::env;
+use std::net::TcpListener;
+use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::net::UnixListener;
+
+use log::warn;
+
+/// The first file descriptor systemd passes, see `sd_listen_fds(3)`.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// A listener handed to us by systemd socket activation.
+pub enum ActivatedListener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// Takes the listening socket systemd passed in through `LISTEN_FDS`, if there is one.
+///
+/// Like `sd_listen_fds` this only trusts the variables when `LISTEN_PID` is our process and
+/// unsets them afterwards so child processes don't pick them up. Only the first socket is used.
+pub fn systemd_listener() -> Option<ActivatedListener> {
+    let count = listen_fds(
+        env::var("LISTEN_PID").ok().as_deref(),
+        env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    match count {
+        0 => return None,
+        1 => {}
+        count => warn!(
+            "systemd passed {} sockets, only the first will be used",
+            count
+        ),
+    }
+
+    // Safety: systemd guarantees fds 3..3+LISTEN_FDS are open sockets owned by this process.
+    let tcp = unsafe { TcpListener::from_raw_fd(SD_LISTEN_FDS_START) };
+    if tcp.local_addr().is_ok() {
+        return Some(ActivatedListener::Tcp(tcp));
+    }
+
+    // `getsockname` fails for anything that isn't an IP socket, so it's a unix socket.
+    let fd = tcp.into_raw_fd();
+    Some(ActivatedListener::Unix(unsafe {
+        UnixListener::from_raw_fd(fd)
+    }))
+}
+
+/// How many sockets were passed to process `pid` given `LISTEN_PID` and `LISTEN_FDS`.
+fn listen_fds(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> usize {
+    match listen_pid.and_then(|listen_pid| listen_pid.parse::<u32>().ok()) {
+        Some(listen_pid) if listen_pid == pid => {
+            listen_fds.and_then(|fds| fds.parse().ok()).unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_fds_for_our_pid() {
+        assert_eq!(listen_fds(Some("42"), Some("1"), 42), 1);
+        assert_eq!(listen_fds(Some("42"), Some("2"), 42), 2);
+    }
+
+    #[test]
+    fn ignores_fds_for_other_pids() {
+        assert_eq!(listen_fds(Some("41"), Some("1"), 42), 0);
+        assert_eq!(listen_fds(None, Some("1"), 42), 0);
+        assert_eq!(listen_fds(Some("42"), None, 42), 0);
+        assert_eq!(listen_fds(Some("42"), Some("lots"), 42), 0);
+    }
+}
@@ -0,0 +1,137 @@
+use pulldown_cmark::{Event, Tag};
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// The attribute top-level blocks get with the line of the source they start on, see
+/// [`source_lines`].
+pub const SOURCE_LINE_ATTRIBUTE: &str = "data-source-line";
+
+/// Marks each top-level block of the markdown with the 1-based line of `md` it starts on, so the
+/// page can scroll to the part of the document an editor's cursor is in.
+///
+/// Paragraphs, headings, quotes, lists and rules get the attribute themselves. Code blocks and
+/// tables are wrapped in a `div` with it instead, so they're still highlighted and aligned.
+pub(crate) fn source_lines<'a>(
+    md: &'a str,
+    events: impl Iterator<Item = (Event<'a>, Range<usize>)> + 'a,
+) -> impl Iterator<Item = Event<'a>> + 'a {
+    SourceLines {
+        md,
+        events,
+        depth: 0,
+        pending: VecDeque::new(),
+        counted: (0, 1),
+    }
+}
+
+struct SourceLines<'a, I> {
+    md: &'a str,
+    events: I,
+    /// How many blocks deep the last event left us.
+    depth: usize,
+    /// Events to hand out before reading more.
+    pending: VecDeque<Event<'a>>,
+    /// The offset newlines have been counted up to and the line it's on, blocks come in order.
+    counted: (usize, usize),
+}
+
+impl<'a, I> SourceLines<'a, I> {
+    fn line_at(&mut self, offset: usize) -> usize {
+        let (from, line) = self.counted;
+        let line = match self.md.get(from..offset) {
+            Some(between) => line + between.matches('\n').count(),
+            None => line,
+        };
+        self.counted = (offset.max(from), line);
+        line
+    }
+}
+
+impl<'a, I: Iterator<Item = (Event<'a>, Range<usize>)>> Iterator for SourceLines<'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        if let Some(event) = self.pending.pop_front() {
+            return Some(event);
+        }
+
+        let (event, range) = self.events.next()?;
+        let top_level = self.depth == 0;
+        match &event {
+            Event::Start(_) => self.depth += 1,
+            Event::End(_) => self.depth = self.depth.saturating_sub(1),
+            _ => {}
+        }
+
+        let attribute = |line: usize| format!("{}=\"{}\"", SOURCE_LINE_ATTRIBUTE, line);
+        let marked = match &event {
+            Event::Start(tag) if top_level => {
+                let attribute = attribute(self.line_at(range.start));
+                match tag {
+                    Tag::Paragraph => format!("<p {}>", attribute),
+                    Tag::Heading(level) => format!("<h{} {}>", level, attribute),
+                    Tag::BlockQuote => format!("<blockquote {}>\n", attribute),
+                    Tag::List(None) => format!("<ul {}>\n", attribute),
+                    Tag::List(Some(1)) => format!("<ol {}>\n", attribute),
+                    Tag::List(Some(start)) => format!("<ol start=\"{}\" {}>\n", start, attribute),
+                    Tag::CodeBlock(_) | Tag::Table(_) => format!("<div {}>\n", attribute),
+                    _ => return Some(event),
+                }
+            }
+            Event::End(Tag::CodeBlock(_)) | Event::End(Tag::Table(_)) if self.depth == 0 => {
+                self.pending.push_back(Event::Html("</div>\n".into()));
+                return Some(event);
+            }
+            Event::Rule if top_level => {
+                format!("<hr {} />\n", attribute(self.line_at(range.start)))
+            }
+            _ => return Some(event),
+        };
+
+        if let Event::Start(Tag::CodeBlock(_)) | Event::Start(Tag::Table(_)) = event {
+            self.pending.push_back(event);
+        }
+        Some(Event::Html(marked.into()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::{html, Options, Parser};
+
+    fn marked(md: &str) -> String {
+        let mut out = String::new();
+        let events = source_lines(md, Parser::new_ext(md, Options::all()).into_offset_iter());
+        html::push_html(&mut out, events);
+        out
+    }
+
+    #[test]
+    fn marks_top_level_blocks_with_their_line() {
+        let md = "# Title\n\nSome *text*\nover two lines\n\n- one\n- two\n\n3. three\n\n> quote\n\n---\n";
+
+        assert_eq!(
+            marked(md),
+            "<h1 data-source-line=\"1\">Title</h1>\n\
+             <p data-source-line=\"3\">Some <em>text</em>\nover two lines</p>\n\
+             <ul data-source-line=\"6\">\n<li>one</li>\n<li>two</li>\n</ul>\n\
+             <ol start=\"3\" data-source-line=\"9\">\n<li>three</li>\n</ol>\n\
+             <blockquote data-source-line=\"11\">\n<p>quote</p>\n</blockquote>\n\
+             <hr data-source-line=\"13\" />\n"
+        );
+    }
+
+    #[test]
+    fn wraps_code_blocks_and_tables() {
+        let md = "```rust\nfn main() {}\n```\n\n| a |\n|---|\n| 1 |\n";
+
+        assert_eq!(
+            marked(md),
+            "<div data-source-line=\"1\">\n<pre><code class=\"language-rust\">fn main() {}\n</code></pre>\n</div>\n\
+             <div data-source-line=\"5\">\n<table><thead><tr><th>a</th></tr></thead><tbody>\n\
+             <tr><td>1</td></tr>\n</tbody></table>\n</div>\n"
+        );
+    }
+}
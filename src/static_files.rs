@@ -1,65 +1,128 @@
-use super::{ContentFinder, MarkdownConverter, State};
-use http_types::mime;
-use std::sync::Arc;
-use tide::{http::StatusCode, Request, Response};
+use crate::code_theme::CodeTheme;
+use crate::handlers::RenderedResponse;
+use crate::render::RELOAD_SCRIPT;
+use http_types::{mime, StatusCode};
 
 // This will bundle the necessary files in the final binary so we don't have to worry about
 // portability.
-const OCTICON_CSS: &str = include_str!("../static/octicons/octicons.css");
+const OCTICON_CSS: &[u8] = include_bytes!("../static/octicons/octicons.css");
 const OCTICON_EOT: &[u8] = include_bytes!("../static/octicons/octicons.eot");
-const OCTICON_SVG: &str = include_str!("../static/octicons/octicons.svg");
+const OCTICON_SVG: &[u8] = include_bytes!("../static/octicons/octicons.svg");
 const OCTICON_TTF: &[u8] = include_bytes!("../static/octicons/octicons.ttf");
 const OCTICON_WOFF: &[u8] = include_bytes!("../static/octicons/octicons.woff");
 const OCTICON_WOFF2: &[u8] = include_bytes!("../static/octicons/octicons.woff2");
 
-const STYLE_CSS: &str = include_str!("../static/style.css");
+const STYLE_CSS: &[u8] = include_bytes!("../static/style.css");
 
-/// The endpoint to return files related to octicons
-pub async fn octicons(
-    req: Request<
-        Arc<State<impl MarkdownConverter + Send + Sync, impl ContentFinder + Send + Sync>>,
-    >,
-) -> tide::Result {
-    match req.param("file") {
-        Ok(path) if path.starts_with("octicons.css") => Ok(Response::builder(StatusCode::Ok)
-            .body(OCTICON_CSS.to_string())
-            .content_type(mime::CSS)
-            .build()),
-        Ok(path) if path.starts_with("octicons.eot") => Ok(Response::builder(StatusCode::Ok)
-            .body(OCTICON_EOT)
-            .content_type("application/vnd.ms-fontobject".parse().unwrap_or(mime::ANY))
-            .build()),
-        Ok(path) if path.starts_with("octicons.svg") => Ok(Response::builder(StatusCode::Ok)
-            .body(OCTICON_SVG.to_string())
-            .content_type(mime::SVG)
-            .build()),
-        Ok(path) if path.starts_with("octicons.ttf") => Ok(Response::builder(StatusCode::Ok)
-            .body(OCTICON_TTF)
-            .content_type("font/ttf".parse().unwrap_or(mime::ANY))
-            .build()),
-        Ok(path) if path.starts_with("octicons.woff2") => Ok(Response::builder(StatusCode::Ok)
-            .body(OCTICON_WOFF2)
-            .content_type("font/woff2".parse().unwrap_or(mime::ANY))
-            .build()),
-        Ok(path) if path.starts_with("octicons.woff") => Ok(Response::builder(StatusCode::Ok)
-            .body(OCTICON_WOFF)
-            .content_type("font/woff".parse().unwrap_or(mime::ANY))
-            .build()),
-        _ => Ok(Response::builder(StatusCode::NotFound)
-            .body("This file does not exist".to_string())
-            .content_type(mime::HTML)
-            .build()),
+// Gzipped copies made by `build.rs`, the woff fonts are already compressed.
+const OCTICON_CSS_GZ: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/octicons.css.gz"));
+const OCTICON_EOT_GZ: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/octicons.eot.gz"));
+const OCTICON_SVG_GZ: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/octicons.svg.gz"));
+const OCTICON_TTF_GZ: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/octicons.ttf.gz"));
+
+const STYLE_CSS_GZ: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/style.css.gz"));
+const RELOAD_JS_GZ: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/reload.js.gz"));
+
+/// The octicon font and CSS files, `file` is the name of the one asked for. `gzip` is whether
+/// the client accepts gzipped responses, see [`accepts_gzip`].
+pub fn octicons(file: &str, gzip: bool) -> RenderedResponse {
+    match file {
+        path if path.starts_with("octicons.css") => {
+            asset(mime::CSS, OCTICON_CSS, Some(OCTICON_CSS_GZ), gzip)
+        }
+        path if path.starts_with("octicons.eot") => asset(
+            "application/vnd.ms-fontobject",
+            OCTICON_EOT,
+            Some(OCTICON_EOT_GZ),
+            gzip,
+        ),
+        path if path.starts_with("octicons.svg") => {
+            asset(mime::SVG, OCTICON_SVG, Some(OCTICON_SVG_GZ), gzip)
+        }
+        path if path.starts_with("octicons.ttf") => {
+            asset("font/ttf", OCTICON_TTF, Some(OCTICON_TTF_GZ), gzip)
+        }
+        path if path.starts_with("octicons.woff2") => {
+            asset("font/woff2", OCTICON_WOFF2, None, gzip)
+        }
+        path if path.starts_with("octicons.woff") => asset("font/woff", OCTICON_WOFF, None, gzip),
+        _ => RenderedResponse::new(StatusCode::NotFound, mime::HTML, "This file does not exist"),
+    }
+}
+
+/// Our styles.
+pub fn style(gzip: bool) -> RenderedResponse {
+    asset(mime::CSS, STYLE_CSS, Some(STYLE_CSS_GZ), gzip)
+}
+
+/// The CSS of the [`CodeTheme`] named in `file`, like `github.css`.
+pub fn code_theme(file: &str) -> RenderedResponse {
+    match file.strip_suffix(".css").map(str::parse::<CodeTheme>) {
+        Some(Ok(theme)) => RenderedResponse::new(StatusCode::Ok, mime::CSS, theme.css()),
+        _ => RenderedResponse::new(StatusCode::NotFound, mime::HTML, "This file does not exist"),
+    }
+}
+
+/// The live-reload script, see [`crate::render::reload_script_url`]. Pages ask for it by the hash
+/// of its contents, so it's cached for as long as browsers will keep it.
+pub fn reload_script(gzip: bool) -> RenderedResponse {
+    RenderedResponse {
+        cache_control: Some("public, max-age=31536000, immutable".to_string()),
+        ..asset(
+            mime::JAVASCRIPT,
+            RELOAD_SCRIPT.as_bytes(),
+            Some(RELOAD_JS_GZ),
+            gzip,
+        )
     }
 }
 
-/// The endpoint to return our styles
-pub async fn style(
-    _req: Request<
-        Arc<State<impl MarkdownConverter + Send + Sync, impl ContentFinder + Send + Sync>>,
-    >,
-) -> tide::Result {
-    Ok(Response::builder(StatusCode::Ok)
-        .body(STYLE_CSS.to_string())
-        .content_type(mime::CSS)
-        .build())
+/// Whether an `Accept-Encoding` header allows gzip.
+pub fn accepts_gzip(accept_encoding: Option<&str>) -> bool {
+    accept_encoding.is_some_and(|encodings| {
+        encodings.split(',').any(|encoding| {
+            let mut parts = encoding.split(';');
+            let name = parts.next().unwrap_or("").trim();
+            let refused = parts.any(|param| {
+                param
+                    .trim()
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q == 0.0)
+            });
+
+            (name == "gzip" || name == "*") && !refused
+        })
+    })
+}
+
+/// Sends the gzipped copy of an asset when there is one and the client takes it.
+fn asset(
+    mime: impl ToString,
+    body: &'static [u8],
+    gzipped: Option<&'static [u8]>,
+    gzip: bool,
+) -> RenderedResponse {
+    match gzipped {
+        Some(gzipped) if gzip => RenderedResponse {
+            content_encoding: Some("gzip".to_string()),
+            ..RenderedResponse::new(StatusCode::Ok, mime, gzipped)
+        },
+        _ => RenderedResponse::new(StatusCode::Ok, mime, body),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_accept_encoding() {
+        assert!(accepts_gzip(Some("gzip, deflate, br")));
+        assert!(accepts_gzip(Some("br;q=1.0, gzip;q=0.8")));
+        assert!(accepts_gzip(Some("*")));
+        assert!(!accepts_gzip(Some("gzip;q=0, br")));
+        assert!(!accepts_gzip(Some("identity")));
+        assert!(!accepts_gzip(None));
+    }
 }
@@ -0,0 +1,88 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// What the server found to serve, printed when it starts so a wrong `--folder` or a missing
+/// README shows up before the first `404`, see [`crate::State::summary`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    /// The folder documents are served from, canonicalized. `None` when they come from a URL or
+    /// an archive.
+    pub root: Option<PathBuf>,
+
+    /// How many markdown files are in the root folder.
+    pub documents: Option<usize>,
+
+    /// The document `/` shows.
+    pub index: String,
+
+    /// Whether the index was looked for and isn't there.
+    pub index_missing: bool,
+
+    /// The converter used unless a request asks for another, like `offline`.
+    pub converter: String,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(root) = &self.root {
+            write!(f, "Serving {}", root.display())?;
+            match self.documents {
+                Some(1) => writeln!(f, " (1 markdown file)")?,
+                Some(documents) => writeln!(f, " ({} markdown files)", documents)?,
+                None => writeln!(f)?,
+            }
+        }
+        if self.index_missing {
+            writeln!(
+                f,
+                "There's no {}, so / will be a 404, is the folder right?",
+                self.index
+            )?;
+        } else {
+            writeln!(f, "/ shows {}", self.index)?;
+        }
+        writeln!(f, "Converting with the {} converter", self.converter)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn summary() -> Summary {
+        Summary {
+            root: Some(PathBuf::from("/home/me/docs")),
+            documents: Some(12),
+            index: "README.md".to_string(),
+            index_missing: false,
+            converter: "offline".to_string(),
+        }
+    }
+
+    #[test]
+    fn says_what_is_served() {
+        assert_eq!(
+            summary().to_string(),
+            "Serving /home/me/docs (12 markdown files)\n\
+             / shows README.md\n\
+             Converting with the offline converter\n"
+        );
+    }
+
+    #[test]
+    fn says_when_the_index_is_missing() {
+        let summary = Summary {
+            root: None,
+            documents: None,
+            index_missing: true,
+            ..summary()
+        };
+
+        assert_eq!(
+            summary.to_string(),
+            "There's no README.md, so / will be a 404, is the folder right?\n\
+             Converting with the offline converter\n"
+        );
+    }
+}
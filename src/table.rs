@@ -0,0 +1,175 @@
+use crate::markdown_converter::MarkdownError;
+
+/// The most rows a table shows, the rest can be seen in the raw file.
+pub const MAX_TABLE_ROWS: usize = 1000;
+
+/// How many rows a table can have before [`sortable_tables`] gives it a filter box, unless told
+/// otherwise.
+pub const DEFAULT_FILTER_ROWS: usize = 20;
+
+/// Marks every plain `<table>` in `html` to sort when a header is clicked, like the CSV tables
+/// of [`render_table`], and the ones with more than `filter_over` rows to get a box filtering
+/// them. `reload.js` does the sorting and filtering.
+pub(crate) fn sortable_tables(html: &str, filter_over: usize) -> String {
+    const SORTABLE: &str = "<table class=\"rs-readme-sortable\"";
+    const CLOSING: &str = "</table>";
+
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("<table") {
+        let tag_end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let tag = &rest[start..tag_end];
+        out.push_str(&rest[..start]);
+        if tag != "<table" && tag != SORTABLE {
+            out.push_str(tag);
+            rest = &rest[tag_end..];
+            continue;
+        }
+
+        let table = &rest[tag_end..];
+        let table = &table[..table.find(CLOSING).unwrap_or(table.len())];
+        let rows = table
+            .find("<tbody")
+            .map_or(0, |body| table[body..].matches("<tr").count());
+        out.push_str(SORTABLE);
+        if rows > filter_over {
+            out.push_str(" data-filter");
+        }
+        rest = &rest[tag_end..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Renders a CSV (or TSV, with `delimiter` `b'\t'`) document as an HTML table that sorts when a
+/// header is clicked. The first row is the header.
+///
+/// Only the first `max_rows` rows are shown, `raw` is where the whole file can be downloaded.
+pub(crate) fn render_table(
+    source: &str,
+    delimiter: u8,
+    max_rows: usize,
+    raw: &str,
+) -> Result<String, MarkdownError> {
+    let mut records = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(source.as_bytes())
+        .into_records();
+
+    let header = match records.next() {
+        Some(header) => header.map_err(unparsable)?,
+        None => csv::StringRecord::new(),
+    };
+
+    let mut rows = Vec::new();
+    let mut total = 0;
+    for record in records {
+        let record = record.map_err(unparsable)?;
+        total += 1;
+        if rows.len() < max_rows {
+            rows.push(record);
+        }
+    }
+
+    let summary = if rows.len() < total {
+        format!("Showing the first {} of {} rows. ", rows.len(), total)
+    } else {
+        format!("{} row{}. ", total, if total == 1 { "" } else { "s" })
+    };
+
+    Ok(format!(
+        "{}",
+        html! {
+            table(class="rs-readme-sortable") {
+                thead {
+                    tr {
+                        @ for cell in header.iter() {
+                            th : cell;
+                        }
+                    }
+                }
+                tbody {
+                    @ for row in &rows {
+                        tr {
+                            @ for cell in row.iter() {
+                                td : cell;
+                            }
+                        }
+                    }
+                }
+            }
+            p {
+                : &summary;
+                a(href=raw, download) : "Download";
+            }
+        }
+    ))
+}
+
+fn unparsable(err: csv::Error) -> MarkdownError {
+    MarkdownError::ConverterUnavailable(format!("Could not parse the table: {}", err))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn renders_a_header_and_rows() {
+        let table = render_table("name,size\na.md,\"1,024\"\n", b',', 10, "/raw.csv").unwrap();
+
+        assert_eq!(
+            table,
+            "<table class=\"rs-readme-sortable\">\
+<thead><tr><th>name</th><th>size</th></tr></thead>\
+<tbody><tr><td>a.md</td><td>1,024</td></tr></tbody>\
+</table>\
+<p>1 row. <a href=\"/raw.csv\" download>Download</a></p>"
+        );
+    }
+
+    #[test]
+    fn caps_the_rows_shown() {
+        let table = render_table("n\n1\n2\n3\n", b'\t', 2, "/raw.tsv").unwrap();
+
+        assert!(table.contains("<td>2</td>"));
+        assert!(!table.contains("<td>3</td>"));
+        assert!(table.contains("Showing the first 2 of 3 rows."));
+    }
+
+    #[test]
+    fn marks_tables_sortable_and_big_ones_filterable() {
+        let html = "<table><thead><tr><th>a</th></tr></thead>\
+                    <tbody><tr><td>1</td></tr><tr><td>2</td></tr></tbody></table>\
+                    <table class=\"other\"><tbody><tr><td>1</td></tr></tbody></table>";
+
+        assert_eq!(
+            sortable_tables(html, 1),
+            "<table class=\"rs-readme-sortable\" data-filter><thead><tr><th>a</th></tr></thead>\
+             <tbody><tr><td>1</td></tr><tr><td>2</td></tr></tbody></table>\
+             <table class=\"other\"><tbody><tr><td>1</td></tr></tbody></table>"
+        );
+        assert_eq!(
+            sortable_tables(html, 2),
+            html.replacen("<table>", "<table class=\"rs-readme-sortable\">", 1)
+        );
+        let csv = render_table("n\n1\n2\n", b',', 10, "/raw.csv").unwrap();
+        assert!(sortable_tables(&csv, 1)
+            .starts_with("<table class=\"rs-readme-sortable\" data-filter>"));
+    }
+
+    #[test]
+    fn escapes_cells() {
+        let table = render_table("<b>\n<script>\n", b',', 10, "/raw.csv").unwrap();
+
+        assert!(table.contains("<th>&lt;b&gt;</th>"));
+        assert!(table.contains("<td>&lt;script&gt;</td>"));
+    }
+}
@@ -0,0 +1,69 @@
+use http_types::Url;
+use opentelemetry::trace::{TraceError, TracerProvider as _};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Where OTLP/HTTP collectors take spans, below the endpoint they're reached at.
+const TRACES_PATH: &str = "v1/traces";
+
+/// Sends the spans around requests, file reads, conversions and cache lookups to the OTLP/HTTP
+/// collector at `endpoint`, like `http://localhost:4318`, see `--otel-endpoint`.
+///
+/// Spans are sent in batches from a background thread. Shut the returned provider down before
+/// exiting to send the last of them.
+pub fn otlp_tracing(endpoint: &Url) -> Result<SdkTracerProvider, TraceError> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(traces_url(endpoint).as_str())
+        .build()?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name("rs-readme").build())
+        .build();
+
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("rs-readme")))
+        .try_init()
+        .map_err(|err| TraceError::Other(err.into()))?;
+
+    Ok(provider)
+}
+
+/// The collector's traces URL, `endpoint` itself when it's already that.
+fn traces_url(endpoint: &Url) -> Url {
+    if endpoint.path().ends_with(TRACES_PATH) {
+        return endpoint.clone();
+    }
+
+    let mut url = endpoint.clone();
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    url.join(TRACES_PATH).unwrap_or_else(|_| endpoint.clone())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sends_spans_to_the_traces_path() {
+        let traces = |endpoint: &str| traces_url(&Url::parse(endpoint).unwrap()).to_string();
+
+        assert_eq!(
+            traces("http://localhost:4318"),
+            "http://localhost:4318/v1/traces"
+        );
+        assert_eq!(
+            traces("https://otel.example.com/collector/"),
+            "https://otel.example.com/collector/v1/traces"
+        );
+        assert_eq!(
+            traces("http://localhost:4318/v1/traces"),
+            "http://localhost:4318/v1/traces"
+        );
+    }
+}
@@ -0,0 +1,74 @@
+//! Mock converters and finders for testing code built on rs-readme, enabled with the
+//! `test-util` feature.
+
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use crate::content_finder::{ContentError, ContentFinder, ContentHash};
+use crate::markdown_converter::{MarkdownConverter, MarkdownError};
+
+/// A mock [`MarkdownConverter`] that returns:
+/// `<h1>A Readme</h1>`
+pub struct MockConverter;
+
+#[async_trait]
+impl MarkdownConverter for MockConverter {
+    async fn convert_markdown(&self, _md: &str) -> Result<String, MarkdownError> {
+        Ok("<h1>A Readme</h1>".to_string())
+    }
+}
+
+/// A mock [`ContentFinder`] the returns:
+/// `# A Readme`
+pub struct MockFinder;
+
+impl ContentFinder for MockFinder {
+    fn content_for(&self, _resource: &str) -> Result<(String, ContentHash), ContentError> {
+        let content = "# A Readme".to_string();
+        let hash = ContentHash::of(content.as_bytes());
+        Ok((content, hash))
+    }
+}
+
+/// A mock [`ContentFinder`] and [`MarkdownConverter`] that keeps track of arguments
+///
+/// Intended to be used to verify that an endpoint is calling its dependencies in
+/// the expected way. It takes an `Arc<Mutex<HashSet<String>>>` so you can query
+/// the `HashSet` later to verify what was placed in it.
+///
+/// The `Arc` and `Mutex` are necessary for working across threads/async runtimes.
+pub struct MockAssertSeen {
+    seen: Arc<Mutex<HashSet<String>>>,
+}
+
+impl MockAssertSeen {
+    pub fn new(seen: Arc<Mutex<HashSet<String>>>) -> MockAssertSeen {
+        MockAssertSeen { seen }
+    }
+}
+
+impl ContentFinder for MockAssertSeen {
+    fn content_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+        self.seen
+            .lock()
+            .expect("Could not lock mutex in content_for")
+            .insert(resource.to_string());
+
+        let content = format!("content for: {}", resource);
+        let hash = ContentHash::of(content.as_bytes());
+        Ok((content, hash))
+    }
+}
+
+#[async_trait]
+impl MarkdownConverter for MockAssertSeen {
+    async fn convert_markdown(&self, md: &str) -> Result<String, MarkdownError> {
+        self.seen
+            .lock()
+            .expect("Could not lock mutex in convert_markdown")
+            .insert(md.to_string());
+
+        Ok(md.to_string())
+    }
+}
@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::stream::{self, Stream, StreamExt};
+use log::debug;
+
+use crate::content_finder::ContentHash;
+use crate::error::Error;
+use crate::render::RenderOptions;
+use crate::web_server::State;
+
+/// How often a document is re-read to see if it changed.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long after the converter failed on a document it's tried again, for pages showing an
+/// earlier version until it works.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a changed document has to stay the same before it's converted, unless
+/// `--debounce-ms` says otherwise.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// How long after sending a page an update the next one waits, unless
+/// `--min-push-interval-ms` says otherwise.
+pub const DEFAULT_PUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A new version of a document, see [`State::updates`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentUpdate {
+    /// The document, as given to [`State::updates`].
+    pub path: String,
+
+    /// The hash of the markdown source.
+    pub hash: ContentHash,
+
+    /// The converted markdown.
+    pub contents: String,
+
+    /// When it was converted, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// What a shared watcher is watching: a document, the converter and the git ref.
+type Watched = (String, Option<String>, Option<String>);
+
+/// The watchers of the documents open in pages, one for each document, converter and git ref
+/// however many pages show it, see [`UpdateTasks::subscribe`].
+#[derive(Default)]
+pub(crate) struct UpdateTasks {
+    watchers: Mutex<HashMap<Watched, Watcher>>,
+}
+
+#[derive(Default)]
+struct Watcher {
+    subscribers: Vec<UnboundedSender<Result<DocumentUpdate, Error>>>,
+
+    /// The last update sent, for pages opened after it.
+    latest: Option<Result<DocumentUpdate, Error>>,
+}
+
+impl UpdateTasks {
+    /// The updates of the document at `path` converted with `converter` at the git `reference`,
+    /// see [`document_updates`].
+    ///
+    /// The first subscriber starts a task reading and converting the document, the others get
+    /// what it sends too, starting with the latest version. The task stops once none of them
+    /// are listening.
+    pub(crate) fn subscribe(
+        &self,
+        state: &Arc<State>,
+        path: String,
+        converter: Option<String>,
+        reference: Option<String>,
+    ) -> UnboundedReceiver<Result<DocumentUpdate, Error>> {
+        let (tx, rx) = mpsc::unbounded();
+        let watched = (path, converter, reference);
+        let mut watchers = self.watchers();
+        match watchers.get_mut(&watched) {
+            Some(watcher) => {
+                if let Some(latest) = &watcher.latest {
+                    let _ = tx.unbounded_send(latest.clone());
+                }
+                watcher.subscribers.push(tx);
+            }
+            None => {
+                watchers.insert(
+                    watched.clone(),
+                    Watcher {
+                        subscribers: vec![tx],
+                        latest: None,
+                    },
+                );
+                async_std::task::spawn(watch(state.clone(), watched));
+            }
+        }
+        rx
+    }
+
+    /// How many documents are being watched.
+    pub(crate) fn watching(&self) -> usize {
+        self.watchers().len()
+    }
+
+    /// Sends `update` to the subscribers of `watched`, returning whether any are left. Once none
+    /// are the watcher is forgotten.
+    fn broadcast(&self, watched: &Watched, update: Result<DocumentUpdate, Error>) -> bool {
+        let mut watchers = self.watchers();
+        let watcher = match watchers.get_mut(watched) {
+            Some(watcher) => watcher,
+            None => return false,
+        };
+        watcher
+            .subscribers
+            .retain(|subscriber| subscriber.unbounded_send(update.clone()).is_ok());
+        watcher.latest = Some(update);
+
+        self.retire_if_unwatched(&mut watchers, watched)
+    }
+
+    /// Whether `watched` still has subscribers, forgetting it when it doesn't.
+    fn still_watched(&self, watched: &Watched) -> bool {
+        let mut watchers = self.watchers();
+        self.retire_if_unwatched(&mut watchers, watched)
+    }
+
+    fn retire_if_unwatched(
+        &self,
+        watchers: &mut HashMap<Watched, Watcher>,
+        watched: &Watched,
+    ) -> bool {
+        let watcher = match watchers.get_mut(watched) {
+            Some(watcher) => watcher,
+            None => return false,
+        };
+        watcher
+            .subscribers
+            .retain(|subscriber| !subscriber.is_closed());
+        if watcher.subscribers.is_empty() {
+            watchers.remove(watched);
+            return false;
+        }
+        true
+    }
+
+    fn watchers(&self) -> MutexGuard<'_, HashMap<Watched, Watcher>> {
+        match self.watchers.lock() {
+            Ok(watchers) => watchers,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+/// Sends the updates of `watched` to its subscribers until there aren't any, checking for them
+/// between updates too so a document that doesn't change isn't watched forever.
+async fn watch(state: Arc<State>, watched: Watched) {
+    let (path, converter, reference) = watched.clone();
+    let mut updates = Box::pin(document_updates(state.clone(), path, converter, reference));
+
+    loop {
+        // `next` can be given up on and polled again, the update in progress is kept
+        match async_std::future::timeout(POLL_INTERVAL, updates.next()).await {
+            Ok(Some(update)) => {
+                if !state.update_tasks().broadcast(&watched, update) {
+                    break;
+                }
+            }
+            Ok(None) => break,
+            Err(_) => {
+                if !state.update_tasks().still_watched(&watched) {
+                    break;
+                }
+            }
+        }
+    }
+    debug!("Stopped watching {} for updates", watched.0);
+}
+
+/// Yields the document at `path` converted with the `converter` a request asked for, then again
+/// every time its contents change. With a git `reference` it's the document as of that ref, which
+/// changes when the ref moves.
+///
+/// Changes are debounced, see [`State::with_debounce`], so an editor saving on every keystroke
+/// only gets the settled version converted.
+///
+/// Documents that can't be read are skipped until they can be. A version that can't be converted
+/// yields the error instead, so pages can say why they stopped updating. When it was the
+/// converter that failed, it's tried again every [`RETRY_INTERVAL`] until it works, yielding
+/// just the version that finally converts.
+pub(crate) fn document_updates(
+    state: Arc<State>,
+    path: String,
+    converter: Option<String>,
+    reference: Option<String>,
+) -> impl Stream<Item = Result<DocumentUpdate, Error>> + Send + 'static {
+    let last: Option<ContentHash> = None;
+    // When to convert the last version again, after the converter failed on it
+    let retry: Option<Instant> = None;
+
+    stream::unfold(
+        (state, path, converter, reference, last, retry),
+        |(state, path, converter, reference, mut last, mut retry)| async move {
+            let mut wait = last.is_some();
+            loop {
+                if wait {
+                    async_std::task::sleep(POLL_INTERVAL).await;
+                }
+                wait = true;
+
+                let (contents, hash) = match read(&state, &path, reference.as_deref()).await {
+                    Some(found) => found,
+                    None => continue,
+                };
+
+                let retrying = last == Some(hash);
+                if retrying && retry.is_none_or(|at| Instant::now() < at) {
+                    continue;
+                }
+
+                let (contents, hash) = match last {
+                    Some(_) if !retrying => {
+                        match settle(&state, &path, reference.as_deref(), hash).await {
+                            Some(settled) => settled,
+                            None => continue,
+                        }
+                    }
+                    _ => (contents, hash),
+                };
+
+                if !retrying {
+                    state.events().observe(&path, &hash.to_string());
+                    if last.is_some() {
+                        state.document_changed(&path, hash);
+                    }
+                }
+
+                last = Some(hash);
+
+                let options = RenderOptions {
+                    full_page: false,
+                    ..RenderOptions::default()
+                };
+                let update = state
+                    .render_cached(converter.as_deref(), &path, &contents, hash, &options)
+                    .await
+                    .map(|page| DocumentUpdate {
+                        path: path.clone(),
+                        hash,
+                        contents: page.content,
+                        timestamp: now_millis(),
+                    });
+
+                retry = match &update {
+                    Err(err) if err.converter_failed() => Some(Instant::now() + RETRY_INTERVAL),
+                    _ => None,
+                };
+                if retrying && update.is_err() {
+                    debug!("Converting {} again failed, retrying later", path);
+                    continue;
+                }
+
+                return Some((update, (state, path, converter, reference, last, retry)));
+            }
+        },
+    )
+}
+
+/// The time now in milliseconds since the Unix epoch, for [`DocumentUpdate::timestamp`].
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+async fn read(state: &State, path: &str, reference: Option<&str>) -> Option<(String, ContentHash)> {
+    match state.read_at(reference, path).await {
+        Ok(found) => Some(found),
+        Err(err) => {
+            debug!("Could not read {} for updates: {}", path, err);
+            None
+        }
+    }
+}
+
+/// Re-reads the changed document at `path` until it's stayed the same for the debounce
+/// interval, returning the settled version.
+async fn settle(
+    state: &State,
+    path: &str,
+    reference: Option<&str>,
+    mut hash: ContentHash,
+) -> Option<(String, ContentHash)> {
+    loop {
+        async_std::task::sleep(state.debounce()).await;
+
+        let (contents, settled) = read(state, path, reference).await?;
+        if settled == hash {
+            return Some((contents, settled));
+        }
+        hash = settled;
+    }
+}
+
+/// Yields what `items` does, but at most once every `interval`: an item that comes sooner waits
+/// out the rest of it, and is dropped for any newer one that comes meanwhile. Keeps pages that
+/// are sent every version of a continuously rewritten document from falling behind.
+pub(crate) fn throttle<T: Send + 'static>(
+    items: impl Stream<Item = T> + Send + 'static,
+    interval: Duration,
+) -> impl Stream<Item = T> + Send + 'static {
+    let last: Option<Instant> = None;
+
+    stream::unfold((items.boxed(), last), move |(mut items, last)| async move {
+        let mut item = items.next().await?;
+        if let Some(last) = last {
+            let due = last + interval;
+            while let Some(wait) = due.checked_duration_since(Instant::now()) {
+                match async_std::future::timeout(wait, items.next()).await {
+                    Ok(Some(newer)) => item = newer,
+                    Ok(None) | Err(_) => break,
+                }
+            }
+        }
+        Some((item, (items, Some(Instant::now()))))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::content_finder::{ContentError, ContentFinder};
+    use crate::markdown_converter::{MarkdownConverter, MarkdownError};
+    use crate::offline_converter::OfflineConverter;
+    use futures::StreamExt;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    /// A [`ContentFinder`] whose one document can be edited.
+    #[derive(Clone)]
+    struct Editable(Arc<Mutex<String>>);
+
+    impl ContentFinder for Editable {
+        fn content_for(&self, _resource: &str) -> Result<(String, ContentHash), ContentError> {
+            let content = self.0.lock().unwrap().clone();
+            let hash = ContentHash::of(content.as_bytes());
+            Ok((content, hash))
+        }
+    }
+
+    #[async_std::test]
+    async fn yields_the_document_then_each_change() {
+        let doc = Editable(Arc::new(Mutex::new("# One".to_string())));
+        let state = Arc::new(State::new(OfflineConverter::default(), doc.clone()));
+        let mut updates = Box::pin(state.updates("./README.md"));
+
+        let first = updates.next().await.unwrap();
+        assert_eq!(first.path, "./README.md");
+        assert_eq!(first.hash, ContentHash::of(b"# One"));
+        assert_eq!(first.contents, "<h1>One</h1>\n");
+
+        *doc.0.lock().unwrap() = "# Two".to_string();
+
+        let second = updates.next().await.unwrap();
+        assert_eq!(second.hash, ContentHash::of(b"# Two"));
+        assert_eq!(second.contents, "<h1>Two</h1>\n");
+        assert!(second.timestamp >= first.timestamp);
+    }
+
+    #[async_std::test]
+    async fn changes_to_included_files_are_sent() {
+        /// The README includes `lib.rs`, which can be edited.
+        #[derive(Clone)]
+        struct Including(Arc<Mutex<String>>);
+
+        impl ContentFinder for Including {
+            fn content_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+                let content = match resource {
+                    "./lib.rs" => self.0.lock().unwrap().clone(),
+                    _ => "{{#include lib.rs}}".to_string(),
+                };
+                let hash = ContentHash::of(content.as_bytes());
+                Ok((content, hash))
+            }
+        }
+
+        let lib = Including(Arc::new(Mutex::new("one()".to_string())));
+        let state = Arc::new(State::new(OfflineConverter::default(), lib.clone()));
+        let mut updates = Box::pin(state.updates("./README.md"));
+        updates.next().await.unwrap();
+
+        *lib.0.lock().unwrap() = "two()".to_string();
+
+        let changed = updates.next().await.unwrap();
+        assert!(changed.contents.contains("two"));
+    }
+
+    #[async_std::test]
+    async fn a_burst_of_saves_is_sent_once_settled() {
+        let doc = Editable(Arc::new(Mutex::new("# One".to_string())));
+        let state = State::new(OfflineConverter::default(), doc.clone())
+            .with_debounce(Duration::from_millis(200));
+        let state = Arc::new(state);
+        let mut updates = Box::pin(state.updates("./README.md"));
+        updates.next().await.unwrap();
+
+        let editor = doc.clone();
+        async_std::task::spawn(async move {
+            for draft in 0..12 {
+                *editor.0.lock().unwrap() = format!("# Draft {}", draft);
+                async_std::task::sleep(Duration::from_millis(50)).await;
+            }
+            *editor.0.lock().unwrap() = "# Done".to_string();
+        });
+
+        let settled = updates.next().await.unwrap();
+        assert_eq!(settled.contents, "<h1>Done</h1>\n");
+    }
+
+    #[async_std::test]
+    async fn conversion_failures_are_sent_until_fixed() {
+        /// Converts offline, unless the document says `boom`.
+        struct Fragile;
+
+        #[async_trait::async_trait]
+        impl MarkdownConverter for Fragile {
+            async fn convert_markdown(&self, md: &str) -> Result<String, MarkdownError> {
+                if md.contains("boom") {
+                    return Err(MarkdownError::ConverterUnavailable("boom".to_string()));
+                }
+                OfflineConverter::default().convert_markdown(md).await
+            }
+        }
+
+        let doc = Editable(Arc::new(Mutex::new("# One".to_string())));
+        let state = Arc::new(State::new(Fragile, doc.clone()).with_debounce(Duration::ZERO));
+        let mut updates = Box::pin(state.updates_with("./README.md", None, None));
+        assert!(updates.next().await.unwrap().is_ok());
+
+        *doc.0.lock().unwrap() = "# boom".to_string();
+        assert_eq!(
+            updates.next().await.unwrap(),
+            Err(Error::ConverterUnavailable("boom".to_string()))
+        );
+
+        *doc.0.lock().unwrap() = "# Fixed".to_string();
+        assert_eq!(
+            updates.next().await.unwrap().unwrap().contents,
+            "<h1>Fixed</h1>\n"
+        );
+    }
+
+    #[async_std::test]
+    async fn conversions_are_retried_once_the_converter_works() {
+        /// Fails while it's down.
+        struct Flaky(Arc<AtomicBool>);
+
+        #[async_trait::async_trait]
+        impl MarkdownConverter for Flaky {
+            async fn convert_markdown(&self, md: &str) -> Result<String, MarkdownError> {
+                if self.0.load(Ordering::SeqCst) {
+                    return Err(MarkdownError::ConverterUnavailable("down".to_string()));
+                }
+                OfflineConverter::default().convert_markdown(md).await
+            }
+        }
+
+        let down = Arc::new(AtomicBool::new(true));
+        let doc = Editable(Arc::new(Mutex::new("# One".to_string())));
+        let state = Arc::new(State::new(Flaky(down.clone()), doc));
+        let mut updates = Box::pin(state.updates_with("./README.md", None, None));
+        assert!(updates.next().await.unwrap().is_err());
+
+        down.store(false, Ordering::SeqCst);
+        let start = Instant::now();
+
+        assert_eq!(
+            updates.next().await.unwrap().unwrap().contents,
+            "<h1>One</h1>\n"
+        );
+        assert!(start.elapsed() <= RETRY_INTERVAL + POLL_INTERVAL * 2);
+    }
+
+    #[async_std::test]
+    async fn pages_showing_a_document_share_one_watcher() {
+        let doc = Editable(Arc::new(Mutex::new("# One".to_string())));
+        let state = Arc::new(
+            State::new(OfflineConverter::default(), doc.clone()).with_debounce(Duration::ZERO),
+        );
+        let mut first = Box::pin(state.updates("./README.md"));
+        first.next().await.unwrap();
+        let mut second = Box::pin(state.updates("./README.md"));
+
+        assert_eq!(second.next().await.unwrap().contents, "<h1>One</h1>\n");
+        assert_eq!(state.update_tasks().watching(), 1);
+
+        *doc.0.lock().unwrap() = "# Two".to_string();
+
+        assert_eq!(first.next().await.unwrap().contents, "<h1>Two</h1>\n");
+        assert_eq!(second.next().await.unwrap().contents, "<h1>Two</h1>\n");
+
+        drop(first);
+        drop(second);
+        async_std::task::sleep(POLL_INTERVAL * 3).await;
+        assert_eq!(state.update_tasks().watching(), 0);
+    }
+
+    #[async_std::test]
+    async fn throttled_items_wait_and_keep_the_newest() {
+        let items = stream::iter(1..=3).chain(stream::once(async {
+            async_std::task::sleep(Duration::from_millis(300)).await;
+            4
+        }));
+        let start = Instant::now();
+        let mut throttled = Box::pin(throttle(items, Duration::from_millis(100)));
+
+        assert_eq!(throttled.next().await, Some(1));
+        assert_eq!(throttled.next().await, Some(3));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(throttled.next().await, Some(4));
+        assert_eq!(throttled.next().await, None);
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::content_finder::{ContentFinder, DocumentFormat};
+use crate::pre_process::PreProcessor;
+
+/// How long the output of `git describe` is reused for, live reload reads documents a few times a
+/// second.
+const GIT_DESCRIBE_TTL: Duration = Duration::from_secs(10);
+
+/// Replaces `{{name}}` placeholders in markdown with their values, from the config file's
+/// `[variables]` or `--define name=value`, or the built-ins: `{{date}}`, today as `YYYY-MM-DD`,
+/// and `{{git_describe}}`, the output of `git describe --tags --always`.
+///
+/// `\{{name}}` is shown as `{{name}}`, and placeholders with names we don't know are left as
+/// they are.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Variables {
+    values: HashMap<String, String>,
+}
+
+impl Variables {
+    pub fn new(values: HashMap<String, String>) -> Variables {
+        Variables { values }
+    }
+
+    fn value(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.values.get(name) {
+            return Some(value.clone());
+        }
+
+        match name {
+            "date" => Some(today()),
+            "git_describe" => git_describe(),
+            _ => None,
+        }
+    }
+}
+
+impl PreProcessor for Variables {
+    fn process(&self, path: &str, source: String, _finder: &dyn ContentFinder) -> String {
+        if DocumentFormat::of(Path::new(path)) != Some(DocumentFormat::Markdown)
+            || !source.contains("{{")
+        {
+            return source;
+        }
+
+        substitute(&source, |name| self.value(name))
+    }
+}
+
+/// `source` with each `{{name}}` that `value` knows replaced.
+fn substitute(source: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut rest = source;
+
+    while let Some(open) = rest.find("{{") {
+        let close = match rest[open..].find("}}") {
+            Some(close) => open + close,
+            None => break,
+        };
+        let placeholder = &rest[open..close + 2];
+        let name = rest[open + 2..close].trim();
+
+        if rest[..open].ends_with('\\') {
+            out.push_str(&rest[..open - 1]);
+            out.push_str(placeholder);
+        } else {
+            out.push_str(&rest[..open]);
+            match Some(name).filter(|name| is_name(name)).and_then(&value) {
+                Some(value) => out.push_str(&value),
+                None => out.push_str(placeholder),
+            }
+        }
+
+        rest = &rest[close + 2..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+/// Today's date in UTC, `YYYY-MM-DD`.
+fn today() -> String {
+    date_of(SystemTime::now())
+}
+
+/// The date of `time` in UTC, `YYYY-MM-DD`.
+pub(crate) fn date_of(time: SystemTime) -> String {
+    let days = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() / 86_400)
+        .unwrap_or_default();
+    let (year, month, day) = civil_from_days(days as i64);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// The time of `time` in UTC to the second, `YYYY-MM-DDTHH:MM:SSZ`.
+#[cfg(feature = "server")]
+pub(crate) fn timestamp_of(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default();
+    let secs_of_day = secs % 86_400;
+
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        date_of(time),
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+/// The calendar date `days` after 1970-01-01, from Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// `git describe --tags --always` for the current folder, `None` outside a repository.
+fn git_describe() -> Option<String> {
+    static LAST: Mutex<Option<(Instant, Option<String>)>> = Mutex::new(None);
+
+    let mut last = match LAST.lock() {
+        Ok(last) => last,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    if let Some((at, described)) = last.as_ref() {
+        if at.elapsed() < GIT_DESCRIBE_TTL {
+            return described.clone();
+        }
+    }
+
+    let described = Command::new("git")
+        .args(["describe", "--tags", "--always"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+    *last = Some((Instant::now(), described.clone()));
+
+    described
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "version" => Some("1.2.0".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn replaces_known_names() {
+        assert_eq!(
+            substitute("v{{version}} and {{ version }}", lookup),
+            "v1.2.0 and 1.2.0"
+        );
+        assert_eq!(
+            substitute("{{unknown}} {{#include a.rs}} {{", lookup),
+            "{{unknown}} {{#include a.rs}} {{"
+        );
+    }
+
+    #[test]
+    fn escaped_placeholders_are_kept() {
+        assert_eq!(
+            substitute("\\{{version}} is {{version}}", lookup),
+            "{{version}} is 1.2.0"
+        );
+    }
+
+    #[test]
+    fn config_values_win_over_built_ins() {
+        let variables = Variables::new(
+            vec![("date".to_string(), "someday".to_string())]
+                .into_iter()
+                .collect(),
+        );
+
+        assert_eq!(variables.value("date"), Some("someday".to_string()));
+    }
+
+    #[test]
+    fn dates_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+    }
+
+    #[test]
+    fn timestamps_in_utc() {
+        let time = UNIX_EPOCH + Duration::from_secs(19_782 * 86_400 + 13 * 3600 + 5 * 60 + 9);
+
+        assert_eq!(timestamp_of(time), "2024-02-29T13:05:09Z");
+    }
+}
@@ -0,0 +1,286 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::content_finder::{ContentFinder, DocumentFormat};
+use crate::frontmatter::Frontmatter;
+use crate::includes;
+use crate::links::{images_without_alt, references, resolve, ReferenceKind, Target};
+
+/// The kinds of problem a document can have that don't stop it rendering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WarningKind {
+    /// An image points at a file that isn't there.
+    MissingImage,
+
+    /// An image has no alt text for screen readers to read out.
+    MissingAlt,
+
+    /// An include directive couldn't be followed, see [`crate::Includes`].
+    FailedInclude,
+
+    /// The document starts with frontmatter that isn't a YAML mapping, so it's shown as part of
+    /// the document.
+    InvalidFrontmatter,
+
+    /// The document isn't markdown, but was rendered as markdown because the request asked for
+    /// it with `?force=md`.
+    NotMarkdown,
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WarningKind::MissingImage => write!(f, "missing-image"),
+            WarningKind::MissingAlt => write!(f, "missing-alt"),
+            WarningKind::FailedInclude => write!(f, "failed-include"),
+            WarningKind::InvalidFrontmatter => write!(f, "invalid-frontmatter"),
+            WarningKind::NotMarkdown => write!(f, "not-markdown"),
+        }
+    }
+}
+
+/// Something wrong with a document that it was rendered despite, shown in a panel above it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)
+    }
+}
+
+/// What's wrong with `contents`, the document at `path` as it was read, that doesn't stop it
+/// rendering. Images are looked for with `finder`.
+pub fn document_warnings(path: &str, contents: &str, finder: &dyn ContentFinder) -> Vec<Warning> {
+    if DocumentFormat::of(Path::new(path)) != Some(DocumentFormat::Markdown) {
+        return Vec::new();
+    }
+
+    markdown_warnings(path, contents, finder)
+}
+
+/// [`document_warnings`] for a document rendered as markdown whatever its extension.
+pub(crate) fn markdown_warnings(
+    path: &str,
+    contents: &str,
+    finder: &dyn ContentFinder,
+) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    if let Some(problem) = Frontmatter::problem(contents) {
+        warnings.push(Warning {
+            kind: WarningKind::InvalidFrontmatter,
+            message: format!("The frontmatter couldn't be parsed: {}", problem),
+        });
+    }
+
+    warnings.extend(
+        includes::failures(contents)
+            .into_iter()
+            .map(|(target, reason)| Warning {
+                kind: WarningKind::FailedInclude,
+                message: format!("Could not include {}: {}", target, reason),
+            }),
+    );
+
+    // Parsing big documents for their links is slow, and without this there aren't any images
+    if !contents.contains("![") {
+        return warnings;
+    }
+    for reference in references(contents) {
+        if reference.kind == ReferenceKind::Image && is_missing(path, &reference.target, finder) {
+            warnings.push(Warning {
+                kind: WarningKind::MissingImage,
+                message: format!("Image {} not found", reference.target),
+            });
+        }
+    }
+    warnings.extend(
+        images_without_alt(contents)
+            .into_iter()
+            .map(|image| Warning {
+                kind: WarningKind::MissingAlt,
+                message: format!("Image {} has no alt text", image.target),
+            }),
+    );
+
+    warnings
+}
+
+/// Whether the image at `target`, as linked from the document at `path`, is a file in the root
+/// that `finder` doesn't have.
+fn is_missing(path: &str, target: &str, finder: &dyn ContentFinder) -> bool {
+    match resolve(Path::new(path), target) {
+        Target::Local { path: image, .. } => {
+            !finder.exists(&format!("./{}", image.to_string_lossy()))
+        }
+        _ => false,
+    }
+}
+
+/// `html`, converted from the document at `path`, with every `<img>` of a file `finder` doesn't
+/// have swapped for a placeholder naming it, rather than the browser's broken image icon.
+#[cfg(feature = "server")]
+pub(crate) fn mark_missing_images(html: &str, path: &str, finder: &dyn ContentFinder) -> String {
+    const SRC: &str = " src=\"";
+
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(img) = rest.find("<img") {
+        let tag_end = match rest[img..].find('>') {
+            Some(end) => img + end + 1,
+            None => break,
+        };
+        let tag = &rest[img..tag_end];
+        let src = tag.find(SRC).map(|at| {
+            let src = &tag[at + SRC.len()..];
+            src[..src.find('"').unwrap_or(src.len())].replace("&amp;", "&")
+        });
+
+        out.push_str(&rest[..img]);
+        match src {
+            Some(src) if is_missing(path, &src, finder) => {
+                let message = format!("Image {} not found", src);
+                out.push_str(&format!(
+                    "{}",
+                    html! {
+                        span(class="rs-readme-missing-image", role="img", aria-label=&message) : &message;
+                    }
+                ));
+            }
+            _ => out.push_str(tag),
+        }
+        rest = &rest[tag_end..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// A collapsed panel listing `warnings`, nothing when there aren't any. It's open when the
+/// document isn't markdown, so that's hard to miss.
+pub(crate) fn warnings_html(warnings: &[Warning]) -> String {
+    if warnings.is_empty() {
+        return String::new();
+    }
+
+    let summary = match warnings.len() {
+        1 => "1 warning".to_string(),
+        count => format!("{} warnings", count),
+    };
+    let open = warnings
+        .iter()
+        .any(|warning| warning.kind == WarningKind::NotMarkdown);
+    format!(
+        "{}",
+        html! {
+            details(class="rs-readme-warnings", open?=open) {
+                summary : &summary;
+                ul {
+                    @ for warning in warnings {
+                        li(data-kind=warning.kind.to_string()) : &warning.message;
+                    }
+                }
+            }
+        }
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::content_finder::{ContentError, ContentHash};
+    use pretty_assertions::assert_eq;
+
+    /// Has just `./docs/logo.png`.
+    struct Logo;
+
+    impl ContentFinder for Logo {
+        fn content_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+            Err(ContentError::CouldNotFetch(resource.to_string()))
+        }
+
+        fn exists(&self, resource: &str) -> bool {
+            resource == "./docs/logo.png"
+        }
+    }
+
+    #[test]
+    fn collects_what_the_document_rendered_despite() {
+        let doc = "---\ntitle: [unclosed\n---\n\
+                   > rs-readme could not include `setup.md`: it's outside the root folder\n\
+                   ![logo](logo.png) ![chart](./chart.png) ![badge](https://example.com/b.svg)\n\
+                   ![](logo.png)\n";
+
+        let warnings = document_warnings("./docs/guide.md", doc, &Logo);
+
+        let kinds: Vec<_> = warnings.iter().map(|warning| warning.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                WarningKind::InvalidFrontmatter,
+                WarningKind::FailedInclude,
+                WarningKind::MissingImage,
+                WarningKind::MissingAlt,
+            ]
+        );
+        assert_eq!(
+            warnings[1].message,
+            "Could not include setup.md: it's outside the root folder"
+        );
+        assert_eq!(warnings[2].message, "Image ./chart.png not found");
+        assert_eq!(warnings[3].message, "Image logo.png has no alt text");
+    }
+
+    #[test]
+    fn missing_images_get_a_placeholder() {
+        let html = "<p><img src=\"logo.png\" alt=\"logo\"> <img src=\"chart.png?v=1&amp;x=2\"> \
+                    <img src=\"https://example.com/b.svg\"></p>";
+
+        assert_eq!(
+            mark_missing_images(html, "./docs/guide.md", &Logo),
+            "<p><img src=\"logo.png\" alt=\"logo\"> \
+             <span class=\"rs-readme-missing-image\" role=\"img\" \
+             aria-label=\"Image chart.png?v=1&amp;x=2 not found\">\
+             Image chart.png?v=1&amp;x=2 not found</span> \
+             <img src=\"https://example.com/b.svg\"></p>"
+        );
+    }
+
+    #[test]
+    fn only_markdown_gets_warnings() {
+        assert_eq!(
+            document_warnings("./notes.txt", "![chart](./chart.png)", &Logo),
+            Vec::new()
+        );
+    }
+
+    #[test]
+    fn renders_a_collapsed_panel() {
+        let warnings = vec![Warning {
+            kind: WarningKind::MissingImage,
+            message: "Image ./chart.png not found".to_string(),
+        }];
+
+        assert_eq!(warnings_html(&[]), "");
+        assert!(!warnings_html(&warnings).contains("open"));
+        assert_eq!(
+            warnings_html(&warnings),
+            "<details class=\"rs-readme-warnings\"><summary>1 warning</summary><ul>\
+             <li data-kind=\"missing-image\">Image ./chart.png not found</li></ul></details>"
+        );
+    }
+
+    #[test]
+    fn forced_markdown_opens_the_panel() {
+        let warnings = vec![Warning {
+            kind: WarningKind::NotMarkdown,
+            message: "README isn't a markdown file".to_string(),
+        }];
+
+        assert!(warnings_html(&warnings).starts_with("<details class=\"rs-readme-warnings\" open>"));
+    }
+}
@@ -1,18 +1,69 @@
 use async_trait::async_trait;
-use horrorshow::helper::doctype;
-use horrorshow::prelude::*;
-use http_types::{Body, mime};
-use mime_guess::{self, MimeGuess};
-use serde_json::json;
-use std::sync::Arc;
-use tide::{
-    http::StatusCode, log, sse::Sender, Middleware, Next, Request, Response, Server, Status,
-};
+use futures::io::BufReader;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use futures::{future, Future, FutureExt};
+use glob::Pattern;
+use log::{debug, error, info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tide::{sse::Sender, Body, Middleware, Next, Request, Response, Server};
 
-use crate::content_finder::{ContentError, ContentFinder};
-use crate::markdown_converter::{Converter, MarkdownConverter, MarkdownError};
+use crate::access_log::{Access, AccessLog};
+use crate::code_theme::CodeTheme;
+use crate::config::{Config, ConfigError, Swap, DEFAULT_RST_COMMAND};
+use crate::content_finder::{
+    markdown_files, matches_any, ContentError, ContentFinder, ContentHash, DocumentFormat,
+};
+use crate::diagrams::Graphviz;
+use crate::direction::text_direction;
+use crate::document_stats::task_progress_html;
+use crate::editing::{save_document, Saved};
+use crate::error::Error;
+use crate::events::{Event, Events};
+use crate::external_converter::ExternalConverter;
+use crate::file_tree::{file_tree, TreeNode};
+use crate::frontmatter::Frontmatter;
+use crate::git_finder::GitContentFinder;
+use crate::git_history::{history, last_edit, Commit, LastEdit, HISTORY_LENGTH};
+use crate::git_status::{repo_status, RepoStatus};
+use crate::handlers::{self, ChangeRequest, RenderedResponse, ResponseBody};
+use crate::i18n::Lang;
+use crate::image_proxy::{ImageProxy, IMAGE_ROUTE};
+use crate::includes::Includes;
+use crate::languages::{Languages, Translation};
+use crate::limits::{hold, Limit, Permit, DEFAULT_MAX_CONVERSIONS, DEFAULT_MAX_UPDATE_STREAMS};
+use crate::links::raw_href;
+use crate::markdown_converter::{Converter, MarkdownConverter, MarkdownError, StreamingConverter};
+use crate::navigation::{Navigation, NAV_FILE, SUMMARY_FILE};
 use crate::offline_converter::OfflineConverter;
+use crate::page_cache::{CacheStats, PageCache, PageKey, DEFAULT_CAPACITY};
+use crate::panics;
+use crate::pins::Pins;
+#[cfg(feature = "plugins")]
+use crate::plugins::Plugin;
+use crate::post_process::{post_process, PostProcessor};
+use crate::pre_process::{pre_process, PreProcessor};
+use crate::prefs::{Prefs, PrefsError};
+use crate::prose_diff::prose_diff;
+use crate::recent::{recent_files, RecentFile, RECENT_LIMIT};
+use crate::render::{
+    base_html, markdown_html, page_title, preformatted, render_source, stream_page, RenderOptions,
+    RenderedPage, Surroundings,
+};
+use crate::scroll::Scrolls;
 use crate::static_files;
+use crate::summary::Summary;
+use crate::table::{render_table, sortable_tables, MAX_TABLE_ROWS};
+use crate::updates::{DocumentUpdate, UpdateTasks, DEFAULT_DEBOUNCE, DEFAULT_PUSH_INTERVAL};
+use crate::variables::Variables;
+use crate::warnings::{
+    document_warnings, mark_missing_images, markdown_warnings, Warning, WarningKind,
+};
+use crate::webhook::Webhook;
 
 /// Allows us to use either a GitHub API-based converter or an offline converter
 /// through pulldown cmark.
@@ -25,391 +76,1996 @@ pub enum Converters {
 impl MarkdownConverter for Converters {
     async fn convert_markdown(&self, md: &str) -> Result<String, MarkdownError> {
         match self {
-            Converters::Github(converter) => converter.convert_markdown(&md).await,
-            Converters::Offline(offline) => offline.convert_markdown(&md).await,
+            Converters::Github(converter) => converter.convert_markdown(md).await,
+            Converters::Offline(offline) => offline.convert_markdown(md).await,
+        }
+    }
+
+    fn streaming(&self) -> Option<&dyn StreamingConverter> {
+        match self {
+            Converters::Github(converter) => converter.streaming(),
+            Converters::Offline(offline) => offline.streaming(),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Converters::Github(converter) => converter.name(),
+            Converters::Offline(offline) => offline.name(),
         }
     }
 }
 
+/// How long a document's git history is reused for, see [`State::history`].
+const HISTORY_TTL: Duration = Duration::from_secs(10);
+
+/// How long the branch and dirty state in the footer are reused for, see [`State::repo_status`].
+const REPO_STATUS_TTL: Duration = Duration::from_secs(10);
+
+/// Documents at least this big are streamed to the browser when the converter can do it.
+const STREAM_THRESHOLD: usize = 1024 * 1024;
+
+/// How long a conversion can take before it's given up on, see [`State::with_convert_timeout`].
+pub const DEFAULT_CONVERT_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// The state necessary to process requests.
 ///
 /// It needs something to find some markdown content based on a URL path and something to take that
 /// markdown content and convert it to HTML to display.
-pub struct State<M, C>
-where
-    M: MarkdownConverter,
-    C: ContentFinder,
-{
-    markdown_converter: M,
-    content_finder: C,
-}
-
-impl<M, C> State<M, C>
-where
-    M: MarkdownConverter + Send + Sync + 'static,
-    C: ContentFinder + Send + Sync + 'static,
-{
-    pub fn new(markdown_converter: M, content_finder: C) -> State<M, C> {
+///
+/// Besides the configured converter it keeps an offline converter, and optionally a GitHub
+/// converter and those of plugins, around so a single request can pick one with
+/// `?converter=offline|github|<name>`.
+///
+/// The parts controlled by the config file live behind a [`Swap`] so they can be reloaded while
+/// the server keeps running.
+pub struct State {
+    markdown_converter: Arc<dyn MarkdownConverter + Send + Sync>,
+    content_finder: Arc<dyn ContentFinder + Send + Sync>,
+    offline_converter: OfflineConverter,
+    github_converter: Swap<Option<Converter>>,
+    github_context: Option<String>,
+    converters: Vec<(String, Arc<dyn MarkdownConverter + Send + Sync>)>,
+    config_path: Option<PathBuf>,
+    config: Swap<Config>,
+    events: Events,
+    page_cache: PageCache,
+    conversions: Limit,
+    update_streams: Limit,
+    debounce: Duration,
+    push_interval: Duration,
+    convert_timeout: Duration,
+    sanitize_html: bool,
+    trust_html: bool,
+    sortable_tables: Option<usize>,
+    allow_remote: bool,
+    debug_errors: bool,
+    lang: Lang,
+    title: Option<String>,
+    code_theme: CodeTheme,
+    post_processors: Vec<Arc<dyn PostProcessor>>,
+    pre_processors: Vec<Arc<dyn PreProcessor>>,
+    nav_file: Option<PathBuf>,
+    defines: HashMap<String, String>,
+    treat_as_md: Vec<Pattern>,
+    root: Option<PathBuf>,
+    index: Option<String>,
+    image_proxy: Option<ImageProxy>,
+    webhook: Option<Webhook>,
+    access_log: Option<AccessLog>,
+    pins: Option<Pins>,
+    editing: bool,
+    host: Option<String>,
+    prefs: Mutex<Prefs>,
+    scrolls: Scrolls,
+    update_tasks: UpdateTasks,
+    histories: Mutex<HashMap<String, (Instant, Vec<Commit>)>>,
+    repo_status: Mutex<Option<(Instant, Option<RepoStatus>)>>,
+}
+
+impl State {
+    pub fn new(
+        markdown_converter: impl MarkdownConverter + Send + Sync + 'static,
+        content_finder: impl ContentFinder + Send + Sync + 'static,
+    ) -> State {
         State {
-            markdown_converter,
-            content_finder,
-        }
-    }
-}
-
-/// The basic HTML of our page, the `<head>` and CSS and `<body>`.
-/// Also includes the script to subscribe to the Server Sent Events for the page
-/// and update the page if the file changes.
-fn base_html(title: &str, content: &str) -> String {
-    format!(
-        "{}",
-        html! {
-            : doctype::HTML;
-            html {
-                head {
-                    link(rel="stylesheet", href="/static/octicons/octicons.css");
-                    link(rel="stylesheet", href="https://github.githubassets.com/assets/frameworks-146fab5ea30e8afac08dd11013bb4ee0.css");
-                    link(rel="stylesheet", href="https://github.githubassets.com/assets/site-897ad5fdbe32a5cd67af5d1bdc68a292.css");
-                    link(rel="stylesheet", href="https://github.githubassets.com/assets/github-c21b6bf71617eeeb67a56b0d48b5bb5c.css");
-                    link(rel="stylesheet", href="/static/style.css");
-                    title : title;
-                    script {
-                        : Raw("let hash = '';
-                           let event = new EventSource(`//${location.host}/__rs-readme${location.pathname}`);
-                           event.addEventListener('update', (e) => {
-                              let message = JSON.parse(e.data);
-                              if (message.hash !== hash) {
-                                  hash = message.hash;
-                                  document.getElementById('rs-readme-content').innerHTML = message.contents;
-                              }
-                           });")
-                    }
+            markdown_converter: Arc::new(markdown_converter),
+            content_finder: Arc::new(content_finder),
+            offline_converter: OfflineConverter::default(),
+            github_converter: Swap::new(None),
+            github_context: None,
+            converters: Vec::new(),
+            config_path: None,
+            config: Swap::new(Config::default()),
+            events: Events::disabled(),
+            page_cache: PageCache::new(DEFAULT_CAPACITY),
+            conversions: Limit::new(DEFAULT_MAX_CONVERSIONS),
+            update_streams: Limit::new(DEFAULT_MAX_UPDATE_STREAMS),
+            debounce: DEFAULT_DEBOUNCE,
+            push_interval: DEFAULT_PUSH_INTERVAL,
+            convert_timeout: DEFAULT_CONVERT_TIMEOUT,
+            sanitize_html: false,
+            trust_html: false,
+            sortable_tables: None,
+            allow_remote: false,
+            debug_errors: false,
+            lang: Lang::English,
+            title: None,
+            code_theme: CodeTheme::default(),
+            post_processors: vec![Arc::new(Graphviz::default())],
+            pre_processors: vec![Arc::new(Includes)],
+            nav_file: None,
+            defines: HashMap::new(),
+            treat_as_md: Vec::new(),
+            root: None,
+            index: None,
+            image_proxy: None,
+            webhook: None,
+            access_log: None,
+            pins: None,
+            editing: false,
+            host: None,
+            prefs: Mutex::new(Prefs::default()),
+            scrolls: Scrolls::default(),
+            update_tasks: UpdateTasks::default(),
+            histories: Mutex::new(HashMap::new()),
+            repo_status: Mutex::new(None),
+        }
+    }
+
+    /// Reports what the server is doing to `events`, see `--events-json`.
+    pub fn with_events(mut self, events: Events) -> State {
+        self.events = events;
+        self
+    }
+
+    /// Where the server reports what it's doing.
+    pub fn events(&self) -> &Events {
+        &self.events
+    }
+
+    /// Keeps up to `capacity` rendered pages in memory, `0` turns the cache off.
+    pub fn with_cache_size(mut self, capacity: usize) -> State {
+        self.page_cache = PageCache::new(capacity);
+        self
+    }
+
+    /// The page cache's hit and miss counts.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.page_cache.stats()
+    }
+
+    /// What the server found to serve, for saying so when it starts.
+    pub async fn summary(&self) -> Summary {
+        let root = self
+            .root
+            .as_ref()
+            .map(|root| root.canonicalize().unwrap_or_else(|_| root.clone()));
+        let documents = match root.clone() {
+            Some(root) => {
+                Some(async_std::task::spawn_blocking(move || markdown_files(&root).len()).await)
+            }
+            None => None,
+        };
+        let index = self.index().await;
+        // Documents given as the index are there once they're piped in or fetched
+        let index_missing = self.index.is_none()
+            && matches!(self.read(&index).await, Err(ContentError::CouldNotFetch(_)));
+        let config = self.config.load();
+
+        Summary {
+            root,
+            documents,
+            index,
+            index_missing,
+            converter: config
+                .converter
+                .clone()
+                .unwrap_or_else(|| self.markdown_converter.name().to_string()),
+        }
+    }
+
+    /// The last whole page rendered for the document at `path` that's still cached, for showing
+    /// something when it can't be rendered now.
+    pub(crate) fn last_rendered(&self, path: &str) -> Option<RenderedPage> {
+        self.page_cache.last_for(path, &self.page_options())
+    }
+
+    /// Runs at most `max` conversions at once, the rest wait their turn. `0` means no limit.
+    pub fn with_max_conversions(mut self, max: usize) -> State {
+        self.conversions = Limit::new(max);
+        self
+    }
+
+    /// Keeps at most `max` live-reload connections open, more are turned away with a `503`.
+    /// `0` means no limit.
+    pub fn with_max_update_streams(mut self, max: usize) -> State {
+        self.update_streams = Limit::new(max);
+        self
+    }
+
+    /// Waits for a changed document to stay the same for `debounce` before sending it to
+    /// live-reloading pages, so a burst of saves is only converted once.
+    pub fn with_debounce(mut self, debounce: Duration) -> State {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Sends each live-reloading page at most one update every `push_interval`, just the newest
+    /// when more came meanwhile, so a document rewritten continuously doesn't swamp slow
+    /// browsers. `Duration::ZERO` sends every one.
+    pub fn with_min_push_interval(mut self, push_interval: Duration) -> State {
+        self.push_interval = push_interval;
+        self
+    }
+
+    /// Gives up on conversions that take longer than `convert_timeout`, so a hung converter
+    /// shows an error instead of stalling the page. `Duration::ZERO` waits forever.
+    pub fn with_convert_timeout(mut self, convert_timeout: Duration) -> State {
+        self.convert_timeout = convert_timeout;
+        self
+    }
+
+    /// How long changes are debounced for, see [`State::with_debounce`].
+    pub(crate) fn debounce(&self) -> Duration {
+        self.debounce
+    }
+
+    /// How often pages get updates at most, see [`State::with_min_push_interval`].
+    pub(crate) fn min_push_interval(&self) -> Duration {
+        self.push_interval
+    }
+
+    /// Strips scripts and other active content from `.html` files before serving them.
+    pub fn with_sanitized_html(mut self, sanitize_html: bool) -> State {
+        self.sanitize_html = sanitize_html;
+        self
+    }
+
+    /// Serves `.html` files without the sandbox that otherwise keeps their scripts from running
+    /// and from using rs-readme's routes as the reader, for generated pages that are trusted
+    /// and need their scripts, see `--trust-html`.
+    pub fn with_trusted_html(mut self, trust_html: bool) -> State {
+        self.trust_html = trust_html;
+        self
+    }
+
+    /// Makes tables in documents sort when a header is clicked, and gives the ones with more than
+    /// `filter_over` rows a box to filter them with.
+    pub fn with_sortable_tables(mut self, filter_over: usize) -> State {
+        self.sortable_tables = Some(filter_over);
+        self
+    }
+
+    /// Answers requests from other machines too, not only from this one.
+    pub fn with_remote_access(mut self, allow_remote: bool) -> State {
+        self.allow_remote = allow_remote;
+        self
+    }
+
+    /// Shows everything known about an error on its page, see
+    /// [`handlers::debug_error_response`], instead of a short message.
+    pub fn with_debug_errors(mut self, debug_errors: bool) -> State {
+        self.debug_errors = debug_errors;
+        self
+    }
+
+    /// Whether error pages are expanded, see [`State::with_debug_errors`].
+    pub(crate) fn debug_errors(&self) -> bool {
+        self.debug_errors
+    }
+
+    /// Puts `title` before every page's title in the browser tab, like `myproject –
+    /// Installation`, so tabs from different projects can be told apart.
+    pub fn with_title(mut self, title: impl Into<String>) -> State {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Colors highlighted code with `code_theme`, in its light or dark variant as the page is.
+    pub fn with_code_theme(mut self, code_theme: CodeTheme) -> State {
+        self.code_theme = code_theme;
+        self
+    }
+
+    /// The colors of highlighted code, see [`State::with_code_theme`].
+    pub(crate) fn code_theme(&self) -> CodeTheme {
+        self.code_theme
+    }
+
+    /// How whole pages are rendered.
+    fn page_options(&self) -> RenderOptions {
+        RenderOptions {
+            code_theme: self.code_theme,
+            ..RenderOptions::default()
+        }
+    }
+
+    /// Shows rs-readme's own pages, like its error pages, in `lang` instead of English.
+    pub fn with_lang(mut self, lang: Lang) -> State {
+        self.lang = lang;
+        self
+    }
+
+    /// The language of rs-readme's own pages, see [`State::with_lang`].
+    pub(crate) fn lang(&self) -> Lang {
+        self.lang
+    }
+
+    /// Whether to answer a request from `peer`. Only loopback peers are answered unless remote
+    /// access is allowed, see [`State::with_remote_access`]. Requests from an unknown peer, like
+    /// over a Unix socket, are local.
+    pub(crate) fn allows_peer(&self, peer: Option<IpAddr>) -> bool {
+        self.allow_remote || peer.is_none_or(|peer| peer.to_canonical().is_loopback())
+    }
+
+    /// The hashes the GitHub stylesheets have to match, from the config file's
+    /// `stylesheet_integrity`.
+    pub(crate) fn stylesheet_integrity(&self) -> BTreeMap<String, String> {
+        self.config.load().stylesheet_integrity.clone()
+    }
+
+    /// Whether `.html` files are sanitized, see [`State::with_sanitized_html`].
+    pub(crate) fn sanitizes_html(&self) -> bool {
+        self.sanitize_html
+    }
+
+    /// Whether `.html` files are served unsandboxed, see [`State::with_trusted_html`].
+    pub(crate) fn trusts_html(&self) -> bool {
+        self.trust_html
+    }
+
+    /// Points remote images in documents at `proxy`, which keeps copies of them for when the
+    /// network isn't there.
+    pub fn with_image_proxy(mut self, proxy: ImageProxy) -> State {
+        self.image_proxy = Some(proxy);
+        self
+    }
+
+    /// The image proxy, if there is one, see [`State::with_image_proxy`].
+    pub(crate) fn image_proxy(&self) -> Option<&ImageProxy> {
+        self.image_proxy.as_ref()
+    }
+
+    /// Tells `webhook` whenever a document changes, see [`Webhook`].
+    pub fn with_webhook(mut self, webhook: Webhook) -> State {
+        self.webhook = Some(webhook);
+        self
+    }
+
+    /// Writes every answered request to `access_log`, see [`AccessLog`].
+    pub fn with_access_log(mut self, access_log: AccessLog) -> State {
+        self.access_log = Some(access_log);
+        self
+    }
+
+    /// Where requests are logged, see [`State::with_access_log`].
+    pub(crate) fn access_log(&self) -> Option<&AccessLog> {
+        self.access_log.as_ref()
+    }
+
+    /// Lets documents be pinned from their page, keeping the pins in `pins`. Without them there's
+    /// no star in the page header.
+    pub fn with_pins(mut self, pins: Pins) -> State {
+        self.pins = Some(pins);
+        self
+    }
+
+    /// Lets the documents under the root folder be edited from the browser at `/edit/<path>`,
+    /// see `--edit`. It needs [`State::with_root`], there's nowhere to save them without it.
+    pub fn with_editing(mut self, editing: bool) -> State {
+        self.editing = editing;
+        self
+    }
+
+    /// Whether documents can be edited, see [`State::with_editing`].
+    pub(crate) fn edits(&self) -> bool {
+        self.editing && self.root.is_some()
+    }
+
+    /// The name the server is reached at, like `docs.internal`. Requests that change documents
+    /// are only answered when they're sent to it, `localhost` or an IP address.
+    pub fn with_host(mut self, host: impl Into<String>) -> State {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// The name given to [`State::with_host`].
+    pub(crate) fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    /// Shows pages with `prefs` until readers pick their own, they can be changed later by
+    /// `PUT`ting to `/__rs-readme/prefs`.
+    pub fn with_prefs(self, prefs: Prefs) -> State {
+        *self.lock_prefs() = prefs;
+        self
+    }
+
+    /// How pages are displayed unless readers changed it, see [`State::with_prefs`].
+    pub(crate) fn prefs(&self) -> Prefs {
+        self.lock_prefs().clone()
+    }
+
+    /// Changes the preferences in the JSON object `changes`, returning all of them.
+    pub(crate) fn change_prefs(&self, changes: &str) -> Result<Prefs, PrefsError> {
+        let mut prefs = self.lock_prefs();
+        *prefs = prefs.changed(changes)?;
+        Ok(prefs.clone())
+    }
+
+    fn lock_prefs(&self) -> MutexGuard<'_, Prefs> {
+        match self.prefs.lock() {
+            Ok(prefs) => prefs,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Adds a stage converted documents go through after the default ones (drawing ` ```dot `
+    /// blocks with GraphViz).
+    pub fn with_post_processor(mut self, post_processor: impl PostProcessor + 'static) -> State {
+        self.post_processors.push(Arc::new(post_processor));
+        self
+    }
+
+    /// Adds a stage documents go through when they're read, after the default ones (following
+    /// `<!-- include: ... -->` directives).
+    pub fn with_pre_processor(mut self, pre_processor: impl PreProcessor + 'static) -> State {
+        self.pre_processors.push(Arc::new(pre_processor));
+        self
+    }
+
+    /// Values for `{{name}}` placeholders in documents, taking precedence over the config file's
+    /// `[variables]`.
+    pub fn with_defines(mut self, defines: HashMap<String, String>) -> State {
+        self.defines = defines;
+        self
+    }
+
+    /// Renders documents matching `patterns` as markdown whatever their extension. The content
+    /// finder has to read them too, see [`crate::FileFinder::with_treat_as_md`].
+    pub fn with_treat_as_md(mut self, patterns: Vec<Pattern>) -> State {
+        self.treat_as_md = patterns;
+        self
+    }
+
+    /// The format `path` is rendered as, markdown if it's one of [`State::with_treat_as_md`]'s.
+    fn format_of(&self, path: &str) -> Option<DocumentFormat> {
+        if matches_any(&self.treat_as_md, path) {
+            Some(DocumentFormat::Markdown)
+        } else {
+            DocumentFormat::of(Path::new(path))
+        }
+    }
+
+    /// The placeholder values documents are read with, from the config file and
+    /// [`State::with_defines`].
+    fn variables(&self) -> Variables {
+        let mut values = self.config.load().variables.clone();
+        values.extend(self.defines.clone());
+
+        Variables::new(values)
+    }
+
+    /// The folder the content finder reads from, for what looks at how the folder is laid out,
+    /// like switching between translations in `docs/en/` and `docs/ja/`.
+    pub fn with_root(mut self, root: PathBuf) -> State {
+        self.root = Some(root);
+        self
+    }
+
+    /// The folder files are served from, the working directory without one.
+    pub(crate) fn files_root(&self) -> PathBuf {
+        self.root.clone().unwrap_or_else(|| PathBuf::from("."))
+    }
+
+    /// The document at `path` in each of the languages it's kept in, see [`Languages`]. The
+    /// config file's `languages` names the language folders when they aren't language codes.
+    pub(crate) async fn translations(&self, path: &str) -> Vec<Translation> {
+        let root = match &self.root {
+            Some(root) => root.clone(),
+            None => return Vec::new(),
+        };
+        let config = self.config.load();
+        let path = path.to_string();
+
+        async_std::task::spawn_blocking(move || {
+            Languages::new(&root, config.languages.as_deref()).translations(&path)
+        })
+        .await
+    }
+
+    /// The latest commits touching the document at `path`, see [`history`]. Empty without a
+    /// root folder or outside a git repository.
+    ///
+    /// They're remembered for a few seconds, pages are rendered far more often than commits are
+    /// made.
+    pub(crate) async fn history(&self, path: &str) -> Vec<Commit> {
+        let root = match &self.root {
+            Some(root) => root.clone(),
+            None => return Vec::new(),
+        };
+        if let Some((at, commits)) = self.histories().get(path) {
+            if at.elapsed() < HISTORY_TTL {
+                return commits.clone();
+            }
+        }
+
+        let owned_path = path.to_string();
+        let commits =
+            async_std::task::spawn_blocking(move || history(&root, &owned_path, HISTORY_LENGTH))
+                .await;
+        self.histories()
+            .insert(path.to_string(), (Instant::now(), commits.clone()));
+
+        commits
+    }
+
+    fn histories(&self) -> MutexGuard<'_, HashMap<String, (Instant, Vec<Commit>)>> {
+        match self.histories.lock() {
+            Ok(histories) => histories,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// The markdown files in the root folder as a tree, see [`file_tree`]. Empty without a root
+    /// folder.
+    pub(crate) async fn file_tree(&self) -> Vec<TreeNode> {
+        match self.root.clone() {
+            Some(root) => async_std::task::spawn_blocking(move || file_tree(&root)).await,
+            None => Vec::new(),
+        }
+    }
+
+    /// The most recently modified markdown files in the root folder, see [`recent_files`].
+    /// Empty without a root folder.
+    pub(crate) async fn recent_files(&self) -> Vec<RecentFile> {
+        match self.root.clone() {
+            Some(root) => {
+                async_std::task::spawn_blocking(move || recent_files(&root, RECENT_LIMIT)).await
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// The branch, `HEAD` and dirty state of the checkout being previewed, for the page footer,
+    /// see [`repo_status`]. `None` without a root folder or outside a git repository.
+    ///
+    /// It's remembered for a few seconds, or until a document changes, see
+    /// [`State::document_changed`].
+    pub(crate) async fn repo_status(&self) -> Option<RepoStatus> {
+        let root = self.root.clone()?;
+        if let Some((at, status)) = &*self.cached_repo_status() {
+            if at.elapsed() < REPO_STATUS_TTL {
+                return status.clone();
+            }
+        }
+
+        let status = async_std::task::spawn_blocking(move || repo_status(&root)).await;
+        *self.cached_repo_status() = Some((Instant::now(), status.clone()));
+
+        status
+    }
+
+    /// Looks the checkout's status up again on the next page, as it's likely dirty now, and
+    /// tells the webhook, when the document at `path` changed to `hash`.
+    pub(crate) fn document_changed(&self, path: &str, hash: ContentHash) {
+        *self.cached_repo_status() = None;
+        if let Some(webhook) = &self.webhook {
+            webhook.notify(path, &hash.to_string());
+        }
+    }
+
+    /// Pins the document at `path` or unpins it, returning the pinned documents. `None` when pins
+    /// aren't kept, see [`State::with_pins`].
+    pub(crate) async fn set_pinned(
+        &self,
+        path: &str,
+        pinned: bool,
+    ) -> Option<std::io::Result<Vec<String>>> {
+        let pins = self.pins.clone()?;
+        let path = path.to_string();
+
+        Some(
+            async_std::task::spawn_blocking(move || {
+                if pinned {
+                    pins.pin(&path)
+                } else {
+                    pins.unpin(&path)
                 }
-                body : Raw(content);
+            })
+            .await,
+        )
+    }
+
+    /// Saves `contents` over the document at `path` if it's still the version `base`, see
+    /// [`save_document`]. `None` when documents can't be edited.
+    ///
+    /// The watcher picks the change up like any other, so pages showing the document update.
+    pub(crate) async fn save(
+        &self,
+        path: &str,
+        base: &str,
+        contents: String,
+    ) -> Option<Result<Saved, Error>> {
+        let root = self.root.clone().filter(|_| self.editing)?;
+        if self.format_of(path).is_none() {
+            return Some(Err(Error::NotMarkdown));
+        }
+        let path = path.to_string();
+        let base = base.to_string();
+
+        Some(
+            async_std::task::spawn_blocking(move || save_document(&root, &path, &base, &contents))
+                .await,
+        )
+    }
+
+    /// `contents` converted as the document at `path` would be, without the page around it, for
+    /// the editor's preview.
+    pub(crate) async fn preview(&self, path: &str, contents: String) -> Result<String, Error> {
+        let pre_processors = self.pre_processors();
+        let finder = self.content_finder.clone();
+        let found_path = path.to_string();
+        let (contents, hash) = async_std::task::spawn_blocking(move || {
+            let hash = ContentHash::of(contents.as_bytes());
+            pre_process(&found_path, (contents, hash), &pre_processors, &*finder)
+        })
+        .await;
+        let options = RenderOptions {
+            full_page: false,
+            ..self.page_options()
+        };
+        let page = self
+            .render_cached(None, path, &contents, hash, &options)
+            .await?;
+
+        Ok(page.content)
+    }
+
+    fn cached_repo_status(&self) -> MutexGuard<'_, Option<(Instant, Option<RepoStatus>)>> {
+        match self.repo_status.lock() {
+            Ok(status) => status,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// When the document at `path` was last changed, see [`last_edit`].
+    pub(crate) async fn last_edit(&self, path: &str, history: &[Commit]) -> Option<LastEdit> {
+        let root = self.root.clone()?;
+        let path = path.to_string();
+        let history = history.to_vec();
+
+        async_std::task::spawn_blocking(move || last_edit(&root, &path, &history)).await
+    }
+
+    /// Orders the sidebar by the [`NAV_FILE`] at `path`, when it exists, instead of a
+    /// `SUMMARY.md`. It's read again for every page so changes show up straight away.
+    pub fn with_nav_file(mut self, path: PathBuf) -> State {
+        self.nav_file = Some(path);
+        self
+    }
+
+    /// Asks the open pages showing the document at `path` to scroll to `line` of its source,
+    /// returning how many there are.
+    pub(crate) fn scroll_to(&self, path: &str, line: usize) -> usize {
+        self.scrolls.scroll(path, line)
+    }
+
+    /// The lines the pages showing the document at `path` are asked to scroll to.
+    pub(crate) fn scroll_requests(&self, path: &str) -> impl Stream<Item = usize> {
+        self.scrolls.subscribe(path)
+    }
+
+    /// Marks top-level blocks of documents converted offline with the line of the source they
+    /// start on, so pages can be scrolled to a line, see [`crate::SOURCE_LINE_ATTRIBUTE`].
+    /// The converter given to [`State::new`] is left as it is.
+    pub fn with_source_lines(mut self, source_lines: bool) -> State {
+        self.offline_converter = OfflineConverter::new().with_source_lines(source_lines);
+        self
+    }
+
+    /// A place for one more live-reload connection, held until the permit is dropped.
+    pub(crate) fn open_update_stream(&self) -> Result<Permit, Error> {
+        self.update_streams.try_acquire()
+    }
+
+    /// Shows the document at `path` at the root of the site, instead of looking for one, see
+    /// [`State::index`].
+    pub fn with_index(mut self, path: impl Into<String>) -> State {
+        self.index = Some(path.into());
+        self
+    }
+
+    /// The document the root of the site shows: the one given to [`State::with_index`], the
+    /// first chapter of a `SUMMARY.md`, otherwise `README.md`, or `README.rst` when there's only
+    /// that.
+    pub(crate) async fn index(&self) -> String {
+        if let Some(index) = &self.index {
+            return index.clone();
+        }
+        if let Some(first) = self
+            .navigation()
+            .await
+            .and_then(|(navigation, _)| navigation.first_page().map(str::to_string))
+        {
+            return first;
+        }
+
+        match self.read("README.md").await {
+            Err(ContentError::CouldNotFetch(_)) if self.read("README.rst").await.is_ok() => {
+                "README.rst".to_string()
             }
+            _ => "README.md".to_string(),
         }
-    )
-}
+    }
+
+    /// The sidebar from the [`NAV_FILE`], or else the root's `SUMMARY.md`, and the hash of the
+    /// file it came from, if there is one that links to something.
+    pub(crate) async fn navigation(&self) -> Option<(Navigation, ContentHash)> {
+        let (navigation, hash) = match self.nav_file_navigation().await {
+            Some(nav_file) => nav_file,
+            None => {
+                let (summary, hash) = self.read(SUMMARY_FILE).await.ok()?;
+                (Navigation::from_summary(&summary), hash)
+            }
+        };
+
+        navigation
+            .first_page()
+            .is_some()
+            .then_some((navigation, hash))
+    }
+
+    async fn nav_file_navigation(&self) -> Option<(Navigation, ContentHash)> {
+        let path = self.nav_file.as_ref()?;
+        let toml = async_std::fs::read_to_string(path).await.ok()?;
+
+        match Navigation::from_nav_file(&toml) {
+            Ok(navigation) => Some((navigation, ContentHash::of(toml.as_bytes()))),
+            Err(err) => {
+                warn!("Ignoring {}: {}", NAV_FILE, err);
+                None
+            }
+        }
+    }
+
+    /// Reads `path` with the content finder on the blocking task pool, so a slow disk or
+    /// network mount doesn't hold up everything else the executor is serving.
+    ///
+    /// The source has been through the pre-processors, see [`State::with_pre_processor`], then
+    /// had its `{{name}}` placeholders filled in.
+    pub(crate) async fn read(&self, path: &str) -> Result<(String, ContentHash), ContentError> {
+        self.read_from(self.content_finder.clone(), path, false)
+            .await
+    }
+
+    /// [`State::read`] as of the git `reference`, read from the repository at the root, or from
+    /// the working tree when there's no reference.
+    pub(crate) async fn read_at(
+        &self,
+        reference: Option<&str>,
+        path: &str,
+    ) -> Result<(String, ContentHash), ContentError> {
+        let finder = self.finder_at(reference, path)?;
+        self.read_from(finder, path, false).await
+    }
+
+    /// [`State::read_at`] for any text file, whatever its extension, for rendering it as
+    /// markdown when a request insists with `?force=md`.
+    pub(crate) async fn read_forced(
+        &self,
+        reference: Option<&str>,
+        path: &str,
+    ) -> Result<(String, ContentHash), ContentError> {
+        let finder = self.finder_at(reference, path)?;
+        self.read_from(finder, path, true).await
+    }
+
+    /// The document at `path` as it is, without the pre-processors or placeholders of
+    /// [`State::read_at`], for clients asking for the source.
+    #[cfg_attr(feature = "otel", tracing::instrument(name = "read_source", skip_all, fields(path = %path)))]
+    pub(crate) async fn source_at(
+        &self,
+        reference: Option<&str>,
+        path: &str,
+    ) -> Result<(String, ContentHash), ContentError> {
+        let finder = self.finder_at(reference, path)?;
+        let path = path.to_string();
+        async_std::task::spawn_blocking(move || finder.content_for(&path)).await
+    }
 
-/// The wrapping necessary to make the rendered markdown file to look right
-fn markdown_html(file_name: &str, md_content: &str) -> String {
-    format!(
-        "{}",
-        html! {
-            div(class="page") {
-                div(id="preview-page", class="preview-page") {
-                    div(role="main", class="main-content") {
-                        div(class="container new-discussion-timeline experiment-repo-nav") {
-                            div(class="repository-content") {
-                                div(id="readme", class="readme boxed-group clearfix announce instapaper_body md") {
-                                    h3 {
-                                        span(class="octicon octicon-book");
-                                        : format!(" {}",file_name);
-                                    }
-                                    article(id="rs-readme-content", class="markdown-body entry-content", itemprop="text") {
-                                        : Raw(md_content);
-                                    }
-                                }
-                            }
-                        }
-                    }
+    /// The legacy encoding the document at `path` was converted from, see
+    /// [`ContentFinder::encoding_of`].
+    pub(crate) fn encoding_of(&self, path: &str) -> Option<&'static str> {
+        self.content_finder.encoding_of(path)
+    }
+
+    /// Any text file at `path` as it is, as of the git `reference`, for showing it as source
+    /// code.
+    pub(crate) async fn code_at(
+        &self,
+        reference: Option<&str>,
+        path: &str,
+    ) -> Result<(String, ContentHash), ContentError> {
+        let finder = self.finder_at(reference, path)?;
+        let path = path.to_string();
+        async_std::task::spawn_blocking(move || finder.source_for(&path)).await
+    }
+
+    /// The content finder for the git `reference`, or for the working tree without one.
+    fn finder_at(
+        &self,
+        reference: Option<&str>,
+        path: &str,
+    ) -> Result<Arc<dyn ContentFinder + Send + Sync>, ContentError> {
+        match (reference, &self.root) {
+            (None, _) => Ok(self.content_finder.clone()),
+            (Some(reference), Some(root)) => {
+                Ok(Arc::new(GitContentFinder::new(root.clone(), reference)))
+            }
+            (Some(_), None) => Err(ContentError::CouldNotFetch(path.to_string())),
+        }
+    }
+
+    #[cfg_attr(feature = "otel", tracing::instrument(name = "read", skip_all, fields(path = %path)))]
+    async fn read_from(
+        &self,
+        finder: Arc<dyn ContentFinder + Send + Sync>,
+        path: &str,
+        any_text: bool,
+    ) -> Result<(String, ContentHash), ContentError> {
+        let pre_processors = self.pre_processors();
+        let path = path.to_string();
+        async_std::task::spawn_blocking(move || {
+            let found = if any_text {
+                finder.source_for(&path)?
+            } else {
+                finder.content_for(&path)?
+            };
+            Ok(pre_process(&path, found, &pre_processors, &*finder))
+        })
+        .await
+    }
+
+    /// The pre-processors documents go through, the `{{name}}` placeholders last.
+    fn pre_processors(&self) -> Vec<Arc<dyn PreProcessor>> {
+        let mut pre_processors = self.pre_processors.clone();
+        pre_processors.push(Arc::new(self.variables()));
+        pre_processors
+    }
+
+    /// Makes `converter` available to requests asking for `?converter=github`. Its context is
+    /// used when the config file doesn't set one.
+    pub fn with_github_converter(mut self, converter: Converter) -> State {
+        self.github_context = converter.context().map(str::to_string);
+        self.github_converter.store(Some(converter));
+        self
+    }
+
+    /// Makes `converter` available to requests asking for `?converter=<name>`, and to the config
+    /// file's `converter`.
+    pub fn with_converter(
+        mut self,
+        name: &str,
+        converter: impl MarkdownConverter + Send + Sync + 'static,
+    ) -> State {
+        self.converters
+            .push((name.to_string(), Arc::new(converter)));
+        self
+    }
+
+    /// Adds the converter and code block stage `plugin` has, see [`Plugin`].
+    #[cfg(feature = "plugins")]
+    pub fn with_plugin(self, plugin: Plugin) -> State {
+        let state = if plugin.languages().is_empty() {
+            self
+        } else {
+            self.with_post_processor(plugin.clone())
+        };
+        if plugin.converts() {
+            let name = plugin.name().to_string();
+            state.with_converter(&name, plugin)
+        } else {
+            state
+        }
+    }
+
+    /// The converter [`State::with_converter`] added as `name`.
+    fn named_converter(&self, name: &str) -> Option<&(dyn MarkdownConverter + Send + Sync)> {
+        self.converters
+            .iter()
+            .find(|(converter, _)| converter == name)
+            .map(|(_, converter)| &**converter)
+    }
+
+    /// Loads the config file at `path`, remembering it for [`State::reload_config`].
+    pub fn with_config_file(mut self, path: PathBuf) -> Result<State, ConfigError> {
+        self.config_path = Some(path);
+        self.reload_config()?;
+        Ok(self)
+    }
+
+    /// Re-reads the config file and swaps in the new settings.
+    ///
+    /// Requests already in flight finish with the settings they started with.
+    pub fn reload_config(&self) -> Result<(), ConfigError> {
+        let path = self.config_path.as_ref().ok_or(ConfigError::NoConfigFile)?;
+        let config = Config::load(path)?;
+
+        if let Some(github) = self.github_converter.load().as_ref() {
+            let context = config
+                .context
+                .clone()
+                .or_else(|| self.github_context.clone());
+            self.github_converter
+                .store(Some(github.with_context(context)));
+        }
+        self.config.store(config);
+        self.page_cache.clear();
+
+        Ok(())
+    }
+
+    /// Converts `md` with the converter named by a request's `converter` query parameter,
+    /// then the config file's `converter`, falling back to the configured one when neither
+    /// is set or available.
+    ///
+    /// Waits while the maximum number of conversions are already running.
+    async fn convert(&self, requested: Option<&str>, md: &str) -> Result<String, MarkdownError> {
+        let _permit = self.conversions.acquire().await;
+        let config = self.config.load();
+        let github = self.github_converter.load();
+
+        let requested = requested.or_else(|| config.converter.as_deref());
+        let named = requested.and_then(|name| self.named_converter(name));
+
+        let (kind, converter): (&str, &dyn MarkdownConverter) =
+            match (requested, github.as_ref(), named) {
+                (Some("offline"), _, _) => ("offline", &self.offline_converter),
+                (Some("github"), Some(github), _) => ("github", github),
+                (Some(name), _, Some(named)) => (name, named),
+                (Some(other), _, None) => {
+                    warn!("Converter {} is not available, using the default", other);
+                    ("default", &*self.markdown_converter)
+                }
+                (None, _, _) => ("default", &*self.markdown_converter),
+            };
+        debug!("Converting {} bytes with the {} converter", md.len(), kind);
+
+        let converted = self.within_timeout(kind, converter.convert_markdown(md));
+        #[cfg(feature = "otel")]
+        let converted = tracing::Instrument::instrument(
+            converted,
+            tracing::info_span!("convert", converter = kind, bytes = md.len()),
+        );
+        converted.await
+    }
+
+    /// Waits for the `kind` converter's `conversion` for up to the convert timeout, see
+    /// [`State::with_convert_timeout`].
+    async fn within_timeout(
+        &self,
+        kind: &str,
+        conversion: impl Future<Output = Result<String, MarkdownError>>,
+    ) -> Result<String, MarkdownError> {
+        if self.convert_timeout.is_zero() {
+            return conversion.await;
+        }
+
+        async_std::future::timeout(self.convert_timeout, conversion)
+            .await
+            .unwrap_or_else(|_| {
+                warn!("The {} converter timed out", kind);
+                Err(MarkdownError::ConverterUnavailable(format!(
+                    "The {} converter didn't finish within {:?}, see --convert-timeout",
+                    kind, self.convert_timeout
+                )))
+            })
+    }
+
+    /// Renders the page for `path` like [`crate::render_document`], reporting it as an event.
+    ///
+    /// Big documents are streamed instead, when the converter can do that. With
+    /// `force_markdown` any text file is rendered as markdown, see [`State::read_forced`].
+    ///
+    /// Comes with a hash of everything the page shows, not just the document, for its `ETag`:
+    /// the page changes when it's pinned, the checkout gets dirty or the converter is switched
+    /// too.
+    pub(crate) async fn render(
+        &self,
+        requested: Option<&str>,
+        reference: Option<&str>,
+        path: &str,
+        force_markdown: bool,
+    ) -> Result<(ContentHash, ResponseBody), Error> {
+        let markdown = self.format_of(path) == Some(DocumentFormat::Markdown);
+        let (contents, hash) = if force_markdown && !markdown {
+            self.read_forced(reference, path).await?
+        } else {
+            self.read_at(reference, path).await?
+        };
+
+        let (version, body) = match self.streaming_converter(requested) {
+            Some(streaming)
+                if markdown
+                    && contents.len() >= STREAM_THRESHOLD
+                    && Frontmatter::split(&contents).is_none() =>
+            {
+                let navigation = self.navigation().await.map(|(navigation, _)| navigation);
+                let translations = self.translations(path).await;
+                let history = self.history(path).await;
+                let last_edit = self.last_edit(path, &history).await;
+                let repo = self.repo_status().await;
+                let pins = self.pins.as_ref().map(Pins::pinned);
+                let encoding = self.content_finder.encoding_of(path);
+                let config = self.config.load();
+                let warnings = document_warnings(path, &contents, self.content_finder.as_ref());
+                let surroundings = Surroundings {
+                    navigation: navigation.as_ref(),
+                    translations: &translations,
+                    history: &history,
+                    last_edit: last_edit.as_ref(),
+                    repo: repo.as_ref(),
+                    pins: pins.as_deref(),
+                    index: pins.is_some() && path == self.index().await,
+                    encoding,
+                    integrity: Some(&config.stylesheet_integrity),
+                    warnings: &warnings,
+                    site_title: self.title.as_deref(),
+                };
+                let title = page_title(path, &contents, true);
+                let tasks = task_progress_html(&contents);
+                let direction = text_direction(&contents);
+                let version = ContentHash::of(
+                    format!(
+                        "{:?}",
+                        (
+                            hash,
+                            requested.or(config.converter.as_deref()),
+                            &surroundings,
+                            &title,
+                            self.code_theme,
+                        )
+                    )
+                    .as_bytes(),
+                );
+                let permit = self.conversions.acquire().await;
+                let html = hold(streaming.convert_markdown_stream(contents), permit);
+                let body = ResponseBody::Stream(stream_page(
+                    path,
+                    &title,
+                    &tasks,
+                    &direction,
+                    self.code_theme,
+                    &surroundings,
+                    html,
+                ));
+                (version, body)
+            }
+            _ => {
+                let options = RenderOptions {
+                    force_markdown: force_markdown && !markdown,
+                    ..self.page_options()
+                };
+                let page = self
+                    .render_cached(requested, path, &contents, hash, &options)
+                    .await?;
+                let version = ContentHash::of(page.html.as_bytes());
+                (version, ResponseBody::Bytes(page.html.into_bytes()))
+            }
+        };
+
+        self.events.emit(Event::FileRendered {
+            path: path.to_string(),
+            hash: hash.to_string(),
+        });
+
+        Ok((version, body))
+    }
+
+    /// The page for the document at `path` with the words that changed between the git refs
+    /// `from` and `to` marked, see [`prose_diff`]. Without `to` it's compared with the working
+    /// tree.
+    pub(crate) async fn render_diff(
+        &self,
+        from: &str,
+        to: Option<&str>,
+        path: &str,
+    ) -> Result<String, Error> {
+        let format = self.format_of(path);
+        if format != Some(DocumentFormat::Markdown) {
+            return Err(Error::NotMarkdown);
+        }
+        let (old, _) = self.read_at(Some(from), path).await?;
+        let (new, _) = self.read_at(to, path).await?;
+
+        let marked = prose_diff(&old, &new);
+        let content = self.convert_file(None, path, format, &marked).await?;
+        let title = format!(
+            "{} ({}..{})",
+            path.rsplit('/').next().unwrap_or(path),
+            from,
+            to.unwrap_or_default()
+        );
+
+        Ok(base_html(
+            &title,
+            &self.config.load().stylesheet_integrity,
+            self.code_theme,
+            &markdown_html(&title, &content),
+        ))
+    }
+
+    /// The streaming side of the converter [`State::convert`] would pick, if it has one.
+    fn streaming_converter(&self, requested: Option<&str>) -> Option<&dyn StreamingConverter> {
+        let config = self.config.load();
+
+        match requested.or(config.converter.as_deref()) {
+            Some("offline") => self.offline_converter.streaming(),
+            Some("github") if self.github_converter.load().is_some() => None,
+            Some(name) => match self.named_converter(name) {
+                Some(named) => named.streaming(),
+                None => self.markdown_converter.streaming(),
+            },
+            None => self.markdown_converter.streaming(),
+        }
+    }
+
+    /// Renders `contents`, read from `path`, unless the same page is already in the cache.
+    pub(crate) async fn render_cached(
+        &self,
+        requested: Option<&str>,
+        path: &str,
+        contents: &str,
+        hash: ContentHash,
+        options: &RenderOptions,
+    ) -> Result<RenderedPage, Error> {
+        let config = self.config.load();
+        let (navigation, translations, history) = if options.full_page {
+            (
+                self.navigation().await,
+                self.translations(path).await,
+                self.history(path).await,
+            )
+        } else {
+            (None, Vec::new(), Vec::new())
+        };
+        let (last_edit, repo) = if options.full_page {
+            (
+                self.last_edit(path, &history).await,
+                self.repo_status().await,
+            )
+        } else {
+            (None, None)
+        };
+        let pins = self
+            .pins
+            .as_ref()
+            .filter(|_| options.full_page)
+            .map(Pins::pinned);
+        let encoding = self
+            .content_finder
+            .encoding_of(path)
+            .filter(|_| options.full_page);
+        let format = self.format_of(path);
+        let forced = options.force_markdown && format != Some(DocumentFormat::Markdown);
+        let markdown = options.force_markdown || format == Some(DocumentFormat::Markdown);
+        let mut warnings = Vec::new();
+        if forced {
+            warnings.push(Warning {
+                kind: WarningKind::NotMarkdown,
+                message: format!(
+                    "{} isn't a markdown file, it's rendered as one because of ?force=md",
+                    path.rsplit('/').next().unwrap_or(path)
+                ),
+            });
+        }
+        if markdown {
+            warnings.extend(markdown_warnings(
+                path,
+                contents,
+                self.content_finder.as_ref(),
+            ));
+        }
+        let key = PageKey {
+            path: path.to_string(),
+            hash,
+            converter: requested
+                .or(config.converter.as_deref())
+                .map(str::to_string),
+            options: options.clone(),
+            navigation: navigation.as_ref().map(|(_, hash)| *hash),
+            translations: translations.clone(),
+            history: history.clone(),
+            last_edit: last_edit.clone(),
+            repo: repo.clone(),
+            pins: pins.clone(),
+            encoding,
+            warnings: warnings.clone(),
+        };
+        if let Some(page) = self.page_cache.get(&key) {
+            return Ok(page);
+        }
+
+        let converter = RequestConverter {
+            state: self,
+            requested,
+            path,
+            format: markdown.then_some(DocumentFormat::Markdown).or(format),
+        };
+        let surroundings = Surroundings {
+            navigation: navigation.as_ref().map(|(navigation, _)| navigation),
+            translations: &translations,
+            history: &history,
+            last_edit: last_edit.as_ref(),
+            repo: repo.as_ref(),
+            pins: pins.as_deref(),
+            index: pins.is_some() && path == self.index().await,
+            encoding,
+            integrity: Some(&config.stylesheet_integrity),
+            warnings: &warnings,
+            site_title: self.title.as_deref(),
+        };
+        // Documents treated as markdown get their frontmatter split off like `.md` files
+        let render_options = RenderOptions {
+            force_markdown: markdown,
+            ..options.clone()
+        };
+        let page = render_source(
+            &converter,
+            path,
+            contents,
+            hash,
+            &render_options,
+            &surroundings,
+        )
+        .await?;
+
+        self.page_cache.insert(key, page.clone());
+
+        Ok(page)
+    }
+
+    /// Renders every markdown file under `root` into the page cache, `parallelism` at a time,
+    /// so the first visit to each page is fast. Returns how many were rendered.
+    ///
+    /// `root` should be the folder the content finder reads from.
+    pub async fn prewarm(&self, root: &Path, parallelism: usize) -> usize {
+        let mut paths: Vec<String> = markdown_files(root)
+            .iter()
+            .map(|file| format!("./{}", file.to_string_lossy()))
+            .collect();
+        // The index page asks for the README without the `./`
+        if paths.iter().any(|path| path == "./README.md") {
+            paths.push("README.md".to_string());
+        }
+
+        let total = paths.len();
+        if total > self.page_cache.stats().capacity {
+            warn!(
+                "Pre-rendering {} pages but the cache only holds {}, see --cache-size",
+                total,
+                self.page_cache.stats().capacity
+            );
+        }
+
+        let mut rendered = 0;
+        let mut done = 0;
+        let mut renders = stream::iter(paths)
+            .map(|path| async move {
+                let result = self.prewarm_page(&path).await;
+                (path, result)
+            })
+            .buffer_unordered(parallelism.max(1));
+
+        while let Some((path, result)) = renders.next().await {
+            done += 1;
+            match result {
+                Ok(()) => {
+                    rendered += 1;
+                    info!("Pre-rendered {} ({}/{})", path, done, total);
                 }
-                div : Raw("&nbsp;");
+                Err(err) => warn!("Could not pre-render {}: {}", path, err),
             }
         }
+
+        rendered
+    }
+
+    async fn prewarm_page(&self, path: &str) -> Result<(), Error> {
+        let (contents, hash) = self.read(path).await?;
+        self.render_cached(None, path, &contents, hash, &RenderOptions::default())
+            .await?;
+        Ok(())
+    }
+
+    /// The converted document at `path`, as it is now and then every time it changes, with the
+    /// default converter.
+    ///
+    /// This is what live-reloading pages are sent, without the HTTP. However many pages show a
+    /// document it's only read and converted once for all of them.
+    pub fn updates(self: &Arc<Self>, path: &str) -> impl Stream<Item = DocumentUpdate> {
+        self.updates_with(path, None, None)
+            .filter_map(|update| future::ready(update.ok()))
+    }
+
+    /// [`State::updates`] with the converter and git ref a request asked for, and the errors of
+    /// the versions that couldn't be converted.
+    pub(crate) fn updates_with(
+        self: &Arc<Self>,
+        path: &str,
+        requested: Option<&str>,
+        reference: Option<&str>,
+    ) -> impl Stream<Item = Result<DocumentUpdate, Error>> + Send + 'static {
+        self.update_tasks.subscribe(
+            self,
+            path.to_string(),
+            requested.map(str::to_string),
+            reference.map(str::to_string),
+        )
+    }
+
+    /// The shared watchers of the documents open in pages, see [`UpdateTasks`].
+    pub(crate) fn update_tasks(&self) -> &UpdateTasks {
+        &self.update_tasks
+    }
+
+    /// Converts reStructuredText with the config's `rst_command`.
+    #[cfg_attr(
+        feature = "otel",
+        tracing::instrument(name = "convert", skip_all, fields(converter = "rst", bytes = rst.len()))
+    )]
+    async fn convert_rst(&self, rst: &str) -> Result<String, MarkdownError> {
+        let _permit = self.conversions.acquire().await;
+        let config = self.config.load();
+        let command = config.rst_command.as_deref().unwrap_or(DEFAULT_RST_COMMAND);
+
+        match ExternalConverter::from_command_line(command) {
+            Some(converter) => {
+                self.within_timeout("rst", converter.convert_markdown(rst))
+                    .await
+            }
+            None => Err(MarkdownError::ConverterUnavailable(
+                "rst_command is empty".to_string(),
+            )),
+        }
+    }
+
+    /// Converts the contents of `path` like [`State::convert`], reporting failures as events.
+    ///
+    /// Documents in the reStructuredText `format` go through the `rst_command` instead,
+    /// `.csv`/`.tsv` files become tables and plain text is shown as it is. The HTML then goes
+    /// through the post-processors, markdown's images that aren't there are swapped for
+    /// placeholders, see [`mark_missing_images`], and tables are made sortable when
+    /// [`State::with_sortable_tables`] asks.
+    pub(crate) async fn convert_file(
+        &self,
+        requested: Option<&str>,
+        path: &str,
+        format: Option<DocumentFormat>,
+        md: &str,
+    ) -> Result<String, MarkdownError> {
+        let converted = match format {
+            Some(DocumentFormat::ReStructuredText) => self.convert_rst(md).await,
+            Some(DocumentFormat::Csv) => render_table(md, b',', MAX_TABLE_ROWS, &raw_href(path)),
+            Some(DocumentFormat::Tsv) => render_table(md, b'\t', MAX_TABLE_ROWS, &raw_href(path)),
+            Some(DocumentFormat::PlainText) => Ok(preformatted(md)),
+            _ => self.convert(requested, md).await,
+        };
+
+        let html = converted.inspect_err(|err| {
+            self.events.emit(Event::ConversionError {
+                path: path.to_string(),
+                message: err.to_string(),
+            });
+        })?;
+
+        let html = post_process(&html, &self.post_processors).await;
+        let html = if format == Some(DocumentFormat::Markdown) && html.contains("<img") {
+            mark_missing_images(&html, path, self.content_finder.as_ref())
+        } else {
+            html
+        };
+        let html = match self.sortable_tables {
+            Some(filter_over) if html.contains("<table") => sortable_tables(&html, filter_over),
+            _ => html,
+        };
+
+        Ok(match &self.image_proxy {
+            Some(proxy) => proxy.rewrite(&html),
+            None => html,
+        })
+    }
+}
+
+/// Converts with whichever converter a request picked, see [`State::convert_file`].
+struct RequestConverter<'a> {
+    state: &'a State,
+    requested: Option<&'a str>,
+    path: &'a str,
+    /// What to convert the document as, see [`State::format_of`].
+    format: Option<DocumentFormat>,
+}
+
+#[async_trait]
+impl MarkdownConverter for RequestConverter<'_> {
+    async fn convert_markdown(&self, md: &str) -> Result<String, MarkdownError> {
+        self.state
+            .convert_file(self.requested, self.path, self.format, md)
+            .await
+    }
+}
+
+/// The value of the `converter` query parameter, if the request has one.
+fn requested_converter<S>(req: &Request<S>) -> Option<String> {
+    query_param(req, "converter")
+}
+
+/// What the `force` query parameter asks to render a document as, `md` for markdown.
+fn requested_force<S>(req: &Request<S>) -> Option<String> {
+    query_param(req, "force")
+}
+
+/// The git ref in the `ref` query parameter, if the request has one.
+fn requested_reference<S>(req: &Request<S>) -> Option<String> {
+    query_param(req, "ref")
+}
+
+fn query_param<S>(req: &Request<S>, name: &str) -> Option<String> {
+    req.url()
+        .query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+/// The `tide::Endpoint` to render the `README.md`, see [`handlers::readme`].
+async fn render_readme(req: Request<Arc<State>>) -> tide::Result {
+    let res = handlers::readme(
+        req.state(),
+        requested_converter(&req).as_deref(),
+        requested_reference(&req).as_deref(),
+        requested_force(&req).as_deref(),
+        if_none_match(&req).as_deref(),
+        accept(&req).as_deref(),
     )
+    .await;
+
+    Ok(res.into())
 }
 
-/// The error HTML indicating the requested file is not markdown
-/// and therefore can't be rendered.
-fn not_markdown_html(title: &str, file: &str) -> String {
-    format!(
-        "{}",
-        html! {
-            : doctype::HTML;
-            html {
-                head {
-                    title : title;
-                }
-                body {
-                    h1 : "Not a Markdown File";
-                    p {
-                        strong : file;
-                        : " is not a markdown file and cannot be rendered";
-                    }
-                }
-        }}
+/// Renders any given file path, see [`handlers::markdown_path`].
+async fn render_markdown_path(req: Request<Arc<State>>) -> tide::Result {
+    let res = handlers::markdown_path(
+        req.state(),
+        req.url().path(),
+        requested_converter(&req).as_deref(),
+        requested_reference(&req).as_deref(),
+        requested_force(&req).as_deref(),
+        if_none_match(&req).as_deref(),
+        accept(&req).as_deref(),
     )
+    .await;
+
+    Ok(res.into())
 }
 
-/// The error HTML indicating the requested file cannot be found.
-fn file_not_found(title: &str, file: &str) -> String {
-    format!(
-        "{}",
-        html! {
-            : doctype::HTML;
-            html {
-                head {
-                    title : title;
-                }
-                body {
-                    h1 {
-                        : "Couldn't find ";
-                        : file;
-                    }
-                     p {
-                         : "For the index page ";
-                         em : "rs-readme";
-                         : " will look for a file named README in the root folder. Otherwise it looks for an exact file name.";
-                     }
+/// The `Accept` header, if the client sent one.
+fn accept<S>(req: &Request<S>) -> Option<String> {
+    req.header("Accept").map(|types| {
+        types
+            .iter()
+            .map(|media| media.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    })
+}
 
+/// The `If-None-Match` header, if the browser sent one.
+fn if_none_match<S>(req: &Request<S>) -> Option<String> {
+    req.header("If-None-Match").map(|tags| {
+        tags.iter()
+            .map(|tag| tag.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    })
+}
+
+/// Sends an event with the converted file contents and the hash of the contents every time the
+/// file changes, until the browser goes away.
+///
+/// Answers with a `503` instead of an event stream when too many are already open.
+async fn render_page_update(req: Request<Arc<State>>) -> tide::Result {
+    let updates = handlers::page_updates(
+        req.state(),
+        &req.url().path()["/__rs-readme".len()..],
+        requested_converter(&req).as_deref(),
+        requested_reference(&req).as_deref(),
+    )
+    .await?;
+    // `upgrade` wants a handler it could call more than once, it only calls it once.
+    let updates = Mutex::new(Some(Box::pin(updates)));
+
+    Ok(tide::sse::upgrade(req, move |_req, sender: Sender| {
+        let updates = updates.lock().ok().and_then(|mut updates| updates.take());
+        async move {
+            if let Some(mut updates) = updates {
+                while let Some((event, message)) = updates.next().await {
+                    sender.send(event, &message, None).await?;
                 }
             }
+            Ok(())
         }
+    }))
+}
+
+/// The document's hash, and its contents when they changed, see [`handlers::poll`].
+async fn poll(req: Request<Arc<State>>) -> tide::Result {
+    let path = &req.url().path()["/__rs-readme/poll".len()..];
+    let known = query_param(&req, "hash");
+    Ok(handlers::poll(
+        req.state(),
+        path,
+        requested_converter(&req).as_deref(),
+        requested_reference(&req).as_deref(),
+        known.as_deref(),
     )
+    .await
+    .into())
 }
 
-/// The `tide::Endpoint` to render the `README.md`.
-///
-/// It assumes that there will be a `README.md` in your folder. It lets us have a special error
-/// message for it and lets the root of the website render `README.md`. It might not be necessary
-/// though, maybe we could just redirect `/` to `/README.md`.
-async fn render_readme(
-    req: Request<
-        Arc<
-            State<impl MarkdownConverter + Send + Sync + 'static, impl ContentFinder + Send + Sync>,
-        >,
-    >,
-) -> tide::Result {
-    let state = req.state();
+/// A highlighted source file, see [`handlers::code`].
+async fn code(req: Request<Arc<State>>) -> tide::Result {
+    Ok(handlers::code(
+        req.state(),
+        &req.url().path()["/__rs-readme/code".len()..],
+        requested_reference(&req).as_deref(),
+    )
+    .await
+    .into())
+}
 
-    let (contents, _hash) = state
-        .content_finder
-        .content_for("README.md")
-        .with_status(|| StatusCode::NotFound)?;
+/// The raw contents of a file, see [`handlers::raw`].
+async fn raw(req: Request<Arc<State>>) -> tide::Result {
+    Ok(
+        handlers::raw(req.state(), &req.url().path()["/__rs-readme/raw".len()..])
+            .await
+            .into(),
+    )
+}
 
-    let converted = state.markdown_converter.convert_markdown(&contents).await?;
+/// The frontmatter of a document as JSON, see [`handlers::frontmatter`].
+async fn frontmatter(req: Request<Arc<State>>) -> tide::Result {
+    let path = &req.url().path()["/__rs-readme/frontmatter".len()..];
+    Ok(handlers::frontmatter(req.state(), path).await.into())
+}
 
-    let resp = base_html("README.md", &markdown_html("README.md", &converted));
+/// The recently modified documents, see [`handlers::recent`].
+async fn recent(req: Request<Arc<State>>) -> tide::Result {
+    Ok(handlers::recent(req.state()).await.into())
+}
 
-    Ok(Response::builder(StatusCode::Ok)
-        .body(resp)
-        .content_type(mime::HTML)
-        .build())
+/// The markdown files being served as JSON, see [`handlers::tree`].
+async fn tree(req: Request<Arc<State>>) -> tide::Result {
+    Ok(handlers::tree(req.state()).await.into())
 }
 
-/// Renders any given file path, since the tool should only be used locally I assume people aren't hacking themselves.
-/// Please tell me if this is a terrible idea so I can fix it :)
-async fn render_markdown_path(
-    req: Request<
-        Arc<
-            State<impl MarkdownConverter + Send + Sync + 'static, impl ContentFinder + Send + Sync>,
-        >,
-    >,
-) -> tide::Result {
-    let state = req.state();
+/// Counts of what's in a document as JSON, see [`handlers::stats`].
+async fn stats(req: Request<Arc<State>>) -> tide::Result {
+    let path = &req.url().path()["/api/stats".len()..];
+    let reference = requested_reference(&req);
+    Ok(handlers::stats(req.state(), path, reference.as_deref())
+        .await
+        .into())
+}
 
-    let path = req.url().path();
-    let file = path.split('/').last().unwrap_or("rs-readme");
+/// The heading tree of a document as JSON, see [`handlers::outline`].
+async fn outline(req: Request<Arc<State>>) -> tide::Result {
+    let path = &req.url().path()["/api/outline".len()..];
+    let reference = requested_reference(&req);
+    Ok(handlers::outline(req.state(), path, reference.as_deref())
+        .await
+        .into())
+}
 
-    match MimeGuess::from_path(file).first_or_text_plain().type_() {
-        mime_guess::mime::IMAGE => return_file(&format!(".{}", path)).await,
-        _ => return_html(state, &format!(".{}", path)).await,
-    }
+/// A remote image from the image proxy, see [`handlers::image`].
+async fn image(req: Request<Arc<State>>) -> tide::Result {
+    let url = query_param(&req, "url");
+    Ok(handlers::image(req.state(), url.as_deref()).await.into())
 }
 
-/// Returns static files for rendering things like images in markdown documents.
-async fn return_file(path: &str) -> tide::Result {
-    Ok(Response::builder(StatusCode::Ok)
-        .content_type(MimeGuess::from_path(path).first_or_text_plain().as_ref())
-        .body(Body::from_file(path).await?)
-        .build())
+async fn metrics(req: Request<Arc<State>>) -> tide::Result {
+    Ok(handlers::metrics(req.state()).into())
 }
 
-/// Converts markdown to HTML and returns it.
-async fn return_html(state: &Arc<State<impl MarkdownConverter + Send + Sync + 'static, impl ContentFinder + Send + Sync>>, path: &str) -> tide::Result {
-    let file = path.split('/').last().unwrap_or("rs-readme");
+async fn reload_script(req: Request<Arc<State>>) -> tide::Result {
+    Ok(static_files::reload_script(accepts_gzip(&req)).into())
+}
 
-    let (contents, _hash) = state.content_finder.content_for(path)?;
+async fn code_theme(req: Request<Arc<State>>) -> tide::Result {
+    Ok(static_files::code_theme(req.param("file").unwrap_or_default()).into())
+}
 
-    let converted = state.markdown_converter.convert_markdown(&contents).await?;
+async fn octicons(req: Request<Arc<State>>) -> tide::Result {
+    let file = req.param("file").unwrap_or_default();
+    Ok(static_files::octicons(file, accepts_gzip(&req)).into())
+}
 
-    let resp = base_html(file, &markdown_html(file, &converted));
+async fn style(req: Request<Arc<State>>) -> tide::Result {
+    Ok(static_files::style(accepts_gzip(&req)).into())
+}
 
-    Ok(Response::builder(StatusCode::Ok)
-        .body(resp)
-        .content_type(mime::HTML)
-        .build())
+fn accepts_gzip<S>(req: &Request<S>) -> bool {
+    static_files::accepts_gzip(
+        req.header("Accept-Encoding")
+            .map(|encodings| encodings.as_str()),
+    )
 }
 
-/// Sends an event periodically with the file contents and the SHA1 of the contents.
-/// The front end will update if the hash differs.
-async fn render_page_update(
-    req: Request<
-        Arc<State<impl MarkdownConverter + Send + Sync, impl ContentFinder + Send + Sync>>,
-    >,
-    sender: Sender,
-) -> Result<(), http_types::Error> {
-    let state = req.state();
+/// Re-reads the config file, the same as sending the process a `SIGHUP`.
+async fn reload_config(req: Request<Arc<State>>) -> tide::Result {
+    Ok(handlers::reload_config(req.state()).into())
+}
 
-    let path = &req.url().path()["/__rs-readme".len()..];
-    let (contents, hash) = if path == "/" {
-        state.content_finder.content_for("./README.md")?
-    } else {
-        state.content_finder.content_for(&format!(".{}", path))?
-    };
+/// The display preferences pages start with, see [`handlers::prefs`].
+async fn prefs(req: Request<Arc<State>>) -> tide::Result {
+    Ok(handlers::prefs(req.state()).into())
+}
 
-    let converted = state.markdown_converter.convert_markdown(&contents).await?;
+/// Changes the display preferences pages start with, see [`handlers::change_prefs`].
+async fn change_prefs(mut req: Request<Arc<State>>) -> tide::Result {
+    let changes = req.body_string().await?;
+    Ok(handlers::change_prefs(req.state(), &changes).into())
+}
+
+/// Pins a document, see [`handlers::pin`].
+async fn pin(req: Request<Arc<State>>) -> tide::Result {
+    let host = request_host(&req);
+    let path = &req.url().path()["/__rs-readme/pin".len()..];
+    let request = change_request(&req, host.as_deref());
+    Ok(handlers::pin(req.state(), path, &request, true)
+        .await
+        .into())
+}
 
-    let message = json!({
-        "contents": &converted,
-        "hash": &format!("{:x}", &hash),
-    });
+/// Unpins a document, see [`handlers::pin`].
+async fn unpin(req: Request<Arc<State>>) -> tide::Result {
+    let host = request_host(&req);
+    let path = &req.url().path()["/__rs-readme/unpin".len()..];
+    let request = change_request(&req, host.as_deref());
+    Ok(handlers::pin(req.state(), path, &request, false)
+        .await
+        .into())
+}
 
-    sender.send("update", &message.to_string(), None).await?;
+/// The editor for a document, see [`handlers::edit`].
+async fn edit(req: Request<Arc<State>>) -> tide::Result {
+    let path = &req.url().path()["/edit".len()..];
+    Ok(handlers::edit(req.state(), path).await.into())
+}
 
-    Ok(())
+/// Saves a document from the editor, see [`handlers::save`].
+async fn save(mut req: Request<Arc<State>>) -> tide::Result {
+    let body = req.body_string().await?;
+    let base = query_param(&req, "hash");
+    let host = request_host(&req);
+    let path = &req.url().path()["/__rs-readme/save".len()..];
+    Ok(handlers::save(
+        req.state(),
+        path,
+        &change_request(&req, host.as_deref()),
+        base.as_deref(),
+        &body,
+    )
+    .await
+    .into())
 }
 
-struct ErrorMiddleware {}
+/// Converts the editor's source for its preview, see [`handlers::preview`].
+async fn preview(mut req: Request<Arc<State>>) -> tide::Result {
+    let body = req.body_string().await?;
+    let host = request_host(&req);
+    let path = &req.url().path()["/__rs-readme/preview".len()..];
+    Ok(handlers::preview(
+        req.state(),
+        path,
+        &change_request(&req, host.as_deref()),
+        &body,
+    )
+    .await
+    .into())
+}
 
-impl ErrorMiddleware {
-    fn not_markdown(&self, path: &str) -> tide::Result {
-        Ok(Response::builder(StatusCode::BadRequest)
-            .body(not_markdown_html("rs-readme", path))
-            .content_type(mime::HTML)
-            .build())
+/// The `host:port` a request was sent to, from its `Host` header.
+fn request_host<S>(req: &Request<S>) -> Option<String> {
+    match req.header("Host") {
+        Some(host) => Some(host.as_str().to_string()),
+        None => {
+            let url = req.url();
+            let host = url.host_str()?;
+            Some(match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_string(),
+            })
+        }
     }
+}
 
-    fn not_found(&self, resource: &str) -> tide::Result {
-        Ok(Response::builder(StatusCode::NotFound)
-            .body(file_not_found("rs-readme", resource))
-            .content_type(mime::HTML)
-            .build())
+/// The headers [`handlers::save`], [`handlers::preview`] and [`handlers::pin`] check to tell
+/// that one of rs-readme's own pages sent a request.
+fn change_request<'a, S>(req: &'a Request<S>, host: Option<&'a str>) -> ChangeRequest<'a> {
+    let header = |name: &str| req.header(name).map(|values| values.as_str());
+    ChangeRequest {
+        host,
+        origin: header("Origin"),
+        fetch_site: header("Sec-Fetch-Site"),
+        content_type: header("Content-Type"),
     }
 }
 
+/// Scrolls the pages showing a document to a line, see [`handlers::scroll`].
+async fn scroll(req: Request<Arc<State>>) -> tide::Result {
+    let path = &req.url().path()["/__rs-readme/scroll".len()..];
+    let line = query_param(&req, "line");
+    Ok(handlers::scroll(req.state(), path, line.as_deref())
+        .await
+        .into())
+}
+
+impl From<RenderedResponse> for Response {
+    fn from(res: RenderedResponse) -> Response {
+        let body = match res.body {
+            ResponseBody::Bytes(bytes) => Body::from(bytes),
+            ResponseBody::Stream(chunks) => {
+                Body::from_reader(BufReader::new(chunks.map(Ok).into_async_read()), None)
+            }
+        };
+        let mut builder = Response::builder(res.status)
+            .body(body)
+            .content_type(res.mime.as_str());
+        if let Some(etag) = res.etag {
+            builder = builder.header("ETag", etag);
+        }
+        let vary = match (res.vary, &res.content_encoding) {
+            (Some(vary), Some(_)) => Some(format!("{}, Accept-Encoding", vary)),
+            (None, Some(_)) => Some("Accept-Encoding".to_string()),
+            (vary, None) => vary,
+        };
+        if let Some(vary) = vary {
+            builder = builder.header("Vary", vary);
+        }
+        if let Some(encoding) = res.content_encoding {
+            builder = builder.header("Content-Encoding", encoding);
+        }
+        if let Some(retry_after) = res.retry_after {
+            builder = builder.header("Retry-After", retry_after.to_string());
+        }
+        if let Some(cache_control) = res.cache_control {
+            builder = builder.header("Cache-Control", cache_control);
+        }
+        if let Some(policy) = res.content_security_policy {
+            builder = builder
+                .header("Content-Security-Policy", policy)
+                .header("X-Content-Type-Options", "nosniff");
+        }
+        if let Some(request_id) = res.request_id {
+            builder = builder.header("X-Request-Id", request_id);
+        }
+        let mut response = builder.build();
+        if let Some(err) = res.error {
+            response.insert_ext(err);
+        }
+        response
+    }
+}
+
+/// Turns [`Error`]s from any endpoint, ours or an extension's, into error pages, or into JSON
+/// for the clients and routes that want that, see [`handlers::wants_json`]. With `debug` the
+/// pages are expanded, see [`State::with_debug_errors`].
+struct ErrorMiddleware {
+    debug: bool,
+    lang: Lang,
+}
+
 #[async_trait]
 impl<State: Clone + Send + Sync + 'static> Middleware<State> for ErrorMiddleware {
     async fn handle(&self, req: Request<State>, next: Next<'_, State>) -> tide::Result {
         let url = req.url().clone();
+        let json = handlers::wants_json(accept(&req).as_deref(), url.path());
+        let method = req.method().to_string();
+        let headers: Vec<(String, String)> = req
+            .iter()
+            .map(|(name, values)| {
+                (
+                    name.to_string(),
+                    values
+                        .iter()
+                        .map(|value| value.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                )
+            })
+            .collect();
         let res = next.run(req).await;
-        if let Some(err) = res.downcast_error::<ContentError>() {
-            match err {
-                ContentError::NotMarkdown => self.not_markdown(url.path()),
-                ContentError::CouldNotFetch(resource) => self.not_found(resource),
+        // Our endpoints send their error pages with the error attached, extensions' errors need one
+        let (err, rendered) = match res.downcast_error::<Error>() {
+            Some(err) => (Some(err), false),
+            None => (res.ext::<Error>(), true),
+        };
+        match err {
+            Some(err) if json => Ok(handlers::json_error_response(err, url.path()).into()),
+            Some(err) if self.debug => {
+                Ok(handlers::debug_error_response(err, &method, url.as_str(), &headers).into())
+            }
+            // Converter errors' pages are already in the language, with what they had to add
+            Some(err) if !rendered => {
+                Ok(handlers::localized_error_response(err, url.path(), self.lang).into())
+            }
+            Some(err) if self.lang != Lang::English && !err.converter_failed() => {
+                Ok(handlers::localized_error_response(err, url.path(), self.lang).into())
+            }
+            _ => Ok(res),
+        }
+    }
+}
+
+/// Writes every request to an [`AccessLog`] once it's answered, see [`State::with_access_log`].
+struct AccessLogMiddleware {
+    access_log: AccessLog,
+}
+
+#[async_trait]
+impl<State: Clone + Send + Sync + 'static> Middleware<State> for AccessLogMiddleware {
+    async fn handle(&self, req: Request<State>, next: Next<'_, State>) -> tide::Result {
+        let started = Instant::now();
+        let peer = req.peer_addr().map(str::to_string);
+        let method = req.method().to_string();
+        let path = req.url().path().to_string();
+        let res = next.run(req).await;
+
+        self.access_log.record(&Access {
+            peer: peer.as_deref(),
+            method: &method,
+            path: &path,
+            status: res.status().into(),
+            bytes: res.len().map(|len| len as u64),
+            duration: started.elapsed(),
+        });
+        Ok(res)
+    }
+}
+
+/// Catches handlers that panic, logging where with a request ID and answering with
+/// [`handlers::panic_response`], so one bad page doesn't take the server down.
+struct PanicMiddleware {
+    lang: Lang,
+}
+
+#[async_trait]
+impl<State: Clone + Send + Sync + 'static> Middleware<State> for PanicMiddleware {
+    async fn handle(&self, req: Request<State>, next: Next<'_, State>) -> tide::Result {
+        let path = req.url().path().to_string();
+        match AssertUnwindSafe(next.run(req)).catch_unwind().await {
+            Ok(res) => Ok(res),
+            Err(payload) => {
+                let request_id = panics::request_id();
+                error!(
+                    "Request {} for {} panicked: {}\n{}",
+                    request_id,
+                    path,
+                    panics::panic_message(&*payload),
+                    panics::take_backtrace().map_or(String::new(), |trace| trace.to_string())
+                );
+                Ok(handlers::panic_response(&request_id, self.lang).into())
             }
-        } else {
-            Ok(res)
         }
     }
 }
 
+/// Refuses requests from other machines unless remote access is allowed, see
+/// [`State::allows_peer`].
+struct LoopbackMiddleware {}
+
+#[async_trait]
+impl Middleware<Arc<State>> for LoopbackMiddleware {
+    async fn handle(&self, req: Request<Arc<State>>, next: Next<'_, Arc<State>>) -> tide::Result {
+        let peer = req
+            .peer_addr()
+            .and_then(|peer| peer.parse::<SocketAddr>().ok())
+            .map(|peer| peer.ip());
+        if !req.state().allows_peer(peer) {
+            warn!("Refused a request from {:?}, see --allow-remote", peer);
+            return Ok(handlers::remote_forbidden(req.state().lang()).into());
+        }
+
+        Ok(next.run(req).await)
+    }
+}
+
+/// Wraps each request in a tracing span with its method, path and status, see `--otel-endpoint`.
+#[cfg(feature = "otel")]
+struct TraceMiddleware {}
+
+#[cfg(feature = "otel")]
+#[async_trait]
+impl<State: Clone + Send + Sync + 'static> Middleware<State> for TraceMiddleware {
+    async fn handle(&self, req: Request<State>, next: Next<'_, State>) -> tide::Result {
+        use tracing::Instrument;
+
+        let span = tracing::info_span!(
+            "request",
+            "http.request.method" = %req.method(),
+            "url.path" = req.url().path(),
+            "http.response.status_code" = tracing::field::Empty,
+        );
+        let res = next.run(req).instrument(span.clone()).await;
+        span.record("http.response.status_code", u16::from(res.status()));
+
+        Ok(res)
+    }
+}
+
 /// Builds a `tide::Server` with the appropriate endpoint mappings.
-pub fn build_app(
-    state: Arc<
-        State<
-            impl MarkdownConverter + Send + Sync + 'static,
-            impl ContentFinder + Send + Sync + 'static,
-        >,
-    >,
-) -> Server<Arc<State<impl MarkdownConverter, impl ContentFinder>>> {
+pub fn build_app(state: Arc<State>) -> Server<Arc<State>> {
+    build_app_with(state, |_| {})
+}
+
+/// Builds the same `tide::Server` as [`build_app`] and then hands it to `extend`, so library
+/// users can add their own endpoints and middleware.
+///
+/// Routes added in `extend` take priority over the catch-all markdown route as long as they're
+/// more specific than `/*`.
+pub fn build_app_with(
+    state: Arc<State>,
+    extend: impl FnOnce(&mut Server<Arc<State>>),
+) -> Server<Arc<State>> {
+    panics::record_backtraces();
+    let debug = state.debug_errors();
+    let lang = state.lang();
+    let access_log = state.access_log().cloned();
+    let edits = state.edits();
     let mut app = Server::with_state(state);
-    app.with(log::LogMiddleware::new());
-    app.with(ErrorMiddleware {});
+    #[cfg(feature = "otel")]
+    app.with(TraceMiddleware {});
+    if let Some(access_log) = access_log {
+        app.with(AccessLogMiddleware { access_log });
+    }
+    app.with(tide::log::LogMiddleware::new());
+    app.with(PanicMiddleware { lang });
+    app.with(ErrorMiddleware { debug, lang });
+    app.with(LoopbackMiddleware {});
     app.at("").get(render_readme);
-    app.at("/static/octicons/:file").get(static_files::octicons);
-    app.at("/static/style.css").get(static_files::style);
-    app.at("/__rs-readme/")
-        .get(tide::sse::endpoint(render_page_update));
-    app.at("/__rs-readme/*")
-        .get(tide::sse::endpoint(render_page_update));
+    app.at("/static/octicons/:file").get(octicons);
+    app.at("/static/code-theme/:file").get(code_theme);
+    app.at("/static/style.css").get(style);
+    app.at("/static/reload.js").get(reload_script);
+    app.at("/__rs-readme/reload-config").post(reload_config);
+    app.at("/__rs-readme/scroll/*").post(scroll);
+    app.at("/__rs-readme/prefs").get(prefs).put(change_prefs);
+    app.at("/__rs-readme/pin/*").post(pin);
+    app.at("/__rs-readme/unpin/*").post(unpin);
+    app.at("/__rs-readme/metrics").get(metrics);
+    app.at("/__rs-readme/recent").get(recent);
+    app.at("/__rs-readme/raw/*").get(raw);
+    app.at("/__rs-readme/poll/*").get(poll);
+    app.at("/__rs-readme/code/*").get(code);
+    app.at("/__rs-readme/frontmatter/*").get(frontmatter);
+    app.at("/api/outline/*").get(outline);
+    app.at("/api/tree").get(tree);
+    app.at("/api/stats/*").get(stats);
+    app.at(IMAGE_ROUTE).get(image);
+    // Only there with `--edit`, so documents in an `edit/` folder can be read otherwise
+    if edits {
+        app.at("/edit/").get(edit);
+        app.at("/edit/*").get(edit);
+        app.at("/__rs-readme/save/*").post(save);
+        app.at("/__rs-readme/preview/*").post(preview);
+    }
+    app.at("/__rs-readme/").get(render_page_update);
+    app.at("/__rs-readme/*").get(render_page_update);
     app.at("/*").get(render_markdown_path);
 
-    app
-}
+    extend(&mut app);
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use pretty_assertions::assert_eq;
-
-    #[test]
-    fn test_base_html() {
-        let expected = "\
-<!DOCTYPE html>\
-<html>\
-  <head>\
-  <link rel=\"stylesheet\" href=\"/static/octicons/octicons.css\">\
-  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/frameworks-146fab5ea30e8afac08dd11013bb4ee0.css\">\
-  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/site-897ad5fdbe32a5cd67af5d1bdc68a292.css\">\
-  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/github-c21b6bf71617eeeb67a56b0d48b5bb5c.css\">\
-  <link rel=\"stylesheet\" href=\"/static/style.css\">\
-    <title>test title</title>\
-    <script>let hash = '';
-                           let event = new EventSource(`//${location.host}/__rs-readme${location.pathname}`);
-                           event.addEventListener('update', (e) => {
-                              let message = JSON.parse(e.data);
-                              if (message.hash !== hash) {
-                                  hash = message.hash;
-                                  document.getElementById('rs-readme-content').innerHTML = message.contents;
-                              }
-                           });</script>\
-  </head>\
-  <body>\
-    Test content\
-  </body>\
-</html>";
-
-        let actual = base_html("test title", "Test content");
-
-        assert_eq!(expected, actual);
-    }
-
-    #[test]
-    fn test_markdown_html() {
-        let expected = "\
-<div class=\"page\">\
-  <div id=\"preview-page\" class=\"preview-page\">\
-    <div role=\"main\" class=\"main-content\">\
-      <div class=\"container new-discussion-timeline experiment-repo-nav\">\
-        <div class=\"repository-content\">\
-          <div id=\"readme\" class=\"readme boxed-group clearfix announce instapaper_body md\">\
-            <h3>\
-              <span class=\"octicon octicon-book\"></span> \
-              file_name.md\
-            </h3>\
-            <article id=\"rs-readme-content\" class=\"markdown-body entry-content\" itemprop=\"text\">\
-              Test content\
-            </article>\
-          </div>\
-        </div>\
-      </div>\
-    </div>\
-  </div>\
-  <div>&nbsp;</div>\
-</div>";
-
-        let actual = markdown_html("file_name.md", "Test content");
-
-        assert_eq!(expected, actual);
-    }
-
-    #[test]
-    fn test_not_markdown_html() {
-        let expected = "\
-<!DOCTYPE html>\
-<html>\
-<head><title>rs-readme</title></head>\
-<body>\
-<h1>Not a Markdown File</h1>\
-<p><strong>test_file</strong> is not a markdown file and cannot be rendered</p>\
-</body>\
-</html>\
-";
-
-        let actual = not_markdown_html("rs-readme", "test_file");
-
-        assert_eq!(expected, actual);
-    }
+    app
 }
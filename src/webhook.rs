@@ -0,0 +1,161 @@
+use async_std::future::timeout;
+use http_types::Url;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How many times a notification is sent before giving up on it.
+const ATTEMPTS: u32 = 4;
+
+/// How long to wait before the first retry, doubled for each one after it.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// How long to wait for the webhook to answer.
+const POST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What's posted to a [`Webhook`] when a document changes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DocumentChange {
+    pub path: String,
+    pub hash: String,
+
+    /// When the change was seen, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// Posts a [`DocumentChange`] as JSON to a URL whenever a document changes, so other tools can
+/// react to edits when the preview does, see `--webhook`.
+///
+/// Changes are noticed by the live-reload watchers, so only documents open in a browser are
+/// reported. Each change is posted once however many pages are watching it, and retried a few
+/// times when the webhook doesn't answer with a success.
+#[derive(Clone)]
+pub struct Webhook {
+    url: Url,
+    retry_delay: Duration,
+    last_hashes: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl Webhook {
+    pub fn new(url: Url) -> Webhook {
+        Webhook {
+            url,
+            retry_delay: RETRY_DELAY,
+            last_hashes: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Waits `retry_delay` before the first retry instead of half a second.
+    pub fn with_retry_delay(mut self, retry_delay: Duration) -> Webhook {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Posts the change of the document at `path` to `hash` in the background, unless that
+    /// change was already posted.
+    pub fn notify(&self, path: &str, hash: &str) {
+        let previous = self
+            .last_hashes()
+            .insert(path.to_string(), hash.to_string());
+        if previous.as_deref() == Some(hash) {
+            return;
+        }
+
+        let change = DocumentChange {
+            path: path.to_string(),
+            hash: hash.to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64),
+        };
+        let webhook = self.clone();
+        async_std::task::spawn(async move { webhook.send(&change).await });
+    }
+
+    /// Posts `change`, retrying with a growing delay. Returns whether it was delivered.
+    pub async fn send(&self, change: &DocumentChange) -> bool {
+        let mut delay = self.retry_delay;
+        for attempt in 1..=ATTEMPTS {
+            match timeout(POST_TIMEOUT, self.post(change)).await {
+                Ok(Ok(())) => return true,
+                Ok(Err(err)) => debug!("Attempt {} at the webhook failed: {}", attempt, err),
+                Err(_) => debug!("Attempt {} at the webhook timed out", attempt),
+            }
+
+            if attempt < ATTEMPTS {
+                async_std::task::sleep(delay).await;
+                delay *= 2;
+            }
+        }
+
+        warn!("Could not tell {} that {} changed", self.url, change.path);
+        false
+    }
+
+    async fn post(&self, change: &DocumentChange) -> Result<(), String> {
+        let body = http_types::Body::from_json(change).map_err(|err| err.to_string())?;
+        let resp = surf::post(self.url.as_str())
+            .body(body)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("{} answered {}", self.url, resp.status()))
+        }
+    }
+
+    fn last_hashes(&self) -> MutexGuard<'_, HashMap<String, String>> {
+        match self.last_hashes.lock() {
+            Ok(hashes) => hashes,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn change() -> DocumentChange {
+        DocumentChange {
+            path: "./README.md".to_string(),
+            hash: "0b41c2".to_string(),
+            timestamp: 1_700_000_000_000,
+        }
+    }
+
+    #[async_std::test]
+    async fn posts_changes_as_json() {
+        let m = mockito::mock("POST", "/hooks/docs")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "path": "./README.md",
+                "hash": "0b41c2",
+                "timestamp": 1_700_000_000_000u64,
+            })))
+            .create();
+        let url = Url::parse(&format!("{}/hooks/docs", mockito::server_url())).unwrap();
+
+        let delivered = Webhook::new(url).send(&change()).await;
+
+        m.assert();
+        assert!(delivered);
+    }
+
+    #[async_std::test]
+    async fn retries_failed_posts() {
+        let m = mockito::mock("POST", "/hooks/down")
+            .with_status(503)
+            .expect(ATTEMPTS as usize)
+            .create();
+        let url = Url::parse(&format!("{}/hooks/down", mockito::server_url())).unwrap();
+        let webhook = Webhook::new(url).with_retry_delay(Duration::from_millis(1));
+
+        let delivered = webhook.send(&change()).await;
+
+        m.assert();
+        assert!(!delivered);
+    }
+}
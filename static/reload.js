@@ -0,0 +1,470 @@
+// Live reload for rs-readme pages: swaps in the converted document when it changes unless
+// paused, scrolls to where editors ask, sorts and filters tables, pins documents, shows
+// footnotes in popovers, applies the reader's display preferences and runs the editor.
+let hash = '';
+let toast = (text) => {
+    let shown = document.querySelector('.rs-readme-toast');
+    if (shown) shown.remove();
+    let note = document.createElement('div');
+    note.className = 'rs-readme-toast';
+    note.setAttribute('role', 'status');
+    note.textContent = text;
+    let close = document.createElement('button');
+    close.title = 'Dismiss';
+    close.textContent = '×';
+    close.addEventListener('click', () => note.remove());
+    note.appendChild(close);
+    document.body.appendChild(note);
+    setTimeout(() => note.remove(), 5000);
+};
+let failed = (message) => {
+    let banner = document.querySelector('.rs-readme-failed');
+    if (!banner) {
+        banner = document.createElement('div');
+        banner.className = 'rs-readme-failed';
+        banner.setAttribute('role', 'alert');
+        document.body.prepend(banner);
+    }
+    let text = document.createElement('pre');
+    text.textContent = message;
+    let close = document.createElement('button');
+    close.title = 'Dismiss';
+    close.textContent = '×';
+    close.addEventListener('click', () => banner.remove());
+    banner.replaceChildren(close, document.createTextNode('The page stopped updating:'), text);
+};
+// Applies the block-level patch of an update, answering with the blocks it inserted
+let patch = (content, { ops, lines }) => {
+    let inserted = [];
+    ops.forEach(({ at, remove, insert }) => {
+        for (let n = 0; n < remove; n++) content.children[at].remove();
+        let next = content.children[at] || null;
+        insert.forEach((html) => {
+            let parsed = document.createElement('template');
+            parsed.innerHTML = html;
+            let block = parsed.content.firstElementChild;
+            content.insertBefore(block, next);
+            inserted.push(block);
+        });
+    });
+    if (lines) {
+        Array.from(content.children).forEach((block, n) => {
+            if (lines[n] == null) delete block.dataset.sourceLine;
+            else block.dataset.sourceLine = lines[n];
+        });
+    }
+    return inserted;
+};
+// Where a node is in the content, as the indexes of it and its parents among their siblings
+let pathTo = (content, node) => {
+    let path = [];
+    for (; node && node !== content && node.parentNode; node = node.parentNode) {
+        path.unshift(Array.prototype.indexOf.call(node.parentNode.childNodes, node));
+    }
+    return node === content ? path : null;
+};
+let follow = (content, path) => path && path.reduce((node, n) => node && node.childNodes[n], content);
+// The collapsible sections in the content, keyed by their summary and which of the sections
+// with that summary they are, so they can be found again after an update
+let sections = (content) => {
+    let seen = new Map();
+    return Array.from(content.querySelectorAll('details'), (details) => {
+        let summary = details.querySelector(':scope > summary');
+        let text = summary ? summary.textContent.trim() : '';
+        seen.set(text, (seen.get(text) || 0) + 1);
+        return [`${text}\u0000${seen.get(text)}`, details];
+    });
+};
+// The reader's place before an update: the last anchor above the top of the window, what had
+// focus, what was selected and which sections were open
+let remember = (content) => {
+    let focused = document.activeElement;
+    let selection = window.getSelection();
+    let range = selection.rangeCount ? selection.getRangeAt(0) : null;
+    let top = Array.from(content.querySelectorAll('[id]'))
+        .filter((anchor) => anchor.getBoundingClientRect().top <= 1)
+        .pop();
+    return {
+        top: top && { id: top.id, offset: top.getBoundingClientRect().top },
+        focus: content.contains(focused) && { id: focused.id, path: pathTo(content, focused) },
+        selection: range && {
+            start: pathTo(content, range.startContainer),
+            startOffset: range.startOffset,
+            end: pathTo(content, range.endContainer),
+            endOffset: range.endOffset,
+        },
+        open: new Map(sections(content).map(([key, details]) => [key, details.open])),
+    };
+};
+// Puts the reader back where they were, following the fragment when its heading was renamed.
+// Sections are opened and closed first, so the scrolling lands in the right place
+let restore = (content, place, message) => {
+    let renamed = (message.patch && message.patch.renamed) || {};
+    let anchors = message.anchors || [];
+    let fragment = decodeURIComponent(location.hash.slice(1));
+    if (fragment && renamed[fragment]) {
+        fragment = renamed[fragment];
+        history.replaceState(null, '', `#${encodeURIComponent(fragment)}`);
+    }
+    sections(content).forEach(([key, details]) => {
+        if (place.open.has(key)) details.open = place.open.get(key);
+    });
+    let top = place.top && document.getElementById(renamed[place.top.id] || place.top.id);
+    if (top) {
+        window.scrollBy(0, top.getBoundingClientRect().top - place.top.offset);
+    } else if (fragment && anchors.includes(fragment)) {
+        document.getElementById(fragment).scrollIntoView();
+    }
+    if (place.focus) {
+        let focused = (place.focus.id && document.getElementById(place.focus.id)) ||
+            follow(content, place.focus.path);
+        if (focused && focused.focus) focused.focus({ preventScroll: true });
+    }
+    let selected = place.selection;
+    if (selected && selected.start && selected.end) {
+        try {
+            let range = document.createRange();
+            range.setStart(follow(content, selected.start), selected.startOffset);
+            range.setEnd(follow(content, selected.end), selected.endOffset);
+            window.getSelection().removeAllRanges();
+            window.getSelection().addRange(range);
+        } catch (e) {
+            // What was selected changed too much to select again
+        }
+    }
+};
+// Footnote references, from the offline converter and from GitHub
+let footnoteRefs = '#rs-readme-content sup.footnote-reference a, #rs-readme-content a[data-footnote-ref]';
+let noteFor = (ref) => document.getElementById(decodeURIComponent(ref.hash.slice(1)));
+// Links each note back to where it's first referenced, GitHub's notes already have them
+let footnoteBackrefs = () => {
+    document.querySelectorAll(footnoteRefs).forEach((ref, n) => {
+        let note = noteFor(ref);
+        if (!note) return;
+        if (!ref.id) ref.id = `rs-readme-fnref-${n + 1}`;
+        if (note.querySelector('.rs-readme-footnote-backref, [data-footnote-backref]')) return;
+        let back = document.createElement('a');
+        back.className = 'rs-readme-footnote-backref';
+        back.href = `#${ref.id}`;
+        back.setAttribute('aria-label', 'Back to content');
+        back.textContent = '↩';
+        let paragraphs = note.querySelectorAll('p');
+        (paragraphs[paragraphs.length - 1] || note).append(' ', back);
+    });
+};
+let popover = null;
+let hideFootnote = () => {
+    if (popover) popover.remove();
+    popover = null;
+};
+let showFootnote = (ref) => {
+    let note = noteFor(ref);
+    if (!note) return;
+    hideFootnote();
+    let copy = note.cloneNode(true);
+    copy.querySelectorAll('[id]').forEach((element) => element.removeAttribute('id'));
+    copy.querySelectorAll('.footnote-definition-label, .rs-readme-footnote-backref, [data-footnote-backref]')
+        .forEach((element) => element.remove());
+    popover = document.createElement('div');
+    popover.className = 'rs-readme-footnote-popover markdown-body';
+    popover.setAttribute('role', 'tooltip');
+    popover.dataset.for = ref.id;
+    popover.append(...copy.childNodes);
+    document.body.appendChild(popover);
+    let box = ref.getBoundingClientRect();
+    let right = window.scrollX + document.documentElement.clientWidth - popover.offsetWidth - 8;
+    popover.style.left = `${Math.max(window.scrollX + 8, Math.min(window.scrollX + box.left, right))}px`;
+    popover.style.top = `${window.scrollY + box.bottom + 4}px`;
+};
+document.addEventListener('mouseover', (e) => {
+    let ref = e.target.closest(footnoteRefs);
+    if (ref) showFootnote(ref);
+    else if (popover && !e.target.closest('.rs-readme-footnote-popover')) hideFootnote();
+});
+// Without hover the first tap shows the note and the second follows the link
+document.addEventListener('click', (e) => {
+    let ref = e.target.closest(footnoteRefs);
+    if (!ref) {
+        if (!e.target.closest('.rs-readme-footnote-popover')) hideFootnote();
+        return;
+    }
+    if (matchMedia('(hover: none)').matches && !(popover && popover.dataset.for === ref.id)) {
+        e.preventDefault();
+        showFootnote(ref);
+    }
+});
+document.addEventListener('keydown', (e) => { if (e.key === 'Escape') hideFootnote(); });
+// Filter boxes for the tables marked `data-filter`, in their captions so the blocks of the
+// content stay the ones updates patch
+let tableFilters = () => {
+    document.querySelectorAll('#rs-readme-content table[data-filter]').forEach((table) => {
+        if (table.caption) return;
+        let input = document.createElement('input');
+        input.type = 'search';
+        input.className = 'rs-readme-table-filter';
+        input.placeholder = `Filter ${table.tBodies[0].rows.length} rows`;
+        input.setAttribute('aria-label', 'Filter rows');
+        table.createCaption().appendChild(input);
+    });
+};
+document.addEventListener('input', (e) => {
+    if (!e.target.classList.contains('rs-readme-table-filter')) return;
+    let query = e.target.value.trim().toLowerCase();
+    Array.from(e.target.closest('table').tBodies[0].rows).forEach((row) => {
+        row.hidden = query !== '' && !row.textContent.toLowerCase().includes(query);
+    });
+});
+// Following changes scrolls to the section each update changed, remembered between pages
+let following = () => localStorage.getItem('rs-readme-follow') === 'true';
+let followChanges = document.createElement('button');
+let showFollowing = () => followChanges.setAttribute('aria-pressed', String(following()));
+followChanges.textContent = 'Follow changes';
+followChanges.addEventListener('click', () => {
+    localStorage.setItem('rs-readme-follow', String(!following()));
+    showFollowing();
+});
+let update = (message) => {
+    let banner = document.querySelector('.rs-readme-failed');
+    if (banner) banner.remove();
+    if (message.hash === hash) return;
+    let content = document.getElementById('rs-readme-content');
+    content.querySelectorAll('.rs-readme-changed').forEach((block) => {
+        block.classList.remove('rs-readme-changed');
+        if (!block.classList.length) block.removeAttribute('class');
+    });
+    let first = hash === '';
+    hash = message.hash;
+    let place = remember(content);
+    let changed;
+    if (message.patch) {
+        changed = patch(content, message.patch);
+    } else {
+        let before = new Set(Array.from(content.children, (block) => block.outerHTML));
+        content.innerHTML = message.contents;
+        changed = Array.from(content.children).filter((block) => !before.has(block.outerHTML));
+    }
+    restore(content, place, message);
+    hideFootnote();
+    footnoteBackrefs();
+    tableFilters();
+    if (following() && !first) {
+        let section = message.patch && message.patch.section;
+        let target = (section && document.getElementById(section)) || changed[0];
+        if (target) target.scrollIntoView({ block: 'start', behavior: 'smooth' });
+    }
+    if (first) return;
+    changed.forEach((block) => block.classList.add('rs-readme-changed'));
+    let at = message.timestamp ? new Date(message.timestamp) : new Date();
+    toast(`Updated at ${at.toLocaleTimeString()}`);
+};
+// While paused, updates are kept to apply in order on resume, the patches need all of them
+let pending = null;
+let missed = 0;
+let pause = document.createElement('button');
+let badge = document.createElement('span');
+let showPaused = () => {
+    pause.textContent = pending ? 'Resume live reload' : 'Pause live reload';
+    pause.setAttribute('aria-pressed', String(Boolean(pending)));
+    badge.textContent = `${missed} update${missed === 1 ? '' : 's'} pending`;
+    badge.hidden = !missed;
+};
+let receive = (message) => {
+    if (!pending) return update(message);
+    let last = pending[pending.length - 1];
+    if (message.hash === (last ? last.hash : hash)) return;
+    // Nothing before a whole new version is needed
+    if ('contents' in message) pending = [];
+    pending.push(message);
+    missed += 1;
+    showPaused();
+};
+pause.addEventListener('click', () => {
+    let queued = pending;
+    pending = queued ? null : [];
+    missed = 0;
+    if (queued) queued.forEach(update);
+    showPaused();
+});
+// Display preferences are the server's defaults from `/__rs-readme/prefs` with what the reader
+// picked in this browser on top, applied as soon as the script runs so pages don't flash
+let serverPrefs = {};
+let chosenPrefs = () => JSON.parse(localStorage.getItem('rs-readme-prefs') || '{}');
+let darkSystem = matchMedia('(prefers-color-scheme: dark)');
+let display = document.createElement('button');
+let prefsForm = document.createElement('form');
+prefsForm.className = 'rs-readme-prefs';
+prefsForm.hidden = true;
+prefsForm.innerHTML = `
+    <label for="rs-readme-font-size">Font size</label>
+    <input id="rs-readme-font-size" name="font_size" type="range" min="12" max="24">
+    <label for="rs-readme-line-width">Line width</label>
+    <input id="rs-readme-line-width" name="line_width" type="range" min="600" max="1600" step="20">
+    <label for="rs-readme-monospace">Monospace</label>
+    <input id="rs-readme-monospace" name="monospace" type="checkbox">
+    <label for="rs-readme-theme">Theme</label>
+    <select id="rs-readme-theme" name="theme">
+        <option value="auto">Auto</option><option value="light">Light</option><option value="dark">Dark</option>
+    </select>
+    <button name="reset" type="button">Reset</button>`;
+let applyPrefs = () => {
+    let prefs = Object.assign({}, serverPrefs, chosenPrefs());
+    let root = document.documentElement;
+    if (prefs.font_size) root.style.setProperty('--rs-readme-font-size', `${prefs.font_size}px`);
+    if (prefs.line_width) root.style.setProperty('--rs-readme-line-width', `${prefs.line_width}px`);
+    root.toggleAttribute('data-rs-readme-monospace', Boolean(prefs.monospace));
+    let theme = prefs.theme === 'light' || prefs.theme === 'dark' ? prefs.theme : null;
+    root.dataset.rsReadmeTheme = theme || (darkSystem.matches ? 'dark' : 'light');
+    let fields = prefsForm.elements;
+    fields.font_size.value = prefs.font_size || 16;
+    fields.line_width.value = prefs.line_width || 980;
+    fields.monospace.checked = Boolean(prefs.monospace);
+    fields.theme.value = prefs.theme || 'auto';
+};
+applyPrefs();
+darkSystem.addEventListener('change', applyPrefs);
+fetch('/__rs-readme/prefs')
+    .then((res) => (res.ok ? res.json() : {}))
+    .then((prefs) => {
+        serverPrefs = prefs;
+        applyPrefs();
+    })
+    .catch(() => {});
+prefsForm.addEventListener('input', (e) => {
+    let field = e.target;
+    let value = field.type === 'checkbox' ? field.checked : field.value;
+    let chosen = chosenPrefs();
+    chosen[field.name] = field.type === 'range' ? Number(value) : value;
+    localStorage.setItem('rs-readme-prefs', JSON.stringify(chosen));
+    applyPrefs();
+});
+prefsForm.elements.reset.addEventListener('click', () => {
+    localStorage.removeItem('rs-readme-prefs');
+    applyPrefs();
+});
+let showDisplay = (open) => {
+    prefsForm.hidden = !open;
+    display.setAttribute('aria-expanded', String(open));
+};
+display.textContent = 'Display';
+display.addEventListener('click', () => showDisplay(prefsForm.hidden));
+document.addEventListener('keydown', (e) => { if (e.key === 'Escape') showDisplay(false); });
+let controls = () => {
+    if (!document.getElementById('rs-readme-content')) return;
+    footnoteBackrefs();
+    tableFilters();
+    let live = document.createElement('div');
+    live.className = 'rs-readme-live';
+    badge.className = 'rs-readme-pending';
+    showPaused();
+    showFollowing();
+    showDisplay(false);
+    live.append(pause, badge, followChanges, display);
+    document.body.append(live, prefsForm);
+};
+if (document.readyState === 'loading') document.addEventListener('DOMContentLoaded', controls);
+else controls();
+let scrollToLine = (line) => {
+    let blocks = Array.from(document.querySelectorAll('#rs-readme-content [data-source-line]'));
+    let block = blocks.filter((b) => Number(b.dataset.sourceLine) <= line).pop() || blocks[0];
+    if (block) block.scrollIntoView({ block: 'start' });
+};
+// The editor at /edit/<path>: previews the source as it's typed and saves it with the button or
+// Ctrl+S, asking before overwriting changes made since it was opened. Its page isn't a document,
+// there's nothing to live reload. The source is sent as JSON, which the server insists on so
+// other sites can't post to it.
+let editor = document.getElementById('rs-readme-editor');
+if (editor) {
+    let form = editor.querySelector('form');
+    let source = form.elements.source;
+    let status = editor.querySelector('.rs-readme-editor-status');
+    let preview = editor.querySelector('.rs-readme-editor-preview');
+    let path = editor.dataset.path;
+    let base = editor.dataset.hash;
+    let saved = source.value;
+    let typing;
+    let send = (url, text) => fetch(url, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({ contents: text }),
+    });
+    let changed = () => { status.textContent = source.value === saved ? '' : 'Unsaved changes'; };
+    source.addEventListener('input', () => {
+        changed();
+        clearTimeout(typing);
+        typing = setTimeout(() => {
+            send(`/__rs-readme/preview${path}`, source.value)
+                .then((res) => (res.ok ? res.text() : Promise.reject()))
+                .then((html) => { preview.innerHTML = html; })
+                .catch(() => {});
+        }, 300);
+    });
+    let save = () => {
+        let text = source.value;
+        send(`/__rs-readme/save${path}?hash=${base}`, text)
+            .then((res) => res.json().then((message) => [res.status, message]))
+            .then(([code, message]) => {
+                if (code === 200) {
+                    base = message.hash;
+                    saved = text;
+                    changed();
+                    if (!status.textContent) status.textContent = 'Saved';
+                } else if (code === 409 && message.hash && confirm(`${message.message}, overwrite it?`)) {
+                    base = message.hash;
+                    save();
+                } else {
+                    status.textContent = `Not saved: ${message.message}`;
+                }
+            })
+            .catch(() => { status.textContent = 'Not saved, the server can\'t be reached'; });
+    };
+    form.addEventListener('submit', (e) => {
+        e.preventDefault();
+        save();
+    });
+    document.addEventListener('keydown', (e) => {
+        if ((e.ctrlKey || e.metaKey) && e.key === 's') {
+            e.preventDefault();
+            save();
+        }
+    });
+    window.addEventListener('beforeunload', (e) => { if (source.value !== saved) e.preventDefault(); });
+} else if (window.EventSource) {
+    let event = new EventSource(`//${location.host}/__rs-readme${location.pathname}${location.search}`);
+    event.addEventListener('update', (e) => receive(JSON.parse(e.data)));
+    // The connection dropping is an `error` too, those have no message
+    event.addEventListener('error', (e) => { if (e.data) failed(JSON.parse(e.data).message); });
+    event.addEventListener('scroll', (e) => scrollToLine(JSON.parse(e.data).line));
+} else {
+    let poll = () => {
+        let query = new URLSearchParams(location.search);
+        query.set('hash', hash);
+        fetch(`/__rs-readme/poll${location.pathname}?${query}`)
+            .then((res) => res.json().then((message) => [res.ok, message]))
+            .then(([ok, message]) => {
+                if (!ok) failed(message.message);
+                else if ('contents' in message) receive(message);
+            })
+            .catch(() => {})
+            .finally(() => setTimeout(poll, 2000));
+    };
+    poll();
+}
+let line = new URLSearchParams(location.search).get('line');
+if (line) document.addEventListener('DOMContentLoaded', () => scrollToLine(Number(line)));
+document.addEventListener('click', (e) => {
+    let th = e.target.closest('table.rs-readme-sortable th');
+    if (!th) return;
+    let ascending = th.dataset.order !== 'asc';
+    th.dataset.order = ascending ? 'asc' : 'desc';
+    let rows = th.closest('table').tBodies[0];
+    let text = (row) => (row.cells[th.cellIndex] || {}).textContent || '';
+    let collator = new Intl.Collator(undefined, { numeric: true });
+    Array.from(rows.rows)
+        .sort((a, b) => collator.compare(text(a), text(b)) * (ascending ? 1 : -1))
+        .forEach((row) => rows.appendChild(row));
+});
+document.addEventListener('click', (e) => {
+    let pin = e.target.closest('button.rs-readme-pin');
+    if (!pin) return;
+    fetch(`/__rs-readme/${pin.name}/${pin.value}`, { method: 'POST' }).then(() => location.reload());
+});
@@ -1,84 +1,19 @@
 // Create mock
-use async_std::io::prelude::*;
 use async_trait::async_trait;
-use generic_array::{typenum::U20, GenericArray};
+use flate2::read::GzDecoder;
+use glob::Pattern;
 use http_types::mime;
 use pretty_assertions::assert_eq;
+use rs_readme::testing::{MockAssertSeen, MockConverter, MockFinder};
 use rs_readme::*;
 use rs_readme::{ContentError, ContentFinder, MarkdownConverter};
-use sha1::{Digest, Sha1};
 use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use tide::http::{Method, Request, Response, Url};
 
-/// A mock [`MarkdownConverter`] that returns:
-/// `<h1>A Readme</h1>`
-struct MockConverter;
-
-#[async_trait]
-impl MarkdownConverter for MockConverter {
-    async fn convert_markdown(&self, _md: &str) -> Result<String, MarkdownError> {
-        Ok("<h1>A Readme</h1>".to_string())
-    }
-}
-
-/// A mock [`ContentFinder`] the returns:
-/// `# A Readme`
-struct MockFinder;
-
-impl ContentFinder for MockFinder {
-    fn content_for(
-        &self,
-        _resource: &str,
-    ) -> Result<(String, GenericArray<u8, U20>), ContentError> {
-        let content = "# A Readme".to_string();
-        let hash = Sha1::digest(&content.as_bytes());
-        Ok((content, hash))
-    }
-}
-
-/// A mock [`ContentFinder`] and [`MarkdownConverter`] that keeps track of arguments
-///
-/// Intended to be used to verify that an endpoint is calling its dependencies in
-/// the expected way. It takes an `Arc<Mutex<HashSet<String>>>` so you can query
-/// the `HashSet` later to verify what was placed in it.
-///
-/// The `Arc` and `Mutex` are necessary for working across threads/async runtimes.
-struct MockAssertSeen {
-    seen: Arc<Mutex<HashSet<String>>>,
-}
-
-impl MockAssertSeen {
-    fn new(seen: Arc<Mutex<HashSet<String>>>) -> MockAssertSeen {
-        MockAssertSeen { seen }
-    }
-}
-
-impl ContentFinder for MockAssertSeen {
-    fn content_for(&self, resource: &str) -> Result<(String, GenericArray<u8, U20>), ContentError> {
-        self.seen
-            .lock()
-            .expect("Could not lock mutex in content_for")
-            .insert(resource.to_string());
-
-        let content = format!("content for: {}", resource).to_string();
-        let hash = Sha1::digest(&content.as_bytes());
-        Ok((content, hash))
-    }
-}
-
-#[async_trait]
-impl MarkdownConverter for MockAssertSeen {
-    async fn convert_markdown(&self, md: &str) -> Result<String, MarkdownError> {
-        self.seen
-            .lock()
-            .expect("Could not lock mutex in convert_markdown")
-            .insert(md.to_string());
-
-        Ok(md.to_string())
-    }
-}
-
 #[async_std::test]
 async fn index_wraps_in_html() {
     // Setup
@@ -100,38 +35,35 @@ async fn index_wraps_in_html() {
     assert_eq!(mime, mime::HTML);
 
     let body = res.body_string().await.unwrap();
-    let expected_body = "\
+    let expected_body = format!(
+        "\
 <!DOCTYPE html>\
 <html>\
   <head>\
   <link rel=\"stylesheet\" href=\"/static/octicons/octicons.css\">\
-  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/frameworks-146fab5ea30e8afac08dd11013bb4ee0.css\">\
-  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/site-897ad5fdbe32a5cd67af5d1bdc68a292.css\">\
-  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/github-c21b6bf71617eeeb67a56b0d48b5bb5c.css\">\
+  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/frameworks-146fab5ea30e8afac08dd11013bb4ee0.css\" crossorigin=\"anonymous\">\
+  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/site-897ad5fdbe32a5cd67af5d1bdc68a292.css\" crossorigin=\"anonymous\">\
+  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/github-c21b6bf71617eeeb67a56b0d48b5bb5c.css\" crossorigin=\"anonymous\">\
   <link rel=\"stylesheet\" href=\"/static/style.css\">\
-    <title>README.md</title>\
-    <script>let hash = '';
-                           let event = new EventSource(`//${location.host}/__rs-readme${location.pathname}`);
-                           event.addEventListener('update', (e) => {
-                              let message = JSON.parse(e.data);
-                              if (message.hash !== hash) {
-                                  hash = message.hash;
-                                  document.getElementById('rs-readme-content').innerHTML = message.contents;
-                              }
-                           });</script>\
+  <link rel=\"stylesheet\" href=\"/static/code-theme/github.css\">\
+    <title>A Readme</title>\
+    <script src=\"{}\"></script>\
   </head>\
   <body>\
     <div class=\"page\">\
+      <a class=\"rs-readme-skip\" href=\"#rs-readme-content\">Skip to content</a>\
       <div id=\"preview-page\" class=\"preview-page\">\
-        <div role=\"main\" class=\"main-content\">\
+        <div class=\"main-content\">\
           <div class=\"container new-discussion-timeline experiment-repo-nav\">\
             <div class=\"repository-content\">\
               <div id=\"readme\" class=\"readme boxed-group clearfix announce instapaper_body md\">\
-                <h3>\
-                  <span class=\"octicon octicon-book\"></span> \
-                  README.md\
-                </h3>\
-                <article id=\"rs-readme-content\" class=\"markdown-body entry-content\" itemprop=\"text\">\
+                <header role=\"banner\" class=\"rs-readme-header\">\
+                  <h3>\
+                    <span class=\"octicon octicon-book\"></span> \
+                    A Readme\
+                  </h3>\
+                </header>\
+                <article id=\"rs-readme-content\" role=\"main\" class=\"markdown-body entry-content\" itemprop=\"text\">\
                   <h1>A Readme</h1>\
                 </article>\
               </div>\
@@ -142,7 +74,9 @@ async fn index_wraps_in_html() {
       <div>&nbsp;</div>\
     </div>\
   </body>\
-</html>";
+</html>",
+        reload_script_url()
+    );
     assert_eq!(body, expected_body);
 }
 
@@ -167,38 +101,35 @@ async fn non_index_wraps_in_html() {
     assert_eq!(mime, mime::HTML);
 
     let body = res.body_string().await.unwrap();
-    let expected_body = "\
+    let expected_body = format!(
+        "\
 <!DOCTYPE html>\
 <html>\
   <head>\
   <link rel=\"stylesheet\" href=\"/static/octicons/octicons.css\">\
-  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/frameworks-146fab5ea30e8afac08dd11013bb4ee0.css\">\
-  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/site-897ad5fdbe32a5cd67af5d1bdc68a292.css\">\
-  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/github-c21b6bf71617eeeb67a56b0d48b5bb5c.css\">\
+  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/frameworks-146fab5ea30e8afac08dd11013bb4ee0.css\" crossorigin=\"anonymous\">\
+  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/site-897ad5fdbe32a5cd67af5d1bdc68a292.css\" crossorigin=\"anonymous\">\
+  <link rel=\"stylesheet\" href=\"https://github.githubassets.com/assets/github-c21b6bf71617eeeb67a56b0d48b5bb5c.css\" crossorigin=\"anonymous\">\
   <link rel=\"stylesheet\" href=\"/static/style.css\">\
-    <title>foo.md</title>\
-    <script>let hash = '';
-                           let event = new EventSource(`//${location.host}/__rs-readme${location.pathname}`);
-                           event.addEventListener('update', (e) => {
-                              let message = JSON.parse(e.data);
-                              if (message.hash !== hash) {
-                                  hash = message.hash;
-                                  document.getElementById('rs-readme-content').innerHTML = message.contents;
-                              }
-                           });</script>\
+  <link rel=\"stylesheet\" href=\"/static/code-theme/github.css\">\
+    <title>A Readme</title>\
+    <script src=\"{}\"></script>\
   </head>\
   <body>\
     <div class=\"page\">\
+      <a class=\"rs-readme-skip\" href=\"#rs-readme-content\">Skip to content</a>\
       <div id=\"preview-page\" class=\"preview-page\">\
-        <div role=\"main\" class=\"main-content\">\
+        <div class=\"main-content\">\
           <div class=\"container new-discussion-timeline experiment-repo-nav\">\
             <div class=\"repository-content\">\
               <div id=\"readme\" class=\"readme boxed-group clearfix announce instapaper_body md\">\
-                <h3>\
-                  <span class=\"octicon octicon-book\"></span> \
-                  foo.md\
-                </h3>\
-                <article id=\"rs-readme-content\" class=\"markdown-body entry-content\" itemprop=\"text\">\
+                <header role=\"banner\" class=\"rs-readme-header\">\
+                  <h3>\
+                    <span class=\"octicon octicon-book\"></span> \
+                    A Readme\
+                  </h3>\
+                </header>\
+                <article id=\"rs-readme-content\" role=\"main\" class=\"markdown-body entry-content\" itemprop=\"text\">\
                   <h1>A Readme</h1>\
                 </article>\
               </div>\
@@ -209,7 +140,9 @@ async fn non_index_wraps_in_html() {
       <div>&nbsp;</div>\
     </div>\
   </body>\
-</html>";
+</html>",
+        reload_script_url()
+    );
     assert_eq!(body, expected_body);
 }
 
@@ -256,10 +189,7 @@ async fn returns_400_for_non_md_file() {
     struct MockFinderError;
 
     impl ContentFinder for MockFinderError {
-        fn content_for(
-            &self,
-            _resource: &str,
-        ) -> Result<(String, GenericArray<u8, U20>), ContentError> {
+        fn content_for(&self, _resource: &str) -> Result<(String, ContentHash), ContentError> {
             Err(ContentError::NotMarkdown)
         }
     }
@@ -302,10 +232,7 @@ async fn returns_404_for_missing_readme() {
     struct MockFinderError;
 
     impl ContentFinder for MockFinderError {
-        fn content_for(
-            &self,
-            resource: &str,
-        ) -> Result<(String, GenericArray<u8, U20>), ContentError> {
+        fn content_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
             Err(ContentError::CouldNotFetch(resource.to_string()))
         }
     }
@@ -348,10 +275,7 @@ async fn returns_404_for_missing_file() {
     struct MockFinderError;
 
     impl ContentFinder for MockFinderError {
-        fn content_for(
-            &self,
-            resource: &str,
-        ) -> Result<(String, GenericArray<u8, U20>), ContentError> {
+        fn content_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
             Err(ContentError::CouldNotFetch(resource.to_string()))
         }
     }
@@ -388,6 +312,201 @@ async fn returns_404_for_missing_file() {
     assert_eq!(body, expected_body);
 }
 
+#[async_std::test]
+async fn returns_413_for_files_over_the_limit() {
+    // Setup
+    let finder = FileFinder::new(PathBuf::from(".")).with_max_size(8);
+    let state = State::new(MockConverter, finder);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/test_dir/a.md").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 413);
+    assert_eq!(res.content_type(), Some(mime::HTML));
+
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<h1>Too Large to Render</h1>"));
+    assert!(body.contains("<a href=\"/__rs-readme/raw/test_dir/a.md\">"));
+}
+
+#[async_std::test]
+async fn errors_are_json_for_clients_and_routes_wanting_it() {
+    // Setup
+    let state = State::new(MockConverter, FileFinder::new(PathBuf::from("./test_dir")));
+    let app = build_app_with(Arc::new(state), |server| {
+        server
+            .at("/api/fail")
+            .get(|_| async { Err::<String, _>(tide::Error::new(500, Error::NotMarkdown)) });
+    });
+
+    // Request
+    let mut page_req = Request::new(Method::Get, Url::parse("http://localhost/nope.md").unwrap());
+    page_req.insert_header("Accept", "application/json");
+    let mut page: Response = app.respond(page_req).await.unwrap();
+    let frontmatter_req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/__rs-readme/frontmatter/nope.md").unwrap(),
+    );
+    let mut frontmatter: Response = app.respond(frontmatter_req).await.unwrap();
+    let mut extension_req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/api/fail").unwrap(),
+    );
+    extension_req.insert_header("Accept", "application/json, text/html;q=0.9");
+    let mut extension: Response = app.respond(extension_req).await.unwrap();
+    let html_req = Request::new(Method::Get, Url::parse("http://localhost/nope.md").unwrap());
+    let html: Response = app.respond(html_req).await.unwrap();
+
+    // Assert
+    assert_eq!(page.status(), 404);
+    assert_eq!(page.content_type(), Some(mime::JSON));
+    let body: serde_json::Value = page.body_json().await.unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "code": "not-found",
+            "message": "Could not find nope.md",
+            "resource": "/nope.md",
+        })
+    );
+
+    assert_eq!(frontmatter.status(), 404);
+    let body: serde_json::Value = frontmatter.body_json().await.unwrap();
+    assert_eq!(body["code"], "not-found");
+
+    assert_eq!(extension.status(), 400);
+    let body: serde_json::Value = extension.body_json().await.unwrap();
+    assert_eq!(body["code"], "not-markdown");
+
+    assert_eq!(html.content_type(), Some(mime::HTML));
+}
+
+#[async_std::test]
+async fn raw_returns_the_file_as_text() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/__rs-readme/raw/test_dir/a.md").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.content_type(), Some(mime::PLAIN));
+    assert_eq!(
+        res.body_string().await.unwrap(),
+        include_str!("../test_dir/a.md")
+    );
+}
+
+#[async_std::test]
+async fn raw_files_are_read_from_the_root_folder() {
+    // Setup
+    let root = PathBuf::from("test_dir");
+    let state = State::new(MockConverter, FileFinder::new(root.clone())).with_root(root);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let get = |path: &str| {
+        Request::new(
+            Method::Get,
+            Url::parse(&format!("http://localhost/__rs-readme/raw/{}", path)).unwrap(),
+        )
+    };
+    let mut inside: Response = app.respond(get("a.md")).await.unwrap();
+    let outside: Response = app.respond(get("Cargo.toml")).await.unwrap();
+
+    // Assert
+    assert_eq!(inside.status(), 200);
+    assert_eq!(
+        inside.body_string().await.unwrap(),
+        include_str!("../test_dir/a.md")
+    );
+    assert_eq!(outside.status(), 404);
+}
+
+#[async_std::test]
+async fn binary_files_link_to_their_raw_download() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-binary-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let binary = b"\x00\x01\x02 not markdown".to_vec();
+    std::fs::write(root.join("data.md"), &binary).unwrap();
+    let state = State::new(MockConverter, FileFinder::new(root.clone())).with_root(root.clone());
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/data.md").unwrap());
+    let mut page: Response = app.respond(req).await.unwrap();
+    let body = page.body_string().await.unwrap();
+    let href = body
+        .split("<a href=\"")
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap();
+    let req = Request::new(
+        Method::Get,
+        Url::parse(&format!("http://localhost{}", href)).unwrap(),
+    );
+    let mut download: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(page.status(), 415);
+    assert_eq!(href, "/__rs-readme/raw/data.md");
+    assert_eq!(download.status(), 200);
+    assert_eq!(download.body_bytes().await.unwrap(), binary);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn documents_are_sent_as_markdown_when_accepted() {
+    // Setup
+    let state = State::new(MockConverter, FileFinder::new(PathBuf::from("./test_dir")));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let mut req = Request::new(Method::Get, Url::parse("http://localhost/a.md").unwrap());
+    req.insert_header("Accept", "text/markdown");
+    let mut res: Response = app.respond(req).await.unwrap();
+    let source_etag = res["ETag"].as_str().to_string();
+    let mut page_req = Request::new(Method::Get, Url::parse("http://localhost/a.md").unwrap());
+    page_req.insert_header("Accept", "*/*");
+    page_req.insert_header("If-None-Match", source_etag.as_str());
+    let page: Response = app.respond(page_req).await.unwrap();
+    let mut again_req = Request::new(Method::Get, Url::parse("http://localhost/a.md").unwrap());
+    again_req.insert_header("Accept", "text/markdown");
+    again_req.insert_header("If-None-Match", source_etag.as_str());
+    let again: Response = app.respond(again_req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    assert_eq!(
+        res.content_type().map(|mime| mime.essence().to_string()),
+        Some("text/markdown".to_string())
+    );
+    assert_eq!(res["Vary"].as_str(), "Accept");
+    assert_eq!(
+        res.body_string().await.unwrap(),
+        include_str!("../test_dir/a.md")
+    );
+    assert_eq!(page.status(), 200);
+    assert_eq!(page.content_type(), Some(mime::HTML));
+    assert_eq!(page["Vary"].as_str(), "Accept");
+    assert_ne!(page["ETag"].as_str(), source_etag);
+    assert_eq!(again.status(), 304);
+}
+
 #[async_std::test]
 async fn static_content_returns_appropriate_files() {
     // Setup
@@ -396,8 +515,8 @@ async fn static_content_returns_appropriate_files() {
 
     // Expected results
     // (path, status, mime, body)
-    let expected = vec![
-        ("/static/octicons/octicons.css", 200 as u16, mime::CSS, {
+    let expected = [
+        ("/static/octicons/octicons.css", 200_u16, mime::CSS, {
             let mut vec = Vec::new();
             vec.extend_from_slice(include_bytes!("../static/octicons/octicons.css"));
             vec
@@ -461,13 +580,17 @@ async fn static_content_returns_appropriate_files() {
         let res_status = res.status();
         assert_eq!(&res_status, status, "path: {}", path);
 
-        let res_mime = res.content_type().expect(&format!(
-            "Couldn't get the content-type header, path: {}",
-            path
-        ));
+        let res_mime = res
+            .content_type()
+            .unwrap_or_else(|| panic!("Couldn't get the content-type header, path: {}", path));
         assert_eq!(res_mime, *mime, "path: {}", path);
 
-        assert_eq!(&res.body_bytes().await.expect("Could not fetch body bytes"), body, "path: {}", path);
+        assert_eq!(
+            &res.body_bytes().await.expect("Could not fetch body bytes"),
+            body,
+            "path: {}",
+            path
+        );
     }
 }
 
@@ -498,6 +621,57 @@ async fn styles_returns_right_css() {
     assert_eq!(&body, include_str!("../static/style.css"));
 }
 
+#[async_std::test]
+async fn reload_script_is_cached_by_its_hash() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder);
+    let app = build_app(Arc::new(state));
+
+    // Make request
+    let req = Request::new(
+        Method::Get,
+        Url::parse(&format!("http://localhost{}", reload_script_url())).unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert!(reload_script_url().starts_with("/static/reload.js?v="));
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.content_type(), Some(mime::JAVASCRIPT));
+    assert_eq!(
+        res["Cache-Control"].as_str(),
+        "public, max-age=31536000, immutable"
+    );
+    let body = res.body_string().await.unwrap();
+    assert_eq!(&body, include_str!("../static/reload.js"));
+}
+
+#[async_std::test]
+async fn static_content_is_gzipped_when_accepted() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder);
+    let app = build_app(Arc::new(state));
+
+    // Make request
+    let mut req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/static/octicons/octicons.svg").unwrap(),
+    );
+    req.insert_header("Accept-Encoding", "gzip, deflate");
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    assert_eq!(res["Content-Encoding"].as_str(), "gzip");
+    assert_eq!(res.content_type(), Some(mime::SVG));
+
+    let mut body = String::new();
+    GzDecoder::new(&res.body_bytes().await.unwrap()[..])
+        .read_to_string(&mut body)
+        .unwrap();
+    assert_eq!(body, include_str!("../static/octicons/octicons.svg"));
+}
+
 #[async_std::test]
 async fn returns_image() {
     // Setup
@@ -521,5 +695,2405 @@ async fn returns_image() {
     assert_eq!(mime, mime::PNG);
 
     let expected_content = include_bytes!("../test_dir/images/rust-logo.png");
-    assert_eq!(res.body_bytes().await.expect("Couldn't get body bytes"), expected_content);
+    assert_eq!(
+        res.body_bytes().await.expect("Couldn't get body bytes"),
+        expected_content
+    );
+}
+
+#[async_std::test]
+async fn converter_query_param_overrides_configured_converter() {
+    // Setup
+    let converter = Arc::new(Mutex::new(HashSet::new()));
+    let finder = Arc::new(Mutex::new(HashSet::new()));
+    let state = State::new(
+        MockAssertSeen::new(converter.clone()),
+        MockAssertSeen::new(finder.clone()),
+    );
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/foo.md?converter=offline").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<p>content for: ./foo.md</p>"));
+    assert!(converter
+        .lock()
+        .expect("Could not lock in converter assert")
+        .is_empty());
+}
+
+#[async_std::test]
+async fn reload_config_swaps_default_converter() {
+    // Setup
+    let config = std::env::temp_dir().join("rs-readme-reload-test.toml");
+    std::fs::write(&config, "converter = \"offline\"\n").unwrap();
+
+    let converter = Arc::new(Mutex::new(HashSet::new()));
+    let finder = Arc::new(Mutex::new(HashSet::new()));
+    let state = State::new(
+        MockAssertSeen::new(converter.clone()),
+        MockAssertSeen::new(finder.clone()),
+    )
+    .with_config_file(config.clone())
+    .unwrap();
+    let app = build_app(Arc::new(state));
+
+    // Request with the offline converter from the config
+    let req = Request::new(Method::Get, Url::parse("http://localhost/foo.md").unwrap());
+    let mut res: Response = app.respond(req).await.unwrap();
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<p>content for: ./foo.md</p>"));
+
+    // Reload without a converter set
+    std::fs::write(&config, "").unwrap();
+    let req = Request::new(
+        Method::Post,
+        Url::parse("http://localhost/__rs-readme/reload-config").unwrap(),
+    );
+    let res: Response = app.respond(req).await.unwrap();
+    assert_eq!(res.status(), 200);
+
+    // Request with the configured converter again
+    let req = Request::new(Method::Get, Url::parse("http://localhost/foo.md").unwrap());
+    let mut res: Response = app.respond(req).await.unwrap();
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains(">content for: ./foo.md<"));
+
+    let _ = std::fs::remove_file(&config);
+}
+
+#[async_std::test]
+async fn reload_config_clears_a_removed_context() {
+    // Setup
+    let config = std::env::temp_dir().join(format!(
+        "rs-readme-reload-context-{}.toml",
+        std::process::id()
+    ));
+    std::fs::write(&config, "context = \"gregcline/rs-readme\"\n").unwrap();
+    let api = format!("{}/reload-context", mockito::server_url());
+    let _gfm = mockito::mock("POST", "/reload-context/markdown")
+        .match_body(mockito::Matcher::PartialJsonString(
+            "{\"mode\": \"gfm\", \"context\": \"gregcline/rs-readme\"}".to_string(),
+        ))
+        .with_body("<p>In context</p>")
+        .create();
+    let _plain = mockito::mock("POST", "/reload-context/markdown")
+        .match_body(mockito::Matcher::PartialJsonString(
+            "{\"mode\": \"markdown\", \"context\": \"\"}".to_string(),
+        ))
+        .with_body("<p>Without context</p>")
+        .create();
+    let state = State::new(MockConverter, MockFinder)
+        .with_github_converter(Converter::new(api, None))
+        .with_config_file(config.clone())
+        .unwrap();
+    let app = build_app(Arc::new(state));
+    let page_url = Url::parse("http://localhost/foo.md?converter=github").unwrap();
+
+    // Request
+    let mut before: Response = app
+        .respond(Request::new(Method::Get, page_url.clone()))
+        .await
+        .unwrap();
+    std::fs::write(&config, "").unwrap();
+    let reloaded: Response = app
+        .respond(Request::new(
+            Method::Post,
+            Url::parse("http://localhost/__rs-readme/reload-config").unwrap(),
+        ))
+        .await
+        .unwrap();
+    let mut after: Response = app
+        .respond(Request::new(Method::Get, page_url))
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(before
+        .body_string()
+        .await
+        .unwrap()
+        .contains("<p>In context</p>"));
+    assert_eq!(reloaded.status(), 200);
+    assert!(after
+        .body_string()
+        .await
+        .unwrap()
+        .contains("<p>Without context</p>"));
+
+    let _ = std::fs::remove_file(&config);
+}
+
+#[async_std::test]
+async fn reload_config_without_file_is_404() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Post,
+        Url::parse("http://localhost/__rs-readme/reload-config").unwrap(),
+    );
+    let res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 404);
+}
+
+#[async_std::test]
+async fn build_app_with_adds_routes() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder);
+    let app = build_app_with(Arc::new(state), |server| {
+        server.at("/auth/callback").get(|_| async { Ok("authed") });
+    });
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/auth/callback").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.body_string().await.unwrap(), "authed");
+}
+
+#[async_std::test]
+async fn builder_extensions_are_applied() {
+    // Setup
+    let app = App::builder()
+        .root("test_dir")
+        .extend(|server| {
+            server.at("/healthz").get(|_| async { Ok("ok") });
+        })
+        .build()
+        .unwrap();
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/healthz").unwrap());
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.body_string().await.unwrap(), "ok");
+}
+
+#[async_std::test]
+async fn archives_are_served_without_extracting() {
+    // Setup
+    let archive = std::env::temp_dir().join(format!("rs-readme-bundle-{}.zip", std::process::id()));
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+    for (name, contents) in &[
+        ("bundle/README.md", "# Bundle"),
+        ("bundle/guide/setup.md", "Run it"),
+    ] {
+        zip.start_file(*name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        std::io::Write::write_all(&mut zip, contents.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+    let app = App::builder()
+        .root(archive.clone())
+        .offline(true)
+        .build()
+        .unwrap();
+
+    // Request
+    let mut bodies = Vec::new();
+    for url in &["http://localhost/", "http://localhost/guide/setup.md"] {
+        let req = Request::new(Method::Get, Url::parse(url).unwrap());
+        let mut res: Response = app.respond(req).await.unwrap();
+        assert_eq!(res.status(), 200, "{}", url);
+        bodies.push(res.body_string().await.unwrap());
+    }
+    let _ = std::fs::remove_file(&archive);
+
+    // Assert
+    assert!(bodies[0].contains(">Bundle</h1>"));
+    assert!(bodies[1].contains("<p data-source-line=\"1\">Run it</p>"));
+}
+
+#[async_std::test]
+async fn remote_documents_are_fetched() {
+    // Setup
+    let _notes = mockito::mock("GET", "/gist/raw/notes.md")
+        .with_body("See [more](./more.md)")
+        .create();
+    let _more = mockito::mock("GET", "/gist/raw/more.md")
+        .with_body("More notes")
+        .create();
+    let url = Url::parse(&format!("{}/gist/raw/notes.md", mockito::server_url())).unwrap();
+    let app = App::builder().remote(url).offline(true).build().unwrap();
+
+    // Request
+    let mut bodies = Vec::new();
+    for url in &["http://localhost/", "http://localhost/more.md"] {
+        let req = Request::new(Method::Get, Url::parse(url).unwrap());
+        let mut res: Response = app.respond(req).await.unwrap();
+        assert_eq!(res.status(), 200, "{}", url);
+        bodies.push(res.body_string().await.unwrap());
+    }
+
+    // Assert
+    assert!(bodies[0].contains("<a href=\"./more.md\">more</a>"));
+    assert!(bodies[1].contains("<p data-source-line=\"1\">More notes</p>"));
+}
+
+#[async_std::test]
+async fn piped_documents_are_served_at_the_root() {
+    // Setup
+    let document = PipedDocument::new();
+    let app = App::builder()
+        .root("test_dir")
+        .piped(document.clone())
+        .offline(true)
+        .build()
+        .unwrap();
+    let get = |url: &str| Request::new(Method::Get, Url::parse(url).unwrap());
+
+    // Request
+    let waiting: Response = app.respond(get("http://localhost/")).await.unwrap();
+    document.set("# Generated".to_string());
+    let mut piped: Response = app.respond(get("http://localhost/")).await.unwrap();
+    let linked: Response = app.respond(get("http://localhost/a.md")).await.unwrap();
+
+    // Assert
+    assert_eq!(waiting.status(), 404);
+    assert_eq!(piped.status(), 200);
+    assert!(piped
+        .body_string()
+        .await
+        .unwrap()
+        .contains(">Generated</h1>"));
+    assert_eq!(linked.status(), 200);
+}
+
+#[async_std::test]
+async fn returns_502_when_converter_unavailable() {
+    // Create mock
+    struct MockConverterError;
+
+    #[async_trait]
+    impl MarkdownConverter for MockConverterError {
+        async fn convert_markdown(&self, _md: &str) -> Result<String, MarkdownError> {
+            Err(MarkdownError::ConverterUnavailable(
+                "GitHub is down".to_string(),
+            ))
+        }
+    }
+
+    // Setup
+    let state = State::new(MockConverterError, MockFinder);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/foo.md").unwrap());
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 502);
+
+    let body = res.body_string().await.unwrap();
+    let expected_body = "\
+<!DOCTYPE html>\
+<html>\
+  <head>\
+    <meta charset=\"utf-8\">\
+    <title>rs-readme</title>\
+    <link rel=\"stylesheet\" href=\"/static/style.css\">\
+  </head>\
+  <body>\
+    <div class=\"rs-readme-converter-error\">\
+      <h1>Bad Gateway</h1>\
+      <pre>Could not convert\nGitHub is down</pre>\
+      <p>\
+        <a href=\"/foo.md\">Retry</a> · \
+        <a href=\"/foo.md?converter=offline\">Render offline instead</a>\
+      </p>\
+    </div>\
+  </body>\
+</html>";
+    assert_eq!(body, expected_body);
+}
+
+#[async_std::test]
+async fn returns_502_when_converter_times_out() {
+    // Create mock
+    struct MockConverterHung;
+
+    #[async_trait]
+    impl MarkdownConverter for MockConverterHung {
+        async fn convert_markdown(&self, _md: &str) -> Result<String, MarkdownError> {
+            async_std::future::pending().await
+        }
+    }
+
+    // Setup
+    let state = State::new(MockConverterHung, MockFinder)
+        .with_convert_timeout(std::time::Duration::from_millis(10));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/foo.md").unwrap());
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 502);
+    assert!(res.body_string().await.unwrap().contains(
+        "<pre>Could not convert\nThe default converter didn't finish within 10ms, see --convert-timeout</pre>"
+    ));
+}
+
+#[async_std::test]
+async fn pages_are_tagged_with_a_hash_of_the_page() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/foo.md").unwrap());
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let etag = res["ETag"].as_str().to_string();
+    let page = res.body_string().await.unwrap();
+    assert_eq!(etag, format!("W/\"{}\"", ContentHash::of(page.as_bytes())));
+
+    // Request again with the tag
+    let mut req = Request::new(Method::Get, Url::parse("http://localhost/foo.md").unwrap());
+    req.insert_header("If-None-Match", etag.as_str());
+    let res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 304);
+}
+
+#[async_std::test]
+async fn unchanged_pages_come_from_the_cache() {
+    // Setup
+    let seen = Arc::new(Mutex::new(HashSet::new()));
+    let state = State::new(MockAssertSeen::new(seen.clone()), MockFinder);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/foo.md").unwrap());
+    let res: Response = app.respond(req).await.unwrap();
+    assert_eq!(res.status(), 200);
+    assert!(seen.lock().unwrap().contains("# A Readme"));
+    seen.lock().unwrap().clear();
+
+    let req = Request::new(Method::Get, Url::parse("http://localhost/foo.md").unwrap());
+    let res: Response = app.respond(req).await.unwrap();
+    assert_eq!(res.status(), 200);
+
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/__rs-readme/metrics").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert!(seen.lock().unwrap().is_empty());
+    assert_eq!(res.status(), 200);
+    let metrics: serde_json::Value = res.body_json().await.unwrap();
+    assert_eq!(metrics["page_cache"]["hits"], 1);
+    assert_eq!(metrics["page_cache"]["misses"], 1);
+    assert_eq!(metrics["watched_documents"], 0);
+}
+
+#[async_std::test]
+async fn prewarm_fills_the_cache() {
+    // Setup
+    let state = Arc::new(State::new(
+        MockConverter,
+        FileFinder::new(PathBuf::from("test_dir")),
+    ));
+    let app = build_app(state.clone());
+
+    // Request
+    let rendered = state.prewarm(Path::new("test_dir"), 2).await;
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/sub_dir/a.md").unwrap(),
+    );
+    let res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    assert_eq!(rendered, 6);
+    assert_eq!(state.cache_stats().hits, 1);
+    assert_eq!(state.cache_stats().misses, 6);
+}
+
+#[async_std::test]
+async fn big_documents_are_streamed_whole() {
+    // Create mock
+    struct BigFinder;
+
+    impl ContentFinder for BigFinder {
+        fn content_for(&self, _resource: &str) -> Result<(String, ContentHash), ContentError> {
+            let content = "## Section\n\nSome *text* and a [link](./a.md).\n\n".repeat(40_000);
+            let hash = ContentHash::of(content.as_bytes());
+            Ok((content, hash))
+        }
+    }
+
+    // Setup
+    let state = State::new(OfflineConverter::default(), BigFinder);
+    let app = build_app(Arc::new(state));
+    let expected = render_document(
+        &BigFinder,
+        &OfflineConverter::default(),
+        "./big.md",
+        &RenderOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/big.md").unwrap());
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.len(), None);
+    assert!(res["ETag"].as_str().starts_with("W/\""));
+    assert_ne!(res["ETag"].as_str(), format!("W/\"{}\"", expected.hash));
+    assert_eq!(res.body_string().await.unwrap(), expected.html);
+}
+
+#[async_std::test]
+async fn update_streams_over_the_limit_are_503() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder).with_max_update_streams(1);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let first: Response = app
+        .respond(Request::new(
+            Method::Get,
+            Url::parse("http://localhost/__rs-readme/").unwrap(),
+        ))
+        .await
+        .unwrap();
+    let second: Response = app
+        .respond(Request::new(
+            Method::Get,
+            Url::parse("http://localhost/__rs-readme/").unwrap(),
+        ))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(first.status(), 200);
+    assert_eq!(first.content_type(), Some(mime::SSE));
+    assert_eq!(second.status(), 503);
+    assert_eq!(second["Retry-After"], "5");
+}
+
+#[cfg(unix)]
+#[async_std::test]
+async fn index_falls_back_to_readme_rst() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-rst-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("README.rst"), "<p>A reStructuredText readme</p>").unwrap();
+    let config = root.join(".rs-readme.toml");
+    std::fs::write(&config, "rst_command = \"cat\"\n").unwrap();
+
+    let state = State::new(MockConverter, FileFinder::new(root.clone()))
+        .with_config_file(config)
+        .unwrap();
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/").unwrap());
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<title>README.rst</title>"));
+    assert!(body.contains("<p>A reStructuredText readme</p>"));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn summary_md_drives_the_index_and_sidebar() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-book-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(
+        root.join("SUMMARY.md"),
+        "# Summary\n\n- [Introduction](intro.md)\n- [Usage](usage.md)\n",
+    )
+    .unwrap();
+    std::fs::write(root.join("intro.md"), "Introduction").unwrap();
+    std::fs::write(root.join("usage.md"), "Usage").unwrap();
+
+    let state = State::new(MockConverter, FileFinder::new(root.clone()));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/").unwrap());
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<title>intro.md</title>"));
+    assert!(body.contains(
+        "<li class=\"rs-readme-nav-current\" style=\"--depth: 0\"><a href=\"/intro.md\">Introduction</a></li>"
+    ));
+    assert!(body.contains("<a href=\"/usage.md\" rel=\"next\">Usage →</a>"));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn nav_file_orders_the_sidebar() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-nav-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("SUMMARY.md"), "- [Summary page](summary.md)\n").unwrap();
+    std::fs::write(
+        root.join(".rs-readme-nav.toml"),
+        "[[section]]\ntitle = \"Start here\"\npages = [{ path = \"b.md\", title = \"Bee\" }, { path = \"a.md\" }]\n",
+    )
+    .unwrap();
+    std::fs::write(root.join("a.md"), "A").unwrap();
+    std::fs::write(root.join("b.md"), "B").unwrap();
+
+    let state = State::new(MockConverter, FileFinder::new(root.clone()))
+        .with_nav_file(root.join(".rs-readme-nav.toml"));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/").unwrap());
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<title>b.md</title>"));
+    assert!(
+        body.contains("<li class=\"rs-readme-nav-section\" style=\"--depth: 0\">Start here</li>")
+    );
+    assert!(body.contains("<a href=\"/a.md\" rel=\"next\">a.md →</a>"));
+    assert!(!body.contains("Summary page"));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn includes_are_rendered_inline() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-include-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("shared")).unwrap();
+    std::fs::write(
+        root.join("README.md"),
+        "# Guide\n\n<!-- include: shared/setup.md -->\n",
+    )
+    .unwrap();
+    std::fs::write(root.join("shared/setup.md"), "Run `make setup`\n").unwrap();
+
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/").unwrap());
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<p>Run <code>make setup</code></p>"));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn placeholders_are_filled_in() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-variables-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(
+        root.join("README.md"),
+        "Install {{version}} from {{channel}}, not \\{{version}}\n",
+    )
+    .unwrap();
+    let config = root.join(".rs-readme.toml");
+    std::fs::write(
+        &config,
+        "[variables]\nversion = \"1.2.0\"\nchannel = \"nightly\"\n",
+    )
+    .unwrap();
+
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()))
+        .with_defines(
+            vec![("channel".to_string(), "stable".to_string())]
+                .into_iter()
+                .collect(),
+        )
+        .with_config_file(config)
+        .unwrap();
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/").unwrap());
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<p>Install 1.2.0 from stable, not {{version}}</p>"));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn translations_are_linked_in_the_header() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-translations-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("docs/en")).unwrap();
+    std::fs::create_dir_all(root.join("docs/ja")).unwrap();
+    std::fs::write(root.join("docs/en/setup.md"), "Setup").unwrap();
+    std::fs::write(root.join("docs/ja/setup.md"), "セットアップ").unwrap();
+
+    let state = State::new(MockConverter, FileFinder::new(root.clone())).with_root(root.clone());
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/docs/en/setup.md").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains(
+        "<span class=\"rs-readme-languages\"><strong>en</strong><a href=\"/docs/ja/setup.md\">ja</a></span>"
+    ));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn csv_files_are_tables() {
+    // Setup
+    let state = State::new(MockConverter, FileFinder::new(PathBuf::from(".")));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/test_dir/table.csv").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.content_type(), Some(mime::HTML));
+
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<th>file</th><th>lines</th>"));
+    assert!(body.contains("<td>src/main.rs</td><td>120</td>"));
+    assert!(body.contains("<a href=\"/__rs-readme/raw/test_dir/table.csv\" download>"));
+}
+
+#[async_std::test]
+async fn plain_text_is_preformatted_when_turned_on() {
+    // Setup
+    let state = State::new(
+        MockConverter,
+        FileFinder::new(PathBuf::from(".")).with_plain_text(true),
+    );
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/test_dir/NOTES").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<pre>Some notes, &lt;not&gt; markdown.\n</pre>"));
+}
+
+#[async_std::test]
+async fn other_files_render_as_markdown_when_forced() {
+    // Setup
+    let state = State::new(MockConverter, FileFinder::new(PathBuf::from(".")));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let refused = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/test_dir/NOTES").unwrap(),
+    );
+    let refused: Response = app.respond(refused).await.unwrap();
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/test_dir/NOTES?force=md").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(refused.status(), 400);
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<details class=\"rs-readme-warnings\" open>"));
+    assert!(body.contains(
+        "<li data-kind=\"not-markdown\">NOTES isn't a markdown file, it's rendered as one \
+         because of ?force=md</li>"
+    ));
+    assert!(body.contains("<h1>A Readme</h1>"));
+}
+
+#[async_std::test]
+async fn files_treated_as_markdown_render_without_asking() {
+    // Setup
+    let patterns = vec![Pattern::new("test_dir/NOTES").unwrap()];
+    let state = State::new(
+        MockConverter,
+        FileFinder::new(PathBuf::from(".")).with_treat_as_md(patterns.clone()),
+    )
+    .with_treat_as_md(patterns);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/test_dir/NOTES").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<h1>A Readme</h1>"));
+    assert!(!body.contains("rs-readme-warnings"));
+}
+
+#[async_std::test]
+async fn code_view_highlights_source_files() {
+    // Setup
+    let state = State::new(MockConverter, FileFinder::new(PathBuf::from(".")));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/__rs-readme/code/src/main.rs").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.content_type(), Some(mime::HTML));
+
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<title>main.rs</title>"));
+    assert!(
+        body.contains("<tr id=\"L1\"><td class=\"rs-readme-line-number\"><a href=\"#L1\">1</a>")
+    );
+    assert!(body.contains("<span class=\"rs-readme-hl-source rs-readme-hl-rust\">"));
+}
+
+#[async_std::test]
+async fn code_view_reads_from_the_content_finder() {
+    // Setup
+    let root = PathBuf::from("test_dir");
+    let state = State::new(MockConverter, FileFinder::new(root.clone())).with_root(root);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let get = |path: &str| {
+        Request::new(
+            Method::Get,
+            Url::parse(&format!("http://localhost/__rs-readme/code/{}", path)).unwrap(),
+        )
+    };
+    let mut inside: Response = app.respond(get("b.txt")).await.unwrap();
+    let outside: Response = app.respond(get("src/main.rs")).await.unwrap();
+
+    // Assert
+    assert_eq!(inside.status(), 200);
+    assert!(inside
+        .body_string()
+        .await
+        .unwrap()
+        .contains("<title>b.txt</title>"));
+    assert_eq!(outside.status(), 404);
+}
+
+#[async_std::test]
+async fn html_files_are_served_from_the_root_folder() {
+    // Setup
+    let root = PathBuf::from("test_dir");
+    let state = State::new(MockConverter, FileFinder::new(root.clone())).with_root(root);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let get = |path: &str| {
+        Request::new(
+            Method::Get,
+            Url::parse(&format!("http://localhost/{}", path)).unwrap(),
+        )
+    };
+    let mut inside: Response = app.respond(get("html/report.html")).await.unwrap();
+    let outside: Response = app.respond(get("test_dir/html/report.html")).await.unwrap();
+
+    // Assert
+    assert_eq!(inside.status(), 200);
+    assert_eq!(
+        inside.body_string().await.unwrap(),
+        include_str!("../test_dir/html/report.html")
+    );
+    assert_eq!(outside.status(), 404);
+}
+
+#[async_std::test]
+async fn html_files_and_their_assets_are_served_as_they_are() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let page: Response = app
+        .respond(Request::new(
+            Method::Get,
+            Url::parse("http://localhost/test_dir/html/report.html").unwrap(),
+        ))
+        .await
+        .unwrap();
+    let mut style: Response = app
+        .respond(Request::new(
+            Method::Get,
+            Url::parse("http://localhost/test_dir/html/report.css").unwrap(),
+        ))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(page.status(), 200);
+    assert_eq!(page.content_type(), Some(mime::HTML));
+    assert_eq!(page["Content-Security-Policy"], "sandbox");
+    assert_eq!(page["X-Content-Type-Options"], "nosniff");
+    assert_eq!(style.status(), 200);
+    assert_eq!(style.content_type(), Some(mime::CSS));
+    assert_eq!(
+        style.body_string().await.unwrap(),
+        include_str!("../test_dir/html/report.css")
+    );
+}
+
+#[async_std::test]
+async fn trusted_html_files_are_not_sandboxed() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder).with_trusted_html(true);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/test_dir/html/report.html").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    assert!(res.header("Content-Security-Policy").is_none());
+    assert!(res.body_string().await.unwrap().contains("<script>"));
+}
+
+#[async_std::test]
+async fn html_files_can_be_sanitized() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder).with_sanitized_html(true);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/test_dir/html/report.html").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<h1>Coverage</h1>"));
+    assert!(!body.contains("<script>"));
+}
+
+#[async_std::test]
+async fn frontmatter_is_shown_and_served_as_json() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-frontmatter-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(
+        root.join("rfc.md"),
+        "---\nauthor: Ada\nreviewers: [Grace, Alan]\n---\n# Storage\n",
+    )
+    .unwrap();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let page_req = Request::new(Method::Get, Url::parse("http://localhost/rfc.md").unwrap());
+    let mut page: Response = app.respond(page_req).await.unwrap();
+    let json_req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/__rs-readme/frontmatter/rfc.md").unwrap(),
+    );
+    let mut json: Response = app.respond(json_req).await.unwrap();
+
+    // Assert
+    assert_eq!(page.status(), 200);
+    let body = page.body_string().await.unwrap();
+    assert!(body.contains("<th>author</th><th>reviewers</th>"));
+    assert!(body.contains("<td>Ada</td><td>Grace, Alan</td>"));
+    assert!(!body.contains("author: Ada"));
+
+    assert_eq!(json.status(), 200);
+    let fields: serde_json::Value = json.body_json().await.unwrap();
+    assert_eq!(
+        fields,
+        serde_json::json!({ "author": "Ada", "reviewers": ["Grace", "Alan"] })
+    );
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn outline_is_served_as_json() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-outline-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("guide.md"), "# Guide\n\n## Install\n\n## Usage\n").unwrap();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/api/outline/guide.md").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+    let missing_req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/api/outline/missing.md").unwrap(),
+    );
+    let mut missing: Response = app.respond(missing_req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let outline: serde_json::Value = res.body_json().await.unwrap();
+    assert_eq!(
+        outline,
+        serde_json::json!([{
+            "level": 1, "text": "Guide", "slug": "guide", "line": 1,
+            "children": [
+                { "level": 2, "text": "Install", "slug": "install", "line": 3, "children": [] },
+                { "level": 2, "text": "Usage", "slug": "usage", "line": 5, "children": [] },
+            ],
+        }])
+    );
+
+    assert_eq!(missing.status(), 404);
+    let error: serde_json::Value = missing.body_json().await.unwrap();
+    assert_eq!(error["code"], "not-found");
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn file_tree_is_served_as_json() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-api-tree-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("docs")).unwrap();
+    std::fs::write(root.join("docs/guide.md"), "# Guide\n").unwrap();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()))
+        .with_root(root.clone());
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/api/tree").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let mut tree: serde_json::Value = res.body_json().await.unwrap();
+    tree[0]["children"][0]["modified"] = serde_json::Value::Null;
+    assert_eq!(
+        tree,
+        serde_json::json!([{
+            "kind": "folder", "name": "docs", "path": "docs",
+            "children": [{
+                "kind": "file", "name": "guide.md", "path": "docs/guide.md",
+                "title": "Guide", "size": 8, "modified": null,
+            }],
+        }])
+    );
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn document_stats_are_served_as_json() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-api-stats-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(
+        root.join("guide.md"),
+        "# Guide\n\nSee [the API](./api.md).\n\n```toml\nkey = 1\n```\n",
+    )
+    .unwrap();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/api/stats/guide.md").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+    let image_req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/api/stats/logo.png").unwrap(),
+    );
+    let image: Response = app.respond(image_req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let stats: serde_json::Value = res.body_json().await.unwrap();
+    assert_eq!(
+        stats,
+        serde_json::json!({
+            "words": 4, "headings": 1, "links": 1, "images": 0, "code_blocks": 1,
+            "code_languages": ["toml"], "reading_minutes": 1, "tasks": 0, "tasks_done": 0,
+        })
+    );
+    assert_eq!(image.status(), 400);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn blocks_are_marked_with_their_source_line() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-source-lines-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(
+        root.join("notes.md"),
+        "---\nauthor: Ada\n---\n# Notes\n\nFirst\n",
+    )
+    .unwrap();
+    let converter = OfflineConverter::new().with_source_lines(true);
+    let state = State::new(converter, FileFinder::new(root.clone()));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let page_req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/notes.md").unwrap(),
+    );
+    let mut page: Response = app.respond(page_req).await.unwrap();
+    let scroll_req = Request::new(
+        Method::Post,
+        Url::parse("http://localhost/__rs-readme/scroll/notes.md?line=6").unwrap(),
+    );
+    let mut scroll: Response = app.respond(scroll_req).await.unwrap();
+    let no_line_req = Request::new(
+        Method::Post,
+        Url::parse("http://localhost/__rs-readme/scroll/notes.md").unwrap(),
+    );
+    let no_line: Response = app.respond(no_line_req).await.unwrap();
+
+    // Assert
+    let body = page.body_string().await.unwrap();
+    assert!(body.contains("<h1 data-source-line=\"4\">Notes</h1>"));
+    assert!(body.contains("<p data-source-line=\"6\">First</p>"));
+
+    assert_eq!(scroll.status(), 200);
+    let told: serde_json::Value = scroll.body_json().await.unwrap();
+    assert_eq!(told, serde_json::json!({ "pages": 0 }));
+    assert_eq!(no_line.status(), 400);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn recently_modified_documents_are_listed() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-recent-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("docs")).unwrap();
+    let now = std::time::SystemTime::now();
+    for (path, contents, ago) in &[
+        ("README.md", "# Project\n", 3 * 24 * 60 * 60),
+        ("docs/draft.md", "# Draft\n", 5 * 60),
+    ] {
+        std::fs::write(root.join(path), contents).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(root.join(path))
+            .unwrap()
+            .set_modified(now - std::time::Duration::from_secs(*ago))
+            .unwrap();
+    }
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()))
+        .with_root(root.clone());
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/__rs-readme/recent").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    let draft = body.find("<a href=\"/docs/draft.md\">Draft</a>").unwrap();
+    let readme = body.find("<a href=\"/README.md\">Project</a>").unwrap();
+    assert!(draft < readme);
+    assert!(body.contains(" 5 minutes ago"));
+    assert!(body.contains(" 3 days ago"));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn pinning_a_document_changes_its_etag() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-pin-etag-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("README.md"), "# Project\n").unwrap();
+    let pins = Pins::new(root.join("state").join(PINS_FILE), &root);
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()))
+        .with_root(root.clone())
+        .with_pins(pins);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/").unwrap());
+    let before: Response = app.respond(req).await.unwrap();
+    let etag = before["ETag"].as_str().to_string();
+    let req = Request::new(
+        Method::Post,
+        Url::parse("http://localhost/__rs-readme/pin/README.md").unwrap(),
+    );
+    app.respond::<_, Response>(req).await.unwrap();
+    let mut req = Request::new(Method::Get, Url::parse("http://localhost/").unwrap());
+    req.insert_header("If-None-Match", etag.as_str());
+    let mut after: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(after.status(), 200);
+    assert_ne!(after["ETag"].as_str(), etag);
+    assert!(after
+        .body_string()
+        .await
+        .unwrap()
+        .contains("rs-readme-pinned"));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn pinned_documents_are_listed_on_the_index() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-pinned-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("docs")).unwrap();
+    std::fs::write(root.join("README.md"), "# Project\n").unwrap();
+    std::fs::write(root.join("docs/guide.md"), "# Guide\n").unwrap();
+    let pins = Pins::new(root.join("state").join(PINS_FILE), &root);
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()))
+        .with_root(root.clone())
+        .with_pins(pins);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Post,
+        Url::parse("http://localhost/__rs-readme/pin/docs/guide.md").unwrap(),
+    );
+    let mut pinned: Response = app.respond(req).await.unwrap();
+    let req = Request::new(Method::Get, Url::parse("http://localhost/").unwrap());
+    let mut index: Response = app.respond(req).await.unwrap();
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/docs/guide.md").unwrap(),
+    );
+    let mut guide: Response = app.respond(req).await.unwrap();
+    let req = Request::new(
+        Method::Post,
+        Url::parse("http://localhost/__rs-readme/unpin/docs/guide.md").unwrap(),
+    );
+    let mut unpinned: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(pinned.status(), 200);
+    assert_eq!(
+        pinned.body_string().await.unwrap(),
+        "{\"pinned\":[\"docs/guide.md\"]}"
+    );
+    let index = index.body_string().await.unwrap();
+    assert!(index.contains(
+        "<div class=\"rs-readme-pinned\"><strong>Pinned</strong>\
+         <ul><li><a href=\"/docs/guide.md\">docs/guide.md</a></li></ul></div>"
+    ));
+    assert!(index.contains("name=\"pin\" value=\"README.md\""));
+    let guide = guide.body_string().await.unwrap();
+    assert!(guide.contains("name=\"unpin\" value=\"docs/guide.md\""));
+    assert!(!guide.contains("rs-readme-pinned"));
+    assert_eq!(unpinned.body_string().await.unwrap(), "{\"pinned\":[]}");
+    let saved = std::fs::read_to_string(root.join("state").join(PINS_FILE)).unwrap();
+    assert_eq!(saved, "{}");
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn only_rs_readme_pages_can_pin_documents() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-pin-origin-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("README.md"), "# Project\n").unwrap();
+    let pins = Pins::new(root.join("state").join(PINS_FILE), &root);
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()))
+        .with_root(root.clone())
+        .with_pins(pins);
+    let app = build_app(Arc::new(state));
+    let pin = |headers: &[(&str, &str)]| {
+        let mut req = Request::new(
+            Method::Post,
+            Url::parse("http://localhost/__rs-readme/pin/README.md").unwrap(),
+        );
+        for (name, value) in headers {
+            req.insert_header(*name, *value);
+        }
+        req
+    };
+
+    // Request
+    let mut other_site: Response = app
+        .respond(pin(&[("Origin", "https://example.com")]))
+        .await
+        .unwrap();
+    let cross_site: Response = app
+        .respond(pin(&[("Sec-Fetch-Site", "cross-site")]))
+        .await
+        .unwrap();
+    let rebound: Response = app
+        .respond(pin(&[("Host", "attacker.example:4000")]))
+        .await
+        .unwrap();
+    let own_page: Response = app
+        .respond(pin(&[
+            ("Origin", "http://localhost"),
+            ("Sec-Fetch-Site", "same-origin"),
+        ]))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(other_site.status(), 403);
+    let body: serde_json::Value =
+        serde_json::from_str(&other_site.body_string().await.unwrap()).unwrap();
+    assert_eq!(body["code"], "cross-origin");
+    assert_eq!(cross_site.status(), 403);
+    assert_eq!(rebound.status(), 403);
+    assert_eq!(own_page.status(), 200);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn legacy_encodings_are_converted() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-latin1-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(
+        root.join("notes.md"),
+        b"# Caf\xe9\n\nLe gar\xe7on a mang\xe9 \xe0 la fen\xeatre du th\xe9\xe2tre.\n",
+    )
+    .unwrap();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/notes.md").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<h1>Café</h1>"));
+    assert!(body
+        .contains("<div class=\"rs-readme-encoding\">Converted to UTF-8 from windows-1252</div>"));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn other_machines_are_refused_unless_allowed() {
+    // Setup
+    let local_only = build_app(Arc::new(State::new(MockConverter, MockFinder)));
+    let shared = build_app(Arc::new(
+        State::new(MockConverter, MockFinder).with_remote_access(true),
+    ));
+    let from = |peer: &str| {
+        let mut req = Request::new(Method::Get, Url::parse("http://localhost/").unwrap());
+        req.set_peer_addr(Some(peer));
+        req
+    };
+
+    // Request
+    let mut remote: Response = local_only
+        .respond(from("192.168.1.20:50000"))
+        .await
+        .unwrap();
+    let loopback: Response = local_only.respond(from("[::1]:50000")).await.unwrap();
+    let allowed: Response = shared.respond(from("192.168.1.20:50000")).await.unwrap();
+
+    // Assert
+    assert_eq!(remote.status(), 403);
+    assert!(remote
+        .body_string()
+        .await
+        .unwrap()
+        .contains("--allow-remote"));
+    assert_eq!(loopback.status(), 200);
+    assert_eq!(allowed.status(), 200);
+}
+
+#[async_std::test]
+async fn panicking_handlers_get_a_500_page() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder);
+    let app = build_app_with(Arc::new(state), |server| {
+        server.at("/api/boom").get(|_| async {
+            panic!("boom");
+            #[allow(unreachable_code)]
+            Ok("")
+        });
+    });
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/api/boom").unwrap(),
+    );
+    let mut panicked: Response = app.respond(req).await.unwrap();
+    let req = Request::new(Method::Get, Url::parse("http://localhost/").unwrap());
+    let after: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(panicked.status(), 500);
+    let request_id = panicked["X-Request-Id"].as_str().to_string();
+    let body = panicked.body_string().await.unwrap();
+    assert!(body.contains("Something went wrong"));
+    assert!(body.contains(&format!("<code>{}</code>", request_id)));
+    assert_eq!(after.status(), 200);
+}
+
+#[async_std::test]
+async fn debug_errors_show_the_whole_error_and_request() {
+    // Create mock
+    struct MockConverterError;
+
+    #[async_trait]
+    impl MarkdownConverter for MockConverterError {
+        async fn convert_markdown(&self, _md: &str) -> Result<String, MarkdownError> {
+            Err(MarkdownError::ConverterUnavailable(
+                "{\"message\": \"Problems parsing JSON\"}".to_string(),
+            ))
+        }
+    }
+
+    // Setup
+    let state = State::new(MockConverterError, MockFinder).with_debug_errors(true);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let mut req = Request::new(Method::Get, Url::parse("http://localhost/foo.md").unwrap());
+    req.insert_header("User-Agent", "route-tests");
+    req.insert_header("Cookie", "session=secret");
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 502);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<td><code>converter-unavailable</code></td>"));
+    assert!(body.contains("Problems parsing JSON"));
+    assert!(body.contains("<td><code>http://localhost/foo.md</code></td>"));
+    assert!(body.contains("<td><code>route-tests</code></td>"));
+    assert!(!body.contains("session=secret"));
+}
+
+#[async_std::test]
+async fn requests_are_written_to_the_access_log() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-access-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    let log = root.join("access.log");
+    let state = State::new(MockConverter, MockFinder).with_access_log(AccessLog::new(&log));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let mut req = Request::new(Method::Get, Url::parse("http://localhost/foo.md").unwrap());
+    req.set_peer_addr(Some("10.0.0.7:50000"));
+    let _: Response = app.respond(req).await.unwrap();
+    let req = Request::new(Method::Get, Url::parse("http://localhost/bar.md").unwrap());
+    let _: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    let written = std::fs::read_to_string(&log).unwrap();
+    let lines: Vec<_> = written.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains("Z 10.0.0.7:50000 GET /foo.md 403 "));
+    assert!(lines[1].contains("Z - GET /bar.md 200 "));
+    assert!(lines.iter().all(|line| line.ends_with("ms")));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn polling_sends_contents_only_when_changed() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder);
+    let app = build_app(Arc::new(state));
+    let hash = ContentHash::of(b"# A Readme").to_string();
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/__rs-readme/poll/foo.md?hash=outdated").unwrap(),
+    );
+    let mut changed: Response = app.respond(req).await.unwrap();
+    let req = Request::new(
+        Method::Get,
+        Url::parse(&format!(
+            "http://localhost/__rs-readme/poll/foo.md?hash={}",
+            hash
+        ))
+        .unwrap(),
+    );
+    let mut unchanged: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(changed.status(), 200);
+    assert_eq!(changed.content_type(), Some(mime::JSON));
+    let body: serde_json::Value = changed.body_json().await.unwrap();
+    assert_eq!(body["hash"], hash.as_str());
+    assert!(body["contents"].is_string());
+    assert!(body["anchors"].is_array());
+    assert!(body["timestamp"].is_u64());
+    let body: serde_json::Value = unchanged.body_json().await.unwrap();
+    assert_eq!(body, serde_json::json!({ "hash": hash }));
+}
+
+#[async_std::test]
+async fn folders_named_like_documents_are_not_files() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-folder-md-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("notes.md")).unwrap();
+    let state = State::new(MockConverter, FileFinder::new(root.clone()));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/notes.md").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 404);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<h1>Not a File</h1>"));
+    assert!(body.contains("<strong>notes.md</strong> is a folder"));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn unreadable_files_get_a_permission_denied_page() {
+    // Create mock
+    struct Unreadable;
+
+    impl ContentFinder for Unreadable {
+        fn content_for(&self, resource: &str) -> Result<(String, ContentHash), ContentError> {
+            Err(ContentError::Forbidden {
+                resource: resource.to_string(),
+                reason: "Permission denied (os error 13)".to_string(),
+            })
+        }
+    }
+
+    // Setup
+    let state = State::new(MockConverter, Unreadable);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/secret.md").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 403);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<h1>Permission Denied</h1>"));
+    assert!(body.contains("<strong>secret.md</strong> is there, but rs-readme isn't allowed"));
+    assert!(body.contains("<pre>Permission denied (os error 13)</pre>"));
+}
+
+#[async_std::test]
+async fn error_pages_are_in_the_chosen_language() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-lang-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("notes.md")).unwrap();
+    let state = State::new(MockConverter, FileFinder::new(root.clone())).with_lang(Lang::Japanese);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/missing.md").unwrap(),
+    );
+    let mut missing: Response = app.respond(req).await.unwrap();
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/notes.md").unwrap(),
+    );
+    let mut folder: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(missing.status(), 404);
+    let body = missing.body_string().await.unwrap();
+    assert!(body.contains("missing.md が見つかりません</h1>"));
+    assert!(body.contains("<p>インデックスページでは <em>rs-readme</em> がルートフォルダー"));
+    assert_eq!(folder.status(), 404);
+    let body = folder.body_string().await.unwrap();
+    assert!(body.contains("<h1>ファイルではありません</h1>"));
+    assert!(body.contains("<p><strong>notes.md</strong> はフォルダーのため"));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn converter_errors_serve_the_last_rendered_version() {
+    // Create mock
+    struct MockConverterDown(Arc<AtomicBool>);
+
+    #[async_trait]
+    impl MarkdownConverter for MockConverterDown {
+        async fn convert_markdown(&self, _md: &str) -> Result<String, MarkdownError> {
+            if self.0.load(Ordering::SeqCst) {
+                return Err(MarkdownError::ConverterUnavailable(
+                    "GitHub is down".to_string(),
+                ));
+            }
+            Ok("<h1>A Readme</h1>".to_string())
+        }
+    }
+
+    // Setup
+    let down = Arc::new(AtomicBool::new(false));
+    let state = State::new(MockConverterDown(down.clone()), MockFinder);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/foo.md").unwrap());
+    let rendered: Response = app.respond(req).await.unwrap();
+    down.store(true, Ordering::SeqCst);
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/foo.md?converter=github").unwrap(),
+    );
+    let mut failed: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(rendered.status(), 200);
+    assert_eq!(failed.status(), 200);
+    assert_eq!(failed["Cache-Control"].as_str(), "no-store");
+    assert!(failed.header("ETag").is_none());
+    let body = failed.body_string().await.unwrap();
+    assert!(body.contains("<body><div class=\"rs-readme-failed rs-readme-stale\" role=\"alert\">"));
+    assert!(body.contains("<pre>Could not convert\nGitHub is down</pre>"));
+    assert!(body.contains("<a href=\"/foo.md?converter=github\">Retry</a>"));
+    assert!(body.contains("<h1>A Readme</h1>"));
+}
+
+#[async_std::test]
+async fn documents_render_with_their_warnings() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-warnings-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(
+        root.join("guide.md"),
+        "# Guide\n\n![here](./here.png) ![gone](./gone.png)\n",
+    )
+    .unwrap();
+    std::fs::write(root.join("here.png"), [0x89, b'P', b'N', b'G']).unwrap();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/guide.md").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains(
+        "<details class=\"rs-readme-warnings\"><summary>1 warning</summary><ul>\
+         <li data-kind=\"missing-image\">Image ./gone.png not found</li></ul></details>"
+    ));
+    assert!(body.contains("<h1"));
+    assert!(body.contains("src=\"./here.png\""));
+    assert!(body.contains(
+        "<span class=\"rs-readme-missing-image\" role=\"img\" \
+         aria-label=\"Image ./gone.png not found\">Image ./gone.png not found</span>"
+    ));
+    assert!(!body.contains("src=\"./gone.png\""));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn pages_are_titled_after_the_site_and_document() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder).with_title("myproject");
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/docs/install.md").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains("<title>myproject – A Readme</title>"));
+}
+
+#[async_std::test]
+async fn tables_are_sortable_and_filterable_when_turned_on() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-tables-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(
+        root.join("sizes.md"),
+        "| file | size |\n|---|---|\n| a.md | 1 |\n| b.md | 2 |\n| c.md | 3 |\n",
+    )
+    .unwrap();
+    let plain = State::new(OfflineConverter::default(), FileFinder::new(root.clone()));
+    let sortable = State::new(OfflineConverter::default(), FileFinder::new(root.clone()))
+        .with_sortable_tables(2);
+
+    // Request
+    let req = || {
+        Request::new(
+            Method::Get,
+            Url::parse("http://localhost/sizes.md").unwrap(),
+        )
+    };
+    let mut plain: Response = build_app(Arc::new(plain)).respond(req()).await.unwrap();
+    let mut sortable: Response = build_app(Arc::new(sortable)).respond(req()).await.unwrap();
+
+    // Assert
+    let plain = plain.body_string().await.unwrap();
+    assert!(plain.contains("<table><thead>"));
+    let sortable = sortable.body_string().await.unwrap();
+    assert!(sortable.contains("<table class=\"rs-readme-sortable\" data-filter><thead>"));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn task_lists_show_their_progress() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-tasks-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(
+        root.join("TODO.md"),
+        "# Release\n\n- [x] Changelog\n- [x] Version\n- [ ] Tag\n",
+    )
+    .unwrap();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/TODO.md").unwrap());
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains(
+        "<article id=\"rs-readme-content\" role=\"main\" class=\"markdown-body entry-content\" \
+         itemprop=\"text\">\
+         <div class=\"rs-readme-tasks\"><progress max=\"3\" value=\"2\">2/3</progress> \
+         2/3 tasks complete</div>"
+    ));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn display_preferences_can_be_changed_by_the_server() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder).with_prefs(Prefs {
+        font_size: 18,
+        ..Prefs::default()
+    });
+    let app = build_app(Arc::new(state));
+    let prefs_url = Url::parse("http://localhost/__rs-readme/prefs").unwrap();
+
+    // Request
+    let mut defaults: Response = app
+        .respond(Request::new(Method::Get, prefs_url.clone()))
+        .await
+        .unwrap();
+    let mut change_req = Request::new(Method::Put, prefs_url.clone());
+    change_req.set_body(r#"{"theme": "dark", "monospace": true}"#);
+    let mut changed: Response = app.respond(change_req).await.unwrap();
+    let mut bad_req = Request::new(Method::Put, prefs_url.clone());
+    bad_req.set_body(r#"{"line_width": 20}"#);
+    let mut bad: Response = app.respond(bad_req).await.unwrap();
+    let mut after: Response = app
+        .respond(Request::new(Method::Get, prefs_url))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(defaults.status(), 200);
+    let defaults: serde_json::Value = defaults.body_json().await.unwrap();
+    assert_eq!(
+        defaults,
+        serde_json::json!({
+            "font_size": 18, "line_width": 980, "monospace": false, "theme": "auto",
+        })
+    );
+    assert_eq!(changed.status(), 200);
+    let expected = serde_json::json!({
+        "font_size": 18, "line_width": 980, "monospace": true, "theme": "dark",
+    });
+    let changed: serde_json::Value = changed.body_json().await.unwrap();
+    assert_eq!(changed, expected);
+    assert_eq!(bad.status(), 400);
+    let bad: serde_json::Value = bad.body_json().await.unwrap();
+    assert_eq!(
+        bad,
+        serde_json::json!({
+            "code": "invalid-prefs", "message": "line_width has to be between 600 and 1600",
+        })
+    );
+    let after: serde_json::Value = after.body_json().await.unwrap();
+    assert_eq!(after, expected);
+}
+
+#[async_std::test]
+async fn right_to_left_documents_get_a_direction() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-rtl-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("he.md"), "# שלום\n\nזהו פרויקט קטן.\n").unwrap();
+    std::fs::write(
+        root.join("ar.md"),
+        "---\ndir: rtl\nlang: ar\n---\n# Hello\n",
+    )
+    .unwrap();
+    std::fs::write(root.join("en.md"), "# Hello\n").unwrap();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let mut bodies = Vec::new();
+    for file in &["he.md", "ar.md", "en.md"] {
+        let url = Url::parse(&format!("http://localhost/{}", file)).unwrap();
+        let mut res: Response = app.respond(Request::new(Method::Get, url)).await.unwrap();
+        assert_eq!(res.status(), 200);
+        bodies.push(res.body_string().await.unwrap());
+    }
+
+    // Assert
+    let article = "<article id=\"rs-readme-content\" role=\"main\" \
+                   class=\"markdown-body entry-content\" itemprop=\"text\"";
+    assert!(bodies[0].contains(&format!("{} dir=\"rtl\">", article)));
+    assert!(bodies[1].contains(&format!("{} dir=\"rtl\" lang=\"ar\">", article)));
+    assert!(bodies[2].contains(&format!("{}>", article)));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn pages_load_the_chosen_code_theme() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder).with_code_theme(CodeTheme::Solarized);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let page_req = Request::new(Method::Get, Url::parse("http://localhost/").unwrap());
+    let mut page: Response = app.respond(page_req).await.unwrap();
+    let css_req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/static/code-theme/solarized.css").unwrap(),
+    );
+    let mut css: Response = app.respond(css_req).await.unwrap();
+    let unknown_req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/static/code-theme/monokai.css").unwrap(),
+    );
+    let unknown: Response = app.respond(unknown_req).await.unwrap();
+
+    // Assert
+    let body = page.body_string().await.unwrap();
+    assert!(body.contains("<link rel=\"stylesheet\" href=\"/static/code-theme/solarized.css\">"));
+    assert_eq!(css.status(), 200);
+    assert_eq!(css.content_type(), Some(mime::CSS));
+    assert_eq!(css.body_string().await.unwrap(), CodeTheme::Solarized.css());
+    assert_eq!(unknown.status(), 404);
+}
+
+#[async_std::test]
+async fn code_fences_can_mark_lines_and_have_titles() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-fences-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(
+        root.join("README.md"),
+        "# Fences\n\n```rust {2-3} title=\"src/main.rs\"\nfn main() {\n    run();\n}\n```\n",
+    )
+    .unwrap();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(Method::Get, Url::parse("http://localhost/").unwrap());
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains(
+        "<figure class=\"rs-readme-code-block\"><figcaption class=\"rs-readme-code-title\">src/main.rs</figcaption><pre><code class=\"language-rust\">"
+    ));
+    assert_eq!(
+        body.matches("<span class=\"rs-readme-code-mark\">").count(),
+        2
+    );
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn documents_in_legacy_encodings_are_not_edited() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-edit-latin1-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("README.md"), b"# Caf\xe9\n").unwrap();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()))
+        .with_root(root.clone())
+        .with_editing(true);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/edit/README.md").unwrap(),
+    );
+    let res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 403);
+    assert_eq!(
+        std::fs::read(root.join("README.md")).unwrap(),
+        b"# Caf\xe9\n"
+    );
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn documents_can_be_edited_and_saved() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-edit-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("README.md"), "# Old\n").unwrap();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()))
+        .with_root(root.clone())
+        .with_editing(true);
+    let app = build_app(Arc::new(state));
+    let old_hash = ContentHash::of(b"# Old\n").to_string();
+    let save_url = |hash: &str| {
+        Url::parse(&format!(
+            "http://localhost/__rs-readme/save/README.md?hash={}",
+            hash
+        ))
+        .unwrap()
+    };
+
+    // Request
+    let edit_req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/edit/README.md").unwrap(),
+    );
+    let mut editor: Response = app.respond(edit_req).await.unwrap();
+    let mut preview_req = Request::new(
+        Method::Post,
+        Url::parse("http://localhost/__rs-readme/preview/README.md").unwrap(),
+    );
+    preview_req.set_body(serde_json::json!({ "contents": "# Typed" }));
+    let mut preview: Response = app.respond(preview_req).await.unwrap();
+    let mut save_req = Request::new(Method::Post, save_url(&old_hash));
+    save_req.insert_header("Origin", "http://localhost");
+    save_req.insert_header("Sec-Fetch-Site", "same-origin");
+    save_req.set_body(serde_json::json!({ "contents": "# New\n" }));
+    let mut saved: Response = app.respond(save_req).await.unwrap();
+    let mut stale_req = Request::new(Method::Post, save_url(&old_hash));
+    stale_req.set_body(serde_json::json!({ "contents": "# Stale\n" }));
+    let mut stale: Response = app.respond(stale_req).await.unwrap();
+    let mut outside_req = Request::new(
+        Method::Post,
+        Url::parse(&format!(
+            "http://localhost/__rs-readme/save/../README.md?hash={}",
+            old_hash
+        ))
+        .unwrap(),
+    );
+    outside_req.set_body(serde_json::json!({ "contents": "# Outside\n" }));
+    let outside: Response = app.respond(outside_req).await.unwrap();
+
+    // Assert
+    assert_eq!(editor.status(), 200);
+    let body = editor.body_string().await.unwrap();
+    assert!(body.contains(&format!(
+        "<div id=\"rs-readme-editor\" class=\"rs-readme-editor\" data-path=\"/README.md\" data-hash=\"{}\">",
+        old_hash
+    )));
+    assert!(body.contains("spellcheck=\"false\">\n# Old\n</textarea>"));
+    assert!(body.contains("<h1>Old</h1>"));
+    assert_eq!(preview.status(), 200);
+    assert!(preview
+        .body_string()
+        .await
+        .unwrap()
+        .contains("<h1>Typed</h1>"));
+    assert_eq!(saved.status(), 200);
+    let new_hash = ContentHash::of(b"# New\n").to_string();
+    assert_eq!(
+        saved.body_json::<serde_json::Value>().await.unwrap(),
+        serde_json::json!({ "hash": new_hash })
+    );
+    assert_eq!(
+        std::fs::read_to_string(root.join("README.md")).unwrap(),
+        "# New\n"
+    );
+    assert_eq!(stale.status(), 409);
+    assert_eq!(
+        stale.body_json::<serde_json::Value>().await.unwrap(),
+        serde_json::json!({
+            "code": "changed",
+            "message": "README.md changed since it was opened",
+            "hash": new_hash,
+        })
+    );
+    assert_ne!(outside.status(), 200);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn only_the_editor_can_save_documents() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-csrf-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("README.md"), "# Old\n").unwrap();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()))
+        .with_root(root.clone())
+        .with_editing(true);
+    let app = build_app(Arc::new(state));
+    let hash = ContentHash::of(b"# Old\n").to_string();
+    let save_url = Url::parse(&format!(
+        "http://localhost:4000/__rs-readme/save/README.md?hash={}",
+        hash
+    ))
+    .unwrap();
+    let json = serde_json::json!({ "contents": "# Overwritten\n" });
+
+    // Request
+    let mut cross_origin_req = Request::new(Method::Post, save_url.clone());
+    cross_origin_req.insert_header("Origin", "https://evil.example");
+    cross_origin_req.insert_header("Sec-Fetch-Site", "cross-site");
+    cross_origin_req.set_body(json.clone());
+    let mut cross_origin: Response = app.respond(cross_origin_req).await.unwrap();
+    let mut rebound_req = Request::new(
+        Method::Post,
+        Url::parse(&format!(
+            "http://evil.example:4000/__rs-readme/save/README.md?hash={}",
+            hash
+        ))
+        .unwrap(),
+    );
+    rebound_req.insert_header("Origin", "http://evil.example:4000");
+    rebound_req.set_body(json.clone());
+    let rebound: Response = app.respond(rebound_req).await.unwrap();
+    let mut form_req = Request::new(Method::Post, save_url.clone());
+    form_req.set_body("{\"contents\": \"# Overwritten\\n\"}");
+    form_req.set_content_type(mime::PLAIN);
+    let form: Response = app.respond(form_req).await.unwrap();
+    let mut unhashed_req = Request::new(
+        Method::Post,
+        Url::parse("http://localhost:4000/__rs-readme/save/README.md").unwrap(),
+    );
+    unhashed_req.set_body(json.clone());
+    let mut unhashed: Response = app.respond(unhashed_req).await.unwrap();
+    let mut preview_req = Request::new(
+        Method::Post,
+        Url::parse("http://localhost:4000/__rs-readme/preview/README.md").unwrap(),
+    );
+    preview_req.insert_header("Origin", "https://evil.example");
+    preview_req.set_body(json);
+    let preview: Response = app.respond(preview_req).await.unwrap();
+
+    // Assert
+    assert_eq!(cross_origin.status(), 403);
+    assert_eq!(
+        cross_origin.body_json::<serde_json::Value>().await.unwrap()["code"],
+        "cross-origin"
+    );
+    assert_eq!(rebound.status(), 403);
+    assert_eq!(form.status(), 415);
+    assert_eq!(unhashed.status(), 409);
+    assert_eq!(
+        unhashed.body_json::<serde_json::Value>().await.unwrap()["code"],
+        "missing-hash"
+    );
+    assert_eq!(preview.status(), 403);
+    assert_eq!(
+        std::fs::read_to_string(root.join("README.md")).unwrap(),
+        "# Old\n"
+    );
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn documents_cannot_be_edited_without_edit() {
+    // Setup
+    let state = State::new(MockConverter, MockFinder).with_root(PathBuf::from("."));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let mut save_req = Request::new(
+        Method::Post,
+        Url::parse("http://localhost/__rs-readme/save/README.md").unwrap(),
+    );
+    save_req.set_body("# New\n");
+    let save: Response = app.respond(save_req).await.unwrap();
+    let edit_req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/edit/README.md").unwrap(),
+    );
+    let mut edit: Response = app.respond(edit_req).await.unwrap();
+
+    // Assert
+    assert_ne!(save.status(), 200);
+    assert!(!edit
+        .body_string()
+        .await
+        .unwrap()
+        .contains("rs-readme-editor"));
+}
+
+#[async_std::test]
+async fn converter_query_param_picks_named_converters() {
+    // Create mock
+    struct MockConverterShout;
+
+    #[async_trait]
+    impl MarkdownConverter for MockConverterShout {
+        async fn convert_markdown(&self, md: &str) -> Result<String, MarkdownError> {
+            Ok(format!("<p>{}</p>", md.to_uppercase()))
+        }
+    }
+
+    // Setup
+    let state = State::new(MockConverter, MockFinder).with_converter("shout", MockConverterShout);
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let mut named: Response = app
+        .respond(Request::new(
+            Method::Get,
+            Url::parse("http://localhost/foo.md?converter=shout").unwrap(),
+        ))
+        .await
+        .unwrap();
+    let mut default: Response = app
+        .respond(Request::new(
+            Method::Get,
+            Url::parse("http://localhost/foo.md?converter=whisper").unwrap(),
+        ))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(named.status(), 200);
+    assert!(named
+        .body_string()
+        .await
+        .unwrap()
+        .contains("<p># A README</p>"));
+    assert!(default
+        .body_string()
+        .await
+        .unwrap()
+        .contains("<h1>A Readme</h1>"));
+}
+
+#[async_std::test]
+async fn changelogs_get_version_anchors() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-changelog-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(
+        root.join("CHANGELOG.md"),
+        "# Changelog\n\n## [1.1.0] - 2024-02-01\n\nAdded\n\n## [1.0.0] - 2024-01-01\n\nFirst\n",
+    )
+    .unwrap();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/CHANGELOG.md").unwrap(),
+    );
+    let mut res: Response = app.respond(req).await.unwrap();
+
+    // Assert
+    assert_eq!(res.status(), 200);
+    let body = res.body_string().await.unwrap();
+    assert!(body.contains(
+        "<details class=\"rs-readme-release rs-readme-latest\" id=\"1.1.0\" open><summary><h2>[1.1.0] - 2024-02-01</h2></summary>"
+    ));
+    assert!(body.contains("<details class=\"rs-readme-release\" id=\"1.0.0\" open>"));
+    assert!(body.contains("<option value=\"1.0.0\">[1.0.0] - 2024-01-01</option>"));
+    assert!(body.contains("class=\"rs-readme-latest-toggle\""));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn remote_images_are_proxied() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-proxy-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let badge = format!("{}/route-badge.svg", mockito::server_url());
+    std::fs::write(root.join("README.md"), format!("![build]({})\n", badge)).unwrap();
+    let _m = mockito::mock("GET", "/route-badge.svg")
+        .with_header("content-type", "image/svg+xml")
+        .with_body("<svg>passing</svg>")
+        .create();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()))
+        .with_image_proxy(ImageProxy::new(root.join("cache")));
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let page_req = Request::new(Method::Get, Url::parse("http://localhost/").unwrap());
+    let mut page: Response = app.respond(page_req).await.unwrap();
+    let body = page.body_string().await.unwrap();
+    let img = body.find("<img").unwrap();
+    let src_start = img + body[img..].find("src=\"").unwrap() + "src=\"".len();
+    let src = &body[src_start..src_start + body[src_start..].find('"').unwrap()];
+    let image_req = Request::new(
+        Method::Get,
+        Url::parse(&format!("http://localhost{}", src)).unwrap(),
+    );
+    let mut image: Response = app.respond(image_req).await.unwrap();
+    let elsewhere_req = Request::new(
+        Method::Get,
+        Url::parse(&format!(
+            "http://localhost/__rs-readme/image?url={}/route-other.svg",
+            mockito::server_url()
+        ))
+        .unwrap(),
+    );
+    let elsewhere: Response = app.respond(elsewhere_req).await.unwrap();
+
+    // Assert
+    assert!(src.starts_with("/__rs-readme/image?url=http%3A%2F%2F"));
+    assert_eq!(image.status(), 200);
+    assert_eq!(image.content_type().unwrap().essence(), "image/svg+xml");
+    assert_eq!(
+        image["Content-Security-Policy"],
+        "sandbox; default-src 'none'"
+    );
+    assert_eq!(image["X-Content-Type-Options"], "nosniff");
+    assert_eq!(image.body_string().await.unwrap(), "<svg>passing</svg>");
+    assert_eq!(elsewhere.status(), 403);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn documents_are_rendered_at_a_git_ref() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-git-ref-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .arg("-C")
+            .arg(&root)
+            .args([
+                "-c",
+                "user.name=rs-readme",
+                "-c",
+                "user.email=rs-readme@example.com",
+            ])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    git(&["init", "-q"]);
+    std::fs::write(root.join("README.md"), "Released").unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-q", "-m", "Release"]);
+    git(&["tag", "v1"]);
+    std::fs::write(root.join("README.md"), "Work in progress").unwrap();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()))
+        .with_root(root.clone());
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let mut bodies = Vec::new();
+    for url in &[
+        "http://localhost/README.md",
+        "http://localhost/README.md?ref=v1",
+        "http://localhost/git/v1/README.md",
+        "http://localhost/git/v1/",
+    ] {
+        let req = Request::new(Method::Get, Url::parse(url).unwrap());
+        let mut res: Response = app.respond(req).await.unwrap();
+        assert_eq!(res.status(), 200, "{}", url);
+        bodies.push(res.body_string().await.unwrap());
+    }
+    let missing_req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/git/no-such-ref/README.md").unwrap(),
+    );
+    let missing: Response = app.respond(missing_req).await.unwrap();
+
+    // Assert
+    assert!(bodies[0].contains("<p>Work in progress</p>"));
+    assert!(bodies[0].contains("<select class=\"rs-readme-history\""));
+    assert!(bodies[0].contains("Release</option>"));
+    assert!(bodies[0].contains("<div class=\"rs-readme-last-edit\">Last edited by rs-readme on "));
+    assert!(bodies[0].contains("<footer class=\"rs-readme-footer\">"));
+    assert!(bodies[0].contains("title=\"Uncommitted changes\""));
+    for body in &bodies[1..] {
+        assert!(body.contains("<p>Released</p>"));
+    }
+    assert_eq!(missing.status(), 404);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[async_std::test]
+async fn changes_between_git_refs_are_marked() {
+    // Setup
+    let root = std::env::temp_dir().join(format!("rs-readme-git-diff-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .arg("-C")
+            .arg(&root)
+            .args([
+                "-c",
+                "user.name=rs-readme",
+                "-c",
+                "user.email=rs-readme@example.com",
+            ])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    git(&["init", "-q"]);
+    std::fs::write(root.join("README.md"), "Run the old installer.\n").unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-q", "-m", "First"]);
+    git(&["tag", "v1"]);
+    std::fs::write(root.join("README.md"), "Run the new installer.\n").unwrap();
+    git(&["commit", "-q", "-am", "Second"]);
+    git(&["tag", "v2"]);
+    std::fs::write(root.join("README.md"), "Run the newest installer.\n").unwrap();
+    let state = State::new(OfflineConverter::default(), FileFinder::new(root.clone()))
+        .with_root(root.clone());
+    let app = build_app(Arc::new(state));
+
+    // Request
+    let mut bodies = Vec::new();
+    for url in &[
+        "http://localhost/diff/v1..v2/README.md",
+        "http://localhost/diff/v2../README.md",
+    ] {
+        let req = Request::new(Method::Get, Url::parse(url).unwrap());
+        let mut res: Response = app.respond(req).await.unwrap();
+        assert_eq!(res.status(), 200, "{}", url);
+        bodies.push(res.body_string().await.unwrap());
+    }
+    let invalid_req = Request::new(
+        Method::Get,
+        Url::parse("http://localhost/diff/v1/README.md").unwrap(),
+    );
+    let invalid: Response = app.respond(invalid_req).await.unwrap();
+
+    // Assert
+    assert!(bodies[0].contains("<p>Run the <del>old</del><ins>new</ins> installer.</p>"));
+    assert!(bodies[0].contains("<title>README.md (v1..v2)</title>"));
+    assert!(bodies[1].contains("<p>Run the <del>new</del><ins>newest</ins> installer.</p>"));
+    assert_eq!(invalid.status(), 400);
+
+    let _ = std::fs::remove_dir_all(&root);
 }